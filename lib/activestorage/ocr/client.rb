@@ -261,7 +261,8 @@ module ActiveStorage
           processing_time_ms: data[:processing_time_ms],
           warnings: data[:warnings] || [],
           engine: data[:engine],
-          preprocessing: data[:preprocessing]
+          preprocessing: data[:preprocessing],
+          no_text_detected: data[:no_text_detected] || false
         )
       end
     end
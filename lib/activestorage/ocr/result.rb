@@ -34,6 +34,9 @@ module ActiveStorage
       # nil if preprocessing was skipped.
       attr_reader :preprocessing
 
+      # Whether OCR completed but found no text in the file.
+      attr_reader :no_text_detected
+
       # Creates a new Result.
       #
       # ==== Parameters
@@ -44,13 +47,16 @@ module ActiveStorage
       # * +warnings+ - Array of warning messages (optional)
       # * +engine+ - The OCR engine used (optional)
       # * +preprocessing+ - Preprocessing stats hash (optional)
-      def initialize(text:, confidence:, processing_time_ms:, warnings: [], engine: nil, preprocessing: nil)
+      # * +no_text_detected+ - Whether OCR completed but found no text (optional)
+      def initialize(text:, confidence:, processing_time_ms:, warnings: [], engine: nil, preprocessing: nil,
+                     no_text_detected: false)
         @text = text
         @confidence = confidence
         @processing_time_ms = processing_time_ms
         @warnings = warnings
         @engine = engine
         @preprocessing = preprocessing
+        @no_text_detected = no_text_detected
       end
 
       # Returns whether OCR successfully extracted text.
@@ -62,6 +68,14 @@ module ActiveStorage
         !text.nil? && !text.empty?
       end
 
+      # Returns whether OCR completed successfully but the file contained no text.
+      #
+      # Unlike a failed request (which raises), this means the file was
+      # processed and is genuinely blank.
+      def no_text_detected?
+        no_text_detected == true
+      end
+
       # Returns the preprocessing time in milliseconds, or 0 if not preprocessed.
       def preprocessing_time_ms
         preprocessing&.dig(:total_time_ms) || 0
@@ -0,0 +1,224 @@
+//! ALTO output
+//!
+//! Renders an OCR response as ALTO v4 (Analyzed Layout and Text Object), the
+//! Library of Congress format digital archives ingest: `TextBlock`,
+//! `TextLine` and `String` elements positioned with `HPOS`, `VPOS`, `WIDTH`
+//! and `HEIGHT` in pixels. Lines and blocks are rebuilt from the word boxes
+//! the same way as for PAGE-XML.
+
+use crate::page_xml::{escape, group_lines, group_regions, Rect};
+use crate::server::OcrResponse;
+use std::fmt::Write;
+
+/// Content type of ALTO responses
+pub const CONTENT_TYPE: &str = "application/xml";
+
+const NAMESPACE: &str = "http://www.loc.gov/standards/alto/ns-v4#";
+const SCHEMA_LOCATION: &str = "http://www.loc.gov/alto/v4/alto-4-2.xsd";
+
+/// Render a response's word boxes as an ALTO document for one image
+pub fn render(response: &OcrResponse, image_width: u32, image_height: u32) -> String {
+    let words = response
+        .boxes
+        .as_ref()
+        .map(|boxes| boxes.boxes.as_slice())
+        .unwrap_or_default();
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<alto xmlns=\"{ns}\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+         xsi:schemaLocation=\"{ns} {schema}\">",
+        ns = NAMESPACE,
+        schema = SCHEMA_LOCATION
+    );
+    xml.push_str("  <Description>\n");
+    xml.push_str("    <MeasurementUnit>pixel</MeasurementUnit>\n");
+    if let Some(filename) = &response.filename {
+        xml.push_str("    <sourceImageInformation>\n");
+        let _ = writeln!(xml, "      <fileName>{}</fileName>", escape(filename));
+        xml.push_str("    </sourceImageInformation>\n");
+    }
+    xml.push_str("    <OCRProcessing ID=\"OCR_0\">\n");
+    xml.push_str("      <ocrProcessingStep>\n");
+    xml.push_str("        <processingSoftware>\n");
+    let _ = writeln!(
+        xml,
+        "          <softwareName>activestorage-ocr-server ({})</softwareName>",
+        escape(&response.engine)
+    );
+    let _ = writeln!(
+        xml,
+        "          <softwareVersion>{}</softwareVersion>",
+        env!("CARGO_PKG_VERSION")
+    );
+    xml.push_str("        </processingSoftware>\n");
+    xml.push_str("      </ocrProcessingStep>\n");
+    xml.push_str("    </OCRProcessing>\n");
+    xml.push_str("  </Description>\n");
+    xml.push_str("  <Layout>\n");
+    let _ = writeln!(
+        xml,
+        "    <Page ID=\"page_1\" PHYSICAL_IMG_NR=\"1\" WIDTH=\"{}\" HEIGHT=\"{}\">",
+        image_width, image_height
+    );
+    let _ = writeln!(
+        xml,
+        "      <PrintSpace HPOS=\"0\" VPOS=\"0\" WIDTH=\"{}\" HEIGHT=\"{}\">",
+        image_width, image_height
+    );
+
+    // IDs are unique within the document, so each kind is numbered through it
+    let (mut line_number, mut word_number) = (0, 0);
+    for (b, block) in group_regions(group_lines(words)).iter().enumerate() {
+        let bounds = block
+            .iter()
+            .map(|line| line.bounds)
+            .reduce(Rect::union)
+            .expect("regions are never empty");
+        let _ = writeln!(
+            xml,
+            "        <TextBlock ID=\"block_{}\" {}>",
+            b + 1,
+            position(bounds)
+        );
+
+        for line in block {
+            line_number += 1;
+            let _ = writeln!(
+                xml,
+                "          <TextLine ID=\"line_{}\" {}>",
+                line_number,
+                position(line.bounds)
+            );
+            for (w, word) in line.words.iter().enumerate() {
+                if w > 0 {
+                    xml.push_str("            <SP/>\n");
+                }
+                word_number += 1;
+                let confidence = word
+                    .confidence
+                    .map(|c| format!(" WC=\"{:.2}\"", c.clamp(0.0, 1.0)))
+                    .unwrap_or_default();
+                let _ = writeln!(
+                    xml,
+                    "            <String ID=\"string_{}\" {}{} CONTENT=\"{}\"/>",
+                    word_number,
+                    position(Rect::of(word)),
+                    confidence,
+                    escape(&word.text)
+                );
+            }
+            xml.push_str("          </TextLine>\n");
+        }
+
+        xml.push_str("        </TextBlock>\n");
+    }
+
+    xml.push_str("      </PrintSpace>\n");
+    xml.push_str("    </Page>\n");
+    xml.push_str("  </Layout>\n");
+    xml.push_str("</alto>\n");
+    xml
+}
+
+/// A rectangle as ALTO `HPOS`, `VPOS`, `WIDTH` and `HEIGHT` attributes
+fn position(rect: Rect) -> String {
+    format!(
+        "HPOS=\"{}\" VPOS=\"{}\" WIDTH=\"{}\" HEIGHT=\"{}\"",
+        rect.x,
+        rect.y,
+        rect.right - rect.x,
+        rect.bottom - rect.y
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{BoxesResponse, TextBoxResponse};
+
+    fn word(text: &str, x: u32, y: u32, confidence: Option<f32>) -> TextBoxResponse {
+        TextBoxResponse {
+            text: text.to_string(),
+            page: 1,
+            x,
+            y,
+            width: 50,
+            height: 20,
+            confidence,
+            polygon: None,
+        }
+    }
+
+    fn response(words: Vec<TextBoxResponse>) -> OcrResponse {
+        OcrResponse {
+            schema_version: 1,
+            text: String::new(),
+            raw_text: None,
+            truncated: None,
+            confidence: Some(0.9),
+            confidence_stats: None,
+            word_confidences: None,
+            processing_time_ms: 0,
+            warnings: Vec::new(),
+            engine: "ocrs".to_string(),
+            detected_format: "image/png".to_string(),
+            filename: Some("scan <1>.png".to_string()),
+            no_text_detected: false,
+            orientation_degrees: None,
+            preprocessing: None,
+            boxes: Some(BoxesResponse {
+                level: "word".to_string(),
+                boxes: words,
+            }),
+            lines: None,
+            regions: None,
+            pdf_verification: None,
+        }
+    }
+
+    #[test]
+    fn test_renders_blocks_lines_and_strings() {
+        let xml = render(
+            &response(vec![
+                word("Fish", 10, 10, Some(0.914)),
+                word("&", 70, 12, None),
+                word("Chips", 10, 40, Some(1.2)),
+            ]),
+            200,
+            100,
+        );
+
+        assert!(xml.contains("<fileName>scan &lt;1&gt;.png</fileName>"));
+        assert!(
+            xml.contains("<Page ID=\"page_1\" PHYSICAL_IMG_NR=\"1\" WIDTH=\"200\" HEIGHT=\"100\">")
+        );
+        assert!(xml.contains(
+            "<TextBlock ID=\"block_1\" HPOS=\"10\" VPOS=\"10\" WIDTH=\"110\" HEIGHT=\"50\">"
+        ));
+        assert!(xml.contains(
+            "<TextLine ID=\"line_1\" HPOS=\"10\" VPOS=\"10\" WIDTH=\"110\" HEIGHT=\"22\">"
+        ));
+        assert!(xml.contains(
+            "<String ID=\"string_1\" HPOS=\"10\" VPOS=\"10\" WIDTH=\"50\" HEIGHT=\"20\" WC=\"0.91\" CONTENT=\"Fish\"/>\n            <SP/>\n"
+        ));
+        // Words without a confidence leave WC out
+        assert!(xml.contains(
+            "<String ID=\"string_2\" HPOS=\"70\" VPOS=\"12\" WIDTH=\"50\" HEIGHT=\"20\" CONTENT=\"&amp;\"/>"
+        ));
+        // WC is clamped to the 0.0-1.0 the schema allows
+        assert!(xml.contains("ID=\"line_2\""));
+        assert!(xml.contains("WC=\"1.00\" CONTENT=\"Chips\"/>"));
+    }
+
+    #[test]
+    fn test_renders_empty_page_without_words() {
+        let xml = render(&response(Vec::new()), 200, 100);
+
+        assert!(xml.contains("<PrintSpace HPOS=\"0\" VPOS=\"0\" WIDTH=\"200\" HEIGHT=\"100\">"));
+        assert!(!xml.contains("<TextLine"));
+        assert!(xml.ends_with("</alto>\n"));
+    }
+}
@@ -1,4 +1,8 @@
+use crate::engine::DecodeMethod;
+use crate::languages::LanguageDefaults;
 use crate::Args;
+use std::path::PathBuf;
+use std::time::Duration;
 
 /// Server configuration
 #[derive(Debug, Clone)]
@@ -6,9 +10,66 @@ pub struct Config {
     pub host: String,
     pub port: u16,
     pub default_language: String,
+    pub language_defaults: LanguageDefaults,
     pub max_file_size: usize,
     #[allow(dead_code)]
     pub tessdata_path: Option<String>,
+    pub probe_max_dimension: u32,
+    pub min_image_dimension: u32,
+    pub pdf_lenient: bool,
+    /// Sharpness above which the sharpen step is skipped
+    pub sharpen_skip_threshold: Option<f32>,
+    /// In-flight OCR requests above which presets are downgraded
+    pub adaptive_preset_threshold: Option<usize>,
+    /// OCR requests processed at once
+    pub max_concurrency: usize,
+    /// Time a request may wait for one of the `max_concurrency` slots
+    pub queue_timeout: Option<Duration>,
+    pub handwriting_model_url: Option<String>,
+    pub fallback_engine: Option<String>,
+    pub compress_model_cache: bool,
+    pub max_model_size: u64,
+    /// Hosts models and `url` uploads may be downloaded from (None allows any
+    /// host)
+    pub allowed_download_hosts: Option<Vec<String>>,
+    /// Time allowed for downloading a file sent as a `url`
+    pub url_fetch_timeout: Option<Duration>,
+    /// Hosts `url` uploads may be fetched from despite resolving to
+    /// non-public addresses
+    pub url_fetch_allowed_hosts: Vec<String>,
+    /// Base URLs tessdata is downloaded from when GitHub fails
+    #[cfg_attr(not(feature = "engine-leptess"), allow(dead_code))]
+    pub tessdata_mirrors: Vec<String>,
+    pub model_verify_interval: Option<Duration>,
+    pub model_verify_repair: bool,
+    pub decode_method: DecodeMethod,
+    pub beam_width: u32,
+    pub keep_alive_timeout: Option<Duration>,
+    pub header_read_timeout: Option<Duration>,
+    pub request_timeout: Option<Duration>,
+    /// Time after which a running engine is considered stuck and restarted
+    pub engine_hard_timeout: Option<Duration>,
+    /// Most stuck engine calls left running before OCR requests are refused
+    pub max_abandoned_workers: usize,
+    /// Time after which a request stops waiting for the engine
+    pub processing_timeout: Option<Duration>,
+    pub warmup_on_start: bool,
+    /// Time a finished background job is kept for polling
+    pub job_ttl: Duration,
+    /// Most background jobs kept in memory
+    pub max_jobs: usize,
+    /// Most background jobs waiting for an OCR slot
+    pub max_pending_jobs: usize,
+    pub admin_token: Option<String>,
+    /// Directory successful OCR results are persisted to
+    pub result_store_dir: Option<PathBuf>,
+    /// Most warnings returned with a result (None returns all)
+    pub max_warnings: Option<usize>,
+    /// Pages of a scanned PDF OCRed at once when the server is idle
+    pub pdf_page_threads: usize,
+    /// Fail startup when any engine fails to start
+    #[cfg_attr(not(feature = "engine-leptess"), allow(dead_code))]
+    pub require_all_engines: bool,
 }
 
 impl From<Args> for Config {
@@ -17,8 +78,78 @@ impl From<Args> for Config {
             host: args.host,
             port: args.port,
             default_language: args.default_language,
+            language_defaults: LanguageDefaults::new(&args.language_defaults.unwrap_or_default()),
             max_file_size: args.max_file_size,
             tessdata_path: args.tessdata_path,
+            probe_max_dimension: args.probe_max_dimension,
+            min_image_dimension: args.min_image_dimension,
+            pdf_lenient: args.pdf_lenient,
+            sharpen_skip_threshold: Some(args.sharpen_skip_threshold)
+                .filter(|threshold| *threshold > 0.0),
+            adaptive_preset_threshold: Some(args.adaptive_preset_threshold)
+                .filter(|threshold| *threshold > 0),
+            max_concurrency: args
+                .max_concurrency
+                .filter(|max| *max > 0)
+                .unwrap_or_else(|| {
+                    std::thread::available_parallelism()
+                        .map(|cpus| cpus.get())
+                        .unwrap_or(1)
+                }),
+            queue_timeout: seconds(args.queue_timeout),
+            handwriting_model_url: args.handwriting_model_url,
+            fallback_engine: args.fallback_engine,
+            compress_model_cache: args.compress_model_cache,
+            max_model_size: args.max_model_size,
+            allowed_download_hosts: Some(
+                args.allowed_download_hosts
+                    .into_iter()
+                    .map(|host| host.trim().to_string())
+                    .filter(|host| !host.is_empty())
+                    .collect::<Vec<_>>(),
+            )
+            .filter(|hosts| !hosts.is_empty()),
+            url_fetch_timeout: seconds(args.url_fetch_timeout),
+            url_fetch_allowed_hosts: args
+                .url_fetch_allowed_hosts
+                .into_iter()
+                .map(|host| host.trim().to_string())
+                .filter(|host| !host.is_empty())
+                .collect(),
+            tessdata_mirrors: args
+                .tessdata_mirrors
+                .into_iter()
+                .map(|mirror| mirror.trim().trim_end_matches('/').to_string())
+                .filter(|mirror| !mirror.is_empty())
+                .collect(),
+            model_verify_interval: seconds(args.model_verify_interval),
+            model_verify_repair: args.model_verify_repair,
+            // Validated by clap's value_parser
+            decode_method: DecodeMethod::from_str(&args.decode_method).unwrap_or_default(),
+            beam_width: args.beam_width,
+            keep_alive_timeout: seconds(args.keep_alive_timeout),
+            header_read_timeout: seconds(args.header_read_timeout),
+            request_timeout: seconds(args.request_timeout),
+            engine_hard_timeout: seconds(args.engine_hard_timeout),
+            max_abandoned_workers: args.max_abandoned_workers.max(1),
+            processing_timeout: seconds(args.processing_timeout),
+            warmup_on_start: args.warmup_on_start,
+            // Finished jobs are held in memory, so they always expire
+            job_ttl: Duration::from_secs(args.job_ttl.max(1)),
+            max_jobs: args.max_jobs.max(1),
+            max_pending_jobs: args.max_pending_jobs.max(1),
+            admin_token: args.admin_token.filter(|token| !token.is_empty()),
+            result_store_dir: args
+                .result_store_dir
+                .filter(|dir| !dir.as_os_str().is_empty()),
+            max_warnings: Some(args.max_warnings).filter(|max| *max > 0),
+            pdf_page_threads: args.pdf_page_threads.max(1),
+            require_all_engines: args.require_all_engines,
         }
     }
 }
+
+/// Convert a timeout in seconds, treating 0 as disabled
+fn seconds(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
@@ -0,0 +1,182 @@
+//! Office document support
+//!
+//! DOCX and ODT files are zip archives. Scans pasted into them are stored as
+//! ordinary image files in a media folder (`word/media/` in DOCX, `Pictures/`
+//! in ODT), so they can be extracted and OCRed like the images of a scanned
+//! PDF. Text typed into the document itself is not extracted.
+
+use crate::error::OcrError;
+use image::DynamicImage;
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+pub const DOCX_MIME: &str =
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+pub const ODT_MIME: &str = "application/vnd.oasis.opendocument.text";
+
+/// Folders holding embedded images, for DOCX and ODT respectively
+const MEDIA_FOLDERS: [&str; 2] = ["word/media/", "Pictures/"];
+
+/// Images extracted from a document, in document order as far as it is known
+pub struct DocumentImages {
+    pub images: Vec<DynamicImage>,
+    /// Embedded files that could not be used (e.g. vector drawings)
+    pub warnings: Vec<String>,
+}
+
+/// MIME type of a DOCX or ODT upload, or None for other files
+///
+/// Clients don't always send the right content type, so zip archives are
+/// recognized by their contents, falling back to the declared type.
+pub fn document_mime(mime: &str, data: &[u8]) -> Option<&'static str> {
+    let declared = [DOCX_MIME, ODT_MIME].into_iter().find(|&m| m == mime);
+    if !data.starts_with(b"PK\x03\x04") {
+        return declared;
+    }
+
+    let Ok(mut archive) = ZipArchive::new(Cursor::new(data)) else {
+        return declared;
+    };
+    if archive.by_name("word/document.xml").is_ok() {
+        return Some(DOCX_MIME);
+    }
+    // ODT stores its MIME type uncompressed in a "mimetype" entry
+    let mut mimetype = String::new();
+    let is_odt = archive
+        .by_name("mimetype")
+        .is_ok_and(|mut file| file.read_to_string(&mut mimetype).is_ok())
+        && mimetype.trim() == ODT_MIME;
+    if is_odt {
+        Some(ODT_MIME)
+    } else {
+        declared
+    }
+}
+
+/// Extract the embedded images of a DOCX or ODT document
+///
+/// `max_size` limits the total size of the extracted files, so a small
+/// archive can't expand into more data than an upload is allowed to be.
+pub fn extract_images(data: &[u8], max_size: usize) -> Result<DocumentImages, OcrError> {
+    let mut archive = ZipArchive::new(Cursor::new(data))
+        .map_err(|e| OcrError::UnsupportedFormat(format!("Failed to open document: {}", e)))?;
+
+    // Media files are numbered in the order they were added (image1.png,
+    // image2.jpeg, ...), which is usually the order they appear in
+    let mut names: Vec<String> = archive
+        .file_names()
+        .filter(|name| {
+            MEDIA_FOLDERS.iter().any(|folder| name.starts_with(folder)) && !name.ends_with('/')
+        })
+        .map(str::to_string)
+        .collect();
+    names.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+
+    let mut images = Vec::new();
+    let mut warnings = Vec::new();
+    let mut remaining = max_size;
+
+    for name in names {
+        let mut file = archive
+            .by_name(&name)
+            .map_err(|e| OcrError::ProcessingError(format!("Failed to read '{}': {}", name, e)))?;
+
+        let mut bytes = Vec::new();
+        (&mut file)
+            .take((remaining as u64).saturating_add(1))
+            .read_to_end(&mut bytes)
+            .map_err(|e| OcrError::ProcessingError(format!("Failed to read '{}': {}", name, e)))?;
+        if bytes.len() > remaining {
+            return Err(OcrError::InvalidRequest(format!(
+                "Images in the document exceed the maximum of {} bytes",
+                max_size
+            )));
+        }
+        remaining -= bytes.len();
+
+        match image::load_from_memory(&bytes) {
+            Ok(image) => images.push(image),
+            Err(e) => {
+                tracing::warn!("Skipping embedded file '{}': {}", name, e);
+                warnings.push(format!("Skipped embedded file '{}': {}", name, e));
+            }
+        }
+    }
+
+    Ok(DocumentImages { images, warnings })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    const SAMPLE_PNG: &[u8] = include_bytes!("../tests/fixtures/sample_text.png");
+
+    /// PNG of the given width, to tell extracted images apart
+    fn png(width: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        DynamicImage::new_rgb8(width, 10)
+            .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_detects_documents_by_contents() {
+        let docx = archive(&[("word/document.xml", b"<w:document/>")]);
+        let odt = archive(&[("mimetype", ODT_MIME.as_bytes())]);
+        let other = archive(&[("readme.txt", b"hello")]);
+
+        assert_eq!(
+            document_mime("application/octet-stream", &docx),
+            Some(DOCX_MIME)
+        );
+        assert_eq!(document_mime("application/zip", &odt), Some(ODT_MIME));
+        assert_eq!(document_mime("application/zip", &other), None);
+        assert_eq!(document_mime("image/png", SAMPLE_PNG), None);
+        assert_eq!(document_mime(DOCX_MIME, b""), Some(DOCX_MIME));
+
+        // The contents win over a mislabeled content type
+        assert_eq!(document_mime(DOCX_MIME, &odt), Some(ODT_MIME));
+    }
+
+    #[test]
+    fn test_extracts_media_images_in_order() {
+        let docx = archive(&[
+            ("word/document.xml", b"<w:document/>"),
+            ("word/media/image10.png", &png(30)),
+            ("word/media/image2.png", &png(20)),
+            ("word/media/image3.emf", b"not a raster image"),
+            ("docProps/thumbnail.png", &png(40)),
+        ]);
+
+        let extracted = extract_images(&docx, 10 * 1024 * 1024).unwrap();
+
+        let widths: Vec<u32> = extracted.images.iter().map(|i| i.width()).collect();
+        assert_eq!(widths, vec![20, 30]);
+        assert_eq!(extracted.warnings.len(), 1);
+        assert!(extracted.warnings[0].contains("image3.emf"));
+    }
+
+    #[test]
+    fn test_extract_images_enforces_size_limit() {
+        let odt = archive(&[
+            ("mimetype", ODT_MIME.as_bytes()),
+            ("Pictures/scan.png", SAMPLE_PNG),
+        ]);
+
+        let err = extract_images(&odt, SAMPLE_PNG.len() - 1).err().unwrap();
+        assert!(matches!(err, OcrError::InvalidRequest(_)));
+    }
+}
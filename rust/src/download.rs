@@ -0,0 +1,201 @@
+//! HTTP downloads shared by the model cache and `url` uploads
+//!
+//! Redirects are followed here rather than by ureq, so callers can check
+//! every hop before it is requested, and bodies are read with a size limit,
+//! so a misconfigured URL or a hostile server can't fill memory or disk.
+
+use crate::error::OcrError;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+pub type Response = ureq::http::Response<ureq::Body>;
+
+/// Why a response body couldn't be read
+#[derive(Debug)]
+pub enum BodyError {
+    /// The body is over the limit, with its announced length or the bytes
+    /// read before giving up
+    TooLarge(u64),
+    Read(std::io::Error),
+}
+
+/// An agent that leaves redirects and error statuses to [`get`]
+pub fn agent(timeout: Option<Duration>) -> ureq::Agent {
+    config(timeout).build().into()
+}
+
+/// Configuration for agents that leave redirects and error statuses to
+/// [`get`]
+pub fn config(
+    timeout: Option<Duration>,
+) -> ureq::config::ConfigBuilder<ureq::typestate::AgentScope> {
+    ureq::Agent::config_builder()
+        .max_redirects(0)
+        .timeout_global(timeout)
+        .http_status_as_error(false)
+}
+
+/// GET `url`, following up to `max_redirects` redirects
+///
+/// `agent_for` is called with each URL before it is requested, to check it
+/// and return the agent to request it with; its errors are returned as they
+/// are. Failed requests are described to `failed`. Error statuses are left
+/// to the caller.
+pub fn get(
+    url: &str,
+    max_redirects: usize,
+    mut agent_for: impl FnMut(&str) -> Result<ureq::Agent, OcrError>,
+    failed: impl Fn(&dyn std::fmt::Display) -> OcrError,
+) -> Result<Response, OcrError> {
+    let mut url = url.to_string();
+    let mut redirects = 0;
+    loop {
+        let response = agent_for(&url)?.get(&url).call().map_err(|e| failed(&e))?;
+        if !response.status().is_redirection() {
+            return Ok(response);
+        }
+
+        redirects += 1;
+        if redirects > max_redirects {
+            return Err(failed(&"too many redirects"));
+        }
+        let location = response
+            .headers()
+            .get(ureq::http::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .ok_or_else(|| failed(&"redirect without a location"))?;
+        url = resolve_redirect(&url, location);
+    }
+}
+
+/// Copy a response body to `out`, giving up once it is over `max_size`
+/// bytes, and return the number of bytes copied
+pub fn read_limited(
+    response: Response,
+    max_size: u64,
+    out: &mut impl Write,
+) -> Result<u64, BodyError> {
+    // Fail before downloading anything when the size is announced up front
+    if let Some(length) = response
+        .body()
+        .content_length()
+        .filter(|&length| length > max_size)
+    {
+        return Err(BodyError::TooLarge(length));
+    }
+
+    // Read one byte past the limit to tell "exactly at the limit" from "over"
+    let mut body = response.into_body().into_reader().take(max_size + 1);
+    let copied = std::io::copy(&mut body, out).map_err(BodyError::Read)?;
+    if copied > max_size {
+        return Err(BodyError::TooLarge(copied));
+    }
+    Ok(copied)
+}
+
+/// Resolve a redirect's Location header against the URL that returned it
+pub fn resolve_redirect(url: &str, location: &str) -> String {
+    if location.contains("://") {
+        return location.to_string();
+    }
+
+    let Ok(uri) = url.parse::<ureq::http::Uri>() else {
+        return location.to_string();
+    };
+    let origin = format!(
+        "{}://{}",
+        uri.scheme_str().unwrap_or("https"),
+        uri.authority().map(|a| a.as_str()).unwrap_or_default()
+    );
+    if location.starts_with('/') {
+        format!("{}{}", origin, location)
+    } else {
+        // Relative to the directory of the current path
+        let path = uri.path();
+        let directory = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+        format!("{}{}{}", origin, directory, location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn failed(e: &dyn std::fmt::Display) -> OcrError {
+        OcrError::ProcessingError(e.to_string())
+    }
+
+    #[test]
+    fn test_get_checks_every_hop() {
+        let target = test_support::serve_once(
+            "/scan",
+            "HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\n",
+            b"data".to_vec(),
+        );
+        let url = test_support::serve_once(
+            "/start",
+            &format!(
+                "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n",
+                target
+            ),
+            Vec::new(),
+        );
+
+        let mut hops = Vec::new();
+        let response = get(
+            &url,
+            1,
+            |url| {
+                hops.push(url.to_string());
+                Ok(agent(None))
+            },
+            failed,
+        )
+        .unwrap();
+        assert_eq!(hops, [url, target]);
+
+        let mut data = Vec::new();
+        assert_eq!(read_limited(response, 4, &mut data).unwrap(), 4);
+        assert_eq!(data, b"data");
+    }
+
+    #[test]
+    fn test_read_limited_stops_past_the_limit() {
+        let announced = test_support::serve_once(
+            "/scan",
+            "HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\n",
+            vec![0; 8],
+        );
+        let response = get(&announced, 0, |_| Ok(agent(None)), failed).unwrap();
+        assert!(matches!(
+            read_limited(response, 4, &mut Vec::new()),
+            Err(BodyError::TooLarge(8))
+        ));
+
+        let streamed = test_support::serve_once(
+            "/scan",
+            "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n",
+            vec![0; 64],
+        );
+        let response = get(&streamed, 0, |_| Ok(agent(None)), failed).unwrap();
+        assert!(matches!(
+            read_limited(response, 4, &mut Vec::new()),
+            Err(BodyError::TooLarge(5))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_redirect() {
+        let url = "https://github.com/tesseract-ocr/tessdata_fast/raw/main/eng.traineddata";
+        assert_eq!(
+            resolve_redirect(url, "https://raw.githubusercontent.com/x"),
+            "https://raw.githubusercontent.com/x"
+        );
+        assert_eq!(resolve_redirect(url, "/other"), "https://github.com/other");
+        assert_eq!(
+            resolve_redirect(url, "deu.traineddata"),
+            "https://github.com/tesseract-ocr/tessdata_fast/raw/main/deu.traineddata"
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use crate::error::OcrError;
 use image::DynamicImage;
 use std::path::Path;
+use std::sync::Arc;
 
 /// OCR processing result
 #[derive(Debug, Clone)]
@@ -8,6 +9,200 @@ pub struct OcrResult {
     pub text: String,
     pub confidence: f32,
     pub warnings: Vec<String>,
+    /// Bounding boxes, when requested via [`OcrOptions::coords_level`]
+    pub boxes: Option<TextBoxes>,
+    /// Per-word recognition confidences (0.0-1.0), if the engine reports them
+    pub word_confidences: Option<Vec<f32>>,
+    /// Recognized words with a confidence (0.0-1.0) each, when requested via
+    /// [`OcrOptions::word_confidence`]
+    pub word_scores: Option<Vec<(String, f32)>>,
+    /// Recognized lines, when requested via [`OcrOptions::lines`]
+    pub lines: Option<Vec<LineBox>>,
+    /// Clockwise rotation in degrees applied to turn the text upright, when
+    /// requested via [`OcrOptions::detect_orientation`] and detected
+    pub orientation_degrees: Option<i32>,
+}
+
+/// Bounding boxes of recognized text at a given granularity
+#[derive(Debug, Clone)]
+pub struct TextBoxes {
+    /// Level the engine actually produced (may be coarser than requested)
+    pub level: CoordsLevel,
+    pub boxes: Vec<TextBox>,
+}
+
+/// A piece of recognized text and where it was found in the image
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextBox {
+    pub text: String,
+    /// 1-based image number (always 1 except for scanned PDFs)
+    pub page: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Recognition confidence (0.0-1.0), if the engine reports one
+    pub confidence: Option<f32>,
+    /// Corners of the text's oriented bounds as [x, y] pairs, clockwise from
+    /// the text's top left (only when polygons are requested)
+    pub polygon: Option<[[f32; 2]; 4]>,
+}
+
+/// A recognized line of text
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineBox {
+    /// Text, axis-aligned bounds and confidence of the whole line
+    pub text_box: TextBox,
+    /// Angle of the baseline in degrees, counterclockwise from horizontal
+    pub baseline_angle: f32,
+}
+
+/// Text regions found by detection alone, without recognition
+#[derive(Debug, Clone)]
+pub struct Detection {
+    /// Every detected word
+    pub words: Vec<DetectedWord>,
+    /// Words grouped into lines, in reading order
+    pub lines: Vec<Vec<DetectedWord>>,
+}
+
+/// A detected word region, which may be rotated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DetectedWord {
+    /// Corner points as `[x, y]` pixel coordinates
+    pub corners: [[f32; 2]; 4],
+}
+
+/// Granularity of returned bounding boxes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoordsLevel {
+    Word,
+    Character,
+}
+
+impl CoordsLevel {
+    /// Parse level from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "word" => Some(Self::Word),
+            "character" => Some(Self::Character),
+            _ => None,
+        }
+    }
+
+    /// Get level name as string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Word => "word",
+            Self::Character => "character",
+        }
+    }
+}
+
+/// Recognition model to use for a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RecognitionModel {
+    /// The engine's standard printed-text model
+    #[default]
+    Default,
+    /// A model tuned for handwritten text
+    Handwriting,
+}
+
+impl RecognitionModel {
+    /// Parse model from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "default" => Some(Self::Default),
+            "handwriting" => Some(Self::Handwriting),
+            _ => None,
+        }
+    }
+
+    /// Get model name as string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::Handwriting => "handwriting",
+        }
+    }
+}
+
+/// How recognized character sequences are decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DecodeMethod {
+    /// Pick the most likely character at each step (fast)
+    #[default]
+    Greedy,
+    /// Explore several candidate sequences (slower, more accurate)
+    BeamSearch,
+}
+
+impl DecodeMethod {
+    /// Parse decode method from string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "greedy" => Some(Self::Greedy),
+            "beam_search" => Some(Self::BeamSearch),
+            _ => None,
+        }
+    }
+
+    /// Get decode method name as string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Greedy => "greedy",
+            Self::BeamSearch => "beam_search",
+        }
+    }
+}
+
+/// Widest beam accepted for beam search decoding
+///
+/// Recognition time grows roughly linearly with the width, so wider beams
+/// mostly cost time without improving results.
+pub const MAX_BEAM_WIDTH: u32 = 500;
+
+/// Per-request OCR options
+#[derive(Debug, Clone, Default)]
+pub struct OcrOptions {
+    pub model: RecognitionModel,
+    /// Return bounding boxes at this level (None skips box extraction)
+    pub coords_level: Option<CoordsLevel>,
+    /// Decode method (None uses the engine's configured default)
+    pub decode: Option<DecodeMethod>,
+    /// Beam width for beam search decoding (None uses the engine's default)
+    pub beam_width: Option<u32>,
+    /// Return recognized lines with their baseline angle
+    pub lines: bool,
+    /// Languages to recognize, `+`-separated (None uses the server default)
+    #[cfg_attr(not(feature = "engine-leptess"), allow(dead_code))]
+    pub languages: Option<String>,
+    /// Tesseract page segmentation mode (None uses the language's default)
+    pub psm: Option<u32>,
+    /// Only recognize these characters (Tesseract only)
+    pub char_whitelist: Option<String>,
+    /// Never recognize these characters (Tesseract only)
+    pub char_blacklist: Option<String>,
+    /// Stop recognizing once more than this many words are found (engines
+    /// that recognize the whole page at once ignore it)
+    pub max_words: Option<usize>,
+    /// Skip work done only to score confidence (the response reports none)
+    pub skip_confidence: bool,
+    /// Fill in [`OcrResult::word_scores`]
+    pub word_confidence: bool,
+    /// OCR the images of PDFs even when they have embedded text
+    pub force_ocr: bool,
+    /// Read only the first page of PDFs
+    pub first_page_only: bool,
+    /// Fill in [`TextBox::polygon`] for boxes and lines
+    pub polygons: bool,
+    /// Detect which way up the text is and rotate the image to match
+    /// (engines that can't ignore it)
+    pub detect_orientation: bool,
+    /// Pages of a scanned PDF recognized at once (0 or 1 recognizes them
+    /// one at a time)
+    pub page_threads: usize,
 }
 
 /// Trait that all OCR engines must implement
@@ -19,14 +214,53 @@ pub trait OcrEngine: Send + Sync {
     fn description(&self) -> &'static str;
 
     /// Process a file (image or PDF) and return the extracted text
-    fn process(&self, path: &Path) -> Result<OcrResult, OcrError>;
+    fn process(&self, path: &Path, options: &OcrOptions) -> Result<OcrResult, OcrError>;
+
+    /// Process a file, calling `on_page` with each page's result as it completes
+    ///
+    /// Returns the combined result, the same as [`OcrEngine::process`]. By
+    /// default the whole file is reported as page 1.
+    fn process_pages(
+        &self,
+        path: &Path,
+        options: &OcrOptions,
+        on_page: &dyn Fn(u32, &OcrResult),
+    ) -> Result<OcrResult, OcrError> {
+        let result = self.process(path, options)?;
+        on_page(1, &result);
+        Ok(result)
+    }
 
     /// Process a DynamicImage directly (for preprocessed images)
-    fn process_image(&self, image: &DynamicImage) -> Result<OcrResult, OcrError>;
+    fn process_image(
+        &self,
+        image: &DynamicImage,
+        options: &OcrOptions,
+    ) -> Result<OcrResult, OcrError>;
 
     /// Get supported MIME types
     fn supported_formats(&self) -> Vec<String>;
 
     /// Get supported languages
     fn supported_languages(&self) -> Vec<String>;
+
+    /// Find text regions without recognizing their contents
+    fn detect(&self, image: &DynamicImage) -> Result<Detection, OcrError>;
+
+    /// Get the recognition models this engine can use
+    fn supported_models(&self) -> Vec<RecognitionModel>;
+
+    /// Download and load the data a request with these options would need
+    /// (its model and languages), so that request doesn't wait for it
+    fn prepare(&self, _options: &OcrOptions) -> Result<(), OcrError> {
+        Ok(())
+    }
+
+    /// The engine that should recognize `image`, for engines that hand the
+    /// work to others (None recognizes it with this engine)
+    ///
+    /// `image` is None when there is no image to inspect, as for PDFs.
+    fn route(&self, _image: Option<&DynamicImage>) -> Option<Arc<dyn OcrEngine>> {
+        None
+    }
 }
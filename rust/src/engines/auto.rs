@@ -0,0 +1,265 @@
+//! Automatic engine selection
+//!
+//! Routes each image to one of two engines by looking at the preprocessed
+//! image: clean, high-contrast scans go to the fast engine and noisy or
+//! washed-out images, such as phone photos, to the more robust one. The
+//! thresholds below are the knobs for tuning the split.
+
+// Only registered when both engines are built in
+#![cfg_attr(
+    not(all(feature = "engine-ocrs", feature = "engine-leptess")),
+    allow(dead_code)
+)]
+
+use crate::engine::{Detection, OcrEngine, OcrOptions, OcrResult, RecognitionModel};
+use crate::error::OcrError;
+use image::{DynamicImage, GrayImage};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Share of pixels that are isolated specks above which an image is noisy
+pub const NOISE_THRESHOLD: f32 = 0.005;
+
+/// How much darker or lighter than all its neighbours a pixel must be to
+/// count as a speck
+pub const SPECK_DELTA: u8 = 64;
+
+/// Spread between the darkest and lightest pixels below which an image is
+/// low contrast
+pub const MIN_CONTRAST: u8 = 64;
+
+/// Share of pixels ignored at each end when measuring contrast, so a few
+/// stray pixels don't make a washed-out image look crisp
+const CONTRAST_OUTLIERS: f32 = 0.01;
+
+/// Engine that hands each image to `clean` or `noisy`
+pub struct AutoEngine {
+    clean: Arc<dyn OcrEngine>,
+    noisy: Arc<dyn OcrEngine>,
+}
+
+impl AutoEngine {
+    pub fn new(clean: Arc<dyn OcrEngine>, noisy: Arc<dyn OcrEngine>) -> Self {
+        Self { clean, noisy }
+    }
+
+    fn choose(&self, image: &DynamicImage) -> &Arc<dyn OcrEngine> {
+        if needs_robust_engine(image) {
+            &self.noisy
+        } else {
+            &self.clean
+        }
+    }
+}
+
+impl OcrEngine for AutoEngine {
+    fn name(&self) -> &'static str {
+        "auto"
+    }
+
+    fn description(&self) -> &'static str {
+        "Picks ocrs for clean scans and leptess for noisy or low-contrast images"
+    }
+
+    /// Files are recognized without an image to inspect, so the clean engine
+    /// reads them
+    fn process(&self, path: &Path, options: &OcrOptions) -> Result<OcrResult, OcrError> {
+        self.clean.process(path, options)
+    }
+
+    fn process_pages(
+        &self,
+        path: &Path,
+        options: &OcrOptions,
+        on_page: &dyn Fn(u32, &OcrResult),
+    ) -> Result<OcrResult, OcrError> {
+        self.clean.process_pages(path, options, on_page)
+    }
+
+    fn process_image(
+        &self,
+        image: &DynamicImage,
+        options: &OcrOptions,
+    ) -> Result<OcrResult, OcrError> {
+        self.choose(image).process_image(image, options)
+    }
+
+    fn supported_formats(&self) -> Vec<String> {
+        self.clean.supported_formats()
+    }
+
+    fn supported_languages(&self) -> Vec<String> {
+        let mut languages = self.clean.supported_languages();
+        for language in self.noisy.supported_languages() {
+            if !languages.contains(&language) {
+                languages.push(language);
+            }
+        }
+        languages
+    }
+
+    fn detect(&self, image: &DynamicImage) -> Result<Detection, OcrError> {
+        self.clean.detect(image)
+    }
+
+    /// Only models both engines have, since either may get the image
+    fn supported_models(&self) -> Vec<RecognitionModel> {
+        let noisy = self.noisy.supported_models();
+        self.clean
+            .supported_models()
+            .into_iter()
+            .filter(|model| noisy.contains(model))
+            .collect()
+    }
+
+    fn prepare(&self, options: &OcrOptions) -> Result<(), OcrError> {
+        self.clean.prepare(options)?;
+        self.noisy.prepare(options)
+    }
+
+    fn route(&self, image: Option<&DynamicImage>) -> Option<Arc<dyn OcrEngine>> {
+        Some(
+            image
+                .map_or(&self.clean, |image| self.choose(image))
+                .clone(),
+        )
+    }
+}
+
+/// Whether an image is noisy or low contrast enough to need the robust engine
+pub fn needs_robust_engine(image: &DynamicImage) -> bool {
+    let gray = image.to_luma8();
+    estimate_noise(&gray) > NOISE_THRESHOLD || estimate_contrast(&gray) < MIN_CONTRAST
+}
+
+/// Share of pixels that are isolated specks: at least [`SPECK_DELTA`] darker
+/// or lighter than all eight neighbours
+///
+/// Text strokes have neighbours of their own color, so only noise counts.
+pub fn estimate_noise(gray: &GrayImage) -> f32 {
+    let (width, height) = gray.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+
+    let mut specks = 0u64;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let pixel = gray.get_pixel(x, y).0[0] as i16;
+            let (mut darkest, mut lightest) = (i16::MAX, i16::MIN);
+            for (dx, dy) in [
+                (0, 0),
+                (1, 0),
+                (2, 0),
+                (0, 1),
+                (2, 1),
+                (0, 2),
+                (1, 2),
+                (2, 2),
+            ] {
+                let neighbour = gray.get_pixel(x + dx - 1, y + dy - 1).0[0] as i16;
+                darkest = darkest.min(neighbour);
+                lightest = lightest.max(neighbour);
+            }
+            let delta = SPECK_DELTA as i16;
+            if pixel + delta <= darkest || pixel - delta >= lightest {
+                specks += 1;
+            }
+        }
+    }
+
+    specks as f32 / ((width - 2) as u64 * (height - 2) as u64) as f32
+}
+
+/// Spread between the darkest and lightest pixels, leaving out the most
+/// extreme [`CONTRAST_OUTLIERS`] at each end
+pub fn estimate_contrast(gray: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in gray.pixels() {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+    let total: u64 = histogram.iter().sum();
+    let outliers = (total as f32 * CONTRAST_OUTLIERS) as u64;
+
+    let percentile = |levels: &mut dyn Iterator<Item = usize>| {
+        let mut seen = 0;
+        for level in levels {
+            seen += histogram[level];
+            if seen > outliers {
+                return level as u8;
+            }
+        }
+        0
+    };
+    let dark = percentile(&mut (0..256));
+    let light = percentile(&mut (0..256).rev());
+    light.saturating_sub(dark)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    /// Rows of "text" strokes on a plain background
+    fn page(ink: u8, paper: u8) -> GrayImage {
+        GrayImage::from_fn(200, 100, |x, y| {
+            let stroke = y % 20 >= 8 && y % 20 < 12 && x % 12 < 8;
+            Luma([if stroke { ink } else { paper }])
+        })
+    }
+
+    /// Sprinkle salt-and-pepper specks over about 5% of the pixels
+    fn speckle(mut image: GrayImage) -> GrayImage {
+        let mut seed = 12345u32;
+        for pixel in image.pixels_mut() {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            match (seed >> 16) % 40 {
+                0 => pixel.0[0] = 0,
+                1 => pixel.0[0] = 255,
+                _ => {}
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_clean_scan_uses_fast_engine() {
+        let clean = page(0, 255);
+
+        assert_eq!(estimate_noise(&clean), 0.0);
+        assert_eq!(estimate_contrast(&clean), 255);
+        assert!(!needs_robust_engine(&DynamicImage::ImageLuma8(clean)));
+    }
+
+    #[test]
+    fn test_speckled_image_uses_robust_engine() {
+        let noisy = speckle(page(20, 240));
+
+        assert!(estimate_noise(&noisy) > NOISE_THRESHOLD);
+        assert!(needs_robust_engine(&DynamicImage::ImageLuma8(noisy)));
+    }
+
+    #[test]
+    fn test_low_contrast_image_uses_robust_engine() {
+        let faded = page(140, 170);
+
+        assert_eq!(estimate_noise(&faded), 0.0);
+        assert_eq!(estimate_contrast(&faded), 30);
+        assert!(needs_robust_engine(&DynamicImage::ImageLuma8(faded)));
+    }
+
+    #[test]
+    fn test_contrast_ignores_stray_pixels() {
+        let mut faded = page(140, 170);
+        faded.put_pixel(0, 0, Luma([0]));
+        faded.put_pixel(1, 0, Luma([255]));
+
+        assert_eq!(estimate_contrast(&faded), 30);
+    }
+
+    #[test]
+    fn test_tiny_images_have_no_noise() {
+        assert_eq!(estimate_noise(&GrayImage::new(2, 2)), 0.0);
+    }
+}
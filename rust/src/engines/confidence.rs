@@ -0,0 +1,340 @@
+//! Confidence scoring heuristics
+//!
+//! Scores text by how much it looks like real language, for engines that
+//! don't report confidences and for text extracted directly from PDFs.
+
+use crate::engine::OcrOptions;
+
+/// Text shorter than this (in characters) is too short for word and
+/// whitespace statistics
+const SHORT_TEXT_CHARS: usize = 5;
+
+/// Confidence of recognized text, or 0 when the request skips confidence
+pub fn text_confidence(text: &str, options: &OcrOptions) -> f32 {
+    if options.skip_confidence {
+        0.0
+    } else {
+        calculate_confidence(text)
+    }
+}
+
+/// Each word of recognized text with its confidence, when the request asks
+/// for word confidences
+pub fn word_scores(text: &str, options: &OcrOptions) -> Option<Vec<(String, f32)>> {
+    options.word_confidence.then(|| {
+        text.split_whitespace()
+            .map(|word| (word.to_string(), word_confidence(word)))
+            .collect()
+    })
+}
+
+/// Score a single word with the character, length and repetition heuristics
+///
+/// Whitespace statistics don't apply to a single word, so its weight is
+/// shared among the others.
+fn word_confidence(word: &str) -> f32 {
+    if word.is_empty() {
+        return 0.0;
+    }
+
+    let char_score = analyze_char_frequency(word);
+    let length_score = analyze_word_lengths(word);
+    let repetition_score = detect_repetition(word);
+
+    (0.50 * char_score + 0.25 * length_score + 0.25 * repetition_score).clamp(0.0, 1.0)
+}
+
+/// Calculate confidence score based on text quality heuristics.
+///
+/// For text that comes without per-character confidence scores, such as
+/// ocrs output or text embedded in a PDF, we analyze it for patterns that
+/// indicate OCR quality.
+fn calculate_confidence(text: &str) -> f32 {
+    if text.is_empty() {
+        return 0.0;
+    }
+    let chars = text.chars().count();
+    if chars < SHORT_TEXT_CHARS {
+        // Only the characters can be judged, and the fewer there are the less
+        // they tell, so lean towards a neutral 0.5 by length
+        let weight = chars as f32 / SHORT_TEXT_CHARS as f32;
+        return 0.5 + (analyze_short_text_chars(text) - 0.5) * weight;
+    }
+
+    let char_score = analyze_char_frequency(text);
+    let word_score = analyze_word_lengths(text);
+    let whitespace_score = analyze_whitespace(text);
+    let repetition_score = detect_repetition(text);
+
+    let confidence =
+        0.40 * char_score + 0.30 * word_score + 0.15 * whitespace_score + 0.15 * repetition_score;
+
+    confidence.clamp(0.0, 1.0)
+}
+
+/// Analyze character frequency for signs of garbled OCR.
+///
+/// Penalizes text with too many special/control characters or too few letters.
+fn analyze_char_frequency(text: &str) -> f32 {
+    let total = text.chars().count();
+    if total == 0 {
+        return 0.0;
+    }
+
+    let letters = text.chars().filter(|c| c.is_alphabetic()).count();
+    let special = text
+        .chars()
+        .filter(|c| !c.is_alphanumeric() && !c.is_whitespace() && !c.is_ascii_punctuation())
+        .count();
+
+    // Penalize high special char ratio
+    let special_ratio = special as f32 / total as f32;
+    let special_penalty = 1.0 - (special_ratio * 10.0).min(1.0);
+
+    // Penalize very low letter content (unless it's a numeric document)
+    let letter_ratio = letters as f32 / total as f32;
+    let letter_score = (letter_ratio * 1.5).min(1.0);
+
+    special_penalty * 0.6 + letter_score * 0.4
+}
+
+/// Analyze the characters of very short text, such as "OK" or "42".
+///
+/// Like [`analyze_char_frequency`], but digits count as much as letters,
+/// since short results are often numbers.
+fn analyze_short_text_chars(text: &str) -> f32 {
+    let visible: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if visible.is_empty() {
+        return 0.0;
+    }
+    let total = visible.len() as f32;
+
+    let alphanumeric = visible.iter().filter(|c| c.is_alphanumeric()).count();
+    let special = visible
+        .iter()
+        .filter(|c| !c.is_alphanumeric() && !c.is_ascii_punctuation())
+        .count();
+
+    let special_penalty = 1.0 - (special as f32 / total * 2.0).min(1.0);
+    let alphanumeric_ratio = alphanumeric as f32 / total;
+
+    special_penalty * 0.6 + alphanumeric_ratio * 0.4
+}
+
+/// Analyze word length distribution.
+///
+/// Garbled OCR often produces single-character "words" or very long sequences.
+fn analyze_word_lengths(text: &str) -> f32 {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.is_empty() {
+        return 0.5;
+    }
+
+    let total_len: usize = words.iter().map(|w| w.len()).sum();
+    let avg_len = total_len as f32 / words.len() as f32;
+
+    // Ideal average word length: 4-8 chars
+    let avg_score = match avg_len as usize {
+        0..=1 => 0.3,
+        2..=3 => 0.7,
+        4..=8 => 1.0,
+        9..=12 => 0.8,
+        _ => 0.4,
+    };
+
+    // Penalize too many single-char "words"
+    let single_count = words.iter().filter(|w| w.len() == 1).count();
+    let single_ratio = single_count as f32 / words.len() as f32;
+    let single_penalty = 1.0 - (single_ratio * 1.5).min(0.5);
+
+    avg_score * single_penalty
+}
+
+/// Analyze whitespace ratio.
+///
+/// Normal text has ~10-25% whitespace. Too dense or too sparse indicates issues.
+fn analyze_whitespace(text: &str) -> f32 {
+    let total = text.chars().count();
+    if total == 0 {
+        return 0.0;
+    }
+
+    let whitespace = text.chars().filter(|c| c.is_whitespace()).count();
+    let ratio = (whitespace as f32 / total as f32) * 100.0;
+
+    match ratio as usize {
+        0..=5 => 0.5,   // Too dense
+        6..=10 => 0.8,  // Slightly dense
+        11..=25 => 1.0, // Ideal
+        26..=40 => 0.7, // Slightly sparse
+        _ => 0.3,       // Too sparse
+    }
+}
+
+/// Characters that legitimately repeat in documents: dotted leaders in tables
+/// of contents, "------" separators, "____" form fields and "====" rules.
+const LEGITIMATE_REPEATS: &[char] = &['.', '-', '_', '='];
+
+/// Detect repeated character sequences.
+///
+/// Patterns like "aaaa" or "####" often indicate OCR confusion. Runs of
+/// [`LEGITIMATE_REPEATS`] are ignored.
+fn detect_repetition(text: &str) -> f32 {
+    let mut max_repeat = 1;
+    let mut current = 1;
+    let mut prev: Option<char> = None;
+
+    for c in text.chars() {
+        if Some(c) == prev && !c.is_whitespace() && !LEGITIMATE_REPEATS.contains(&c) {
+            current += 1;
+            max_repeat = max_repeat.max(current);
+        } else {
+            current = 1;
+        }
+        prev = Some(c);
+    }
+
+    match max_repeat {
+        1..=3 => 1.0,
+        4..=5 => 0.8,
+        6..=10 => 0.5,
+        _ => 0.2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_text_returns_zero() {
+        assert_eq!(calculate_confidence(""), 0.0);
+    }
+
+    #[test]
+    fn test_short_text_scored_by_characters() {
+        let clean = calculate_confidence("42");
+        let garbled = calculate_confidence("§@");
+        assert!(clean > 0.5, "Expected > 0.5, got {}", clean);
+        assert!(garbled < 0.5, "Expected < 0.5, got {}", garbled);
+        assert_eq!(calculate_confidence("OK"), clean);
+    }
+
+    #[test]
+    fn test_short_text_confidence_grows_with_length() {
+        let one = calculate_confidence("7");
+        let four = calculate_confidence("Test");
+        assert!(one > 0.5 && one < four, "Expected 0.5 < {} < {}", one, four);
+        assert!(four < 1.0);
+    }
+
+    #[test]
+    fn test_clean_text_high_confidence() {
+        let text = "Hello World OCR Test 12345";
+        let confidence = calculate_confidence(text);
+        assert!(confidence > 0.7, "Expected > 0.7, got {}", confidence);
+    }
+
+    #[test]
+    fn test_garbled_text_low_confidence() {
+        // Lots of special characters indicates bad OCR
+        let text = "§±®©¥€£¢¤";
+        let confidence = calculate_confidence(text);
+        assert!(confidence < 0.5, "Expected < 0.5, got {}", confidence);
+    }
+
+    #[test]
+    fn test_repeated_chars_lower_confidence() {
+        let text = "Hello aaaaaaaaaaaa World";
+        let confidence = calculate_confidence(text);
+        // Should be lower than clean text due to repetition
+        assert!(confidence < 0.9, "Expected < 0.9, got {}", confidence);
+    }
+
+    #[test]
+    fn test_single_char_words_lower_confidence() {
+        // Many single-char "words" suggests garbled OCR
+        let text = "a b c d e f g h i j k l m n o p";
+        let confidence = calculate_confidence(text);
+        assert!(confidence < 0.7, "Expected < 0.7, got {}", confidence);
+    }
+
+    #[test]
+    fn test_normal_sentence_good_confidence() {
+        let text = "The quick brown fox jumps over the lazy dog.";
+        let confidence = calculate_confidence(text);
+        assert!(confidence > 0.75, "Expected > 0.75, got {}", confidence);
+    }
+
+    #[test]
+    fn test_analyze_char_frequency_normal() {
+        let score = analyze_char_frequency("Hello World");
+        assert!(score > 0.8, "Expected > 0.8, got {}", score);
+    }
+
+    #[test]
+    fn test_analyze_char_frequency_special() {
+        let score = analyze_char_frequency("§±®©¥€£¢¤ƒ");
+        assert!(score < 0.5, "Expected < 0.5, got {}", score);
+    }
+
+    #[test]
+    fn test_analyze_word_lengths_normal() {
+        let score = analyze_word_lengths("Hello World Test");
+        assert!(score > 0.8, "Expected > 0.8, got {}", score);
+    }
+
+    #[test]
+    fn test_analyze_whitespace_normal() {
+        let text = "Hello World Test String";
+        let score = analyze_whitespace(text);
+        assert!(score > 0.7, "Expected > 0.7, got {}", score);
+    }
+
+    #[test]
+    fn test_detect_repetition_none() {
+        assert_eq!(detect_repetition("Hello World"), 1.0);
+    }
+
+    #[test]
+    fn test_detect_repetition_some() {
+        let score = detect_repetition("Hellooooo World");
+        assert!(score < 1.0, "Expected < 1.0, got {}", score);
+    }
+
+    #[test]
+    fn test_detect_repetition_ignores_dotted_leaders() {
+        assert_eq!(detect_repetition("Introduction .................. 1"), 1.0);
+        assert_eq!(detect_repetition("Name ________ Date ========"), 1.0);
+        assert_eq!(detect_repetition("----------------"), 1.0);
+    }
+
+    #[test]
+    fn test_confidence_not_penalized_by_table_of_contents() {
+        let text =
+            "Chapter One Getting Started ........ 1\nChapter Two Configuration .......... 12";
+        assert_eq!(detect_repetition(text), 1.0);
+        assert!(calculate_confidence(text) > 0.7);
+    }
+
+    #[test]
+    fn test_word_scores_flag_garbled_words() {
+        let options = OcrOptions {
+            word_confidence: true,
+            ..Default::default()
+        };
+        let scores = word_scores("Invoice  §§§§§§ total\n42", &options).unwrap();
+
+        let words: Vec<&str> = scores.iter().map(|(word, _)| word.as_str()).collect();
+        assert_eq!(words, ["Invoice", "§§§§§§", "total", "42"]);
+        assert_eq!(scores[0].1, 1.0);
+        assert!(scores[1].1 < 0.4, "Expected < 0.4, got {}", scores[1].1);
+        assert!(scores[1].1 < scores[2].1);
+        assert!(scores[3].1 > 0.5, "Expected > 0.5, got {}", scores[3].1);
+    }
+
+    #[test]
+    fn test_word_scores_only_when_requested() {
+        assert!(word_scores("Hello", &OcrOptions::default()).is_none());
+    }
+}
@@ -4,13 +4,22 @@
 //! Uses tesseract-static crate for static linking (no system dependencies).
 //! Downloads tessdata (training data) automatically on first use.
 
+use super::confidence::{text_confidence, word_scores};
+use super::pdf;
+use super::recognize_pages;
 use crate::config::Config;
-use crate::engine::{OcrEngine, OcrResult};
+use crate::engine::{
+    CoordsLevel, Detection, LineBox, OcrEngine, OcrOptions, OcrResult, RecognitionModel, TextBox,
+    TextBoxes,
+};
 use crate::error::OcrError;
+use crate::languages::LanguageDefaults;
+use crate::model_cache::ModelCache;
 use image::DynamicImage;
-use std::fs::File;
-use std::io::{Read, Write};
+use std::collections::HashMap;
+use std::ops::ControlFlow;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 use tesseract_static::tesseract::Tesseract;
 
 /// Tesseract OCR Engine
@@ -19,15 +28,34 @@ pub struct LeptessEngine {
     tessdata_path: String,
     /// Default language for OCR
     default_language: String,
+    /// Recommended page segmentation mode per language
+    language_defaults: LanguageDefaults,
+    /// Tessdata directories for other models and languages, downloaded on first use
+    tessdata_paths: Mutex<HashMap<(RecognitionModel, String), String>>,
+    model_cache: Arc<ModelCache>,
+    /// Base URLs tried in order when downloading from GitHub fails
+    tessdata_mirrors: Vec<String>,
 }
 
 impl LeptessEngine {
     /// Create a new Tesseract-based OCR engine
-    pub fn new(config: &Config) -> Result<Self, OcrError> {
+    pub fn new(config: &Config, model_cache: Arc<ModelCache>) -> Result<Self, OcrError> {
         let default_language = config.default_language.clone();
 
         // Ensure tessdata is available (download if needed)
-        let tessdata_path = ensure_tessdata_available(&default_language)?;
+        let tessdata_path = ensure_tessdata_available(
+            &model_cache,
+            &config.tessdata_mirrors,
+            &default_language,
+            RecognitionModel::Default,
+        )
+        .map_err(|e| match e {
+            OcrError::NotFound(message) => OcrError::InitializationError(format!(
+                "Default language '{}' is not available: {}",
+                default_language, message
+            )),
+            e => e,
+        })?;
 
         // Validate that tessdata is accessible by doing a test initialization
         let test_tess =
@@ -47,56 +75,154 @@ impl LeptessEngine {
         Ok(Self {
             tessdata_path,
             default_language,
+            language_defaults: config.language_defaults.clone(),
+            tessdata_paths: Mutex::new(HashMap::new()),
+            model_cache,
+            tessdata_mirrors: config.tessdata_mirrors.clone(),
         })
     }
 
+    /// Get the tessdata directory holding every requested language for a
+    /// recognition model
+    fn tessdata_path_for(
+        &self,
+        model: RecognitionModel,
+        languages: &str,
+    ) -> Result<String, OcrError> {
+        if model == RecognitionModel::Default && languages == self.default_language {
+            return Ok(self.tessdata_path.clone());
+        }
+
+        // Hold the lock while downloading so concurrent requests only download once
+        let mut paths = self.tessdata_paths.lock().unwrap();
+        let key = (model, languages.to_string());
+        if let Some(path) = paths.get(&key) {
+            return Ok(path.clone());
+        }
+
+        // Every language lands in the same directory. A language no source
+        // has is a code Tesseract doesn't know; other failures are ours
+        let mut path = self.tessdata_path.clone();
+        for language in languages.split('+') {
+            path = ensure_tessdata_available(
+                &self.model_cache,
+                &self.tessdata_mirrors,
+                language,
+                model,
+            )
+            .map_err(|e| match e {
+                OcrError::NotFound(_) => OcrError::InvalidRequest(format!(
+                    "Language '{}' is not available for Tesseract",
+                    language
+                )),
+                e => e,
+            })?;
+        }
+        paths.insert(key, path.clone());
+        Ok(path)
+    }
+
     /// Process an image file
-    fn process_image_file(&self, path: &Path) -> Result<OcrResult, OcrError> {
+    fn process_image_file(&self, path: &Path, options: &OcrOptions) -> Result<OcrResult, OcrError> {
         // Load image using the image crate
         let img = image::open(path)
             .map_err(|e| OcrError::ProcessingError(format!("Failed to load image: {}", e)))?;
 
-        self.process_dynamic_image(&img)
+        self.process_dynamic_image(&img, options)
     }
 
     /// Process a DynamicImage directly (used by both process_image and process_pdf)
-    fn process_dynamic_image(&self, img: &image::DynamicImage) -> Result<OcrResult, OcrError> {
+    fn process_dynamic_image(
+        &self,
+        img: &image::DynamicImage,
+        options: &OcrOptions,
+    ) -> Result<OcrResult, OcrError> {
+        let languages = options
+            .languages
+            .as_deref()
+            .unwrap_or(&self.default_language);
+        let tessdata_path = self.tessdata_path_for(options.model, languages)?;
+
         // Convert to RGB8 for consistent handling
-        let rgb_img = img.to_rgb8();
+        let mut rgb_img = img.to_rgb8();
+        let mut warnings = Vec::new();
+        let orientation_degrees = if options.detect_orientation {
+            match detect_orientation(&rgb_img, &tessdata_path, languages)? {
+                Some(degrees) => {
+                    rgb_img = rotate_clockwise(&rgb_img, degrees);
+                    Some(degrees as i32)
+                }
+                None => {
+                    warnings.push(
+                        "Text orientation could not be detected confidently, image not rotated"
+                            .to_string(),
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
         let (width, height) = rgb_img.dimensions();
 
-        // Convert to BMP in memory (BMP is always supported by leptonica)
-        let mut bmp_data = Vec::new();
-        {
-            let mut cursor = std::io::Cursor::new(&mut bmp_data);
-            rgb_img
-                .write_to(&mut cursor, image::ImageFormat::Bmp)
-                .map_err(|e| {
-                    OcrError::ProcessingError(format!("Failed to convert to BMP: {}", e))
-                })?;
-        }
+        // Encode in memory for Leptonica
+        let (image_data, format) = encode_for_leptonica(&rgb_img)?;
 
         tracing::debug!(
-            "Processing image: {}x{}, BMP size: {} bytes",
+            "Processing image: {}x{}, {:?} size: {} bytes",
             width,
             height,
-            bmp_data.len()
+            format,
+            image_data.len()
         );
 
-        let mut tess = Tesseract::new(Some(&self.tessdata_path), Some(&self.default_language))
+        let mut tess = Tesseract::new(Some(&tessdata_path), Some(languages))
             .map_err(|e| OcrError::ProcessingError(format!("Failed to create Tesseract: {}", e)))?;
 
-        // Use set_image_from_mem with BMP data
-        tess = tess.set_image_from_mem(&bmp_data).map_err(|e| {
+        // An explicit psm wins over the recommended mode for the language
+        let psm = options
+            .psm
+            .or_else(|| self.language_defaults.get(languages).psm);
+        if let Some(psm) = psm {
+            tess = tess
+                .set_variable("tessedit_pageseg_mode", &psm.to_string())
+                .map_err(|e| {
+                    OcrError::ProcessingError(format!(
+                        "Failed to set page segmentation mode: {}",
+                        e
+                    ))
+                })?;
+        }
+
+        for (variable, chars) in [
+            ("tessedit_char_whitelist", &options.char_whitelist),
+            ("tessedit_char_blacklist", &options.char_blacklist),
+        ] {
+            if let Some(chars) = chars {
+                tess = tess.set_variable(variable, chars).map_err(|e| {
+                    OcrError::ProcessingError(format!("Failed to set {}: {}", variable, e))
+                })?;
+            }
+        }
+
+        tess = tess.set_image_from_mem(&image_data).map_err(|e| {
             OcrError::ProcessingError(format!(
-                "Failed to set image ({}x{}, {} bytes): {}",
+                "Failed to set image ({}x{}, {:?}, {} bytes): {}",
                 width,
                 height,
-                bmp_data.len(),
+                format,
+                image_data.len(),
                 e
             ))
         })?;
 
+        if options.coords_level == Some(CoordsLevel::Character) {
+            // Adds per-character ocrx_cinfo spans to the hOCR output
+            tess = tess.set_variable("hocr_char_boxes", "1").map_err(|e| {
+                OcrError::ProcessingError(format!("Failed to enable character boxes: {}", e))
+            })?;
+        }
+
         tess = tess
             .recognize()
             .map_err(|e| OcrError::ProcessingError(format!("Failed to recognize text: {}", e)))?;
@@ -106,22 +232,98 @@ impl LeptessEngine {
             .map_err(|e| OcrError::ProcessingError(format!("Failed to get text: {}", e)))?;
 
         // Get confidence score (0-100 scale, convert to 0.0-1.0)
-        let confidence = tess.mean_text_conf() as f32 / 100.0;
+        let confidence = if options.skip_confidence {
+            0.0
+        } else {
+            tess.mean_text_conf() as f32 / 100.0
+        };
+
+        // Word data is read for the per-word confidence distribution, unless
+        // confidence is skipped and neither word boxes nor lines need it
+        let needs_words = !options.skip_confidence
+            || options.coords_level == Some(CoordsLevel::Word)
+            || options.lines;
+        let tsv = if needs_words {
+            tess.get_tsv_text(0)
+                .map_err(|e| OcrError::ProcessingError(format!("Failed to get word data: {}", e)))?
+        } else {
+            String::new()
+        };
+        let words = parse_tsv_word_boxes(&tsv);
+        let word_confidences = words.iter().filter_map(|word| word.confidence).collect();
+        // Tesseract scores words itself, so the text heuristics aren't needed
+        let scored_words = options.word_confidence.then(|| {
+            words
+                .iter()
+                .filter_map(|word| Some((word.text.clone(), word.confidence?)))
+                .collect()
+        });
+
+        let boxes = match options.coords_level {
+            Some(CoordsLevel::Word) => Some(TextBoxes {
+                level: CoordsLevel::Word,
+                boxes: words,
+            }),
+            Some(CoordsLevel::Character) => {
+                let hocr = tess.get_hocr_text(0).map_err(|e| {
+                    OcrError::ProcessingError(format!("Failed to get character boxes: {}", e))
+                })?;
+                Some(TextBoxes {
+                    level: CoordsLevel::Character,
+                    boxes: parse_hocr_character_boxes(&hocr),
+                })
+            }
+            None => None,
+        };
+
+        let lines = if options.lines {
+            let hocr = tess.get_hocr_text(0).map_err(|e| {
+                OcrError::ProcessingError(format!("Failed to get line baselines: {}", e))
+            })?;
+            Some(parse_lines(&tsv, &hocr))
+        } else {
+            None
+        };
 
         Ok(OcrResult {
             text: text.trim().to_string(),
             confidence,
-            warnings: Vec::new(),
+            warnings,
+            boxes,
+            word_confidences: Some(word_confidences),
+            word_scores: scored_words,
+            lines,
+            orientation_degrees,
         })
     }
 
     /// Process a PDF file
-    fn process_pdf(&self, path: &Path) -> Result<OcrResult, OcrError> {
+    ///
+    /// Each scanned page is passed to `on_page` as soon as it has been
+    /// recognized. Embedded text is extracted in one pass and reported as page 1.
+    fn process_pdf(
+        &self,
+        path: &Path,
+        options: &OcrOptions,
+        on_page: &dyn Fn(u32, &OcrResult),
+    ) -> Result<OcrResult, OcrError> {
         let mut warnings = Vec::new();
 
         // First, try to extract text directly from the PDF
-        let direct_text = pdf_extract::extract_text(path)
-            .map_err(|e| OcrError::ProcessingError(format!("Failed to parse PDF: {}", e)))?;
+        let parse_failed = |e: pdf_extract::OutputError| {
+            OcrError::ProcessingError(format!("Failed to parse PDF: {}", e))
+        };
+        let direct_text = if options.force_ocr {
+            String::new()
+        } else if options.first_page_only {
+            pdf_extract::extract_text_by_pages(path)
+                .map_err(parse_failed)?
+                .into_iter()
+                .next()
+                .unwrap_or_default()
+        } else {
+            pdf_extract::extract_text(path).map_err(parse_failed)?
+        };
 
         // If we got meaningful text, return it
         let trimmed_text = direct_text.trim();
@@ -130,11 +332,28 @@ impl LeptessEngine {
                 "Extracted {} chars of text directly from PDF",
                 trimmed_text.len()
             );
-            return Ok(OcrResult {
+            if options.coords_level.is_some() {
+                warnings.push(
+                    "Bounding boxes are not available for PDFs with embedded text".to_string(),
+                );
+            }
+            if options.lines {
+                warnings.push("Lines are not available for PDFs with embedded text".to_string());
+            }
+            let result = OcrResult {
                 text: trimmed_text.to_string(),
-                confidence: 0.95, // High confidence for direct text extraction
+                // Embedded text can be a garbled layer from an earlier OCR
+                // pass, so it is scored like recognized text
+                confidence: text_confidence(trimmed_text, options),
                 warnings,
-            });
+                boxes: None,
+                word_confidences: None,
+                word_scores: word_scores(trimmed_text, options),
+                lines: None,
+                orientation_degrees: None,
+            };
+            on_page(1, &result);
+            return Ok(result);
         }
 
         // If direct extraction yielded little/no text, try to extract and OCR images
@@ -142,27 +361,66 @@ impl LeptessEngine {
         warnings
             .push("PDF appears to be scanned/image-based, extracting images for OCR".to_string());
 
-        let images = extract_images_from_pdf(path)?;
+        let images = pdf::extract_images(path, options.first_page_only)?;
 
         if images.is_empty() {
             return Ok(OcrResult {
                 text: String::new(),
                 confidence: 0.0,
                 warnings: vec!["No text or images found in PDF".to_string()],
+                boxes: None,
+                word_confidences: None,
+                word_scores: options.word_confidence.then(Vec::new),
+                lines: None,
+                orientation_degrees: None,
             });
         }
 
         // OCR each image and combine results
         let mut all_text = Vec::new();
+        let mut all_boxes = options.coords_level.map(|level| TextBoxes {
+            level,
+            boxes: Vec::new(),
+        });
         let mut total_confidence = 0.0;
         let mut confidence_count = 0;
+        let mut word_confidences = Vec::new();
+        let mut all_word_scores = options.word_confidence.then(Vec::new);
+        let mut all_lines = options.lines.then(Vec::new);
 
-        for (i, img) in images.iter().enumerate() {
-            tracing::info!("Processing image {} of {} from PDF", i + 1, images.len());
+        let count = images.len();
+        let recognize = |i: usize, img: Option<DynamicImage>| {
+            tracing::info!("Processing image {} of {} from PDF", i + 1, count);
+            let img = img.ok_or_else(pdf::undecodable_image)?;
 
             // Process the image directly without saving to temp file
-            match self.process_dynamic_image(img) {
-                Ok(result) => {
+            self.process_dynamic_image(&img, options)
+        };
+        recognize_pages(images, options.page_threads, recognize, |i, result| {
+            match result {
+                Ok(mut result) => {
+                    // Number boxes by the image they came from
+                    let page = i as u32 + 1;
+                    if let Some(found) = result.boxes.as_mut() {
+                        found.boxes.iter_mut().for_each(|b| b.page = page);
+                    }
+                    for line in result.lines.iter_mut().flatten() {
+                        line.text_box.page = page;
+                    }
+                    on_page(page, &result);
+
+                    if let (Some(all), Some(found)) = (all_boxes.as_mut(), result.boxes) {
+                        all.level = found.level;
+                        all.boxes.extend(found.boxes);
+                    }
+                    if let (Some(all), Some(found)) = (all_lines.as_mut(), result.lines) {
+                        all.extend(found);
+                    }
+                    word_confidences.extend(result.word_confidences.unwrap_or_default());
+                    if let (Some(all), Some(found)) = (all_word_scores.as_mut(), result.word_scores)
+                    {
+                        all.extend(found);
+                    }
                     if !result.text.is_empty() {
                         all_text.push(result.text);
                         total_confidence += result.confidence;
@@ -173,7 +431,8 @@ impl LeptessEngine {
                     warnings.push(format!("Failed to OCR image {}: {}", i + 1, e));
                 }
             }
-        }
+            ControlFlow::Continue(())
+        });
 
         let combined_text = all_text.join("\n\n");
         let avg_confidence = if confidence_count > 0 {
@@ -186,6 +445,11 @@ impl LeptessEngine {
             text: combined_text,
             confidence: avg_confidence,
             warnings,
+            boxes: all_boxes,
+            word_confidences: Some(word_confidences),
+            word_scores: all_word_scores,
+            lines: all_lines,
+            orientation_degrees: None,
         })
     }
 }
@@ -199,17 +463,39 @@ impl OcrEngine for LeptessEngine {
         "Tesseract OCR engine - better for noisy/messy images like phone photos"
     }
 
-    fn process(&self, path: &Path) -> Result<OcrResult, OcrError> {
+    fn process(&self, path: &Path, options: &OcrOptions) -> Result<OcrResult, OcrError> {
+        self.process_pages(path, options, &|_, _| {})
+    }
+
+    fn process_pages(
+        &self,
+        path: &Path,
+        options: &OcrOptions,
+        on_page: &dyn Fn(u32, &OcrResult),
+    ) -> Result<OcrResult, OcrError> {
         // Check if the file is a PDF
-        if is_pdf(path)? {
-            return self.process_pdf(path);
-        }
+        let mut result = if pdf::is_pdf(path)? {
+            self.process_pdf(path, options, on_page)?
+        } else {
+            let result = self.process_image_file(path, options)?;
+            on_page(1, &result);
+            result
+        };
 
-        self.process_image_file(path)
+        warn_if_decode_requested(&mut result, options);
+        rectangle_polygons(&mut result, options);
+        Ok(result)
     }
 
-    fn process_image(&self, image: &DynamicImage) -> Result<OcrResult, OcrError> {
-        self.process_dynamic_image(image)
+    fn process_image(
+        &self,
+        image: &DynamicImage,
+        options: &OcrOptions,
+    ) -> Result<OcrResult, OcrError> {
+        let mut result = self.process_dynamic_image(image, options)?;
+        warn_if_decode_requested(&mut result, options);
+        rectangle_polygons(&mut result, options);
+        Ok(result)
     }
 
     fn supported_formats(&self) -> Vec<String> {
@@ -243,273 +529,654 @@ impl OcrEngine for LeptessEngine {
             "rus".to_string(),     // Russian
         ]
     }
-}
 
-// ============================================================================
-// Helper functions (shared with ocrs engine, could be moved to common module)
-// ============================================================================
-
-/// Check if a file is a PDF by reading its magic bytes
-fn is_pdf(path: &Path) -> Result<bool, OcrError> {
-    // Check file extension first
-    if let Some(ext) = path.extension() {
-        if ext.to_string_lossy().to_lowercase() == "pdf" {
-            return Ok(true);
-        }
+    fn detect(&self, _image: &DynamicImage) -> Result<Detection, OcrError> {
+        Err(OcrError::InvalidRequest(
+            "Text detection is only supported by the ocrs engine".to_string(),
+        ))
     }
 
-    // Also check magic bytes (%PDF-)
-    let mut file = File::open(path)
-        .map_err(|e| OcrError::ProcessingError(format!("Failed to open file: {}", e)))?;
+    fn supported_models(&self) -> Vec<RecognitionModel> {
+        vec![RecognitionModel::Default, RecognitionModel::Handwriting]
+    }
 
-    let mut magic = [0u8; 5];
-    if file.read_exact(&mut magic).is_ok() {
-        return Ok(&magic == b"%PDF-");
+    fn prepare(&self, options: &OcrOptions) -> Result<(), OcrError> {
+        let languages = options
+            .languages
+            .as_deref()
+            .unwrap_or(&self.default_language);
+        self.tessdata_path_for(options.model, languages).map(|_| ())
     }
+}
 
-    Ok(false)
+/// Tesseract's LSTM recognizer always uses its own beam search, so the decode
+/// option doesn't apply
+fn warn_if_decode_requested(result: &mut OcrResult, options: &OcrOptions) {
+    if options.decode.is_some() {
+        result
+            .warnings
+            .push("decode is only supported by the ocrs engine, ignored".to_string());
+    }
 }
 
-/// Extract images from a PDF using lopdf
-fn extract_images_from_pdf(path: &Path) -> Result<Vec<image::DynamicImage>, OcrError> {
-    use lopdf::Document;
-
-    let doc = Document::load(path)
-        .map_err(|e| OcrError::ProcessingError(format!("Failed to load PDF: {}", e)))?;
-
-    let mut images = Vec::new();
-
-    // Iterate through all objects looking for image XObjects
-    for (object_id, object) in doc.objects.iter() {
-        if let Ok(stream) = object.as_stream() {
-            // Check if this is an image XObject
-            if let Ok(subtype) = stream.dict.get(b"Subtype") {
-                if let Ok(name) = subtype.as_name() {
-                    if name == b"Image" {
-                        // Try to extract the image data
-                        match extract_image_from_stream(&doc, stream) {
-                            Ok(img) => images.push(img),
-                            Err(e) => {
-                                tracing::warn!(
-                                    "Failed to extract image from object {:?}: {}",
-                                    object_id,
-                                    e
-                                );
-                            }
-                        }
-                    }
-                }
-            }
-        }
+/// Tesseract only reports axis-aligned boxes, so requested polygons are
+/// their rectangles
+fn rectangle_polygons(result: &mut OcrResult, options: &OcrOptions) {
+    if !options.polygons {
+        return;
+    }
+    let words = result.boxes.iter_mut().flat_map(|found| &mut found.boxes);
+    let lines = result
+        .lines
+        .iter_mut()
+        .flatten()
+        .map(|line| &mut line.text_box);
+    let mut any = false;
+    for text_box in words.chain(lines) {
+        text_box.polygon = Some(rectangle_corners(text_box));
+        any = true;
     }
+    if any {
+        result.warnings.push(
+            "leptess only provides axis-aligned boxes, polygons are their rectangles".to_string(),
+        );
+    }
+}
 
-    Ok(images)
+/// Corners of a box's bounds, in the order of [`TextBox::polygon`]
+fn rectangle_corners(b: &TextBox) -> [[f32; 2]; 4] {
+    let (left, top) = (b.x as f32, b.y as f32);
+    let (right, bottom) = (left + b.width as f32, top + b.height as f32);
+    [[left, top], [right, top], [right, bottom], [left, bottom]]
 }
 
-/// Extract an image from a PDF stream
-fn extract_image_from_stream(
-    doc: &lopdf::Document,
-    stream: &lopdf::Stream,
-) -> Result<image::DynamicImage, OcrError> {
-    // Get image dimensions
-    let width = stream
-        .dict
-        .get(b"Width")
-        .ok()
-        .and_then(|w| w.as_i64().ok())
-        .ok_or_else(|| OcrError::ProcessingError("Missing image width".to_string()))?
-        as u32;
-
-    let height = stream
-        .dict
-        .get(b"Height")
-        .ok()
-        .and_then(|h| h.as_i64().ok())
-        .ok_or_else(|| OcrError::ProcessingError("Missing image height".to_string()))?
-        as u32;
-
-    // Get the image data (decompressed)
-    let data = stream
-        .decompressed_content()
-        .map_err(|e| OcrError::ProcessingError(format!("Failed to decompress image: {}", e)))?;
-
-    // Get color space
-    let color_space = get_color_space(doc, stream);
-
-    // Get bits per component
-    let bits_per_component = stream
-        .dict
-        .get(b"BitsPerComponent")
-        .ok()
-        .and_then(|b| b.as_i64().ok())
-        .unwrap_or(8) as u8;
-
-    // Handle different color spaces
-    match color_space.as_str() {
-        "DeviceGray" => {
-            if bits_per_component == 8 && data.len() >= (width * height) as usize {
-                let img = image::GrayImage::from_raw(width, height, data).ok_or_else(|| {
-                    OcrError::ProcessingError("Invalid grayscale image data".to_string())
-                })?;
-                Ok(image::DynamicImage::ImageLuma8(img))
-            } else {
-                Err(OcrError::ProcessingError(format!(
-                    "Unsupported grayscale format: {} bits",
-                    bits_per_component
-                )))
+// ============================================================================
+// Bounding box parsing
+// ============================================================================
+
+/// TSV row level for words (1=page, 2=block, 3=paragraph, 4=line, 5=word)
+const TSV_WORD_LEVEL: &str = "5";
+
+/// Parse word boxes from Tesseract TSV output
+///
+/// Columns: level, page_num, block_num, par_num, line_num, word_num,
+/// left, top, width, height, conf, text
+fn parse_tsv_word_boxes(tsv: &str) -> Vec<TextBox> {
+    tsv.lines()
+        .filter_map(|line| {
+            let columns: Vec<&str> = line.split('\t').collect();
+            if columns.len() < 12 || columns[0] != TSV_WORD_LEVEL {
+                return None;
+            }
+
+            let text = columns[11].trim();
+            if text.is_empty() {
+                return None;
             }
+
+            let confidence: f32 = columns[10].parse().ok()?;
+            Some(TextBox {
+                text: text.to_string(),
+                page: 1,
+                x: columns[6].parse().ok()?,
+                y: columns[7].parse().ok()?,
+                width: columns[8].parse().ok()?,
+                height: columns[9].parse().ok()?,
+                confidence: (confidence >= 0.0).then_some(confidence / 100.0),
+                polygon: None,
+            })
+        })
+        .collect()
+}
+
+/// TSV row level for lines
+const TSV_LINE_LEVEL: &str = "4";
+
+/// hOCR classes Tesseract uses for lines
+const HOCR_LINE_CLASSES: [&str; 4] = ["ocr_line", "ocr_header", "ocr_caption", "ocr_textfloat"];
+
+/// Parse lines from Tesseract TSV output, with their baselines from hOCR
+///
+/// TSV lists each line's bounds followed by its words, and hOCR lists the
+/// same lines in the same order with a `baseline slope offset` property.
+/// Lines without words are dropped.
+fn parse_lines(tsv: &str, hocr: &str) -> Vec<LineBox> {
+    let mut slopes = parse_hocr_baseline_slopes(hocr).into_iter();
+    let mut lines: Vec<(LineBox, Vec<f32>)> = Vec::new();
+
+    for row in tsv.lines() {
+        let columns: Vec<&str> = row.split('\t').collect();
+        if columns.len() < 12 {
+            continue;
         }
-        "DeviceRGB" | "ICCBased" => {
-            if bits_per_component == 8 && data.len() >= (width * height * 3) as usize {
-                let img = image::RgbImage::from_raw(width, height, data).ok_or_else(|| {
-                    OcrError::ProcessingError("Invalid RGB image data".to_string())
-                })?;
-                Ok(image::DynamicImage::ImageRgb8(img))
-            } else {
-                Err(OcrError::ProcessingError(format!(
-                    "Unsupported RGB format: {} bits",
-                    bits_per_component
-                )))
+
+        if columns[0] == TSV_LINE_LEVEL {
+            let slope = slopes.next().unwrap_or_default();
+            let (Ok(x), Ok(y), Ok(width), Ok(height)) = (
+                columns[6].parse(),
+                columns[7].parse(),
+                columns[8].parse(),
+                columns[9].parse(),
+            ) else {
+                continue;
+            };
+            lines.push((
+                LineBox {
+                    text_box: TextBox {
+                        text: String::new(),
+                        page: 1,
+                        x,
+                        y,
+                        width,
+                        height,
+                        confidence: None,
+                        polygon: None,
+                    },
+                    // Image y grows downwards, so a rising baseline has a negative slope
+                    baseline_angle: -slope.atan().to_degrees(),
+                },
+                Vec::new(),
+            ));
+        } else if columns[0] == TSV_WORD_LEVEL {
+            let text = columns[11].trim();
+            let Some((line, confidences)) = lines.last_mut() else {
+                continue;
+            };
+            if text.is_empty() {
+                continue;
+            }
+
+            let line_text = &mut line.text_box.text;
+            if !line_text.is_empty() {
+                line_text.push(' ');
+            }
+            line_text.push_str(text);
+            if let Some(confidence) = columns[10].parse::<f32>().ok().filter(|c| *c >= 0.0) {
+                confidences.push(confidence / 100.0);
             }
         }
-        "DeviceCMYK" => {
-            if bits_per_component == 8 && data.len() >= (width * height * 4) as usize {
-                let mut rgb_data = Vec::with_capacity((width * height * 3) as usize);
-                for chunk in data.chunks(4) {
-                    if chunk.len() == 4 {
-                        let c = chunk[0] as f32 / 255.0;
-                        let m = chunk[1] as f32 / 255.0;
-                        let y = chunk[2] as f32 / 255.0;
-                        let k = chunk[3] as f32 / 255.0;
-                        let r = ((1.0 - c) * (1.0 - k) * 255.0) as u8;
-                        let g = ((1.0 - m) * (1.0 - k) * 255.0) as u8;
-                        let b = ((1.0 - y) * (1.0 - k) * 255.0) as u8;
-                        rgb_data.push(r);
-                        rgb_data.push(g);
-                        rgb_data.push(b);
-                    }
-                }
-                let img = image::RgbImage::from_raw(width, height, rgb_data).ok_or_else(|| {
-                    OcrError::ProcessingError("Invalid CMYK->RGB conversion".to_string())
-                })?;
-                Ok(image::DynamicImage::ImageRgb8(img))
-            } else {
-                Err(OcrError::ProcessingError(format!(
-                    "Unsupported CMYK format: {} bits",
-                    bits_per_component
-                )))
+    }
+
+    lines
+        .into_iter()
+        .filter(|(line, _)| !line.text_box.text.is_empty())
+        .map(|(mut line, confidences)| {
+            if !confidences.is_empty() {
+                line.text_box.confidence =
+                    Some(confidences.iter().sum::<f32>() / confidences.len() as f32);
             }
+            line
+        })
+        .collect()
+}
+
+/// Baseline slopes of the lines in hOCR output, in document order
+///
+/// A line looks like
+/// `<span class='ocr_line' id='line_1_1' title="bbox 36 92 618 116; baseline 0.015 -5; ...">`;
+/// lines without a baseline get a slope of 0.
+fn parse_hocr_baseline_slopes(hocr: &str) -> Vec<f32> {
+    let mut slopes = Vec::new();
+    let mut rest = hocr;
+    while let Some(start) = rest.find("class='") {
+        rest = &rest[start + "class='".len()..];
+        let Some(tag_end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..tag_end];
+        rest = &rest[tag_end..];
+
+        let class = tag.split('\'').next().unwrap_or_default();
+        if !HOCR_LINE_CLASSES.contains(&class) {
+            continue;
         }
-        _ => Err(OcrError::ProcessingError(format!(
-            "Unsupported color space: {}",
-            color_space
-        ))),
+
+        let slope = hocr_title(tag)
+            .and_then(|title| {
+                title.split(';').find_map(|property| {
+                    let mut parts = property.split_whitespace();
+                    (parts.next() == Some("baseline"))
+                        .then(|| parts.next()?.parse::<f32>().ok())
+                        .flatten()
+                })
+            })
+            .unwrap_or_default();
+        slopes.push(slope);
     }
+    slopes
 }
 
-/// Get the color space name from a PDF stream
-fn get_color_space(doc: &lopdf::Document, stream: &lopdf::Stream) -> String {
-    let cs_obj = match stream.dict.get(b"ColorSpace") {
-        Ok(obj) => obj,
-        Err(_) => return "DeviceRGB".to_string(),
-    };
+/// Value of a tag's title attribute, which Tesseract quotes either way
+fn hocr_title(attributes: &str) -> Option<&str> {
+    let start = attributes.find("title=")? + "title=".len();
+    let quote = attributes[start..].chars().next()?;
+    let value = &attributes[start + 1..];
+    Some(&value[..value.find(quote)?])
+}
 
-    if let Ok(name) = cs_obj.as_name() {
-        return String::from_utf8_lossy(name).to_string();
+/// Parse character boxes from hOCR output generated with `hocr_char_boxes=1`
+///
+/// Each character is a span like
+/// `<span class='ocrx_cinfo' title='x_bboxes 36 92 48 110; x_conf 99.1'>H</span>`
+/// where the bbox is left, top, right, bottom.
+fn parse_hocr_character_boxes(hocr: &str) -> Vec<TextBox> {
+    const CLASS: &str = "class='ocrx_cinfo'";
+
+    let mut boxes = Vec::new();
+    let mut rest = hocr;
+    while let Some(start) = rest.find(CLASS) {
+        rest = &rest[start + CLASS.len()..];
+
+        let Some(tag_end) = rest.find('>') else {
+            break;
+        };
+        let attributes = &rest[..tag_end];
+        rest = &rest[tag_end + 1..];
+
+        let Some(content_end) = rest.find("</span>") else {
+            break;
+        };
+        let text = unescape_html(&rest[..content_end]);
+        rest = &rest[content_end..];
+
+        if let Some(text_box) = parse_hocr_title(attributes, text) {
+            boxes.push(text_box);
+        }
     }
+    boxes
+}
 
-    if let Ok(reference) = cs_obj.as_reference() {
-        if let Ok(resolved) = doc.get_object(reference) {
-            if let Ok(name) = resolved.as_name() {
-                return String::from_utf8_lossy(name).to_string();
-            }
-            if let Ok(array) = resolved.as_array() {
-                if let Some(first) = array.first() {
-                    if let Ok(name) = first.as_name() {
-                        return String::from_utf8_lossy(name).to_string();
-                    }
-                }
+/// Build a box from an hOCR title attribute (`x_bboxes ...; x_conf ...`)
+fn parse_hocr_title(attributes: &str, text: String) -> Option<TextBox> {
+    let title_start = attributes.find("title='")? + "title='".len();
+    let title_len = attributes[title_start..].find('\'')?;
+    let title = &attributes[title_start..title_start + title_len];
+
+    let mut bbox: Option<Vec<u32>> = None;
+    let mut confidence = None;
+    for property in title.split(';') {
+        let mut parts = property.split_whitespace();
+        match parts.next() {
+            Some("x_bboxes") => bbox = parts.map(|v| v.parse().ok()).collect(),
+            Some("x_conf") => {
+                confidence = parts.next().and_then(|v| v.parse::<f32>().ok());
             }
+            _ => {}
         }
     }
 
-    if let Ok(array) = cs_obj.as_array() {
-        if let Some(first) = array.first() {
-            if let Ok(name) = first.as_name() {
-                return String::from_utf8_lossy(name).to_string();
+    let bbox = bbox?;
+    let [left, top, right, bottom] = bbox[..] else {
+        return None;
+    };
+
+    Some(TextBox {
+        text,
+        page: 1,
+        x: left,
+        y: top,
+        width: right.saturating_sub(left),
+        height: bottom.saturating_sub(top),
+        confidence: confidence.map(|c| c / 100.0),
+        polygon: None,
+    })
+}
+
+/// Undo the HTML escaping Tesseract applies to hOCR text
+fn unescape_html(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+// ============================================================================
+// Image encoding
+// ============================================================================
+
+/// Formats Leptonica can read from memory, in order of preference: BMP is
+/// cheapest to encode, PNG and TIFF cover images BMP can't represent
+const LEPTONICA_FORMATS: [image::ImageFormat; 3] = [
+    image::ImageFormat::Bmp,
+    image::ImageFormat::Png,
+    image::ImageFormat::Tiff,
+];
+
+/// Encode an image for Leptonica, falling back to other formats if one fails
+fn encode_for_leptonica(
+    image: &image::RgbImage,
+) -> Result<(Vec<u8>, image::ImageFormat), OcrError> {
+    let mut failures = Vec::new();
+
+    for format in LEPTONICA_FORMATS {
+        let mut data = Vec::new();
+        match image.write_to(&mut std::io::Cursor::new(&mut data), format) {
+            Ok(()) => {
+                if !failures.is_empty() {
+                    tracing::debug!(
+                        "Encoded image as {:?} after: {}",
+                        format,
+                        failures.join("; ")
+                    );
+                }
+                return Ok((data, format));
             }
+            Err(e) => failures.push(format!("{:?}: {}", format, e)),
         }
     }
 
-    "DeviceRGB".to_string()
+    Err(OcrError::ProcessingError(format!(
+        "Failed to encode {}x{} image for Tesseract in any supported format ({})",
+        image.width(),
+        image.height(),
+        failures.join("; ")
+    )))
 }
 
 // ============================================================================
-// Tessdata download helpers
+// Orientation detection
 // ============================================================================
 
-/// Ensure tessdata is available, downloading if needed
-fn ensure_tessdata_available(language: &str) -> Result<String, OcrError> {
-    // Get cache directory for tessdata
-    let cache_dir = dirs::cache_dir()
-        .unwrap_or_else(std::env::temp_dir)
-        .join("activestorage-ocr")
-        .join("tessdata");
-
-    std::fs::create_dir_all(&cache_dir).map_err(|e| {
-        OcrError::InitializationError(format!("Failed to create tessdata directory: {}", e))
-    })?;
-
-    let traineddata_file = format!("{}.traineddata", language);
-    let traineddata_path = cache_dir.join(&traineddata_file);
-
-    // Download if not cached
-    if !traineddata_path.exists() {
-        let url = tessdata_url(language);
-        tracing::info!(
-            "Downloading tessdata for '{}' (this may take a moment)...",
-            language
-        );
-        download_file(&url, &traineddata_path)?;
-        tracing::info!("Downloaded tessdata to {:?}", traineddata_path);
+/// Mean word confidence (0-100) below which no orientation is trusted
+const MIN_ORIENTATION_CONFIDENCE: i32 = 40;
+
+/// How much more confident (0-100) a rotation must be than the image as it
+/// is, so that noise doesn't turn upright pages
+const ORIENTATION_MARGIN: i32 = 10;
+
+/// Longest side, in pixels, orientation probes are recognized at
+const ORIENTATION_PROBE_SIZE: u32 = 1000;
+
+/// Detect the clockwise rotation (0, 90, 180 or 270) that turns an image's
+/// text upright, or None when no rotation is confident enough
+///
+/// The bindings don't expose Tesseract's orientation and script detection,
+/// so a downscaled copy is recognized at each right angle instead and the
+/// most confident one wins.
+fn detect_orientation(
+    image: &image::RgbImage,
+    tessdata_path: &str,
+    languages: &str,
+) -> Result<Option<u32>, OcrError> {
+    let probe = if image.width().max(image.height()) > ORIENTATION_PROBE_SIZE {
+        DynamicImage::ImageRgb8(image.clone())
+            .thumbnail(ORIENTATION_PROBE_SIZE, ORIENTATION_PROBE_SIZE)
+            .to_rgb8()
     } else {
-        tracing::info!("Using cached tessdata from {:?}", cache_dir);
+        image.clone()
+    };
+
+    let mut confidences = Vec::new();
+    for degrees in [0, 90, 180, 270] {
+        let rotated = rotate_clockwise(&probe, degrees);
+        confidences.push((
+            degrees,
+            probe_confidence(&rotated, tessdata_path, languages)?,
+        ));
     }
+    tracing::debug!("Orientation confidences: {:?}", confidences);
+    Ok(pick_orientation(&confidences))
+}
+
+/// Pick the rotation to apply from each rotation's mean word confidence
+fn pick_orientation(confidences: &[(u32, i32)]) -> Option<u32> {
+    let upright = confidences
+        .iter()
+        .find(|(degrees, _)| *degrees == 0)
+        .map_or(0, |(_, confidence)| *confidence);
+    // Ties go to the smallest rotation
+    let &(degrees, best) = confidences
+        .iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))?;
+
+    if degrees != 0 && best - upright < ORIENTATION_MARGIN {
+        (upright >= MIN_ORIENTATION_CONFIDENCE).then_some(0)
+    } else {
+        (best >= MIN_ORIENTATION_CONFIDENCE).then_some(degrees)
+    }
+}
+
+/// Mean word confidence (0-100) of recognizing an image
+fn probe_confidence(
+    image: &image::RgbImage,
+    tessdata_path: &str,
+    languages: &str,
+) -> Result<i32, OcrError> {
+    let (image_data, _) = encode_for_leptonica(image)?;
+    let mut tess = Tesseract::new(Some(tessdata_path), Some(languages))
+        .map_err(|e| OcrError::ProcessingError(format!("Failed to create Tesseract: {}", e)))?
+        .set_image_from_mem(&image_data)
+        .map_err(|e| OcrError::ProcessingError(format!("Failed to set image: {}", e)))?
+        .recognize()
+        .map_err(|e| {
+            OcrError::ProcessingError(format!("Failed to detect text orientation: {}", e))
+        })?;
+    Ok(tess.mean_text_conf())
+}
+
+/// Rotate an image clockwise by a right angle
+fn rotate_clockwise(image: &image::RgbImage, degrees: u32) -> image::RgbImage {
+    match degrees {
+        90 => image::imageops::rotate90(image),
+        180 => image::imageops::rotate180(image),
+        270 => image::imageops::rotate270(image),
+        _ => image.clone(),
+    }
+}
+
+// ============================================================================
+// Tessdata helpers
+// ============================================================================
+
+/// Ensure tessdata is available, downloading if needed
+///
+/// Each model variant gets its own directory since Tesseract looks up
+/// `<language>.traineddata` by name within a single tessdata directory.
+/// GitHub is tried first, then each of `mirrors` in order.
+fn ensure_tessdata_available(
+    model_cache: &ModelCache,
+    mirrors: &[String],
+    language: &str,
+    model: RecognitionModel,
+) -> Result<String, OcrError> {
+    let name = format!("{}/{}.traineddata", tessdata_dir_name(model), language);
+    let urls: Vec<String> = std::iter::once(tessdata_url(language, model))
+        .chain(
+            mirrors
+                .iter()
+                .map(|mirror| tessdata_mirror_url(mirror, language, model)),
+        )
+        .collect();
+    let traineddata_path = model_cache.path_from_any(&name, &urls)?;
 
     // Return the directory path (Tesseract expects the directory, not the file)
-    cache_dir
-        .to_str()
+    traineddata_path
+        .parent()
+        .and_then(|dir| dir.to_str())
         .map(|s| s.to_string())
         .ok_or_else(|| OcrError::InitializationError("Invalid tessdata path".to_string()))
 }
 
+/// Get the cache directory name for a model variant
+fn tessdata_dir_name(model: RecognitionModel) -> &'static str {
+    match model {
+        RecognitionModel::Default => "tessdata",
+        RecognitionModel::Handwriting => "tessdata_best",
+    }
+}
+
+/// Get the tessdata repository a model variant is downloaded from
+fn tessdata_repo(model: RecognitionModel) -> &'static str {
+    match model {
+        // Use tessdata_fast for smaller, faster downloads
+        RecognitionModel::Default => "tessdata_fast",
+        // The slower, full-precision LSTM models cope better with irregular
+        // (e.g. handwritten) strokes
+        RecognitionModel::Handwriting => "tessdata_best",
+    }
+}
+
 /// Get tessdata download URL for a language
-fn tessdata_url(language: &str) -> String {
-    // Use tessdata_fast for smaller, faster downloads
+fn tessdata_url(language: &str, model: RecognitionModel) -> String {
     format!(
-        "https://github.com/tesseract-ocr/tessdata_fast/raw/main/{}.traineddata",
+        "https://github.com/tesseract-ocr/{}/raw/main/{}.traineddata",
+        tessdata_repo(model),
         language
     )
 }
 
-/// Download a file from URL to path using ureq
-fn download_file(url: &str, path: &Path) -> Result<(), OcrError> {
-    let response = ureq::get(url).call().map_err(|e| {
-        OcrError::InitializationError(format!("Failed to download tessdata: {}", e))
-    })?;
+/// Get the URL of a language on a mirror, which keeps each repository in a
+/// directory of the same name (e.g. `<mirror>/tessdata_fast/eng.traineddata`)
+fn tessdata_mirror_url(mirror: &str, language: &str, model: RecognitionModel) -> String {
+    format!(
+        "{}/{}/{}.traineddata",
+        mirror,
+        tessdata_repo(model),
+        language
+    )
+}
 
-    let mut file = File::create(path).map_err(|e| {
-        OcrError::InitializationError(format!("Failed to create tessdata file: {}", e))
-    })?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Read response body and write to file
-    let buffer = response.into_body().read_to_vec().map_err(|e| {
-        OcrError::InitializationError(format!("Failed to read tessdata response: {}", e))
-    })?;
+    #[test]
+    fn test_pick_orientation() {
+        // Clearly upside down
+        assert_eq!(
+            pick_orientation(&[(0, 20), (90, 15), (180, 85), (270, 10)]),
+            Some(180)
+        );
+        // A rotation barely ahead of an upright page leaves it alone
+        assert_eq!(
+            pick_orientation(&[(0, 80), (90, 30), (180, 85), (270, 10)]),
+            Some(0)
+        );
+        // Nothing reads well
+        assert_eq!(
+            pick_orientation(&[(0, 10), (90, 25), (180, 12), (270, 30)]),
+            None
+        );
+        assert_eq!(
+            pick_orientation(&[(0, 30), (90, 35), (180, 12), (270, 10)]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tessdata_mirror_url_uses_repo_directories() {
+        assert_eq!(
+            tessdata_mirror_url(
+                "https://cdn.example.com/tess",
+                "deu",
+                RecognitionModel::Default
+            ),
+            "https://cdn.example.com/tess/tessdata_fast/deu.traineddata"
+        );
+        assert_eq!(
+            tessdata_mirror_url(
+                "https://cdn.example.com",
+                "eng",
+                RecognitionModel::Handwriting
+            ),
+            "https://cdn.example.com/tessdata_best/eng.traineddata"
+        );
+    }
+
+    #[test]
+    fn test_encode_for_leptonica_prefers_bmp() {
+        let image = image::RgbImage::from_pixel(4, 3, image::Rgb([10, 20, 30]));
+
+        let (data, format) = encode_for_leptonica(&image).unwrap();
+
+        assert_eq!(format, image::ImageFormat::Bmp);
+        let decoded = image::load_from_memory_with_format(&data, format).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (4, 3));
+    }
 
-    file.write_all(&buffer).map_err(|e| {
-        OcrError::InitializationError(format!("Failed to write tessdata file: {}", e))
-    })?;
+    #[test]
+    fn test_parse_tsv_word_boxes_keeps_only_words() {
+        let tsv = "1\t1\t0\t0\t0\t0\t0\t0\t640\t480\t-1\t\n\
+                   4\t1\t1\t1\t1\t0\t36\t92\t300\t40\t-1\t\n\
+                   5\t1\t1\t1\t1\t1\t36\t92\t120\t40\t96.5\tHello\n\
+                   5\t1\t1\t1\t1\t2\t170\t92\t130\t40\t91\tWorld\n\
+                   5\t1\t1\t1\t1\t3\t310\t92\t10\t40\t95\t \n";
+
+        let boxes = parse_tsv_word_boxes(tsv);
+
+        assert_eq!(boxes.len(), 2);
+        assert_eq!(boxes[0].text, "Hello");
+        assert_eq!(
+            (boxes[0].x, boxes[0].y, boxes[0].width, boxes[0].height),
+            (36, 92, 120, 40)
+        );
+        assert!((boxes[0].confidence.unwrap() - 0.965).abs() < 1e-6);
+        assert_eq!(boxes[1].text, "World");
+    }
+
+    #[test]
+    fn test_parse_lines_joins_words_and_reads_baselines() {
+        let tsv = "4\t1\t1\t1\t1\t0\t36\t92\t300\t40\t-1\t\n\
+                   5\t1\t1\t1\t1\t1\t36\t92\t120\t40\t96\tHello\n\
+                   5\t1\t1\t1\t1\t2\t170\t92\t130\t40\t90\tWorld\n\
+                   4\t1\t1\t1\t2\t0\t36\t150\t300\t40\t-1\t\n\
+                   5\t1\t1\t1\t2\t1\t36\t150\t10\t40\t95\t \n\
+                   4\t1\t1\t1\t3\t0\t36\t200\t100\t40\t-1\t\n\
+                   5\t1\t1\t1\t3\t1\t36\t200\t100\t40\t80\tBye\n";
+        let hocr = "<div class='ocr_page' title='bbox 0 0 640 480'>\
+                    <span class='ocr_line' id='line_1_1' title=\"bbox 36 92 336 132; baseline -0.017 -5\">\
+                    <span class='ocr_line' id='line_1_2' title=\"bbox 36 150 336 190\">\
+                    <span class='ocr_caption' id='line_1_3' title=\"bbox 36 200 136 240; baseline 0.1 0\">";
+
+        let lines = parse_lines(tsv, hocr);
+
+        // The line without words is dropped
+        assert_eq!(lines.len(), 2);
+        let first = &lines[0].text_box;
+        assert_eq!(first.text, "Hello World");
+        assert_eq!(
+            (first.x, first.y, first.width, first.height),
+            (36, 92, 300, 40)
+        );
+        assert!((first.confidence.unwrap() - 0.93).abs() < 1e-6);
+        // A negative slope rises to the right
+        assert!((lines[0].baseline_angle - 0.974).abs() < 1e-3);
+        assert_eq!(lines[1].text_box.text, "Bye");
+        assert!((lines[1].baseline_angle + 5.711).abs() < 1e-3);
+    }
 
-    Ok(())
+    #[test]
+    fn test_parse_hocr_character_boxes() {
+        let hocr = "<span class='ocrx_word' id='word_1_1' title='bbox 36 92 80 110; x_wconf 96'>\
+                    <span class='ocrx_cinfo' title='x_bboxes 36 92 48 110; x_conf 99.1'>5</span>\
+                    <span class='ocrx_cinfo' title='x_bboxes 50 92 62 110; x_conf 87'>&amp;</span>\
+                    <span class='ocrx_cinfo' title='x_bboxes 64 92 80 110; x_conf 95'>&lt;</span>\
+                    </span>";
+
+        let boxes = parse_hocr_character_boxes(hocr);
+
+        assert_eq!(boxes.len(), 3);
+        assert_eq!(boxes[0].text, "5");
+        assert_eq!(
+            (boxes[0].x, boxes[0].y, boxes[0].width, boxes[0].height),
+            (36, 92, 12, 18)
+        );
+        assert!((boxes[0].confidence.unwrap() - 0.991).abs() < 1e-6);
+        assert_eq!(boxes[1].text, "&");
+        assert_eq!(boxes[2].text, "<");
+    }
+
+    #[test]
+    fn test_parse_hocr_character_boxes_skips_malformed_spans() {
+        let hocr = "<span class='ocrx_cinfo' title='x_bboxes 1 2 3'>a</span>\
+                    <span class='ocrx_cinfo' title='x_conf 90'>b</span>\
+                    <span class='ocrx_cinfo' title='x_bboxes 1 2 5 9'>c</span>";
+
+        let boxes = parse_hocr_character_boxes(hocr);
+
+        assert_eq!(boxes.len(), 1);
+        assert_eq!(boxes[0].text, "c");
+        assert_eq!(boxes[0].confidence, None);
+    }
 }
@@ -9,9 +9,15 @@ pub mod ocrs;
 #[cfg(feature = "engine-leptess")]
 pub mod leptess;
 
+pub mod auto;
+mod confidence;
+mod pdf;
+
 use crate::config::Config;
-use crate::engine::OcrEngine;
+use crate::engine::{OcrEngine, RecognitionModel};
 use crate::error::OcrError;
+use crate::model_cache::ModelCache;
+use std::ops::ControlFlow;
 use std::sync::Arc;
 
 /// Information about an available engine
@@ -21,12 +27,23 @@ pub struct EngineInfo {
     pub description: &'static str,
     pub supported_formats: Vec<String>,
     pub supported_languages: Vec<String>,
+    pub supported_models: Vec<RecognitionModel>,
+}
+
+/// An engine that was built in but failed to start
+#[derive(Debug, Clone)]
+pub struct UnavailableEngine {
+    pub name: &'static str,
+    pub error: String,
 }
 
 /// Registry of available OCR engines
 pub struct EngineRegistry {
     engines: Vec<Arc<dyn OcrEngine>>,
     default_engine: String,
+    model_cache: Arc<ModelCache>,
+    /// Engines left out because they failed to start
+    unavailable: Vec<UnavailableEngine>,
 }
 
 impl EngineRegistry {
@@ -34,27 +51,43 @@ impl EngineRegistry {
     pub fn new(config: &Config) -> Result<Self, OcrError> {
         let mut engines: Vec<Arc<dyn OcrEngine>> = Vec::new();
         let mut default_engine = String::new();
+        let model_cache = Arc::new(ModelCache::new(config));
+        #[cfg_attr(not(feature = "engine-leptess"), allow(unused_mut))]
+        let mut unavailable = Vec::new();
 
         #[cfg(feature = "engine-ocrs")]
         {
-            tracing::info!("Initializing ocrs engine...");
-            let ocrs_engine = ocrs::OcrsEngine::new(config)?;
+            let ocrs_engine = start_engine("ocrs", config, &model_cache)?;
             if default_engine.is_empty() {
                 default_engine = ocrs_engine.name().to_string();
             }
-            engines.push(Arc::new(ocrs_engine));
+            engines.push(ocrs_engine);
         }
 
         #[cfg(feature = "engine-leptess")]
         {
-            tracing::info!("Initializing leptess engine...");
-            let leptess_engine = leptess::LeptessEngine::new(config)?;
-            if default_engine.is_empty() {
-                default_engine = leptess_engine.name().to_string();
+            match start_engine("leptess", config, &model_cache) {
+                Ok(leptess_engine) => {
+                    if default_engine.is_empty() {
+                        default_engine = leptess_engine.name().to_string();
+                    }
+                    engines.push(leptess_engine);
+                }
+                // Missing or broken tessdata shouldn't stop other engines
+                // from serving
+                Err(e) if !engines.is_empty() && !config.require_all_engines => {
+                    tracing::warn!("leptess engine unavailable, continuing without it: {}", e);
+                    unavailable.push(UnavailableEngine {
+                        name: "leptess",
+                        error: e.to_string(),
+                    });
+                }
+                Err(e) => return Err(e),
             }
-            engines.push(Arc::new(leptess_engine));
         }
 
+        engines.extend(auto_engine(&engines));
+
         if engines.is_empty() {
             return Err(OcrError::InitializationError(
                 "No OCR engines available. Build with --features engine-ocrs or --features engine-leptess".to_string()
@@ -64,6 +97,32 @@ impl EngineRegistry {
         Ok(Self {
             engines,
             default_engine,
+            model_cache,
+            unavailable,
+        })
+    }
+
+    /// A copy of this registry with a fresh instance of the `name` engine,
+    /// sharing the others
+    ///
+    /// `auto` runs on the other engines and it can't tell which of them is
+    /// stuck, so restarting it restarts all of them.
+    pub fn restart(&self, name: &str, config: &Config) -> Result<Self, OcrError> {
+        let mut engines = Vec::with_capacity(self.engines.len());
+        for engine in self.engines.iter().filter(|e| e.name() != "auto") {
+            engines.push(if name == "auto" || engine.name() == name {
+                start_engine(engine.name(), config, &self.model_cache)?
+            } else {
+                engine.clone()
+            });
+        }
+        engines.extend(auto_engine(&engines));
+
+        Ok(Self {
+            engines,
+            default_engine: self.default_engine.clone(),
+            model_cache: self.model_cache.clone(),
+            unavailable: self.unavailable.clone(),
         })
     }
 
@@ -82,11 +141,21 @@ impl EngineRegistry {
         &self.default_engine
     }
 
+    /// Cache the engines load their models from
+    pub fn model_cache(&self) -> &Arc<ModelCache> {
+        &self.model_cache
+    }
+
     /// List all available engine names
     pub fn list(&self) -> Vec<&str> {
         self.engines.iter().map(|e| e.name()).collect()
     }
 
+    /// Engines that failed to start, with the reason
+    pub fn unavailable(&self) -> &[UnavailableEngine] {
+        &self.unavailable
+    }
+
     /// Get info about all available engines
     pub fn info(&self) -> Vec<EngineInfo> {
         self.engines
@@ -94,9 +163,155 @@ impl EngineRegistry {
             .map(|e| EngineInfo {
                 name: e.name(),
                 description: e.description(),
-                supported_formats: e.supported_formats(),
+                supported_formats: supported_formats(e.as_ref()),
                 supported_languages: e.supported_languages(),
+                supported_models: e.supported_models(),
             })
             .collect()
     }
 }
+
+/// Start a built-in engine by name
+fn start_engine(
+    name: &str,
+    config: &Config,
+    model_cache: &Arc<ModelCache>,
+) -> Result<Arc<dyn OcrEngine>, OcrError> {
+    #[cfg(not(any(feature = "engine-ocrs", feature = "engine-leptess")))]
+    let _ = (config, model_cache);
+
+    tracing::info!("Initializing {} engine...", name);
+    match name {
+        #[cfg(feature = "engine-ocrs")]
+        "ocrs" => Ok(Arc::new(ocrs::OcrsEngine::new(
+            config,
+            model_cache.clone(),
+        )?)),
+        #[cfg(feature = "engine-leptess")]
+        "leptess" => Ok(Arc::new(leptess::LeptessEngine::new(
+            config,
+            model_cache.clone(),
+        )?)),
+        _ => Err(OcrError::Internal(format!("Unknown engine '{}'", name))),
+    }
+}
+
+/// The `auto` engine, when both engines it routes between are present
+fn auto_engine(engines: &[Arc<dyn OcrEngine>]) -> Option<Arc<dyn OcrEngine>> {
+    // Routing needs both engines, so it's only offered when both started
+    let find = |name: &str| engines.iter().find(|e| e.name() == name).cloned();
+    let (ocrs, leptess) = (find("ocrs")?, find("leptess")?);
+    Some(Arc::new(auto::AutoEngine::new(ocrs, leptess)))
+}
+
+/// Formats an engine accepts, including those the server unpacks for it
+fn supported_formats(engine: &dyn OcrEngine) -> Vec<String> {
+    #[cfg_attr(not(feature = "documents"), allow(unused_mut))]
+    let mut formats = engine.supported_formats();
+    #[cfg(feature = "documents")]
+    formats.extend([crate::documents::DOCX_MIME, crate::documents::ODT_MIME].map(String::from));
+    formats
+}
+
+/// Recognize the pages of a document `threads` at a time, passing each
+/// page's index and result to `handle` in page order
+///
+/// Pages are recognized in batches, so results can be handled in order as
+/// soon as a batch is done. Once `handle` breaks, no more batches are started.
+///
+/// Pages are taken from `pages` a batch at a time, so `recognize` can load
+/// them itself to keep only one batch in memory.
+pub(crate) fn recognize_pages<T: Send, R: Send>(
+    pages: impl IntoIterator<Item = T>,
+    threads: usize,
+    recognize: impl Fn(usize, T) -> R + Sync,
+    mut handle: impl FnMut(usize, R) -> ControlFlow<()>,
+) {
+    let threads = threads.max(1);
+    let mut pages = pages.into_iter();
+    for batch_index in 0.. {
+        let first = batch_index * threads;
+        let mut batch: Vec<T> = pages.by_ref().take(threads).collect();
+        let results: Vec<R> = if batch.len() <= 1 {
+            match batch.pop() {
+                Some(page) => vec![recognize(first, page)],
+                None => return,
+            }
+        } else {
+            let recognize = &recognize;
+            std::thread::scope(|scope| {
+                let running: Vec<_> = batch
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, page)| scope.spawn(move || recognize(first + i, page)))
+                    .collect();
+                running
+                    .into_iter()
+                    .map(|thread| {
+                        thread
+                            .join()
+                            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                    })
+                    .collect()
+            })
+        };
+
+        for (i, result) in results.into_iter().enumerate() {
+            if handle(first + i, result).is_break() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_recognize_pages_keeps_page_order() {
+        // Later pages finish first within a batch
+        let pages = [40, 30, 20, 10, 0];
+        let mut handled = Vec::new();
+        recognize_pages(
+            pages,
+            3,
+            |i, delay| {
+                std::thread::sleep(Duration::from_millis(delay));
+                i
+            },
+            |i, result| {
+                assert_eq!(i, result);
+                handled.push(i);
+                ControlFlow::Continue(())
+            },
+        );
+        assert_eq!(handled, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_recognize_pages_stops_after_break() {
+        let started = std::sync::atomic::AtomicUsize::new(0);
+        let mut handled = Vec::new();
+        recognize_pages(
+            [(); 5],
+            2,
+            |i, _| {
+                started.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                i
+            },
+            |i, _| {
+                handled.push(i);
+                if i == 0 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            },
+        );
+        // The rest of the first batch was already recognized
+        assert_eq!(handled, vec![0]);
+        assert_eq!(started.into_inner(), 2);
+    }
+}
@@ -3,16 +3,27 @@
 //! Pure Rust OCR engine using the ocrs library. No system dependencies required.
 //! Downloads neural network models automatically on first use.
 
+use super::confidence::{text_confidence, word_scores};
+use super::pdf;
+use super::recognize_pages;
 use crate::config::Config;
-use crate::engine::{OcrEngine, OcrResult};
+use crate::engine::{
+    CoordsLevel, DecodeMethod, DetectedWord, Detection, LineBox, OcrEngine, OcrOptions, OcrResult,
+    RecognitionModel, TextBox, TextBoxes,
+};
 use crate::error::OcrError;
+use crate::model_cache::ModelCache;
 use image::DynamicImage;
-use ocrs::{DecodeMethod, ImageSource, OcrEngine as OcrsOcrEngine, OcrEngineParams};
+use ocrs::{
+    DecodeMethod as OcrsDecodeMethod, ImageSource, OcrEngine as OcrsOcrEngine, OcrEngineParams,
+    OcrInput, TextItem, TextLine,
+};
 use rten::Model;
-use std::fs::File;
-use std::io::{Read, Write};
+use rten_imageproc::RotatedRect;
+use std::collections::VecDeque;
+use std::ops::ControlFlow;
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 /// Default model URLs from the ocrs project
 const DETECTION_MODEL_URL: &str =
@@ -20,50 +31,150 @@ const DETECTION_MODEL_URL: &str =
 const RECOGNITION_MODEL_URL: &str =
     "https://ocrs-models.s3-accelerate.amazonaws.com/text-recognition.rten";
 
+/// Lines recognized at a time when a request sets max_words
+const MAX_WORDS_BATCH_LINES: usize = 8;
+
+/// Most engines kept loaded for models, decode methods and beam widths other
+/// than the server's defaults
+const MAX_CACHED_ENGINES: usize = 4;
+
+/// Model, decode method and beam width (0 for greedy decoding) of an engine
+type EngineKey = (RecognitionModel, DecodeMethod, u32);
+
+/// Engines loaded for non-default options, the least recently used first
+struct EngineCache<E> {
+    engines: Mutex<VecDeque<(EngineKey, Arc<E>)>>,
+    capacity: usize,
+}
+
+impl<E> EngineCache<E> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            engines: Mutex::new(VecDeque::new()),
+            capacity,
+        }
+    }
+
+    /// The engine for `key`, `load`ing it if it isn't cached and evicting
+    /// the least recently used engine if the cache is full
+    ///
+    /// Loading happens without the lock held, so requests using other
+    /// engines aren't held up while the models load.
+    fn get_or_load(
+        &self,
+        key: EngineKey,
+        load: impl FnOnce() -> Result<E, OcrError>,
+    ) -> Result<Arc<E>, OcrError> {
+        if let Some(engine) = self.get(key) {
+            return Ok(engine);
+        }
+        let loaded = Arc::new(load()?);
+
+        // Another request may have loaded the same engine meanwhile
+        if let Some(engine) = self.get(key) {
+            return Ok(engine);
+        }
+        let mut engines = self.engines.lock().unwrap();
+        if engines.len() >= self.capacity {
+            engines.pop_front();
+        }
+        engines.push_back((key, loaded.clone()));
+        Ok(loaded)
+    }
+
+    /// A cached engine, marked as the most recently used
+    fn get(&self, key: EngineKey) -> Option<Arc<E>> {
+        let mut engines = self.engines.lock().unwrap();
+        let at = engines.iter().position(|(cached, _)| *cached == key)?;
+        let entry = engines.remove(at)?;
+        let engine = entry.1.clone();
+        engines.push_back(entry);
+        Some(engine)
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.engines.lock().unwrap().len()
+    }
+}
+
 /// OCR Engine wrapping the ocrs library
+///
+/// The decode method is fixed when an ocrs engine is built, so one is kept
+/// per model, decode method and beam width combination. The engine for the
+/// server's defaults is loaded up front and always kept; engines for other
+/// options are loaded on first use and only the most recently used few are
+/// kept, so clients stepping through beam widths can't fill memory.
 pub struct OcrsEngine {
-    engine: Arc<OcrsOcrEngine>,
+    default_key: EngineKey,
+    default_engine: Arc<OcrsOcrEngine>,
+    engines: EngineCache<OcrsOcrEngine>,
+    model_cache: Arc<ModelCache>,
+    /// Where to download the handwriting recognition model from, if configured
+    handwriting_model_url: Option<String>,
+    /// Decode method used when a request doesn't choose one
+    default_decode: DecodeMethod,
+    /// Beam width for beam search decoding
+    beam_width: u32,
 }
 
 impl OcrsEngine {
     /// Create a new OCR processor, downloading models if needed
-    pub fn new(_config: &Config) -> Result<Self, OcrError> {
+    pub fn new(config: &Config, model_cache: Arc<ModelCache>) -> Result<Self, OcrError> {
         tracing::info!("Initializing ocrs OCR engine...");
 
-        // Load models (will download if not cached)
-        let detection_model_path =
-            ensure_model_downloaded(DETECTION_MODEL_URL, "text-detection.rten")?;
-        let recognition_model_path =
-            ensure_model_downloaded(RECOGNITION_MODEL_URL, "text-recognition.rten")?;
-
-        // Load models using rten::Model::load_file
-        let detection_model = Model::load_file(&detection_model_path).map_err(|e| {
-            OcrError::InitializationError(format!("Failed to load detection model: {}", e))
-        })?;
-        let recognition_model = Model::load_file(&recognition_model_path).map_err(|e| {
-            OcrError::InitializationError(format!("Failed to load recognition model: {}", e))
-        })?;
-
-        let engine = OcrsOcrEngine::new(OcrEngineParams {
-            detection_model: Some(detection_model),
-            recognition_model: Some(recognition_model),
-            decode_method: DecodeMethod::Greedy,
-            ..Default::default()
-        })
-        .map_err(|e| {
-            OcrError::InitializationError(format!("Failed to create OCR engine: {}", e))
-        })?;
+        let default_decode = config.decode_method;
+        let default_key = engine_key(&OcrOptions::default(), default_decode, config.beam_width);
+        // Load the default models up front (will download if not cached)
+        let default_engine = Arc::new(load_engine(
+            &model_cache,
+            config.handwriting_model_url.as_deref(),
+            default_key,
+        )?);
+
+        let ocrs_engine = Self {
+            default_key,
+            default_engine,
+            engines: EngineCache::new(MAX_CACHED_ENGINES),
+            model_cache,
+            handwriting_model_url: config.handwriting_model_url.clone(),
+            default_decode,
+            beam_width: config.beam_width,
+        };
+
+        let threads = std::env::var("RTEN_NUM_THREADS")
+            .map(|threads| format!("{} threads", threads))
+            .unwrap_or_else(|_| "one thread per physical core".to_string());
+        tracing::info!(
+            "ocrs inference uses {} matrix kernels and {}",
+            gemm_kernel(),
+            threads
+        );
 
         tracing::info!("ocrs engine initialized successfully");
 
-        Ok(Self {
-            engine: Arc::new(engine),
+        Ok(ocrs_engine)
+    }
+
+    /// Get the engine for a request's model and decode method, loading it on first use
+    fn engine_for(&self, options: &OcrOptions) -> Result<Arc<OcrsOcrEngine>, OcrError> {
+        let key = engine_key(options, self.default_decode, self.beam_width);
+        if key == self.default_key {
+            return Ok(self.default_engine.clone());
+        }
+        self.engines.get_or_load(key, || {
+            load_engine(
+                &self.model_cache,
+                self.handwriting_model_url.as_deref(),
+                key,
+            )
         })
     }
 
     /// Process an image file and return the extracted text
-    fn process_image_file(&self, path: &Path) -> Result<OcrResult, OcrError> {
+    fn process_image_file(&self, path: &Path, options: &OcrOptions) -> Result<OcrResult, OcrError> {
         let warnings = Vec::new();
+        let engine = self.engine_for(options)?;
 
         // Load the image using the image crate
         let img = image::open(path)
@@ -79,56 +190,80 @@ impl OcrsEngine {
         })?;
 
         // Prepare input for OCR
-        let ocr_input = self
-            .engine
+        let ocr_input = engine
             .prepare_input(img_source)
             .map_err(|e| OcrError::ProcessingError(format!("Failed to prepare input: {}", e)))?;
 
         // Detect words
-        let word_rects = self
-            .engine
+        let word_rects = engine
             .detect_words(&ocr_input)
             .map_err(|e| OcrError::ProcessingError(format!("Failed to detect words: {}", e)))?;
 
         // Group words into lines
-        let line_rects = self.engine.find_text_lines(&ocr_input, &word_rects);
+        let line_rects = engine.find_text_lines(&ocr_input, &word_rects);
 
         // Recognize text in each line
-        let line_texts = self
-            .engine
-            .recognize_text(&ocr_input, &line_rects)
-            .map_err(|e| OcrError::ProcessingError(format!("Failed to recognize text: {}", e)))?;
+        let line_texts = recognize_lines(&engine, &ocr_input, &line_rects, options.max_words)?;
 
         // Combine all lines into a single string
         let text: String = line_texts
             .iter()
             .filter_map(|line| line.as_ref())
-            .map(|line| {
-                line.words()
-                    .map(|word| word.to_string())
-                    .collect::<Vec<_>>()
-                    .join(" ")
-            })
+            .map(line_text)
             .collect::<Vec<_>>()
             .join("\n");
 
         // Calculate confidence using text quality heuristics
-        let confidence = calculate_confidence(&text);
+        let confidence = text_confidence(&text, options);
+        let scored_words = word_scores(&text, options);
+
+        let boxes = options.coords_level.map(|_| TextBoxes {
+            level: CoordsLevel::Word,
+            boxes: word_boxes(&line_texts, options.polygons),
+        });
+        let lines = options
+            .lines
+            .then(|| line_boxes(&line_rects, &line_texts, options.polygons));
 
         Ok(OcrResult {
             text,
             confidence,
             warnings,
+            boxes,
+            word_confidences: None,
+            word_scores: scored_words,
+            lines,
+            orientation_degrees: None,
         })
     }
 
     /// Process a PDF file
-    fn process_pdf(&self, path: &Path) -> Result<OcrResult, OcrError> {
+    ///
+    /// Each scanned page is passed to `on_page` as soon as it has been
+    /// recognized. Embedded text is extracted in one pass and reported as page 1.
+    fn process_pdf(
+        &self,
+        path: &Path,
+        options: &OcrOptions,
+        on_page: &dyn Fn(u32, &OcrResult),
+    ) -> Result<OcrResult, OcrError> {
         let mut warnings = Vec::new();
 
         // First, try to extract text directly from the PDF
-        let direct_text = pdf_extract::extract_text(path)
-            .map_err(|e| OcrError::ProcessingError(format!("Failed to parse PDF: {}", e)))?;
+        let parse_failed = |e: pdf_extract::OutputError| {
+            OcrError::ProcessingError(format!("Failed to parse PDF: {}", e))
+        };
+        let direct_text = if options.force_ocr {
+            String::new()
+        } else if options.first_page_only {
+            pdf_extract::extract_text_by_pages(path)
+                .map_err(parse_failed)?
+                .into_iter()
+                .next()
+                .unwrap_or_default()
+        } else {
+            pdf_extract::extract_text(path).map_err(parse_failed)?
+        };
 
         // If we got meaningful text, return it
         let trimmed_text = direct_text.trim();
@@ -137,11 +272,28 @@ impl OcrsEngine {
                 "Extracted {} chars of text directly from PDF",
                 trimmed_text.len()
             );
-            return Ok(OcrResult {
+            if options.coords_level.is_some() {
+                warnings.push(
+                    "Bounding boxes are not available for PDFs with embedded text".to_string(),
+                );
+            }
+            if options.lines {
+                warnings.push("Lines are not available for PDFs with embedded text".to_string());
+            }
+            let result = OcrResult {
                 text: trimmed_text.to_string(),
-                confidence: 0.95, // High confidence for direct text extraction
+                // Embedded text can be a garbled layer from an earlier OCR
+                // pass, so it is scored like recognized text
+                confidence: text_confidence(trimmed_text, options),
                 warnings,
-            });
+                boxes: None,
+                word_confidences: None,
+                word_scores: word_scores(trimmed_text, options),
+                lines: None,
+                orientation_degrees: None,
+            };
+            on_page(1, &result);
+            return Ok(result);
         }
 
         // If direct extraction yielded little/no text, try to extract and OCR images
@@ -149,44 +301,99 @@ impl OcrsEngine {
         warnings
             .push("PDF appears to be scanned/image-based, extracting images for OCR".to_string());
 
-        let images = extract_images_from_pdf(path)?;
+        let images = pdf::extract_images(path, options.first_page_only)?;
 
         if images.is_empty() {
             return Ok(OcrResult {
                 text: String::new(),
                 confidence: 0.0,
                 warnings: vec!["No text or images found in PDF".to_string()],
+                boxes: None,
+                word_confidences: None,
+                word_scores: options.word_confidence.then(Vec::new),
+                lines: None,
+                orientation_degrees: None,
             });
         }
 
         // OCR each image and combine results
         let mut all_text = Vec::new();
-        for (i, img) in images.iter().enumerate() {
-            tracing::info!("Processing image {} of {} from PDF", i + 1, images.len());
-            match self.process_dynamic_image(img) {
-                Ok(result) => {
+        let mut all_boxes = options.coords_level.map(|level| TextBoxes {
+            level,
+            boxes: Vec::new(),
+        });
+        let mut all_lines = options.lines.then(Vec::new);
+        let mut all_word_scores = options.word_confidence.then(Vec::new);
+        let count = images.len();
+        let recognize = |i: usize, img: Option<DynamicImage>| {
+            tracing::info!("Processing image {} of {} from PDF", i + 1, count);
+            let img = img.ok_or_else(pdf::undecodable_image)?;
+            self.process_dynamic_image(&img, options)
+        };
+        recognize_pages(images, options.page_threads, recognize, |i, result| {
+            match result {
+                Ok(mut result) => {
+                    // Number boxes by the image they came from
+                    let page = i as u32 + 1;
+                    if let Some(found) = result.boxes.as_mut() {
+                        found.boxes.iter_mut().for_each(|b| b.page = page);
+                    }
+                    for line in result.lines.iter_mut().flatten() {
+                        line.text_box.page = page;
+                    }
+                    on_page(page, &result);
+
+                    if let (Some(all), Some(found)) = (all_boxes.as_mut(), result.boxes) {
+                        all.level = found.level;
+                        all.boxes.extend(found.boxes);
+                    }
+                    if let (Some(all), Some(found)) = (all_lines.as_mut(), result.lines) {
+                        all.extend(found);
+                    }
+                    if let (Some(all), Some(found)) = (all_word_scores.as_mut(), result.word_scores)
+                    {
+                        all.extend(found);
+                    }
                     if !result.text.is_empty() {
                         all_text.push(result.text);
                     }
+                    let words = all_text.iter().map(|text| text.split_whitespace().count());
+                    if options
+                        .max_words
+                        .is_some_and(|max| words.sum::<usize>() > max)
+                    {
+                        return ControlFlow::Break(());
+                    }
                 }
                 Err(e) => {
                     warnings.push(format!("Failed to OCR image {}: {}", i + 1, e));
                 }
             }
-        }
+            ControlFlow::Continue(())
+        });
 
         let combined_text = all_text.join("\n\n");
-        let confidence = calculate_confidence(&combined_text);
+        let confidence = text_confidence(&combined_text, options);
 
         Ok(OcrResult {
             text: combined_text,
             confidence,
             warnings,
+            boxes: all_boxes,
+            word_confidences: None,
+            word_scores: all_word_scores,
+            lines: all_lines,
+            orientation_degrees: None,
         })
     }
 
     /// Process a DynamicImage directly (used for extracted PDF images)
-    fn process_dynamic_image(&self, img: &DynamicImage) -> Result<OcrResult, OcrError> {
+    fn process_dynamic_image(
+        &self,
+        img: &DynamicImage,
+        options: &OcrOptions,
+    ) -> Result<OcrResult, OcrError> {
+        let engine = self.engine_for(options)?;
         let rgb_img = img.to_rgb8();
         let dimensions = rgb_img.dimensions();
 
@@ -194,41 +401,45 @@ impl OcrsEngine {
             OcrError::ProcessingError(format!("Failed to create image source: {}", e))
         })?;
 
-        let ocr_input = self
-            .engine
+        let ocr_input = engine
             .prepare_input(img_source)
             .map_err(|e| OcrError::ProcessingError(format!("Failed to prepare input: {}", e)))?;
 
-        let word_rects = self
-            .engine
+        let word_rects = engine
             .detect_words(&ocr_input)
             .map_err(|e| OcrError::ProcessingError(format!("Failed to detect words: {}", e)))?;
 
-        let line_rects = self.engine.find_text_lines(&ocr_input, &word_rects);
+        let line_rects = engine.find_text_lines(&ocr_input, &word_rects);
 
-        let line_texts = self
-            .engine
-            .recognize_text(&ocr_input, &line_rects)
-            .map_err(|e| OcrError::ProcessingError(format!("Failed to recognize text: {}", e)))?;
+        let line_texts = recognize_lines(&engine, &ocr_input, &line_rects, options.max_words)?;
 
         let text: String = line_texts
             .iter()
             .filter_map(|line| line.as_ref())
-            .map(|line| {
-                line.words()
-                    .map(|word| word.to_string())
-                    .collect::<Vec<_>>()
-                    .join(" ")
-            })
+            .map(line_text)
             .collect::<Vec<_>>()
             .join("\n");
 
-        let confidence = calculate_confidence(&text);
+        let confidence = text_confidence(&text, options);
+        let scored_words = word_scores(&text, options);
+
+        let boxes = options.coords_level.map(|_| TextBoxes {
+            level: CoordsLevel::Word,
+            boxes: word_boxes(&line_texts, options.polygons),
+        });
+        let lines = options
+            .lines
+            .then(|| line_boxes(&line_rects, &line_texts, options.polygons));
 
         Ok(OcrResult {
             text,
             confidence,
             warnings: Vec::new(),
+            boxes,
+            word_confidences: None,
+            word_scores: scored_words,
+            lines,
+            orientation_degrees: None,
         })
     }
 }
@@ -242,17 +453,43 @@ impl OcrEngine for OcrsEngine {
         "Pure Rust OCR engine - fast, no system dependencies required"
     }
 
-    fn process(&self, path: &Path) -> Result<OcrResult, OcrError> {
-        // Check if the file is a PDF
-        if is_pdf(path)? {
-            return self.process_pdf(path);
-        }
-
-        self.process_image_file(path)
+    fn process(&self, path: &Path, options: &OcrOptions) -> Result<OcrResult, OcrError> {
+        self.process_pages(path, options, &|_, _| {})
     }
 
-    fn process_image(&self, image: &DynamicImage) -> Result<OcrResult, OcrError> {
-        self.process_dynamic_image(image)
+    fn process_pages(
+        &self,
+        path: &Path,
+        options: &OcrOptions,
+        on_page: &dyn Fn(u32, &OcrResult),
+    ) -> Result<OcrResult, OcrError> {
+        // Check if the file is a PDF
+        let mut result = if pdf::is_pdf(path)? {
+            self.process_pdf(path, options, on_page)?
+        } else {
+            let result = self.process_image_file(path, options)?;
+            on_page(1, &result);
+            result
+        };
+
+        warn_if_character_boxes_requested(&mut result, options);
+        warn_if_psm_requested(&mut result, options);
+        warn_if_char_filter_requested(&mut result, options);
+        warn_if_orientation_requested(&mut result, options);
+        Ok(result)
+    }
+
+    fn process_image(
+        &self,
+        image: &DynamicImage,
+        options: &OcrOptions,
+    ) -> Result<OcrResult, OcrError> {
+        let mut result = self.process_dynamic_image(image, options)?;
+        warn_if_character_boxes_requested(&mut result, options);
+        warn_if_psm_requested(&mut result, options);
+        warn_if_char_filter_requested(&mut result, options);
+        warn_if_orientation_requested(&mut result, options);
+        Ok(result)
     }
 
     fn supported_formats(&self) -> Vec<String> {
@@ -271,490 +508,504 @@ impl OcrEngine for OcrsEngine {
         // ocrs currently only supports English/Latin alphabet
         vec!["eng".to_string()]
     }
-}
-
-// ============================================================================
-// Confidence scoring heuristics
-// ============================================================================
-
-/// Calculate confidence score based on text quality heuristics.
-///
-/// Since ocrs doesn't provide per-character confidence scores, we analyze
-/// the recognized text for patterns that indicate OCR quality.
-fn calculate_confidence(text: &str) -> f32 {
-    if text.is_empty() {
-        return 0.0;
-    }
-    if text.len() < 5 {
-        return 0.5; // Too short to judge accurately
-    }
 
-    let char_score = analyze_char_frequency(text);
-    let word_score = analyze_word_lengths(text);
-    let whitespace_score = analyze_whitespace(text);
-    let repetition_score = detect_repetition(text);
+    fn detect(&self, image: &DynamicImage) -> Result<Detection, OcrError> {
+        // Detection doesn't depend on the recognition model or decode method
+        let engine = self.engine_for(&OcrOptions::default())?;
 
-    let confidence =
-        0.40 * char_score + 0.30 * word_score + 0.15 * whitespace_score + 0.15 * repetition_score;
+        let rgb_img = image.to_rgb8();
+        let dimensions = rgb_img.dimensions();
 
-    confidence.clamp(0.0, 1.0)
-}
+        let img_source = ImageSource::from_bytes(rgb_img.as_raw(), dimensions).map_err(|e| {
+            OcrError::ProcessingError(format!("Failed to create image source: {}", e))
+        })?;
 
-/// Analyze character frequency for signs of garbled OCR.
-///
-/// Penalizes text with too many special/control characters or too few letters.
-fn analyze_char_frequency(text: &str) -> f32 {
-    let total = text.chars().count();
-    if total == 0 {
-        return 0.0;
-    }
+        let ocr_input = engine
+            .prepare_input(img_source)
+            .map_err(|e| OcrError::ProcessingError(format!("Failed to prepare input: {}", e)))?;
 
-    let letters = text.chars().filter(|c| c.is_alphabetic()).count();
-    let special = text
-        .chars()
-        .filter(|c| !c.is_alphanumeric() && !c.is_whitespace() && !c.is_ascii_punctuation())
-        .count();
+        let word_rects = engine
+            .detect_words(&ocr_input)
+            .map_err(|e| OcrError::ProcessingError(format!("Failed to detect words: {}", e)))?;
 
-    // Penalize high special char ratio
-    let special_ratio = special as f32 / total as f32;
-    let special_penalty = 1.0 - (special_ratio * 10.0).min(1.0);
+        let line_rects = engine.find_text_lines(&ocr_input, &word_rects);
 
-    // Penalize very low letter content (unless it's a numeric document)
-    let letter_ratio = letters as f32 / total as f32;
-    let letter_score = (letter_ratio * 1.5).min(1.0);
+        Ok(Detection {
+            words: word_rects.iter().map(detected_word).collect(),
+            lines: line_rects
+                .iter()
+                .map(|line| line.iter().map(detected_word).collect())
+                .collect(),
+        })
+    }
 
-    special_penalty * 0.6 + letter_score * 0.4
-}
+    fn supported_models(&self) -> Vec<RecognitionModel> {
+        // Handwriting is only available when a model URL has been configured
+        let mut models = vec![RecognitionModel::Default];
+        if self.handwriting_model_url.is_some() {
+            models.push(RecognitionModel::Handwriting);
+        }
+        models
+    }
 
-/// Analyze word length distribution.
-///
-/// Garbled OCR often produces single-character "words" or very long sequences.
-fn analyze_word_lengths(text: &str) -> f32 {
-    let words: Vec<&str> = text.split_whitespace().collect();
-    if words.is_empty() {
-        return 0.5;
+    fn prepare(&self, options: &OcrOptions) -> Result<(), OcrError> {
+        // Languages don't apply, every model reads Latin script
+        self.engine_for(options).map(|_| ())
     }
+}
 
-    let total_len: usize = words.iter().map(|w| w.len()).sum();
-    let avg_len = total_len as f32 / words.len() as f32;
+// ============================================================================
+// Bounding boxes
+// ============================================================================
 
-    // Ideal average word length: 4-8 chars
-    let avg_score = match avg_len as usize {
-        0..=1 => 0.3,
-        2..=3 => 0.7,
-        4..=8 => 1.0,
-        9..=12 => 0.8,
-        _ => 0.4,
+/// Recognize the text of lines in reading order
+///
+/// With `max_words`, lines are recognized a batch at a time and recognition
+/// stops once more than `max_words` words are found, so fewer lines than
+/// `line_rects` may be returned. Going past the limit lets the server tell a
+/// cut-off page from one with exactly `max_words` words.
+fn recognize_lines(
+    engine: &OcrsOcrEngine,
+    input: &OcrInput,
+    line_rects: &[Vec<RotatedRect>],
+    max_words: Option<usize>,
+) -> Result<Vec<Option<TextLine>>, OcrError> {
+    let recognize = |lines: &[Vec<RotatedRect>]| {
+        engine
+            .recognize_text(input, lines)
+            .map_err(|e| OcrError::ProcessingError(format!("Failed to recognize text: {}", e)))
+    };
+    let Some(max_words) = max_words else {
+        return recognize(line_rects);
     };
 
-    // Penalize too many single-char "words"
-    let single_count = words.iter().filter(|w| w.len() == 1).count();
-    let single_ratio = single_count as f32 / words.len() as f32;
-    let single_penalty = 1.0 - (single_ratio * 1.5).min(0.5);
-
-    avg_score * single_penalty
+    let mut line_texts = Vec::new();
+    let mut words = 0;
+    for batch in line_rects.chunks(MAX_WORDS_BATCH_LINES) {
+        let batch = recognize(batch)?;
+        words += batch
+            .iter()
+            .flatten()
+            .map(|line| line.words().count())
+            .sum::<usize>();
+        line_texts.extend(batch);
+        if words > max_words {
+            break;
+        }
+    }
+    Ok(line_texts)
 }
 
-/// Analyze whitespace ratio.
+/// Collect word bounding boxes from recognized lines
 ///
-/// Normal text has ~10-25% whitespace. Too dense or too sparse indicates issues.
-fn analyze_whitespace(text: &str) -> f32 {
-    let total = text.chars().count();
-    if total == 0 {
-        return 0.0;
-    }
-
-    let whitespace = text.chars().filter(|c| c.is_whitespace()).count();
-    let ratio = (whitespace as f32 / total as f32) * 100.0;
+/// ocrs doesn't report per-word confidence, so confidence is left unset.
+fn word_boxes(line_texts: &[Option<TextLine>], polygons: bool) -> Vec<TextBox> {
+    line_texts
+        .iter()
+        .flatten()
+        .flat_map(|line| {
+            line.words()
+                .map(|word| {
+                    let rect = word.bounding_rect();
+                    TextBox {
+                        text: logical_order(&word.to_string()),
+                        page: 1,
+                        x: rect.left().max(0) as u32,
+                        y: rect.top().max(0) as u32,
+                        width: rect.width().max(0) as u32,
+                        height: rect.height().max(0) as u32,
+                        confidence: None,
+                        polygon: polygons.then(|| polygon(&word)),
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
 
-    match ratio as usize {
-        0..=5 => 0.5,   // Too dense
-        6..=10 => 0.8,  // Slightly dense
-        11..=25 => 1.0, // Ideal
-        26..=40 => 0.7, // Slightly sparse
-        _ => 0.3,       // Too sparse
-    }
+/// Text of a recognized line in reading order
+fn line_text(line: &TextLine) -> String {
+    let visual = line
+        .words()
+        .map(|word| word.to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    logical_order(&visual)
 }
 
-/// Detect repeated character sequences.
+/// Convert text recognized left to right into reading order
 ///
-/// Patterns like "aaaa" or "####" often indicate OCR confusion.
-fn detect_repetition(text: &str) -> f32 {
-    let mut max_repeat = 1;
-    let mut current = 1;
-    let mut prev: Option<char> = None;
-
-    for c in text.chars() {
-        if Some(c) == prev && !c.is_whitespace() {
-            current += 1;
-            max_repeat = max_repeat.max(current);
-        } else {
-            current = 1;
-        }
-        prev = Some(c);
+/// ocrs reads every line from left to right, so right-to-left scripts such
+/// as Arabic and Hebrew come out mirrored. Text that is mostly right-to-left
+/// is reversed, then runs of left-to-right characters such as numbers and
+/// Latin words are turned back around, the way the Unicode bidi algorithm
+/// lays them out. Bidi control characters are dropped, since their position
+/// in visual order means nothing. Other text is returned as is.
+fn logical_order(visual: &str) -> String {
+    let visual: String = visual.chars().filter(|&c| !is_bidi_control(c)).collect();
+    let rtl = visual.chars().filter(|&c| is_rtl(c)).count();
+    let ltr = visual
+        .chars()
+        .filter(|&c| c.is_alphabetic() && !is_rtl(c))
+        .count();
+    if rtl <= ltr {
+        return visual;
     }
 
-    match max_repeat {
-        1..=3 => 1.0,
-        4..=5 => 0.8,
-        6..=10 => 0.5,
-        _ => 0.2,
+    let mut logical = String::with_capacity(visual.len());
+    // Left-to-right run, collected back to front
+    let mut run = Vec::new();
+    for c in visual.chars().rev() {
+        if is_rtl(c) || c.is_whitespace() {
+            logical.extend(run.drain(..).rev());
+            logical.push(c);
+        } else {
+            run.push(c);
+        }
     }
+    logical.extend(run.drain(..).rev());
+    logical
 }
 
-// ============================================================================
-// Helper functions
-// ============================================================================
-
-/// Check if a file is a PDF by reading its magic bytes
-fn is_pdf(path: &Path) -> Result<bool, OcrError> {
-    // Check file extension first
-    if let Some(ext) = path.extension() {
-        if ext.to_string_lossy().to_lowercase() == "pdf" {
-            return Ok(true);
-        }
-    }
+/// Whether a character belongs to a right-to-left script
+///
+/// Arabic-Indic digits are written left to right, like other digits.
+fn is_rtl(c: char) -> bool {
+    matches!(c,
+        '\u{0590}'..='\u{08FF}' | '\u{FB1D}'..='\u{FDFF}' | '\u{FE70}'..='\u{FEFE}')
+        && !matches!(c, '\u{0660}'..='\u{066C}' | '\u{06F0}'..='\u{06F9}')
+}
 
-    // Also check magic bytes (%PDF-)
-    let mut file = File::open(path)
-        .map_err(|e| OcrError::ProcessingError(format!("Failed to open file: {}", e)))?;
+/// Unicode marks, embeddings, overrides and isolates controlling text direction
+fn is_bidi_control(c: char) -> bool {
+    matches!(
+        c,
+        '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'
+    )
+}
 
-    let mut magic = [0u8; 5];
-    if file.read_exact(&mut magic).is_ok() {
-        return Ok(&magic == b"%PDF-");
-    }
+/// Collect recognized lines with their baseline angle
+///
+/// ocrs finds lines as sequences of rotated word rectangles, so the angle is
+/// averaged over the words' orientation. `line_rects` and `line_texts` are
+/// parallel, as returned by ocrs.
+fn line_boxes(
+    line_rects: &[Vec<RotatedRect>],
+    line_texts: &[Option<TextLine>],
+    polygons: bool,
+) -> Vec<LineBox> {
+    line_rects
+        .iter()
+        .zip(line_texts)
+        .filter_map(|(rects, line)| {
+            let line = line.as_ref()?;
+            let text = line_text(line);
+            if text.is_empty() {
+                return None;
+            }
 
-    Ok(false)
+            let words: Vec<DetectedWord> = rects.iter().map(detected_word).collect();
+            let rect = line.bounding_rect();
+            Some(LineBox {
+                text_box: TextBox {
+                    text,
+                    page: 1,
+                    x: rect.left().max(0) as u32,
+                    y: rect.top().max(0) as u32,
+                    width: rect.width().max(0) as u32,
+                    height: rect.height().max(0) as u32,
+                    confidence: None,
+                    polygon: polygons.then(|| polygon(line)),
+                },
+                baseline_angle: baseline_angle(&words),
+            })
+        })
+        .collect()
 }
 
-/// Extract images from a PDF using lopdf
-fn extract_images_from_pdf(path: &Path) -> Result<Vec<DynamicImage>, OcrError> {
-    use lopdf::Document;
-
-    let doc = Document::load(path)
-        .map_err(|e| OcrError::ProcessingError(format!("Failed to load PDF: {}", e)))?;
-
-    let mut images = Vec::new();
-
-    // Iterate through all objects looking for image XObjects
-    for (object_id, object) in doc.objects.iter() {
-        if let Ok(stream) = object.as_stream() {
-            // Check if this is an image XObject
-            if let Ok(subtype) = stream.dict.get(b"Subtype") {
-                if let Ok(name) = subtype.as_name() {
-                    if name == b"Image" {
-                        // Try to extract the image data
-                        match extract_image_from_stream(&doc, stream) {
-                            Ok(img) => images.push(img),
-                            Err(e) => {
-                                tracing::warn!(
-                                    "Failed to extract image from object {:?}: {}",
-                                    object_id,
-                                    e
-                                );
-                            }
-                        }
-                    }
-                }
-            }
-        }
+/// Average baseline angle of a line's words, in degrees counterclockwise
+///
+/// A rectangle's orientation is only known up to quarter turns, so each
+/// word's angle is folded into -45..45 degrees.
+fn baseline_angle(words: &[DetectedWord]) -> f32 {
+    if words.is_empty() {
+        return 0.0;
     }
 
-    Ok(images)
+    let total: f32 = words
+        .iter()
+        .map(|word| {
+            // Any edge gives the same folded angle
+            let [[x0, y0], [x1, y1], _, _] = word.corners;
+            // Image y grows downwards, so a rising edge has negative dy
+            let angle = (y0 - y1).atan2(x1 - x0).to_degrees();
+            (angle + 45.0).rem_euclid(90.0) - 45.0
+        })
+        .sum();
+    total / words.len() as f32
 }
 
-/// Extract an image from a PDF stream
-fn extract_image_from_stream(
-    doc: &lopdf::Document,
-    stream: &lopdf::Stream,
-) -> Result<DynamicImage, OcrError> {
-    // Get image dimensions
-    let width = stream
-        .dict
-        .get(b"Width")
-        .ok()
-        .and_then(|w| w.as_i64().ok())
-        .ok_or_else(|| OcrError::ProcessingError("Missing image width".to_string()))?
-        as u32;
-
-    let height = stream
-        .dict
-        .get(b"Height")
-        .ok()
-        .and_then(|h| h.as_i64().ok())
-        .ok_or_else(|| OcrError::ProcessingError("Missing image height".to_string()))?
-        as u32;
-
-    // Get the image data (decompressed)
-    let data = stream
-        .decompressed_content()
-        .map_err(|e| OcrError::ProcessingError(format!("Failed to decompress image: {}", e)))?;
-
-    // Get color space - handle both direct names and indirect references
-    let color_space = get_color_space(doc, stream);
-
-    // Get bits per component
-    let bits_per_component = stream
-        .dict
-        .get(b"BitsPerComponent")
-        .ok()
-        .and_then(|b| b.as_i64().ok())
-        .unwrap_or(8) as u8;
-
-    tracing::debug!(
-        "PDF image: {}x{}, {} bits, color_space={}, data_len={}",
-        width,
-        height,
-        bits_per_component,
-        color_space,
-        data.len()
-    );
+/// Corners of a recognized item's oriented bounds, clockwise from its top left
+fn polygon(item: &impl TextItem) -> [[f32; 2]; 4] {
+    // ocrs starts from the bottom right of upright text
+    let [bottom_right, bottom_left, top_left, top_right] =
+        item.rotated_rect().corners().map(|p| [p.x, p.y]);
+    [top_left, top_right, bottom_right, bottom_left]
+}
 
-    // Handle different color spaces
-    match color_space.as_str() {
-        "DeviceGray" => {
-            if bits_per_component == 8 && data.len() >= (width * height) as usize {
-                let img = image::GrayImage::from_raw(width, height, data).ok_or_else(|| {
-                    OcrError::ProcessingError("Invalid grayscale image data".to_string())
-                })?;
-                Ok(DynamicImage::ImageLuma8(img))
-            } else {
-                Err(OcrError::ProcessingError(format!(
-                    "Unsupported grayscale format: {} bits, data_len={}, expected={}",
-                    bits_per_component,
-                    data.len(),
-                    width * height
-                )))
-            }
-        }
-        "DeviceRGB" | "ICCBased" => {
-            // ICCBased with 3 components is typically RGB
-            if bits_per_component == 8 && data.len() >= (width * height * 3) as usize {
-                let img = image::RgbImage::from_raw(width, height, data).ok_or_else(|| {
-                    OcrError::ProcessingError("Invalid RGB image data".to_string())
-                })?;
-                Ok(DynamicImage::ImageRgb8(img))
-            } else {
-                Err(OcrError::ProcessingError(format!(
-                    "Unsupported RGB format: {} bits, data_len={}, expected={}",
-                    bits_per_component,
-                    data.len(),
-                    width * height * 3
-                )))
-            }
-        }
-        "DeviceCMYK" => {
-            // Convert CMYK to RGB
-            if bits_per_component == 8 && data.len() >= (width * height * 4) as usize {
-                let mut rgb_data = Vec::with_capacity((width * height * 3) as usize);
-                for chunk in data.chunks(4) {
-                    if chunk.len() == 4 {
-                        let c = chunk[0] as f32 / 255.0;
-                        let m = chunk[1] as f32 / 255.0;
-                        let y = chunk[2] as f32 / 255.0;
-                        let k = chunk[3] as f32 / 255.0;
-                        let r = ((1.0 - c) * (1.0 - k) * 255.0) as u8;
-                        let g = ((1.0 - m) * (1.0 - k) * 255.0) as u8;
-                        let b = ((1.0 - y) * (1.0 - k) * 255.0) as u8;
-                        rgb_data.push(r);
-                        rgb_data.push(g);
-                        rgb_data.push(b);
-                    }
-                }
-                let img = image::RgbImage::from_raw(width, height, rgb_data).ok_or_else(|| {
-                    OcrError::ProcessingError("Invalid CMYK->RGB conversion".to_string())
-                })?;
-                Ok(DynamicImage::ImageRgb8(img))
-            } else {
-                Err(OcrError::ProcessingError(format!(
-                    "Unsupported CMYK format: {} bits, data_len={}, expected={}",
-                    bits_per_component,
-                    data.len(),
-                    width * height * 4
-                )))
-            }
-        }
-        _ => Err(OcrError::ProcessingError(format!(
-            "Unsupported color space: {}",
-            color_space
-        ))),
+fn detected_word(rect: &RotatedRect) -> DetectedWord {
+    DetectedWord {
+        corners: rect.corners().map(|p| [p.x, p.y]),
     }
 }
 
-/// Get the color space name from a PDF stream, resolving indirect references
-fn get_color_space(doc: &lopdf::Document, stream: &lopdf::Stream) -> String {
-    let cs_obj = match stream.dict.get(b"ColorSpace") {
-        Ok(obj) => obj,
-        Err(_) => return "DeviceRGB".to_string(),
-    };
-
-    // Handle direct name
-    if let Ok(name) = cs_obj.as_name() {
-        return String::from_utf8_lossy(name).to_string();
+/// ocrs recognizes whole lines, so its per-character positions are too rough
+/// for precise work like redaction; character requests get word boxes instead
+fn warn_if_character_boxes_requested(result: &mut OcrResult, options: &OcrOptions) {
+    if options.coords_level == Some(CoordsLevel::Character) && result.boxes.is_some() {
+        result.warnings.push(
+            "ocrs only provides word-level boxes, use the leptess engine for character boxes"
+                .to_string(),
+        );
     }
+}
 
-    // Handle indirect reference
-    if let Ok(reference) = cs_obj.as_reference() {
-        if let Ok(resolved) = doc.get_object(reference) {
-            // Could be a name
-            if let Ok(name) = resolved.as_name() {
-                return String::from_utf8_lossy(name).to_string();
-            }
-            // Could be an array like [/ICCBased ref]
-            if let Ok(array) = resolved.as_array() {
-                if let Some(first) = array.first() {
-                    if let Ok(name) = first.as_name() {
-                        return String::from_utf8_lossy(name).to_string();
-                    }
-                }
-            }
-        }
+/// ocrs finds text lines itself and has no page segmentation modes
+fn warn_if_psm_requested(result: &mut OcrResult, options: &OcrOptions) {
+    if options.psm.is_some() {
+        result
+            .warnings
+            .push("psm is only supported by the leptess engine, ignored".to_string());
     }
+}
 
-    // Handle array directly (like [/ICCBased ref])
-    if let Ok(array) = cs_obj.as_array() {
-        if let Some(first) = array.first() {
-            if let Ok(name) = first.as_name() {
-                return String::from_utf8_lossy(name).to_string();
-            }
-        }
+/// Characters are restricted through Tesseract variables, which ocrs lacks
+fn warn_if_char_filter_requested(result: &mut OcrResult, options: &OcrOptions) {
+    if options.char_whitelist.is_some() || options.char_blacklist.is_some() {
+        result.warnings.push(
+            "whitelist and blacklist are only supported by the leptess engine, ignored".to_string(),
+        );
     }
-
-    "DeviceRGB".to_string()
 }
 
-/// Ensure model is downloaded and return its path
-fn ensure_model_downloaded(url: &str, filename: &str) -> Result<std::path::PathBuf, OcrError> {
-    // Get cache directory
-    let cache_dir = dirs::cache_dir()
-        .unwrap_or_else(std::env::temp_dir)
-        .join("activestorage-ocr");
-
-    std::fs::create_dir_all(&cache_dir).map_err(|e| {
-        OcrError::InitializationError(format!("Failed to create cache directory: {}", e))
-    })?;
+/// Orientation detection relies on Tesseract's recognition confidence
+fn warn_if_orientation_requested(result: &mut OcrResult, options: &OcrOptions) {
+    if options.detect_orientation {
+        result
+            .warnings
+            .push("auto_rotate is only supported by the leptess engine, ignored".to_string());
+    }
+}
 
-    let model_path = cache_dir.join(filename);
+// ============================================================================
+// Helper functions
+// ============================================================================
 
-    // Download if not cached
-    if !model_path.exists() {
-        tracing::info!("Downloading {} (this may take a moment)...", filename);
-        download_file(url, &model_path)?;
-        tracing::info!("Downloaded {} to {:?}", filename, model_path);
+/// Matrix multiplication kernels rten picks for this CPU, mirroring its own
+/// runtime detection (its AVX-512 kernels need a nightly-only build feature)
+#[cfg(target_arch = "x86_64")]
+fn gemm_kernel() -> &'static str {
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        "AVX2/FMA"
     } else {
-        tracing::info!("Using cached model from {:?}", model_path);
+        "generic (no SIMD)"
     }
+}
 
-    Ok(model_path)
+/// Matrix multiplication kernels rten picks for this CPU
+#[cfg(target_arch = "aarch64")]
+fn gemm_kernel() -> &'static str {
+    "Arm Neon"
 }
 
-/// Download a file from URL to path using ureq
-fn download_file(url: &str, path: &Path) -> Result<(), OcrError> {
-    let response = ureq::get(url)
-        .call()
-        .map_err(|e| OcrError::InitializationError(format!("Failed to download model: {}", e)))?;
+/// Matrix multiplication kernels rten picks for this CPU
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn gemm_kernel() -> &'static str {
+    "generic (no SIMD)"
+}
 
-    let mut file = File::create(path).map_err(|e| {
-        OcrError::InitializationError(format!("Failed to create model file: {}", e))
+/// Build an ocrs engine from detection and recognition model data
+fn build_engine(
+    detection_model: Vec<u8>,
+    recognition_model: Vec<u8>,
+    decode_method: OcrsDecodeMethod,
+) -> Result<OcrsOcrEngine, OcrError> {
+    // Load models using rten::Model::load
+    let detection_model = Model::load(detection_model).map_err(|e| {
+        OcrError::InitializationError(format!("Failed to load detection model: {}", e))
     })?;
-
-    // Read response body and write to file
-    let buffer = response.into_body().read_to_vec().map_err(|e| {
-        OcrError::InitializationError(format!("Failed to read response body: {}", e))
+    let recognition_model = Model::load(recognition_model).map_err(|e| {
+        OcrError::InitializationError(format!("Failed to load recognition model: {}", e))
     })?;
 
-    file.write_all(&buffer)
-        .map_err(|e| OcrError::InitializationError(format!("Failed to write model file: {}", e)))?;
-
-    Ok(())
+    OcrsOcrEngine::new(OcrEngineParams {
+        detection_model: Some(detection_model),
+        recognition_model: Some(recognition_model),
+        decode_method,
+        ..Default::default()
+    })
+    .map_err(|e| OcrError::InitializationError(format!("Failed to create OCR engine: {}", e)))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// The engine a request's options need, given the server's default decode
+/// method and beam width
+fn engine_key(options: &OcrOptions, default_decode: DecodeMethod, beam_width: u32) -> EngineKey {
+    let decode = options.decode.unwrap_or(default_decode);
+    // Greedy decoding has no beam, so all widths share one engine
+    let beam_width = match decode {
+        DecodeMethod::Greedy => 0,
+        DecodeMethod::BeamSearch => options.beam_width.unwrap_or(beam_width),
+    };
+    (options.model, decode, beam_width)
+}
 
-    #[test]
-    fn test_empty_text_returns_zero() {
-        assert_eq!(calculate_confidence(""), 0.0);
-    }
+/// Load the models for an engine and build it
+fn load_engine(
+    model_cache: &ModelCache,
+    handwriting_model_url: Option<&str>,
+    (model, decode, beam_width): EngineKey,
+) -> Result<OcrsOcrEngine, OcrError> {
+    let (recognition_name, recognition_url) = match model {
+        RecognitionModel::Default => ("text-recognition.rten".to_string(), RECOGNITION_MODEL_URL),
+        RecognitionModel::Handwriting => {
+            let url = handwriting_model_url.ok_or_else(|| {
+                OcrError::InvalidRequest(
+                    "The ocrs engine has no handwriting model configured \
+                     (set --handwriting-model-url) - try the leptess engine instead"
+                        .to_string(),
+                )
+            })?;
+            (handwriting_model_filename(url), url)
+        }
+    };
 
-    #[test]
-    fn test_short_text_returns_half() {
-        assert_eq!(calculate_confidence("Hi"), 0.5);
-        assert_eq!(calculate_confidence("Test"), 0.5);
-    }
+    tracing::info!(
+        "Loading ocrs engine ({} model, {} decoding{})...",
+        model.as_str(),
+        decode.as_str(),
+        match decode {
+            DecodeMethod::Greedy => String::new(),
+            DecodeMethod::BeamSearch => format!(", beam width {}", beam_width),
+        }
+    );
+    let detection_model = model_cache.load("text-detection.rten", DETECTION_MODEL_URL)?;
+    let recognition_model = model_cache.load(&recognition_name, recognition_url)?;
 
-    #[test]
-    fn test_clean_text_high_confidence() {
-        let text = "Hello World OCR Test 12345";
-        let confidence = calculate_confidence(text);
-        assert!(confidence > 0.7, "Expected > 0.7, got {}", confidence);
-    }
+    let decode_method = match decode {
+        DecodeMethod::Greedy => OcrsDecodeMethod::Greedy,
+        DecodeMethod::BeamSearch => OcrsDecodeMethod::BeamSearch { width: beam_width },
+    };
+    build_engine(detection_model, recognition_model, decode_method)
+}
 
-    #[test]
-    fn test_garbled_text_low_confidence() {
-        // Lots of special characters indicates bad OCR
-        let text = "§±®©¥€£¢¤";
-        let confidence = calculate_confidence(text);
-        assert!(confidence < 0.5, "Expected < 0.5, got {}", confidence);
-    }
+/// Cache filename for a handwriting model, derived from its URL so that
+/// changing the URL doesn't reuse a stale download
+fn handwriting_model_filename(url: &str) -> String {
+    let name = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|path| path.rsplit('/').next())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("text-recognition-handwriting.rten");
+
+    format!("handwriting-{}", name)
+}
 
-    #[test]
-    fn test_repeated_chars_lower_confidence() {
-        let text = "Hello aaaaaaaaaaaa World";
-        let confidence = calculate_confidence(text);
-        // Should be lower than clean text due to repetition
-        assert!(confidence < 0.9, "Expected < 0.9, got {}", confidence);
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::MAX_BEAM_WIDTH;
 
     #[test]
-    fn test_single_char_words_lower_confidence() {
-        // Many single-char "words" suggests garbled OCR
-        let text = "a b c d e f g h i j k l m n o p";
-        let confidence = calculate_confidence(text);
-        assert!(confidence < 0.7, "Expected < 0.7, got {}", confidence);
-    }
+    fn test_engine_cache_stays_bounded_across_beam_widths() {
+        let cache = EngineCache::new(MAX_CACHED_ENGINES);
+        let loads = std::sync::atomic::AtomicUsize::new(0);
+        let key = |width| (RecognitionModel::Default, DecodeMethod::BeamSearch, width);
+        let get = |width| {
+            cache
+                .get_or_load(key(width), || {
+                    loads.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    Ok(width)
+                })
+                .unwrap()
+        };
+
+        for width in 1..=MAX_BEAM_WIDTH {
+            assert_eq!(*get(width), width);
+        }
+        assert_eq!(cache.len(), MAX_CACHED_ENGINES);
+        assert_eq!(loads.load(std::sync::atomic::Ordering::SeqCst), 500);
 
-    #[test]
-    fn test_normal_sentence_good_confidence() {
-        let text = "The quick brown fox jumps over the lazy dog.";
-        let confidence = calculate_confidence(text);
-        assert!(confidence > 0.75, "Expected > 0.75, got {}", confidence);
+        // Recently used engines are reused, evicted ones are loaded again
+        get(MAX_BEAM_WIDTH);
+        assert_eq!(loads.load(std::sync::atomic::Ordering::SeqCst), 500);
+        get(1);
+        assert_eq!(loads.load(std::sync::atomic::Ordering::SeqCst), 501);
+        assert_eq!(cache.len(), MAX_CACHED_ENGINES);
     }
 
     #[test]
-    fn test_analyze_char_frequency_normal() {
-        let score = analyze_char_frequency("Hello World");
-        assert!(score > 0.8, "Expected > 0.8, got {}", score);
+    fn test_handwriting_model_filename_uses_url_basename() {
+        assert_eq!(
+            handwriting_model_filename("https://example.com/models/hw-recognition.rten?v=2"),
+            "handwriting-hw-recognition.rten"
+        );
     }
 
     #[test]
-    fn test_analyze_char_frequency_special() {
-        let score = analyze_char_frequency("§±®©¥€£¢¤ƒ");
-        assert!(score < 0.5, "Expected < 0.5, got {}", score);
+    fn test_handwriting_model_filename_never_matches_default_model() {
+        assert_eq!(
+            handwriting_model_filename("https://example.com/text-recognition.rten"),
+            "handwriting-text-recognition.rten"
+        );
+        assert_eq!(
+            handwriting_model_filename("https://example.com/models/"),
+            "handwriting-text-recognition-handwriting.rten"
+        );
     }
 
     #[test]
-    fn test_analyze_word_lengths_normal() {
-        let score = analyze_word_lengths("Hello World Test");
-        assert!(score > 0.8, "Expected > 0.8, got {}", score);
+    fn test_logical_order_reverses_right_to_left_lines() {
+        // "שלום עולם 123" as read from left to right
+        assert_eq!(logical_order("123 םלוע םולש"), "שלום עולם 123");
+        assert_eq!(logical_order("\u{200F}.ابحرم\u{200E}"), "مرحبا.");
+        assert_eq!(logical_order("Hello world 42"), "Hello world 42");
+        // Mostly Latin text keeps its order around an RTL word
+        assert_eq!(logical_order("see םולש here"), "see םולש here");
     }
 
     #[test]
-    fn test_analyze_whitespace_normal() {
-        let text = "Hello World Test String";
-        let score = analyze_whitespace(text);
-        assert!(score > 0.7, "Expected > 0.7, got {}", score);
-    }
+    fn test_baseline_angle_folds_word_orientations() {
+        let word = |corners| DetectedWord { corners };
+        // Rises 1px over 10px, listed from either corner
+        let rising = word([[0.0, 10.0], [10.0, 9.0], [11.0, 19.0], [1.0, 20.0]]);
+        let rotated = word([[10.0, 9.0], [11.0, 19.0], [1.0, 20.0], [0.0, 10.0]]);
+        let level = word([[0.0, 0.0], [10.0, 0.0], [10.0, 5.0], [0.0, 5.0]]);
 
-    #[test]
-    fn test_detect_repetition_none() {
-        assert_eq!(detect_repetition("Hello World"), 1.0);
+        let expected = (0.1f32).atan().to_degrees();
+        assert!((baseline_angle(&[rising]) - expected).abs() < 1e-4);
+        assert!((baseline_angle(&[rotated]) - expected).abs() < 1e-4);
+        assert!((baseline_angle(&[rising, level]) - expected / 2.0).abs() < 1e-4);
+        assert_eq!(baseline_angle(&[]), 0.0);
     }
 
     #[test]
-    fn test_detect_repetition_some() {
-        let score = detect_repetition("Hellooooo World");
-        assert!(score < 1.0, "Expected < 1.0, got {}", score);
+    fn test_polygon_starts_at_top_left() {
+        use ocrs::TextChar;
+        use rten_imageproc::Rect;
+
+        let chars = "abc"
+            .chars()
+            .enumerate()
+            .map(|(i, char)| TextChar {
+                char,
+                rect: Rect::from_tlbr(10, i as i32 * 10, 30, i as i32 * 10 + 10),
+            })
+            .collect();
+        let line = TextLine::new(chars);
+        assert_eq!(
+            polygon(&line),
+            [[0.0, 10.0], [30.0, 10.0], [30.0, 30.0], [0.0, 30.0]]
+        );
     }
 }
@@ -0,0 +1,777 @@
+//! PDF image extraction shared by the engines
+//!
+//! Scanned PDFs are OCRed from the images they embed. Both engines find and
+//! decode them here, then recognize each one like an uploaded image.
+
+use crate::error::OcrError;
+use crate::pdf_text_layer;
+use image::{DynamicImage, ImageFormat};
+use lopdf::{Object, Stream};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Check if a file is a PDF by reading its magic bytes
+pub fn is_pdf(path: &Path) -> Result<bool, OcrError> {
+    // Check file extension first
+    if let Some(ext) = path.extension() {
+        if ext.to_string_lossy().to_lowercase() == "pdf" {
+            return Ok(true);
+        }
+    }
+
+    // Also check magic bytes (%PDF-)
+    let mut file = File::open(path)
+        .map_err(|e| OcrError::ProcessingError(format!("Failed to open file: {}", e)))?;
+
+    let mut magic = [0u8; 5];
+    if file.read_exact(&mut magic).is_ok() {
+        return Ok(&magic == b"%PDF-");
+    }
+
+    Ok(false)
+}
+
+/// Extract images from a PDF using lopdf, only those the first page draws
+/// if `first_page_only` is set
+///
+/// Images are listed by [`pdf_text_layer::images`], and one that can't be
+/// decoded is None, so its place in the list still matches the text layer.
+pub fn extract_images(
+    path: &Path,
+    first_page_only: bool,
+) -> Result<Vec<Option<DynamicImage>>, OcrError> {
+    use lopdf::Document;
+
+    let doc = Document::load(path)
+        .map_err(|e| OcrError::ProcessingError(format!("Failed to load PDF: {}", e)))?;
+
+    let images = pdf_text_layer::images(&doc, first_page_only)
+        .into_iter()
+        .map(|object_id| {
+            let stream = doc
+                .get_object(object_id)
+                .and_then(Object::as_stream)
+                .map_err(|e| OcrError::ProcessingError(e.to_string()));
+            match stream.and_then(|stream| extract_image_from_stream(&doc, stream)) {
+                Ok(img) => Some(img),
+                Err(e) => {
+                    tracing::warn!("Failed to extract image from object {:?}: {}", object_id, e);
+                    None
+                }
+            }
+        })
+        .collect();
+
+    Ok(images)
+}
+
+/// Error for an image [`extract_images`] couldn't decode, reported in its
+/// place among the pages
+pub fn undecodable_image() -> OcrError {
+    OcrError::ProcessingError("the image could not be decoded".to_string())
+}
+
+/// Extract an image from a PDF stream
+fn extract_image_from_stream(
+    doc: &lopdf::Document,
+    stream: &lopdf::Stream,
+) -> Result<DynamicImage, OcrError> {
+    // JPEG and fax images are stored encoded rather than as raw samples
+    if let Some(image) = decode_encoded_image(doc, stream) {
+        return image;
+    }
+
+    // Get image dimensions
+    let width = stream
+        .dict
+        .get(b"Width")
+        .ok()
+        .and_then(|w| w.as_i64().ok())
+        .ok_or_else(|| OcrError::ProcessingError("Missing image width".to_string()))?
+        as u32;
+
+    let height = stream
+        .dict
+        .get(b"Height")
+        .ok()
+        .and_then(|h| h.as_i64().ok())
+        .ok_or_else(|| OcrError::ProcessingError("Missing image height".to_string()))?
+        as u32;
+
+    // Get the image data (decompressed)
+    let data = stream
+        .decompressed_content()
+        .map_err(|e| OcrError::ProcessingError(format!("Failed to decompress image: {}", e)))?;
+
+    // Get color space - handle both direct names and indirect references
+    let color_space = get_color_space(doc, stream);
+
+    // Get bits per component
+    let bits_per_component = stream
+        .dict
+        .get(b"BitsPerComponent")
+        .ok()
+        .and_then(|b| b.as_i64().ok())
+        .unwrap_or(8) as u8;
+
+    tracing::debug!(
+        "PDF image: {}x{}, {} bits, color_space={}, data_len={}",
+        width,
+        height,
+        bits_per_component,
+        color_space,
+        data.len()
+    );
+
+    // Handle different color spaces
+    match color_space.as_str() {
+        "DeviceGray" => {
+            if bits_per_component == 8 && data.len() >= (width * height) as usize {
+                let img = image::GrayImage::from_raw(width, height, data).ok_or_else(|| {
+                    OcrError::ProcessingError("Invalid grayscale image data".to_string())
+                })?;
+                Ok(DynamicImage::ImageLuma8(img))
+            } else {
+                Err(OcrError::ProcessingError(format!(
+                    "Unsupported grayscale format: {} bits, data_len={}, expected={}",
+                    bits_per_component,
+                    data.len(),
+                    width * height
+                )))
+            }
+        }
+        "DeviceRGB" | "ICCBased" => {
+            // ICCBased with 3 components is typically RGB
+            if bits_per_component == 8 && data.len() >= (width * height * 3) as usize {
+                let img = image::RgbImage::from_raw(width, height, data).ok_or_else(|| {
+                    OcrError::ProcessingError("Invalid RGB image data".to_string())
+                })?;
+                Ok(DynamicImage::ImageRgb8(img))
+            } else {
+                Err(OcrError::ProcessingError(format!(
+                    "Unsupported RGB format: {} bits, data_len={}, expected={}",
+                    bits_per_component,
+                    data.len(),
+                    width * height * 3
+                )))
+            }
+        }
+        "DeviceCMYK" => {
+            // Convert CMYK to RGB
+            if bits_per_component == 8 && data.len() >= (width * height * 4) as usize {
+                let mut rgb_data = Vec::with_capacity((width * height * 3) as usize);
+                for chunk in data.chunks_exact(4) {
+                    rgb_data.extend(cmyk_to_rgb(chunk));
+                }
+                let img = image::RgbImage::from_raw(width, height, rgb_data).ok_or_else(|| {
+                    OcrError::ProcessingError("Invalid CMYK->RGB conversion".to_string())
+                })?;
+                Ok(DynamicImage::ImageRgb8(img))
+            } else {
+                Err(OcrError::ProcessingError(format!(
+                    "Unsupported CMYK format: {} bits, data_len={}, expected={}",
+                    bits_per_component,
+                    data.len(),
+                    width * height * 4
+                )))
+            }
+        }
+        "Indexed" => decode_indexed(doc, stream, width, height, bits_per_component, &data),
+        _ => Err(OcrError::ProcessingError(format!(
+            "Unsupported color space: {}",
+            color_space
+        ))),
+    }
+}
+
+/// Convert a CMYK color to RGB
+fn cmyk_to_rgb(cmyk: &[u8]) -> [u8; 3] {
+    let k = 1.0 - cmyk[3] as f32 / 255.0;
+    let channel = |value: u8| ((1.0 - value as f32 / 255.0) * k * 255.0) as u8;
+    [channel(cmyk[0]), channel(cmyk[1]), channel(cmyk[2])]
+}
+
+/// Decode an image whose samples are indexes into a palette, from a
+/// `[/Indexed base hival lookup]` color space, as RGB
+fn decode_indexed(
+    doc: &lopdf::Document,
+    stream: &lopdf::Stream,
+    width: u32,
+    height: u32,
+    bits_per_component: u8,
+    data: &[u8],
+) -> Result<DynamicImage, OcrError> {
+    let invalid =
+        |reason: &str| OcrError::ProcessingError(format!("Invalid Indexed image: {}", reason));
+
+    let color_space = stream
+        .dict
+        .get(b"ColorSpace")
+        .and_then(|color_space| doc.dereference(color_space))
+        .and_then(|(_, color_space)| color_space.as_array())
+        .map_err(|_| invalid("color space is not an array"))?;
+    let [_, base, _, lookup] = color_space.as_slice() else {
+        return Err(invalid("color space needs a base, hival and lookup table"));
+    };
+    let components = color_space_components(doc, base).ok_or_else(|| {
+        OcrError::ProcessingError("Unsupported Indexed base color space".to_string())
+    })?;
+    let lookup = match doc.dereference(lookup) {
+        Ok((_, Object::String(bytes, _))) => bytes.clone(),
+        Ok((_, Object::Stream(lookup))) => lookup.get_plain_content().map_err(|e| {
+            OcrError::ProcessingError(format!("Failed to decompress palette: {}", e))
+        })?,
+        _ => return Err(invalid("lookup table is not a string or stream")),
+    };
+
+    // Entries past hival are never indexed by valid data, so the table's
+    // length is enough
+    let palette: Vec<[u8; 3]> = lookup
+        .chunks_exact(components)
+        .map(|entry| match components {
+            1 => [entry[0]; 3],
+            3 => [entry[0], entry[1], entry[2]],
+            _ => cmyk_to_rgb(entry),
+        })
+        .collect();
+    if palette.is_empty() {
+        return Err(invalid("lookup table is empty"));
+    }
+
+    let bits = bits_per_component as usize;
+    // Rows start on a byte boundary
+    let row_bytes = (width as usize * bits).div_ceil(8);
+    if !matches!(bits, 1 | 2 | 4 | 8) || data.len() < row_bytes * height as usize {
+        return Err(OcrError::ProcessingError(format!(
+            "Unsupported Indexed format: {} bits, data_len={}, expected={}",
+            bits_per_component,
+            data.len(),
+            row_bytes * height as usize
+        )));
+    }
+
+    let mask = (1u16 << bits) as usize - 1;
+    let mut rgb_data = Vec::with_capacity(width as usize * height as usize * 3);
+    for row in data.chunks_exact(row_bytes).take(height as usize) {
+        for x in 0..width as usize {
+            let bit = x * bits;
+            let index = (row[bit / 8] as usize >> (8 - bits - bit % 8)) & mask;
+            rgb_data.extend(palette[index.min(palette.len() - 1)]);
+        }
+    }
+    let img = image::RgbImage::from_raw(width, height, rgb_data)
+        .ok_or_else(|| invalid("palette conversion failed"))?;
+    Ok(DynamicImage::ImageRgb8(img))
+}
+
+/// Number of color components of a base color space for an Indexed image
+fn color_space_components(doc: &lopdf::Document, color_space: &Object) -> Option<usize> {
+    let (_, color_space) = doc.dereference(color_space).ok()?;
+    if let Ok(name) = color_space.as_name() {
+        return match name {
+            b"DeviceGray" | b"CalGray" => Some(1),
+            b"DeviceRGB" | b"CalRGB" => Some(3),
+            b"DeviceCMYK" => Some(4),
+            _ => None,
+        };
+    }
+
+    // [/ICCBased profile] gives its number of components in the profile
+    let array = color_space.as_array().ok()?;
+    match array.first()?.as_name().ok()? {
+        b"ICCBased" => {
+            let (_, profile) = doc.dereference(array.get(1)?).ok()?;
+            let components = profile
+                .as_stream()
+                .ok()?
+                .dict
+                .get(b"N")
+                .ok()?
+                .as_i64()
+                .ok()?;
+            matches!(components, 1 | 3 | 4).then_some(components as usize)
+        }
+        b"CalGray" => Some(1),
+        b"CalRGB" => Some(3),
+        _ => None,
+    }
+}
+
+/// Get the color space name from a PDF stream, resolving indirect references
+fn get_color_space(doc: &lopdf::Document, stream: &lopdf::Stream) -> String {
+    let cs_obj = match stream.dict.get(b"ColorSpace") {
+        Ok(obj) => obj,
+        Err(_) => return "DeviceRGB".to_string(),
+    };
+
+    // Handle direct name
+    if let Ok(name) = cs_obj.as_name() {
+        return String::from_utf8_lossy(name).to_string();
+    }
+
+    // Handle indirect reference
+    if let Ok(reference) = cs_obj.as_reference() {
+        if let Ok(resolved) = doc.get_object(reference) {
+            // Could be a name
+            if let Ok(name) = resolved.as_name() {
+                return String::from_utf8_lossy(name).to_string();
+            }
+            // Could be an array like [/ICCBased ref]
+            if let Ok(array) = resolved.as_array() {
+                if let Some(first) = array.first() {
+                    if let Ok(name) = first.as_name() {
+                        return String::from_utf8_lossy(name).to_string();
+                    }
+                }
+            }
+        }
+    }
+
+    // Handle array directly (like [/ICCBased ref])
+    if let Ok(array) = cs_obj.as_array() {
+        if let Some(first) = array.first() {
+            if let Ok(name) = first.as_name() {
+                return String::from_utf8_lossy(name).to_string();
+            }
+        }
+    }
+
+    "DeviceRGB".to_string()
+}
+
+/// Decode an image stream whose last filter is an image codec lopdf can't
+/// undo: JPEG (`/DCTDecode`) or CCITT fax (`/CCITTFaxDecode`), also when
+/// wrapped in other filters such as `/FlateDecode`
+///
+/// Returns None for streams of raw samples, which are decoded from their
+/// color space instead.
+fn decode_encoded_image(
+    doc: &lopdf::Document,
+    stream: &Stream,
+) -> Option<Result<DynamicImage, OcrError>> {
+    let filters = stream.filters().unwrap_or_default();
+    let (last, outer) = filters.split_last()?;
+    if !matches!(*last, b"DCTDecode" | b"CCITTFaxDecode") {
+        return None;
+    }
+
+    let data = if outer.is_empty() {
+        Ok(stream.content.clone())
+    } else {
+        // Undo only the filters around the encoded image
+        let mut wrapped = stream.clone();
+        let outer = outer.iter().map(|name| Object::Name(name.to_vec()));
+        wrapped.dict.set("Filter", Object::Array(outer.collect()));
+        wrapped
+            .decompressed_content()
+            .map_err(|e| OcrError::ProcessingError(format!("Failed to decompress image: {}", e)))
+    };
+
+    Some(data.and_then(|data| match *last {
+        b"DCTDecode" => {
+            image::load_from_memory_with_format(&data, ImageFormat::Jpeg).map_err(|e| {
+                OcrError::ProcessingError(format!("Failed to decode JPEG image: {}", e))
+            })
+        }
+        _ => decode_ccitt(doc, stream, &data, filters.len() - 1),
+    }))
+}
+
+/// Decode CCITT Group 3 or 4 fax data into a black-and-white image
+///
+/// `filter_index` is the position of `/CCITTFaxDecode` in the stream's
+/// filters, to find its decode parameters.
+fn decode_ccitt(
+    doc: &lopdf::Document,
+    stream: &Stream,
+    data: &[u8],
+    filter_index: usize,
+) -> Result<DynamicImage, OcrError> {
+    // Parameters are a dictionary, or an array with one per filter
+    let params = stream
+        .dict
+        .get(b"DecodeParms")
+        .and_then(|params| doc.dereference(params))
+        .map(|(_, params)| match params.as_array() {
+            Ok(array) => array
+                .get(filter_index)
+                .and_then(|params| doc.dereference(params).ok())
+                .map_or(params, |(_, params)| params),
+            Err(_) => params,
+        })
+        .and_then(Object::as_dict)
+        .ok();
+    let param = |key: &[u8]| params.and_then(|params| params.get(key).ok());
+    let dimension = |param: Option<&Object>, key: &[u8]| {
+        param
+            .or_else(|| stream.dict.get(key).ok())
+            .and_then(|value| value.as_i64().ok())
+            .filter(|value| *value > 0)
+    };
+
+    let k = param(b"K").and_then(|k| k.as_i64().ok()).unwrap_or(0);
+    let black_is_1 = param(b"BlackIs1")
+        .and_then(|black| black.as_bool().ok())
+        .unwrap_or(false);
+    // The spec defaults to 1728 columns, the width of a fax page
+    let width = dimension(param(b"Columns"), b"Width").unwrap_or(1728) as u32;
+    let height = dimension(param(b"Rows"), b"Height").map(|rows| rows as u32);
+
+    let mut lines = Vec::new();
+    let decoded = if k < 0 {
+        fax::decoder::decode_g4(data.iter().copied(), width, height, |line| {
+            lines.push(line.to_vec())
+        })
+    } else if k == 0 {
+        fax::decoder::decode_g3(data.iter().copied(), |line| lines.push(line.to_vec()))
+    } else {
+        return Err(OcrError::ProcessingError(
+            "Unsupported CCITT encoding: mixed 1D/2D Group 3 (K > 0)".to_string(),
+        ));
+    };
+    if decoded.is_none() && lines.is_empty() {
+        return Err(OcrError::ProcessingError(format!(
+            "Failed to decode CCITT Group {} image",
+            if k < 0 { 4 } else { 3 }
+        )));
+    }
+    let height = height.unwrap_or(lines.len() as u32);
+    lines.resize(height as usize, Vec::new());
+
+    // Black pixels are 1 samples with /BlackIs1, otherwise 0, and a /Decode
+    // array of [1 0] inverts them. A 0 sample is black in DeviceGray, as is
+    // the fill color image masks are usually painted with.
+    let inverted = stream
+        .dict
+        .get(b"Decode")
+        .and_then(Object::as_array)
+        .ok()
+        .and_then(|decode| decode.first())
+        .and_then(|first| first.as_float().ok())
+        .is_some_and(|first| first >= 1.0);
+    let black = if black_is_1 != inverted { 255 } else { 0 };
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for line in &lines {
+        pixels.extend(fax::decoder::pels(line, width).map(|color| match color {
+            fax::Color::Black => black,
+            fax::Color::White => 255 - black,
+        }));
+    }
+    let image = image::GrayImage::from_raw(width, height, pixels)
+        .ok_or_else(|| OcrError::ProcessingError("Invalid CCITT image data".to_string()))?;
+    Ok(DynamicImage::ImageLuma8(image))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Document};
+    use std::io::Cursor;
+
+    /// A PDF whose second page's image comes first in object order
+    fn two_page_pdf() -> tempfile::NamedTempFile {
+        let mut doc = lopdf::Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let page = |doc: &mut lopdf::Document, width: i64| {
+            let mut image = Stream::new(
+                dictionary! {
+                    "Type" => "XObject",
+                    "Subtype" => "Image",
+                    "Width" => width,
+                    "Height" => 10,
+                    "ColorSpace" => "DeviceGray",
+                    "BitsPerComponent" => 8,
+                },
+                vec![255; width as usize * 10],
+            );
+            // Uncompressed streams can't be decompressed
+            image.compress().unwrap();
+            let image_id = doc.add_object(image);
+            doc.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "Resources" => dictionary! {
+                    "XObject" => dictionary! { "Im0" => image_id },
+                },
+            })
+        };
+        let second = page(&mut doc, 30);
+        let first = page(&mut doc, 20);
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![first.into(), second.into()],
+                "Count" => 2,
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        doc.save_to(&mut file).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_extract_images_from_first_page_only() {
+        let pdf = two_page_pdf();
+
+        let widths = |first_page_only| {
+            extract_images(pdf.path(), first_page_only)
+                .unwrap()
+                .iter()
+                .map(|image| image.as_ref().unwrap().width())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(widths(false), vec![20, 30]);
+        assert_eq!(widths(true), vec![20]);
+    }
+
+    #[test]
+    fn test_undecodable_image_keeps_its_place() {
+        let mut doc = Document::load(two_page_pdf().path()).unwrap();
+        let broken = pdf_text_layer::images(&doc, false)[0];
+        doc.get_object_mut(broken)
+            .and_then(Object::as_stream_mut)
+            .unwrap()
+            .dict
+            .set("ColorSpace", "Lab");
+        let mut pdf = tempfile::NamedTempFile::new().unwrap();
+        doc.save_to(&mut pdf).unwrap();
+
+        // The text layer numbers the second image 2 as well
+        let images = extract_images(pdf.path(), false).unwrap();
+        assert_eq!(images.len(), 2);
+        assert!(images[0].is_none());
+        assert!(images[1].is_some());
+    }
+
+    #[test]
+    fn test_extract_jpeg_images_from_pdf() {
+        let path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/scanned_document_jpeg.pdf");
+
+        let images = extract_images(&path, false).unwrap();
+        assert_eq!(images.len(), 1);
+        let image = images[0].as_ref().unwrap();
+        assert_eq!((image.width(), image.height()), (400, 100));
+    }
+
+    #[test]
+    fn test_extract_ccitt_images_from_pdf() {
+        let path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/scanned_document_ccitt.pdf");
+
+        let images = extract_images(&path, false).unwrap();
+        assert_eq!(images.len(), 1);
+        let image = images[0].as_ref().unwrap().to_luma8();
+        assert_eq!(image.dimensions(), (400, 100));
+        // Black text on a white page
+        let black = image.pixels().filter(|pixel| pixel.0[0] == 0).count();
+        assert!(
+            black > 0 && black < image.len() / 4,
+            "{} black pixels",
+            black
+        );
+    }
+
+    fn jpeg() -> Vec<u8> {
+        // Plain enough for its compressed data to repeat, so that deflating
+        // it saves space and `Stream::compress` applies
+        let image = image::GrayImage::from_pixel(400, 100, image::Luma([200]));
+        let mut data = Cursor::new(Vec::new());
+        image.write_to(&mut data, ImageFormat::Jpeg).unwrap();
+        data.into_inner()
+    }
+
+    fn image_dict() -> lopdf::Dictionary {
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => 400,
+            "Height" => 100,
+            "ColorSpace" => "DeviceGray",
+            "BitsPerComponent" => 8,
+        }
+    }
+
+    #[test]
+    fn test_decode_jpeg() {
+        let mut dict = image_dict();
+        dict.set("Filter", "DCTDecode");
+        let image = decode_encoded_image(&Document::new(), &Stream::new(dict, jpeg()))
+            .unwrap()
+            .unwrap();
+        assert_eq!((image.width(), image.height()), (400, 100));
+    }
+
+    #[test]
+    fn test_decode_flate_wrapped_jpeg() {
+        let mut stream = Stream::new(image_dict(), jpeg());
+        stream.compress().unwrap();
+        stream.dict.set(
+            "Filter",
+            vec![Object::from("FlateDecode"), Object::from("DCTDecode")],
+        );
+        let image = decode_encoded_image(&Document::new(), &stream)
+            .unwrap()
+            .unwrap();
+        assert_eq!((image.width(), image.height()), (400, 100));
+    }
+
+    #[test]
+    fn test_raw_samples_are_not_jpeg() {
+        let mut stream = Stream::new(image_dict(), vec![0; 400 * 100]);
+        assert!(decode_encoded_image(&Document::new(), &stream).is_none());
+        stream.compress().unwrap();
+        assert!(decode_encoded_image(&Document::new(), &stream).is_none());
+    }
+
+    /// Group 4 data for a 16x4 image with a black square in its top left
+    fn ccitt_g4() -> Vec<u8> {
+        let mut encoder = fax::encoder::Encoder::new(fax::VecWriter::new());
+        for y in 0..4 {
+            let pels = (0..16).map(|x| {
+                if x < 4 && y < 2 {
+                    fax::Color::Black
+                } else {
+                    fax::Color::White
+                }
+            });
+            encoder.encode_line(pels, 16).unwrap();
+        }
+        encoder.finish().unwrap().finish()
+    }
+
+    fn ccitt_stream(params: lopdf::Dictionary) -> Stream {
+        let dict = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => 16,
+            "Height" => 4,
+            "ColorSpace" => "DeviceGray",
+            "BitsPerComponent" => 1,
+            "Filter" => "CCITTFaxDecode",
+            "DecodeParms" => params,
+        };
+        Stream::new(dict, ccitt_g4())
+    }
+
+    #[test]
+    fn test_decode_ccitt_group_4() {
+        let stream = ccitt_stream(dictionary! { "K" => -1, "Columns" => 16, "Rows" => 4 });
+        let image = decode_encoded_image(&Document::new(), &stream)
+            .unwrap()
+            .unwrap()
+            .to_luma8();
+
+        assert_eq!(image.dimensions(), (16, 4));
+        assert_eq!(image.get_pixel(0, 0).0, [0]);
+        assert_eq!(image.get_pixel(3, 1).0, [0]);
+        assert_eq!(image.get_pixel(4, 0).0, [255]);
+        assert_eq!(image.get_pixel(0, 2).0, [255]);
+    }
+
+    #[test]
+    fn test_decode_ccitt_black_is_1() {
+        // Black pixels become 1 samples, which DeviceGray shows as white
+        let stream = ccitt_stream(dictionary! { "K" => -1, "Columns" => 16, "BlackIs1" => true });
+        let image = decode_encoded_image(&Document::new(), &stream)
+            .unwrap()
+            .unwrap()
+            .to_luma8();
+        assert_eq!(image.get_pixel(0, 0).0, [255]);
+        assert_eq!(image.get_pixel(0, 2).0, [0]);
+
+        // Unless /Decode inverts them back
+        let mut stream = stream;
+        stream
+            .dict
+            .set("Decode", vec![Object::from(1), Object::from(0)]);
+        let image = decode_encoded_image(&Document::new(), &stream)
+            .unwrap()
+            .unwrap()
+            .to_luma8();
+        assert_eq!(image.get_pixel(0, 0).0, [0]);
+    }
+
+    #[test]
+    fn test_decode_ccitt_rejects_mixed_group_3() {
+        let stream = ccitt_stream(dictionary! { "K" => 1, "Columns" => 16 });
+        assert!(decode_encoded_image(&Document::new(), &stream)
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_extract_indexed_image() {
+        // Red and blue stripes in an 8-bit image, big enough to compress
+        let dict = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => 64,
+            "Height" => 2,
+            "ColorSpace" => vec![
+                Object::from("Indexed"),
+                Object::from("DeviceRGB"),
+                Object::from(1),
+                Object::string_literal(vec![255, 0, 0, 0, 0, 255]),
+            ],
+            "BitsPerComponent" => 8,
+        };
+        let mut stream = Stream::new(dict, [[0, 1].repeat(32), [1, 0].repeat(32)].concat());
+        stream.compress().unwrap();
+        let image = extract_image_from_stream(&Document::new(), &stream)
+            .unwrap()
+            .to_rgb8();
+
+        assert_eq!(image.dimensions(), (64, 2));
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0]);
+        assert_eq!(image.get_pixel(1, 0).0, [0, 0, 255]);
+        assert_eq!(image.get_pixel(0, 1).0, [0, 0, 255]);
+    }
+
+    #[test]
+    fn test_extract_indexed_image_with_references() {
+        // A gray palette in a stream, with 4-bit samples packed two per byte
+        // and each 63-pixel row padded to a whole byte
+        let mut doc = Document::new();
+        let base = doc.add_object(Object::from("DeviceGray"));
+        let lookup = doc.add_object(Stream::new(dictionary! {}, vec![0, 128, 255]));
+        let color_space = doc.add_object(vec![
+            Object::from("Indexed"),
+            base.into(),
+            Object::from(2),
+            lookup.into(),
+        ]);
+        let dict = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => 63,
+            "Height" => 4,
+            "ColorSpace" => color_space,
+            "BitsPerComponent" => 4,
+        };
+        let mut data = vec![0; 32 * 4];
+        data[0] = 0x21;
+        // The last pixel of the first row, followed by padding
+        data[31] = 0x10;
+        data[32] = 0x20;
+        let mut stream = Stream::new(dict, data);
+        stream.compress().unwrap();
+        let image = extract_image_from_stream(&doc, &stream).unwrap().to_rgb8();
+
+        assert_eq!(image.dimensions(), (63, 4));
+        assert_eq!(image.get_pixel(0, 0).0, [255; 3]);
+        assert_eq!(image.get_pixel(1, 0).0, [128; 3]);
+        assert_eq!(image.get_pixel(2, 0).0, [0; 3]);
+        assert_eq!(image.get_pixel(62, 0).0, [128; 3]);
+        assert_eq!(image.get_pixel(0, 1).0, [255; 3]);
+    }
+}
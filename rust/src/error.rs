@@ -1,3 +1,4 @@
+use crate::server::SCHEMA_VERSION;
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
@@ -18,7 +19,6 @@ pub enum OcrError {
     PreprocessingError(String),
 
     #[error("Unsupported image format: {0}")]
-    #[allow(dead_code)]
     UnsupportedFormat(String),
 
     #[error("Image too large: {size} bytes (max: {max} bytes)")]
@@ -30,19 +30,51 @@ pub enum OcrError {
     #[error("Invalid request: {0}")]
     InvalidRequest(String),
 
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Job expired: {0}")]
+    JobExpired(String),
+
+    #[error("Confidence {confidence} is below the required {required}")]
+    LowConfidence {
+        confidence: f32,
+        required: f32,
+        /// Text that was recognized, returned so the client can inspect it
+        text: String,
+    },
+
+    #[error("Server busy: {0}")]
+    ServerBusy(String),
+
+    #[error("Processing timed out: {0}")]
+    Timeout(String),
+
     #[error("Internal error: {0}")]
     Internal(String),
 }
 
 #[derive(Serialize)]
 pub struct ErrorResponse {
+    /// Always [`SCHEMA_VERSION`]
+    pub schema_version: u32,
     pub error: String,
     pub code: String,
+    /// Recognized text, for LOW_CONFIDENCE errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Overall confidence, for LOW_CONFIDENCE errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
 }
 
-impl IntoResponse for OcrError {
-    fn into_response(self) -> Response {
-        let (status, code) = match &self {
+impl OcrError {
+    /// HTTP status and machine-readable code for this error
+    pub fn status_and_code(&self) -> (StatusCode, &'static str) {
+        match self {
             OcrError::InitializationError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "INIT_ERROR"),
             OcrError::ProcessingError(_) => (StatusCode::INTERNAL_SERVER_ERROR, "PROCESSING_ERROR"),
             OcrError::PreprocessingError(_) => {
@@ -52,14 +84,38 @@ impl IntoResponse for OcrError {
             OcrError::ImageTooLarge { .. } => (StatusCode::PAYLOAD_TOO_LARGE, "IMAGE_TOO_LARGE"),
             OcrError::MissingFile => (StatusCode::BAD_REQUEST, "MISSING_FILE"),
             OcrError::InvalidRequest(_) => (StatusCode::BAD_REQUEST, "INVALID_REQUEST"),
+            OcrError::Unauthorized(_) => (StatusCode::UNAUTHORIZED, "UNAUTHORIZED"),
+            OcrError::NotFound(_) => (StatusCode::NOT_FOUND, "NOT_FOUND"),
+            OcrError::JobExpired(_) => (StatusCode::GONE, "JOB_EXPIRED"),
+            OcrError::LowConfidence { .. } => (StatusCode::UNPROCESSABLE_ENTITY, "LOW_CONFIDENCE"),
+            OcrError::ServerBusy(_) => (StatusCode::SERVICE_UNAVAILABLE, "SERVER_BUSY"),
+            OcrError::Timeout(_) => (StatusCode::GATEWAY_TIMEOUT, "TIMEOUT"),
             OcrError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_ERROR"),
-        };
+        }
+    }
+}
 
-        let body = Json(ErrorResponse {
-            error: self.to_string(),
-            code: code.to_string(),
-        });
+impl From<&OcrError> for ErrorResponse {
+    fn from(error: &OcrError) -> Self {
+        let (text, confidence) = match error {
+            OcrError::LowConfidence {
+                confidence, text, ..
+            } => (Some(text.clone()), Some(*confidence)),
+            _ => (None, None),
+        };
+        ErrorResponse {
+            schema_version: SCHEMA_VERSION,
+            error: error.to_string(),
+            code: error.status_and_code().1.to_string(),
+            text,
+            confidence,
+        }
+    }
+}
 
-        (status, body).into_response()
+impl IntoResponse for OcrError {
+    fn into_response(self) -> Response {
+        let (status, _) = self.status_and_code();
+        (status, Json(ErrorResponse::from(&self))).into_response()
     }
 }
@@ -0,0 +1,196 @@
+//! Upload format detection from magic bytes
+//!
+//! Clients often send the wrong content type, such as
+//! `application/octet-stream` for everything, so uploads are routed by what
+//! their first bytes say they are rather than by what the request claims.
+
+/// How far into a file a PDF header is looked for; viewers accept junk
+/// before `%PDF-` within the first kilobyte
+const PDF_HEADER_WINDOW: usize = 1024;
+
+/// A file format recognized from its contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectedFormat {
+    Pdf,
+    Png,
+    Jpeg,
+    Gif,
+    Bmp,
+    WebP,
+    Tiff,
+}
+
+impl DetectedFormat {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Pdf => "application/pdf",
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Gif => "image/gif",
+            Self::Bmp => "image/bmp",
+            Self::WebP => "image/webp",
+            Self::Tiff => "image/tiff",
+        }
+    }
+}
+
+/// Recognize a file from its magic bytes, None if it isn't a supported format
+pub fn detect_format(data: &[u8]) -> Option<DetectedFormat> {
+    let format = if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        DetectedFormat::Png
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        DetectedFormat::Jpeg
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        DetectedFormat::Gif
+    } else if data.len() >= 14 && data.starts_with(b"BM") && data[6..10] == [0; 4] {
+        DetectedFormat::Bmp
+    } else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        DetectedFormat::WebP
+    } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        DetectedFormat::Tiff
+    } else if has_pdf_header(data) {
+        DetectedFormat::Pdf
+    } else {
+        return None;
+    };
+    Some(format)
+}
+
+fn has_pdf_header(data: &[u8]) -> bool {
+    data[..data.len().min(PDF_HEADER_WINDOW)]
+        .windows(5)
+        .any(|window| window == b"%PDF-")
+}
+
+/// The byte order and field sizes of a TIFF or BigTIFF, for walking its
+/// image file directories
+pub struct TiffHeader<'a> {
+    data: &'a [u8],
+    pub little_endian: bool,
+    /// Size of an offset: 4 bytes in a TIFF, 8 in a BigTIFF
+    pub pointer_size: usize,
+    /// Size of the entry count that starts a directory
+    pub count_size: usize,
+    /// Size of each directory entry
+    pub entry_size: u64,
+}
+
+impl<'a> TiffHeader<'a> {
+    /// Read the header of a TIFF or BigTIFF, None for other data
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let little_endian = match data.get(..2)? {
+            b"II" => true,
+            b"MM" => false,
+            _ => return None,
+        };
+        let mut header = Self {
+            data,
+            little_endian,
+            pointer_size: 4,
+            count_size: 2,
+            entry_size: 12,
+        };
+        match header.read(2, 2)? {
+            42 => {}
+            43 => {
+                header.pointer_size = 8;
+                header.count_size = 8;
+                header.entry_size = 20;
+            }
+            _ => return None,
+        }
+        Some(header)
+    }
+
+    /// The unsigned integer `len` bytes long at `at`, in the file's byte
+    /// order, None past the end
+    pub fn read(&self, at: u64, len: usize) -> Option<u64> {
+        let bytes = self.data.get(usize::try_from(at).ok()?..)?.get(..len)?;
+        Some(if self.little_endian {
+            bytes.iter().rev().fold(0u64, |n, &b| n << 8 | b as u64)
+        } else {
+            bytes.iter().fold(0u64, |n, &b| n << 8 | b as u64)
+        })
+    }
+
+    /// Where the header points at the first directory
+    pub fn first_directory_pointer(&self) -> std::ops::Range<usize> {
+        self.pointer_size..self.pointer_size * 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageFormat, RgbImage};
+    use std::io::Cursor;
+
+    fn encode(format: ImageFormat) -> Vec<u8> {
+        let image = DynamicImage::ImageRgb8(RgbImage::new(16, 16));
+        let mut data = Vec::new();
+        image.write_to(&mut Cursor::new(&mut data), format).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_detects_images_encoded_by_the_image_crate() {
+        for (format, expected) in [
+            (ImageFormat::Png, DetectedFormat::Png),
+            (ImageFormat::Jpeg, DetectedFormat::Jpeg),
+            (ImageFormat::Gif, DetectedFormat::Gif),
+            (ImageFormat::Bmp, DetectedFormat::Bmp),
+            (ImageFormat::WebP, DetectedFormat::WebP),
+            (ImageFormat::Tiff, DetectedFormat::Tiff),
+        ] {
+            assert_eq!(
+                detect_format(&encode(format)),
+                Some(expected),
+                "{:?}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn test_detects_pdf_after_leading_junk() {
+        assert_eq!(detect_format(b"%PDF-1.7\n"), Some(DetectedFormat::Pdf));
+        assert_eq!(
+            detect_format(b"\r\n\xef\xbb\xbf%PDF-1.4\n"),
+            Some(DetectedFormat::Pdf)
+        );
+
+        let mut late = vec![b' '; PDF_HEADER_WINDOW];
+        late.extend_from_slice(b"%PDF-1.4\n");
+        assert_eq!(detect_format(&late), None);
+    }
+
+    #[test]
+    fn test_reads_tiff_headers() {
+        let tiff = encode(ImageFormat::Tiff);
+        let header = TiffHeader::parse(&tiff).unwrap();
+        assert!(header.little_endian);
+        assert_eq!(header.first_directory_pointer(), 4..8);
+        assert_eq!(header.read(2, 2), Some(42));
+        assert_eq!(header.read(tiff.len() as u64 - 1, 2), None);
+
+        // A big-endian BigTIFF header: 8-byte offsets, then the first one
+        let mut big = b"MM\0\x2b\0\x08\0\0".to_vec();
+        big.extend(16u64.to_be_bytes());
+        let header = TiffHeader::parse(&big).unwrap();
+        assert!(!header.little_endian);
+        assert_eq!((header.pointer_size, header.entry_size), (8, 20));
+        assert_eq!(header.read(8, 8), Some(16));
+
+        assert!(TiffHeader::parse(b"II\x2a").is_none());
+        assert!(TiffHeader::parse(&encode(ImageFormat::Png)).is_none());
+    }
+
+    #[test]
+    fn test_unknown_contents() {
+        assert_eq!(detect_format(b""), None);
+        assert_eq!(detect_format(b"hello world"), None);
+        assert_eq!(detect_format(b"PK\x03\x04zip"), None);
+        // Text that happens to start like a BMP file header
+        assert_eq!(detect_format(b"BMW service record"), None);
+    }
+}
@@ -0,0 +1,196 @@
+//! hOCR output
+//!
+//! Renders an OCR response as hOCR, the XHTML format Tesseract and many
+//! layout tools use: `ocr_page`, `ocr_carea`, `ocr_par`, `ocr_line` and
+//! `ocrx_word` elements with their bounding boxes in `title` attributes.
+//! Lines and paragraphs are rebuilt from the word boxes the same way as for
+//! PAGE-XML.
+
+use crate::page_xml::{escape, group_lines, group_regions, Rect};
+use crate::server::OcrResponse;
+use std::fmt::Write;
+
+/// Content type of hOCR responses
+pub const CONTENT_TYPE: &str = "text/html; charset=utf-8";
+
+/// Render a response's word boxes as an hOCR document for one image
+pub fn render(response: &OcrResponse, image_width: u32, image_height: u32) -> String {
+    let words = response
+        .boxes
+        .as_ref()
+        .map(|boxes| boxes.boxes.as_slice())
+        .unwrap_or_default();
+
+    let mut html = String::new();
+    html.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    html.push_str(
+        "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Transitional//EN\" \
+         \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd\">\n",
+    );
+    html.push_str("<html xmlns=\"http://www.w3.org/1999/xhtml\">\n");
+    html.push_str(" <head>\n");
+    html.push_str("  <title></title>\n");
+    html.push_str("  <meta http-equiv=\"Content-Type\" content=\"text/html;charset=utf-8\"/>\n");
+    let _ = writeln!(
+        html,
+        "  <meta name=\"ocr-system\" content=\"activestorage-ocr-server {} ({})\"/>",
+        env!("CARGO_PKG_VERSION"),
+        escape(&response.engine)
+    );
+    html.push_str(
+        "  <meta name=\"ocr-capabilities\" \
+         content=\"ocr_page ocr_carea ocr_par ocr_line ocrx_word\"/>\n",
+    );
+    html.push_str(" </head>\n");
+    html.push_str(" <body>\n");
+    let _ = writeln!(
+        html,
+        "  <div class='ocr_page' id='page_1' title='bbox 0 0 {} {}; ppageno 0'>",
+        image_width, image_height
+    );
+
+    // Tesseract numbers each kind of element through the whole page
+    let (mut line_number, mut word_number) = (0, 0);
+    for (r, region) in group_regions(group_lines(words)).iter().enumerate() {
+        let bounds = region
+            .iter()
+            .map(|line| line.bounds)
+            .reduce(Rect::union)
+            .expect("regions are never empty");
+        let _ = writeln!(
+            html,
+            "   <div class='ocr_carea' id='block_1_{}' title='{}'>",
+            r + 1,
+            bbox(bounds)
+        );
+        let _ = writeln!(
+            html,
+            "    <p class='ocr_par' id='par_1_{}' title='{}'>",
+            r + 1,
+            bbox(bounds)
+        );
+
+        for line in region {
+            line_number += 1;
+            let _ = writeln!(
+                html,
+                "     <span class='ocr_line' id='line_1_{}' title='{}'>",
+                line_number,
+                bbox(line.bounds)
+            );
+            for word in &line.words {
+                word_number += 1;
+                // hOCR word confidences are percentages
+                let confidence = word
+                    .confidence
+                    .map(|c| format!("; x_wconf {}", (c.clamp(0.0, 1.0) * 100.0).round()))
+                    .unwrap_or_default();
+                let _ = writeln!(
+                    html,
+                    "      <span class='ocrx_word' id='word_1_{}' title='{}{}'>{}</span>",
+                    word_number,
+                    bbox(Rect::of(word)),
+                    confidence,
+                    escape(&word.text)
+                );
+            }
+            html.push_str("     </span>\n");
+        }
+
+        html.push_str("    </p>\n");
+        html.push_str("   </div>\n");
+    }
+
+    html.push_str("  </div>\n");
+    html.push_str(" </body>\n");
+    html.push_str("</html>\n");
+    html
+}
+
+/// A rectangle as an hOCR `bbox x0 y0 x1 y1` property
+fn bbox(rect: Rect) -> String {
+    format!("bbox {} {} {} {}", rect.x, rect.y, rect.right, rect.bottom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{BoxesResponse, TextBoxResponse};
+
+    fn word(text: &str, x: u32, y: u32, confidence: Option<f32>) -> TextBoxResponse {
+        TextBoxResponse {
+            text: text.to_string(),
+            page: 1,
+            x,
+            y,
+            width: 50,
+            height: 20,
+            confidence,
+            polygon: None,
+        }
+    }
+
+    fn response(words: Vec<TextBoxResponse>) -> OcrResponse {
+        OcrResponse {
+            schema_version: 1,
+            text: String::new(),
+            raw_text: None,
+            truncated: None,
+            confidence: Some(0.9),
+            confidence_stats: None,
+            word_confidences: None,
+            processing_time_ms: 0,
+            warnings: Vec::new(),
+            engine: "ocrs".to_string(),
+            detected_format: "image/png".to_string(),
+            filename: None,
+            no_text_detected: false,
+            orientation_degrees: None,
+            preprocessing: None,
+            boxes: Some(BoxesResponse {
+                level: "word".to_string(),
+                boxes: words,
+            }),
+            lines: None,
+            regions: None,
+            pdf_verification: None,
+        }
+    }
+
+    #[test]
+    fn test_renders_lines_and_words_with_percent_confidence() {
+        let html = render(
+            &response(vec![
+                word("Fish", 10, 10, Some(0.914)),
+                word("&", 70, 12, None),
+                word("Chips", 10, 40, Some(0.5)),
+            ]),
+            200,
+            100,
+        );
+
+        assert!(
+            html.contains("<div class='ocr_page' id='page_1' title='bbox 0 0 200 100; ppageno 0'>")
+        );
+        assert!(html.contains("<p class='ocr_par' id='par_1_1' title='bbox 10 10 120 60'>"));
+        assert!(html.contains("<span class='ocr_line' id='line_1_1' title='bbox 10 10 120 32'>"));
+        assert!(html.contains(
+            "<span class='ocrx_word' id='word_1_1' title='bbox 10 10 60 30; x_wconf 91'>Fish</span>"
+        ));
+        // Words without a confidence leave it out
+        assert!(html.contains(
+            "<span class='ocrx_word' id='word_1_2' title='bbox 70 12 120 32'>&amp;</span>"
+        ));
+        assert!(html.contains("id='line_1_2'"));
+        assert!(html.contains("title='bbox 10 40 60 60; x_wconf 50'>Chips</span>"));
+    }
+
+    #[test]
+    fn test_renders_empty_page_without_words() {
+        let html = render(&response(Vec::new()), 200, 100);
+
+        assert!(html.contains("class='ocr_page'"));
+        assert!(!html.contains("class='ocr_line'"));
+        assert!(html.ends_with("</html>\n"));
+    }
+}
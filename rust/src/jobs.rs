@@ -0,0 +1,309 @@
+//! Background OCR jobs
+//!
+//! Long documents can take longer to OCR than a gateway lets a request run.
+//! `POST /ocr/jobs` answers right away with a job id, the OCR runs in the
+//! background, and clients poll `GET /ocr/jobs/:id` for the result. Jobs
+//! live in memory only and are forgotten `ttl` after they finish, or sooner
+//! when the store is full. Ids of forgotten jobs are remembered for a while
+//! so polling them reports that the job expired rather than that it never
+//! existed.
+
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Where a job is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    /// Waiting for a free OCR slot
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+/// A job as reported to clients
+#[derive(Debug, Clone, Serialize)]
+pub struct JobResponse {
+    pub job_id: String,
+    pub status: JobStatus,
+    /// The OCR response (only when done)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    /// The error response (only when failed)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<serde_json::Value>,
+}
+
+/// Most ids of forgotten jobs remembered, oldest dropped first
+const MAX_TOMBSTONES: usize = 10_000;
+
+/// What polling a job id found
+#[derive(Debug, Clone)]
+pub enum JobLookup {
+    Found(JobResponse),
+    /// The job finished and its result was forgotten
+    Expired,
+    Unknown,
+}
+
+#[derive(Debug)]
+struct Job {
+    response: JobResponse,
+    /// When the job finished, for expiry
+    finished: Option<Instant>,
+    /// When the finished job was last polled, for eviction
+    used: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct Jobs {
+    jobs: HashMap<String, Job>,
+    /// Ids of forgotten jobs, with their order for dropping the oldest
+    tombstones: HashSet<String>,
+    tombstone_order: VecDeque<String>,
+}
+
+impl Jobs {
+    fn forget(&mut self, id: &str) {
+        if self.jobs.remove(id).is_none() {
+            return;
+        }
+        if self.tombstone_order.len() == MAX_TOMBSTONES {
+            if let Some(oldest) = self.tombstone_order.pop_front() {
+                self.tombstones.remove(&oldest);
+            }
+        }
+        self.tombstones.insert(id.to_string());
+        self.tombstone_order.push_back(id.to_string());
+    }
+}
+
+/// Jobs by id
+#[derive(Debug)]
+pub struct JobStore {
+    jobs: Mutex<Jobs>,
+    /// Most jobs kept at once, finished or not
+    max_jobs: usize,
+    /// Keyed randomly at startup, so ids can't be guessed from earlier ones
+    ids: RandomState,
+    next: AtomicU64,
+}
+
+impl JobStore {
+    pub fn new(max_jobs: usize) -> Self {
+        Self {
+            jobs: Mutex::new(Jobs::default()),
+            max_jobs: max_jobs.max(1),
+            ids: RandomState::new(),
+            next: AtomicU64::new(0),
+        }
+    }
+
+    /// Add a pending job, returning it
+    ///
+    /// When the store is full, the finished job polled least recently is
+    /// forgotten to make room. Returns None if every job is still pending or
+    /// running.
+    pub fn create(&self) -> Option<JobResponse> {
+        let mut jobs = self.lock();
+        if jobs.jobs.len() >= self.max_jobs {
+            let oldest = jobs
+                .jobs
+                .iter()
+                .filter_map(|(id, job)| Some((job.used?, id)))
+                .min()
+                .map(|(_, id)| id.clone())?;
+            jobs.forget(&oldest);
+        }
+
+        let n = self.next.fetch_add(1, Ordering::Relaxed);
+        let id = format!(
+            "{:016x}{:016x}",
+            self.ids.hash_one((n, 0u8)),
+            self.ids.hash_one((n, 1u8))
+        );
+        let response = JobResponse {
+            job_id: id.clone(),
+            status: JobStatus::Pending,
+            result: None,
+            error: None,
+        };
+        jobs.jobs.insert(
+            id,
+            Job {
+                response: response.clone(),
+                finished: None,
+                used: None,
+            },
+        );
+        Some(response)
+    }
+
+    /// Mark a job as started
+    pub fn start(&self, id: &str) {
+        if let Some(job) = self.lock().jobs.get_mut(id) {
+            job.response.status = JobStatus::Running;
+        }
+    }
+
+    /// Record a job's OCR response
+    pub fn complete(&self, id: &str, result: serde_json::Value) {
+        self.finish(id, JobStatus::Done, Some(result), None);
+    }
+
+    /// Record a job's error response
+    pub fn fail(&self, id: &str, error: serde_json::Value) {
+        self.finish(id, JobStatus::Failed, None, Some(error));
+    }
+
+    fn finish(
+        &self,
+        id: &str,
+        status: JobStatus,
+        result: Option<serde_json::Value>,
+        error: Option<serde_json::Value>,
+    ) {
+        if let Some(job) = self.lock().jobs.get_mut(id) {
+            job.response.status = status;
+            job.response.result = result;
+            job.response.error = error;
+            job.finished = Some(Instant::now());
+            job.used = job.finished;
+        }
+    }
+
+    /// Look up a job, counting a finished one as recently used
+    pub fn get(&self, id: &str) -> JobLookup {
+        let mut jobs = self.lock();
+        if let Some(job) = jobs.jobs.get_mut(id) {
+            if job.used.is_some() {
+                job.used = Some(Instant::now());
+            }
+            return JobLookup::Found(job.response.clone());
+        }
+        if jobs.tombstones.contains(id) {
+            JobLookup::Expired
+        } else {
+            JobLookup::Unknown
+        }
+    }
+
+    /// Forget jobs that finished more than `ttl` ago, returning how many
+    pub fn remove_expired(&self, ttl: Duration) -> usize {
+        let mut jobs = self.lock();
+        let expired: Vec<String> = jobs
+            .jobs
+            .iter()
+            .filter(|(_, job)| {
+                job.finished
+                    .is_some_and(|finished| finished.elapsed() >= ttl)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            jobs.forget(id);
+        }
+        expired.len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Jobs> {
+        self.jobs
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn found(store: &JobStore, id: &str) -> JobResponse {
+        match store.get(id) {
+            JobLookup::Found(job) => job,
+            other => panic!("job {} not found: {:?}", id, other),
+        }
+    }
+
+    #[test]
+    fn test_job_goes_from_pending_to_done() {
+        let store = JobStore::new(100);
+        let id = store.create().unwrap().job_id;
+        assert_eq!(found(&store, &id).status, JobStatus::Pending);
+
+        store.start(&id);
+        assert_eq!(found(&store, &id).status, JobStatus::Running);
+
+        store.complete(&id, json!({ "text": "Hello" }));
+        let job = found(&store, &id);
+        assert_eq!(job.status, JobStatus::Done);
+        assert_eq!(job.result.unwrap()["text"], "Hello");
+        assert!(job.error.is_none());
+    }
+
+    #[test]
+    fn test_failed_job_keeps_error() {
+        let store = JobStore::new(100);
+        let id = store.create().unwrap().job_id;
+        store.fail(&id, json!({ "code": "PROCESSING_ERROR" }));
+
+        let job = serde_json::to_value(found(&store, &id)).unwrap();
+        assert_eq!(job["status"], "failed");
+        assert_eq!(job["error"]["code"], "PROCESSING_ERROR");
+        assert!(job.get("result").is_none());
+    }
+
+    #[test]
+    fn test_ids_are_unique() {
+        let store = JobStore::new(100);
+        let (first, second) = (
+            store.create().unwrap().job_id,
+            store.create().unwrap().job_id,
+        );
+        assert_ne!(first, second);
+        assert_eq!(first.len(), 32);
+        assert!(matches!(store.get("unknown"), JobLookup::Unknown));
+    }
+
+    #[test]
+    fn test_remove_expired_keeps_unfinished_jobs() {
+        let store = JobStore::new(100);
+        let running = store.create().unwrap().job_id;
+        store.start(&running);
+        let done = store.create().unwrap().job_id;
+        store.complete(&done, json!({}));
+
+        assert_eq!(store.remove_expired(Duration::from_secs(60)), 0);
+        assert_eq!(store.remove_expired(Duration::ZERO), 1);
+        assert!(matches!(store.get(&running), JobLookup::Found(_)));
+        assert!(matches!(store.get(&done), JobLookup::Expired));
+    }
+
+    #[test]
+    fn test_full_store_evicts_least_recently_polled_finished_job() {
+        let store = JobStore::new(3);
+        let ids: Vec<String> = (0..3).map(|_| store.create().unwrap().job_id).collect();
+        store.complete(&ids[0], json!({}));
+        std::thread::sleep(Duration::from_millis(2));
+        store.complete(&ids[1], json!({}));
+        std::thread::sleep(Duration::from_millis(2));
+        // Polling the older result keeps it
+        found(&store, &ids[0]);
+
+        let fourth = store.create().unwrap().job_id;
+        assert!(matches!(store.get(&ids[1]), JobLookup::Expired));
+        assert!(matches!(store.get(&ids[0]), JobLookup::Found(_)));
+
+        // Unfinished jobs are never evicted
+        store.create().unwrap();
+        assert!(store.create().is_none());
+        assert!(matches!(store.get(&ids[2]), JobLookup::Found(_)));
+        assert!(matches!(store.get(&fourth), JobLookup::Found(_)));
+    }
+}
@@ -0,0 +1,182 @@
+//! Per-language OCR defaults
+//!
+//! Scripts need different settings to recognize well: dense CJK strokes are
+//! damaged by sharpening and thresholding, vertical text needs its own page
+//! segmentation mode, and connected Arabic script segments better as a
+//! single block. When a request names its languages but doesn't choose these
+//! settings itself, the defaults for its primary language are used.
+
+use crate::preprocessing::Preset;
+use std::collections::HashMap;
+
+/// Highest Tesseract page segmentation mode
+pub const MAX_PSM: u32 = 13;
+
+/// Recommended settings for a language
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LanguageDefault {
+    /// Tesseract page segmentation mode (None keeps Tesseract's default)
+    pub psm: Option<u32>,
+    /// Preprocessing preset (None keeps the server default)
+    pub preset: Option<Preset>,
+}
+
+/// Uniform block of horizontal CJK text, light preprocessing
+const CJK: LanguageDefault = LanguageDefault {
+    psm: Some(6),
+    preset: Some(Preset::Minimal),
+};
+
+/// Uniform block of vertical CJK text, light preprocessing
+const CJK_VERTICAL: LanguageDefault = LanguageDefault {
+    psm: Some(5),
+    preset: Some(Preset::Minimal),
+};
+
+/// Uniform block of right-to-left text
+const RTL: LanguageDefault = LanguageDefault {
+    psm: Some(6),
+    preset: None,
+};
+
+/// Built-in defaults; languages not listed use the server defaults
+const BUILTIN: &[(&str, LanguageDefault)] = &[
+    ("chi_sim", CJK),
+    ("chi_tra", CJK),
+    ("jpn", CJK),
+    ("kor", CJK),
+    ("chi_sim_vert", CJK_VERTICAL),
+    ("chi_tra_vert", CJK_VERTICAL),
+    ("jpn_vert", CJK_VERTICAL),
+    ("kor_vert", CJK_VERTICAL),
+    ("ara", RTL),
+    ("fas", RTL),
+    ("heb", RTL),
+    ("urd", RTL),
+];
+
+/// Entries from `--language-defaults`, replacing built-in entries
+#[derive(Debug, Clone, Default)]
+pub struct LanguageOverrides(pub Vec<(String, LanguageDefault)>);
+
+/// Table of per-language defaults
+#[derive(Debug, Clone)]
+pub struct LanguageDefaults {
+    table: HashMap<String, LanguageDefault>,
+}
+
+impl LanguageDefaults {
+    /// Built-in defaults with the given overrides applied
+    pub fn new(overrides: &LanguageOverrides) -> Self {
+        let mut table: HashMap<String, LanguageDefault> = BUILTIN
+            .iter()
+            .map(|(language, default)| (language.to_string(), *default))
+            .collect();
+        table.extend(overrides.0.iter().cloned());
+        Self { table }
+    }
+
+    /// Defaults for a `+`-separated language list, by its first language
+    pub fn get(&self, languages: &str) -> LanguageDefault {
+        let primary = languages.split('+').next().unwrap_or_default();
+        self.table.get(primary).copied().unwrap_or_default()
+    }
+}
+
+impl Default for LanguageDefaults {
+    fn default() -> Self {
+        Self::new(&LanguageOverrides::default())
+    }
+}
+
+/// Parse overrides such as `jpn=5:none,ara=6,eng=:minimal`
+///
+/// Each entry is `language=psm:preset`. Either part may be left empty to
+/// keep the server default for that setting.
+pub fn parse_overrides(spec: &str) -> Result<LanguageOverrides, String> {
+    let mut overrides = Vec::new();
+
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (language, settings) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("expected language=psm:preset, got '{}'", entry))?;
+        if !is_valid_language(language) {
+            return Err(format!("invalid language '{}'", language));
+        }
+
+        let (psm, preset) = settings.split_once(':').unwrap_or((settings, ""));
+        let psm = match psm {
+            "" => None,
+            psm => Some(
+                psm.parse::<u32>()
+                    .ok()
+                    .filter(|psm| *psm <= MAX_PSM)
+                    .ok_or_else(|| format!("invalid psm '{}' for '{}'", psm, language))?,
+            ),
+        };
+        let preset = match preset {
+            "" => None,
+            preset => Some(
+                Preset::from_str(preset)
+                    .ok_or_else(|| format!("invalid preset '{}' for '{}'", preset, language))?,
+            ),
+        };
+
+        overrides.push((language.to_string(), LanguageDefault { psm, preset }));
+    }
+
+    Ok(LanguageOverrides(overrides))
+}
+
+/// Check a single Tesseract language code (e.g. "eng", "chi_sim_vert")
+pub fn is_valid_language(language: &str) -> bool {
+    !language.is_empty()
+        && language
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_defaults_use_primary_language() {
+        let defaults = LanguageDefaults::default();
+
+        assert_eq!(defaults.get("jpn"), CJK);
+        assert_eq!(defaults.get("jpn_vert+eng"), CJK_VERTICAL);
+        assert_eq!(defaults.get("eng+jpn"), LanguageDefault::default());
+    }
+
+    #[test]
+    fn test_overrides_replace_builtin_entries() {
+        let overrides = parse_overrides("jpn=3:none, eng=:aggressive").unwrap();
+        let defaults = LanguageDefaults::new(&overrides);
+
+        assert_eq!(
+            defaults.get("jpn"),
+            LanguageDefault {
+                psm: Some(3),
+                preset: Some(Preset::None),
+            }
+        );
+        assert_eq!(
+            defaults.get("eng"),
+            LanguageDefault {
+                psm: None,
+                preset: Some(Preset::Aggressive),
+            }
+        );
+        assert_eq!(defaults.get("kor"), CJK);
+    }
+
+    #[test]
+    fn test_parse_overrides_rejects_bad_entries() {
+        assert!(parse_overrides("jpn").is_err());
+        assert!(parse_overrides("jpn=14").is_err());
+        assert!(parse_overrides("jpn=6:fancy").is_err());
+        assert!(parse_overrides("../etc=6").is_err());
+        assert!(parse_overrides("").unwrap().0.is_empty());
+    }
+}
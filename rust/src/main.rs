@@ -1,12 +1,39 @@
 use clap::Parser;
+use std::path::PathBuf;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod alto;
 mod config;
+#[cfg(feature = "documents")]
+mod documents;
+mod download;
 mod engine;
 mod engines;
 mod error;
+mod file_format;
+mod hocr;
+mod jobs;
+mod languages;
+mod memory;
+mod metrics;
+mod model_cache;
+mod page_xml;
+mod pdf_repair;
+mod pdf_text_layer;
 mod preprocessing;
+mod protobuf;
+mod resolution;
+mod result_store;
+mod serve;
 mod server;
+#[cfg(test)]
+mod test_support;
+mod tsv;
+mod url_fetch;
+
+/// Counts allocations per thread, for preprocessing memory stats
+#[global_allocator]
+static ALLOCATOR: memory::TrackingAllocator = memory::TrackingAllocator;
 
 #[derive(Parser, Debug)]
 #[command(name = "activestorage-ocr-server")]
@@ -25,6 +52,11 @@ pub struct Args {
     #[arg(long, env = "OCR_DEFAULT_LANGUAGE", default_value = "eng")]
     pub default_language: String,
 
+    /// Per-language defaults overriding the built-in table, as
+    /// language=psm:preset entries (e.g. "jpn=5:none,ara=6")
+    #[arg(long, env = "OCR_LANGUAGE_DEFAULTS", value_parser = languages::parse_overrides)]
+    pub language_defaults: Option<languages::LanguageOverrides>,
+
     /// Maximum file size in bytes (default: 50MB)
     #[arg(long, env = "OCR_MAX_FILE_SIZE", default_value = "52428800")]
     pub max_file_size: usize,
@@ -33,15 +65,194 @@ pub struct Args {
     #[arg(long, env = "TESSDATA_PREFIX")]
     pub tessdata_path: Option<String>,
 
+    /// Longest image side (in pixels) for low-resolution probe passes
+    #[arg(long, env = "OCR_PROBE_MAX_DIMENSION", default_value = "1024")]
+    pub probe_max_dimension: u32,
+
+    /// Images narrower and shorter than this many pixels are rejected as too
+    /// small for OCR (0 disables)
+    #[arg(long, env = "OCR_MIN_IMAGE_DIMENSION", default_value = "20")]
+    pub min_image_dimension: u32,
+
+    /// Laplacian variance above which images are considered sharp enough to
+    /// skip the sharpen step (0 always sharpens)
+    #[arg(long, env = "OCR_SHARPEN_SKIP_THRESHOLD", default_value = "100")]
+    pub sharpen_skip_threshold: f32,
+
+    /// URL of an ocrs recognition model (.rten) trained on handwriting,
+    /// enables `model=handwriting` for the ocrs engine
+    #[arg(long, env = "OCR_HANDWRITING_MODEL_URL")]
+    pub handwriting_model_url: Option<String>,
+
+    /// Default ocrs decode method: greedy (fast) or beam_search (more accurate)
+    #[arg(long, env = "OCR_DECODE_METHOD", default_value = "greedy", value_parser = ["greedy", "beam_search"])]
+    pub decode_method: String,
+
+    /// Number of candidate sequences kept during beam search decoding
+    #[arg(
+        long,
+        env = "OCR_BEAM_WIDTH",
+        default_value = "100",
+        value_parser = clap::value_parser!(u32).range(1..=engine::MAX_BEAM_WIDTH as i64)
+    )]
+    pub beam_width: u32,
+
+    /// Largest model download accepted, in bytes (default: 500MB)
+    #[arg(long, env = "OCR_MAX_MODEL_SIZE", default_value = "524288000")]
+    pub max_model_size: u64,
+
+    /// Hosts models and `url` uploads may be downloaded from, comma-separated
+    /// (any host when unset)
+    #[arg(long, env = "OCR_ALLOWED_DOWNLOAD_HOSTS", value_delimiter = ',')]
+    pub allowed_download_hosts: Vec<String>,
+
+    /// Seconds allowed for downloading a file sent as a `url` (0 disables)
+    #[arg(long, env = "OCR_URL_FETCH_TIMEOUT", default_value = "30")]
+    pub url_fetch_timeout: u64,
+
+    /// Hosts `url` uploads may be fetched from even though they resolve to
+    /// loopback or private addresses, comma-separated
+    #[arg(long, env = "OCR_URL_FETCH_ALLOWED_HOSTS", value_delimiter = ',')]
+    pub url_fetch_allowed_hosts: Vec<String>,
+
+    /// Base URLs of tessdata mirrors, comma-separated, tried in order when
+    /// downloading from GitHub fails
+    #[arg(long, env = "OCR_TESSDATA_MIRRORS", value_delimiter = ',')]
+    pub tessdata_mirrors: Vec<String>,
+
+    /// Store cached models zstd-compressed, decompressing them at startup
+    #[arg(long, env = "OCR_COMPRESS_MODEL_CACHE")]
+    pub compress_model_cache: bool,
+
+    /// Seconds between checks of cached models against their checksums
+    /// (0 disables)
+    #[arg(long, env = "OCR_MODEL_VERIFY_INTERVAL", default_value = "0")]
+    pub model_verify_interval: u64,
+
+    /// Download cached models again when the periodic check finds them corrupted
+    #[arg(long, env = "OCR_MODEL_VERIFY_REPAIR")]
+    pub model_verify_repair: bool,
+
+    /// Number of OCR requests in flight above which new requests get a
+    /// lighter preprocessing preset (0 disables)
+    #[arg(long, env = "OCR_ADAPTIVE_PRESET_THRESHOLD", default_value = "0")]
+    pub adaptive_preset_threshold: usize,
+
+    /// OCR requests processed at once; more wait for a free slot (defaults
+    /// to the number of CPUs)
+    #[arg(long, env = "OCR_MAX_CONCURRENCY")]
+    pub max_concurrency: Option<usize>,
+
+    /// Seconds a request may wait for a free slot before failing with a 503
+    /// (0 waits indefinitely)
+    #[arg(long, env = "OCR_QUEUE_TIMEOUT", default_value = "0")]
+    pub queue_timeout: u64,
+
+    /// Engine to retry with when the requested engine fails to process a file
+    #[arg(long, env = "OCR_FALLBACK_ENGINE")]
+    pub fallback_engine: Option<String>,
+
+    /// Seconds an idle keep-alive connection is held open (0 disables keep-alive)
+    #[arg(long, env = "OCR_KEEP_ALIVE_TIMEOUT", default_value = "75")]
+    pub keep_alive_timeout: u64,
+
+    /// Seconds a client has to send complete request headers (0 disables)
+    #[arg(long, env = "OCR_HEADER_READ_TIMEOUT", default_value = "30")]
+    pub header_read_timeout: u64,
+
+    /// Seconds allowed for a whole request, including OCR (0 disables)
+    #[arg(long, env = "OCR_REQUEST_TIMEOUT", default_value = "300")]
+    pub request_timeout: u64,
+
+    /// Seconds an engine may spend on one request before it is considered
+    /// stuck and restarted (0 disables)
+    #[arg(long, env = "OCR_ENGINE_HARD_TIMEOUT", default_value = "0")]
+    pub engine_hard_timeout: u64,
+
+    /// Engine calls stuck past --engine-hard-timeout that may be left running
+    /// before OCR requests are turned away with a 503
+    #[arg(long, env = "OCR_MAX_ABANDONED_WORKERS", default_value = "2")]
+    pub max_abandoned_workers: usize,
+
+    /// Seconds a request may spend recognizing before it fails with a 504,
+    /// leaving the engine to finish on its own (0 disables)
+    #[arg(long, env = "OCR_PROCESSING_TIMEOUT", default_value = "0")]
+    pub processing_timeout: u64,
+
+    /// Run a small bundled image through the default engine before serving,
+    /// so the first request doesn't pay for cold caches
+    #[arg(long, env = "OCR_WARMUP_ON_START")]
+    pub warmup_on_start: bool,
+
+    /// Seconds a finished background job's result is kept for polling
+    #[arg(long, env = "OCR_JOB_TTL", default_value = "3600")]
+    pub job_ttl: u64,
+
+    /// Most background jobs kept at once; when full, the finished job polled
+    /// least recently is forgotten
+    #[arg(long, env = "OCR_MAX_JOBS", default_value = "1000")]
+    pub max_jobs: usize,
+
+    /// Most background jobs waiting for an OCR slot; further jobs are
+    /// rejected with a 503 before their upload is read
+    #[arg(long, env = "OCR_MAX_PENDING_JOBS", default_value = "100")]
+    pub max_pending_jobs: usize,
+
+    /// Bearer token required by POST /reload (the endpoint is disabled when unset)
+    #[arg(long, env = "OCR_ADMIN_TOKEN")]
+    pub admin_token: Option<String>,
+
+    /// Directory OCR results are stored in, keyed by the SHA-256 of the
+    /// upload, and served from GET /results/:hash (disabled when unset)
+    #[arg(long, env = "OCR_RESULT_STORE_DIR")]
+    pub result_store_dir: Option<PathBuf>,
+
+    /// Most warnings returned with a result, after merging warnings that
+    /// differ only in their numbers (0 returns all)
+    #[arg(long, env = "OCR_MAX_WARNINGS", default_value = "20")]
+    pub max_warnings: usize,
+
+    /// Pages of a scanned PDF OCRed at once (0 or 1 OCRs them one at a
+    /// time). Shared among the OCR requests in flight
+    #[arg(long, env = "OCR_PDF_PAGE_THREADS", default_value = "4")]
+    pub pdf_page_threads: usize,
+
+    /// Exit if any built-in engine fails to start, instead of serving with
+    /// the ones that did
+    #[arg(long, env = "OCR_REQUIRE_ALL_ENGINES")]
+    pub require_all_engines: bool,
+
+    /// Rebuild the cross-reference table of PDFs that fail to load, instead
+    /// of rejecting them
+    #[arg(long, env = "OCR_PDF_LENIENT")]
+    pub pdf_lenient: bool,
+
+    /// Threads the ocrs engine runs inference on (0 uses one per physical
+    /// core). Sets RTEN_NUM_THREADS, so it can't change on reload
+    #[arg(long, env = "OCR_INFERENCE_THREADS", default_value = "0")]
+    pub inference_threads: usize,
+
     /// Log level (trace, debug, info, warn, error)
     #[arg(long, env = "RUST_LOG", default_value = "info")]
     pub log_level: String,
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
+    // rten reads this once, when its thread pool starts. Set it before any
+    // other threads exist, since changing the environment isn't thread-safe
+    if args.inference_threads > 0 {
+        std::env::set_var("RTEN_NUM_THREADS", args.inference_threads.to_string());
+    }
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(run(args))
+}
+
+async fn run(args: Args) -> anyhow::Result<()> {
     // Initialize tracing
     tracing_subscriber::registry()
         .with(
@@ -0,0 +1,99 @@
+//! Allocation tracking for preprocessing memory stats
+//!
+//! The server's global allocator counts the bytes each thread has allocated
+//! and not yet freed, so [`track`] can report the most memory a piece of code
+//! had allocated at once. Only the current thread is counted: work a step
+//! hands to other threads is missed, and memory freed on a different thread
+//! than it was allocated on skews both threads' counts.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    /// Bytes allocated and not yet freed by this thread
+    static CURRENT: Cell<isize> = const { Cell::new(0) };
+    /// Highest value of `CURRENT` since the innermost [`track`] started
+    static PEAK: Cell<isize> = const { Cell::new(0) };
+}
+
+/// System allocator that counts allocations per thread
+pub struct TrackingAllocator;
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            record(layout.size() as isize);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            record(layout.size() as isize);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        record(-(layout.size() as isize));
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            record(new_size as isize - layout.size() as isize);
+        }
+        new_ptr
+    }
+}
+
+/// Count an allocation (positive) or deallocation (negative) on this thread
+fn record(change: isize) {
+    // Allocations while the thread is exiting aren't worth counting
+    let _ = CURRENT.try_with(|current| {
+        let now = current.get() + change;
+        current.set(now);
+        let _ = PEAK.try_with(|peak| peak.set(peak.get().max(now)));
+    });
+}
+
+/// Run `f`, returning its result and the most memory it had allocated on
+/// this thread at once, in bytes
+pub fn track<R>(f: impl FnOnce() -> R) -> (R, u64) {
+    let start = CURRENT.with(Cell::get);
+    let outer_peak = PEAK.with(|peak| peak.replace(start));
+    let result = f();
+    // Restore the enclosing peak, which includes everything `f` allocated
+    let peak = PEAK.with(|peak| peak.replace(outer_peak.max(peak.get())));
+    (result, (peak - start).max(0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hint::black_box;
+
+    #[test]
+    fn test_track_reports_peak_allocation() {
+        const SIZE: usize = 1 << 20;
+
+        // Freed before returning, but still the peak
+        let ((), peak) = track(|| drop(black_box(vec![1u8; SIZE])));
+        assert!(peak >= SIZE as u64, "peak {}", peak);
+
+        let (inner, outer) = track(|| {
+            let first = black_box(vec![1u8; SIZE]);
+            let ((), inner) = track(|| drop(black_box(vec![1u8; SIZE])));
+            drop(first);
+            inner
+        });
+        assert!((SIZE as u64..2 * SIZE as u64).contains(&inner));
+        assert!(outer >= 2 * SIZE as u64, "outer {}", outer);
+
+        let ((), peak) = track(|| black_box(()));
+        assert!(peak < SIZE as u64);
+    }
+}
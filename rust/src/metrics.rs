@@ -0,0 +1,221 @@
+//! Prometheus metrics for OCR requests
+//!
+//! A small registry rendered in the Prometheus text exposition format, so
+//! the server doesn't need a metrics library for a handful of series.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// Content type of the text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Upper bounds of the timing histogram buckets, in milliseconds
+const BUCKETS_MS: [u64; 12] = [
+    10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000, 30000, 60000,
+];
+
+#[derive(Debug, Default, Clone)]
+struct Histogram {
+    /// Observations per bucket, not cumulative
+    buckets: [u64; BUCKETS_MS.len()],
+    count: u64,
+    sum: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value_ms: u64) {
+        if let Some(bucket) = BUCKETS_MS.iter().position(|&bound| value_ms <= bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += value_ms;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Series {
+    /// Requests by engine and status code
+    requests: BTreeMap<(String, u16), u64>,
+    /// Total processing time by engine
+    processing: BTreeMap<String, Histogram>,
+    /// Preprocessing time by engine
+    preprocessing: BTreeMap<String, Histogram>,
+}
+
+/// Request counters and timing histograms, shared by all requests
+#[derive(Debug, Default)]
+pub struct Metrics {
+    series: Mutex<Series>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a finished request
+    pub fn record_request(&self, engine: &str, status: u16) {
+        *self
+            .lock()
+            .requests
+            .entry((engine.to_string(), status))
+            .or_default() += 1;
+    }
+
+    /// Record the timings of a completed OCR run
+    pub fn observe(&self, engine: &str, processing_ms: u64, preprocessing_ms: Option<u64>) {
+        let mut series = self.lock();
+        series
+            .processing
+            .entry(engine.to_string())
+            .or_default()
+            .observe(processing_ms);
+        if let Some(preprocessing_ms) = preprocessing_ms {
+            series
+                .preprocessing
+                .entry(engine.to_string())
+                .or_default()
+                .observe(preprocessing_ms);
+        }
+    }
+
+    /// Render every series, with `in_flight` as the in-flight gauge
+    pub fn render(&self, in_flight: usize) -> String {
+        let series = self.lock();
+        let mut out = String::new();
+
+        out.push_str("# HELP ocr_requests_total OCR requests by engine and status code\n");
+        out.push_str("# TYPE ocr_requests_total counter\n");
+        for ((engine, status), count) in &series.requests {
+            let _ = writeln!(
+                out,
+                "ocr_requests_total{{engine=\"{}\",status=\"{}\"}} {}",
+                escape(engine),
+                status,
+                count
+            );
+        }
+
+        render_histogram(
+            &mut out,
+            "ocr_processing_time_ms",
+            "Total OCR processing time in milliseconds",
+            &series.processing,
+        );
+        render_histogram(
+            &mut out,
+            "ocr_preprocessing_time_ms",
+            "Image preprocessing time in milliseconds",
+            &series.preprocessing,
+        );
+
+        out.push_str("# HELP ocr_requests_in_flight OCR requests being processed\n");
+        out.push_str("# TYPE ocr_requests_in_flight gauge\n");
+        let _ = writeln!(out, "ocr_requests_in_flight {}", in_flight);
+        out
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Series> {
+        // Counters stay usable even if a request panicked while holding the lock
+        self.series
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn render_histogram(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    histograms: &BTreeMap<String, Histogram>,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} histogram", name);
+    for (engine, histogram) in histograms {
+        let engine = escape(engine);
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS_MS.iter().zip(histogram.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{engine=\"{}\",le=\"{}\"}} {}",
+                name, engine, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{engine=\"{}\",le=\"+Inf\"}} {}",
+            name, engine, histogram.count
+        );
+        let _ = writeln!(
+            out,
+            "{}_sum{{engine=\"{}\"}} {}",
+            name, engine, histogram.sum
+        );
+        let _ = writeln!(
+            out,
+            "{}_count{{engine=\"{}\"}} {}",
+            name, engine, histogram.count
+        );
+    }
+}
+
+/// Escape a label value for the text format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_counts_requests_by_engine_and_status() {
+        let metrics = Metrics::new();
+        metrics.record_request("ocrs", 200);
+        metrics.record_request("ocrs", 200);
+        metrics.record_request("ocrs", 400);
+        metrics.record_request("leptess", 200);
+
+        let text = metrics.render(3);
+
+        assert!(text.contains("ocr_requests_total{engine=\"ocrs\",status=\"200\"} 2\n"));
+        assert!(text.contains("ocr_requests_total{engine=\"ocrs\",status=\"400\"} 1\n"));
+        assert!(text.contains("ocr_requests_total{engine=\"leptess\",status=\"200\"} 1\n"));
+        assert!(text.contains("# TYPE ocr_requests_in_flight gauge\nocr_requests_in_flight 3\n"));
+    }
+
+    #[test]
+    fn test_render_histogram_buckets_are_cumulative() {
+        let metrics = Metrics::new();
+        metrics.observe("ocrs", 40, Some(5));
+        metrics.observe("ocrs", 700, None);
+        metrics.observe("ocrs", 120_000, Some(30));
+
+        let text = metrics.render(0);
+
+        for line in [
+            "ocr_processing_time_ms_bucket{engine=\"ocrs\",le=\"25\"} 0",
+            "ocr_processing_time_ms_bucket{engine=\"ocrs\",le=\"50\"} 1",
+            "ocr_processing_time_ms_bucket{engine=\"ocrs\",le=\"1000\"} 2",
+            "ocr_processing_time_ms_bucket{engine=\"ocrs\",le=\"60000\"} 2",
+            "ocr_processing_time_ms_bucket{engine=\"ocrs\",le=\"+Inf\"} 3",
+            "ocr_processing_time_ms_sum{engine=\"ocrs\"} 120740",
+            "ocr_processing_time_ms_count{engine=\"ocrs\"} 3",
+            // Runs without preprocessing stats aren't observed
+            "ocr_preprocessing_time_ms_bucket{engine=\"ocrs\",le=\"10\"} 1",
+            "ocr_preprocessing_time_ms_count{engine=\"ocrs\"} 2",
+        ] {
+            assert!(text.contains(&format!("{}\n", line)), "missing {}", line);
+        }
+    }
+
+    #[test]
+    fn test_escape_label_values() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
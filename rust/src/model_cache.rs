@@ -0,0 +1,815 @@
+//! Model cache
+//!
+//! Downloads models (ocrs `.rten` files, Tesseract traineddata) on first use
+//! and keeps them in the user's cache directory. With compression enabled,
+//! cached models are stored zstd-compressed and decompressed at load time,
+//! either into memory or into a private temporary directory for engines that
+//! need to read models from disk.
+//!
+//! With checksums enabled, each cached file has a `.sha256` sidecar recording
+//! its checksum when it was stored, so [`ModelCache::verify`] can detect
+//! files that have since been corrupted on disk.
+
+use crate::config::Config;
+use crate::download::{self, BodyError};
+use crate::error::OcrError;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tempfile::{NamedTempFile, TempDir};
+
+/// Redirects followed per download, each checked against the allowed hosts
+const MAX_REDIRECTS: usize = 10;
+
+/// Compression level for cached models (compression only happens once, on download)
+const COMPRESSION_LEVEL: i32 = 19;
+
+/// A model found in the cache directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedModel {
+    /// Name the model is cached under, e.g. `tessdata/deu.traineddata`
+    pub name: String,
+    /// Size of the cached file, compressed if compression is enabled
+    pub size_bytes: u64,
+}
+
+/// On-disk cache of downloaded models
+pub struct ModelCache {
+    dir: PathBuf,
+    compress: bool,
+    /// Downloads larger than this many bytes are aborted
+    max_download_size: u64,
+    /// Hosts downloads may come from (None allows any host)
+    allowed_hosts: Option<Vec<String>>,
+    /// Whether to record checksums for [`ModelCache::verify`]
+    checksums: bool,
+    /// Decompressed copies of models that engines load from disk
+    #[cfg_attr(not(feature = "engine-leptess"), allow(dead_code))]
+    extract_dir: Mutex<Option<TempDir>>,
+    /// Models used so far, by name, with the URL to download them from again
+    models: Mutex<BTreeMap<String, String>>,
+}
+
+impl ModelCache {
+    /// Create a cache in the default cache directory
+    pub fn new(config: &Config) -> Self {
+        let dir = dirs::cache_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("activestorage-ocr");
+
+        Self::with_dir(dir, config.compress_model_cache, config.max_model_size)
+            .with_allowed_hosts(config.allowed_download_hosts.clone())
+            .with_checksums(config.model_verify_interval.is_some())
+    }
+
+    /// Create a cache in a specific directory
+    pub fn with_dir(dir: PathBuf, compress: bool, max_download_size: u64) -> Self {
+        Self {
+            dir,
+            compress,
+            max_download_size,
+            allowed_hosts: None,
+            checksums: false,
+            extract_dir: Mutex::new(None),
+            models: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Only download from these hosts (None allows any host)
+    pub fn with_allowed_hosts(mut self, hosts: Option<Vec<String>>) -> Self {
+        self.allowed_hosts = hosts;
+        self
+    }
+
+    /// Record checksums of cached models so they can be verified later
+    ///
+    /// Off by default, so a read-only cache directory, such as one baked
+    /// into an image, only ever needs to be read.
+    pub fn with_checksums(mut self, enabled: bool) -> Self {
+        self.checksums = enabled;
+        self
+    }
+
+    /// Get a model's contents, downloading it if needed
+    pub fn load(&self, name: &str, url: &str) -> Result<Vec<u8>, OcrError> {
+        let cached = self.ensure_cached(name, url).map_err(|e| match e {
+            // A configured model URL that doesn't exist is a server problem
+            OcrError::NotFound(message) => {
+                OcrError::InitializationError(format!("Failed to download {}", message))
+            }
+            e => e,
+        })?;
+        self.read_cached(&cached, name)
+    }
+
+    /// Get a path to the uncompressed model file, downloading it if needed
+    ///
+    /// Fails with [`OcrError::NotFound`] when the server says there is no
+    /// such file.
+    #[cfg_attr(not(feature = "engine-leptess"), allow(dead_code))]
+    pub fn path(&self, name: &str, url: &str) -> Result<PathBuf, OcrError> {
+        let cached = self.ensure_cached(name, url)?;
+        if !self.compress {
+            return Ok(cached);
+        }
+
+        let mut extract_dir = self.extract_dir.lock().unwrap();
+        if extract_dir.is_none() {
+            *extract_dir = Some(
+                tempfile::Builder::new()
+                    .prefix("activestorage-ocr-models")
+                    .tempdir()
+                    .map_err(|e| {
+                        OcrError::InitializationError(format!(
+                            "Failed to create model extraction directory: {}",
+                            e
+                        ))
+                    })?,
+            );
+        }
+
+        let extracted = extract_dir.as_ref().unwrap().path().join(name);
+        if !extracted.exists() {
+            let data = self.read_cached(&cached, name)?;
+            write_file(&extracted, &data, name)?;
+            tracing::debug!("Decompressed {} to {:?}", name, extracted);
+        }
+
+        Ok(extracted)
+    }
+
+    /// Get a path to the uncompressed model file like [`ModelCache::path`],
+    /// trying each URL in order until a download succeeds
+    ///
+    /// Fails with [`OcrError::NotFound`] only when every URL does.
+    #[cfg_attr(not(feature = "engine-leptess"), allow(dead_code))]
+    pub fn path_from_any(&self, name: &str, urls: &[String]) -> Result<PathBuf, OcrError> {
+        let mut last_error = None;
+        for url in urls {
+            match self.path(name, url) {
+                Ok(path) => return Ok(path),
+                Err(e) => {
+                    tracing::warn!("Failed to get {} from {}: {}", name, url, e);
+                    // Keep the first failure that isn't a missing file
+                    if last_error
+                        .as_ref()
+                        .is_none_or(|last| matches!(last, OcrError::NotFound(_)))
+                    {
+                        last_error = Some(e);
+                    }
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            OcrError::InitializationError(format!("No URL to download {} from", name))
+        }))
+    }
+
+    /// Read a cached model, decompressing it if needed
+    fn read_cached(&self, cached: &Path, name: &str) -> Result<Vec<u8>, OcrError> {
+        let data = fs::read(cached).map_err(|e| {
+            OcrError::InitializationError(format!("Failed to read cached {}: {}", name, e))
+        })?;
+
+        if self.compress {
+            decompress(&data, name)
+        } else {
+            Ok(data)
+        }
+    }
+
+    /// Make sure the model is in the cache in the configured format,
+    /// returning the path of the cached file
+    fn ensure_cached(&self, name: &str, url: &str) -> Result<PathBuf, OcrError> {
+        self.models
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), url.to_string());
+
+        let (wanted, other) = self.cached_paths(name);
+
+        if wanted.exists() {
+            tracing::info!("Using cached model from {:?}", wanted);
+            // Models cached before checksums were recorded are trusted as-is
+            if self.checksums && !checksum_path(&wanted).exists() {
+                self.record_checksum(&wanted, name);
+            }
+            return Ok(wanted);
+        }
+
+        if !other.exists() {
+            tracing::info!("Downloading {} (this may take a moment)...", name);
+            let downloaded = download(
+                url,
+                name,
+                &self.dir,
+                self.max_download_size,
+                self.allowed_hosts.as_deref(),
+            )?;
+            self.store_download(downloaded, &wanted, name)?;
+            self.record_checksum(&wanted, name);
+            tracing::info!("Cached {} at {:?}", name, wanted);
+            return Ok(wanted);
+        }
+
+        // Convert a model cached before the compression setting changed
+        // instead of downloading it again
+        tracing::info!("Converting cached {} to the configured format", name);
+        let data = fs::read(&other).map_err(|e| {
+            OcrError::InitializationError(format!("Failed to read cached {}: {}", name, e))
+        })?;
+        let stored = if self.compress {
+            zstd::encode_all(data.as_slice(), COMPRESSION_LEVEL).map_err(|e| {
+                OcrError::InitializationError(format!("Failed to compress {}: {}", name, e))
+            })?
+        } else {
+            decompress(&data, name)?
+        };
+        write_file(&wanted, &stored, name)?;
+        self.record_checksum(&wanted, name);
+
+        fs::remove_file(&other).map_err(|e| {
+            OcrError::InitializationError(format!("Failed to remove old {}: {}", name, e))
+        })?;
+        let _ = fs::remove_file(checksum_path(&other));
+
+        tracing::info!("Cached {} at {:?}", name, wanted);
+        Ok(wanted)
+    }
+
+    /// Record a cached file's checksum if checksums are enabled
+    ///
+    /// A checksum that can't be written only costs verification of that
+    /// model, so it is logged rather than failing the load.
+    fn record_checksum(&self, cached: &Path, name: &str) {
+        if !self.checksums {
+            return;
+        }
+        if let Err(e) = write_checksum(cached, name) {
+            tracing::warn!("Failed to record the checksum of {}: {}", name, e);
+        }
+    }
+
+    /// Paths of a model's cached file in the configured format and in the other one
+    fn cached_paths(&self, name: &str) -> (PathBuf, PathBuf) {
+        let plain = self.dir.join(name);
+        let compressed = self.dir.join(format!("{}.zst", name));
+
+        if self.compress {
+            (compressed, plain)
+        } else {
+            (plain, compressed)
+        }
+    }
+
+    /// List the models in the cache directory, sorted by name
+    ///
+    /// Unlike [`ModelCache::verify`], this includes models cached by earlier
+    /// runs that haven't been used since the server started. Checksums and
+    /// unfinished downloads are left out.
+    pub fn cached(&self) -> Vec<CachedModel> {
+        let mut models = Vec::new();
+        let mut dirs = vec![self.dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                if metadata.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                let Some(name) = path
+                    .strip_prefix(&self.dir)
+                    .ok()
+                    .and_then(|name| name.to_str())
+                else {
+                    continue;
+                };
+                let hidden = entry.file_name().to_string_lossy().starts_with('.');
+                if hidden || name.ends_with(".sha256") {
+                    continue;
+                }
+                models.push(CachedModel {
+                    name: name.strip_suffix(".zst").unwrap_or(name).replace('\\', "/"),
+                    size_bytes: metadata.len(),
+                });
+            }
+        }
+        models.sort_by(|a, b| a.name.cmp(&b.name));
+        models
+    }
+
+    /// Check the cached models used so far against their recorded checksums
+    ///
+    /// Corrupted models are logged as errors and, with `repair`, downloaded
+    /// again. Engines keep models they have already loaded in memory, so a
+    /// repaired model takes effect on the next reload or restart. Returns
+    /// the names of the corrupted models.
+    pub fn verify(&self, repair: bool) -> Vec<String> {
+        let models = self.models.lock().unwrap().clone();
+        let mut corrupted = Vec::new();
+
+        for (name, url) in models {
+            let (cached, _) = self.cached_paths(&name);
+            let problem = match (
+                fs::read(&cached),
+                fs::read_to_string(checksum_path(&cached)),
+            ) {
+                (Err(e), _) => format!("cannot be read: {}", e),
+                (Ok(_), Err(e)) => format!("has no readable checksum: {}", e),
+                (Ok(data), Ok(expected)) if sha256_hex(&data) != expected.trim() => {
+                    "does not match its checksum".to_string()
+                }
+                _ => {
+                    tracing::debug!("Cached {} is intact", name);
+                    continue;
+                }
+            };
+
+            tracing::error!("Cached model {} at {:?} {}", name, cached, problem);
+            corrupted.push(name.clone());
+
+            if repair {
+                let _ = fs::remove_file(&cached);
+                let _ = fs::remove_file(checksum_path(&cached));
+                if let Some(extract_dir) = self.extract_dir.lock().unwrap().as_ref() {
+                    let _ = fs::remove_file(extract_dir.path().join(&name));
+                }
+                match self.ensure_cached(&name, &url) {
+                    Ok(_) => tracing::info!("Downloaded {} again", name),
+                    Err(e) => tracing::error!("Failed to download {} again: {}", name, e),
+                }
+            }
+        }
+
+        corrupted
+    }
+
+    /// Move a finished download into the cache, compressing it if configured
+    ///
+    /// The cached file only appears once it is complete, so an interrupted
+    /// download is never mistaken for a cached model.
+    fn store_download(
+        &self,
+        downloaded: NamedTempFile,
+        wanted: &Path,
+        name: &str,
+    ) -> Result<(), OcrError> {
+        let parent = wanted.parent().unwrap_or(&self.dir);
+        fs::create_dir_all(parent).map_err(|e| {
+            OcrError::InitializationError(format!("Failed to create cache directory: {}", e))
+        })?;
+
+        let stored = if self.compress {
+            let mut compressed = NamedTempFile::new_in(parent).map_err(|e| {
+                OcrError::InitializationError(format!("Failed to create {} file: {}", name, e))
+            })?;
+            let source = downloaded.reopen().map_err(|e| {
+                OcrError::InitializationError(format!("Failed to read {} download: {}", name, e))
+            })?;
+            zstd::stream::copy_encode(source, &mut compressed, COMPRESSION_LEVEL).map_err(|e| {
+                OcrError::InitializationError(format!("Failed to compress {}: {}", name, e))
+            })?;
+            compressed
+        } else {
+            downloaded
+        };
+
+        stored.persist(wanted).map_err(|e| {
+            OcrError::InitializationError(format!("Failed to store {}: {}", name, e))
+        })?;
+        Ok(())
+    }
+}
+
+/// Path of the sidecar file holding a cached file's checksum
+fn checksum_path(cached: &Path) -> PathBuf {
+    let mut path = cached.as_os_str().to_owned();
+    path.push(".sha256");
+    PathBuf::from(path)
+}
+
+/// Record the checksum of a cached file
+fn write_checksum(cached: &Path, name: &str) -> Result<(), OcrError> {
+    let data = fs::read(cached).map_err(|e| {
+        OcrError::InitializationError(format!("Failed to read cached {}: {}", name, e))
+    })?;
+    write_file(&checksum_path(cached), sha256_hex(&data).as_bytes(), name)
+}
+
+/// Hex-encoded SHA-256 digest
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Decompress a zstd-compressed model
+fn decompress(data: &[u8], name: &str) -> Result<Vec<u8>, OcrError> {
+    zstd::decode_all(data)
+        .map_err(|e| OcrError::InitializationError(format!("Failed to decompress {}: {}", name, e)))
+}
+
+/// Write a file, creating parent directories as needed
+fn write_file(path: &Path, data: &[u8], name: &str) -> Result<(), OcrError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| {
+            OcrError::InitializationError(format!("Failed to create cache directory: {}", e))
+        })?;
+    }
+
+    let mut file = File::create(path).map_err(|e| {
+        OcrError::InitializationError(format!("Failed to create {} file: {}", name, e))
+    })?;
+
+    file.write_all(data)
+        .map_err(|e| OcrError::InitializationError(format!("Failed to write {} file: {}", name, e)))
+}
+
+/// Reject URLs whose host isn't allowed
+fn check_host(url: &str, name: &str, allowed_hosts: Option<&[String]>) -> Result<(), OcrError> {
+    let Some(allowed_hosts) = allowed_hosts else {
+        return Ok(());
+    };
+
+    let host = url
+        .parse::<ureq::http::Uri>()
+        .ok()
+        .and_then(|uri| uri.host().map(str::to_string))
+        .unwrap_or_default();
+    if allowed_hosts
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(&host))
+    {
+        return Ok(());
+    }
+
+    Err(OcrError::InitializationError(format!(
+        "Refusing to download {} from '{}': host '{}' is not in the allowed download hosts ({})",
+        name,
+        url,
+        host,
+        allowed_hosts.join(", ")
+    )))
+}
+
+/// Download a file using ureq, streaming it to a temporary file in `dir`
+///
+/// Aborts once more than `max_size` bytes arrive, so a misconfigured URL
+/// can't fill memory or disk. Redirects are followed here rather than by
+/// ureq so every hop can be checked against `allowed_hosts`.
+fn download(
+    url: &str,
+    name: &str,
+    dir: &Path,
+    max_size: u64,
+    allowed_hosts: Option<&[String]>,
+) -> Result<NamedTempFile, OcrError> {
+    let too_large = || {
+        OcrError::InitializationError(format!(
+            "{} exceeds the maximum model size of {} bytes",
+            name, max_size
+        ))
+    };
+    let failed = |e: &dyn std::fmt::Display| {
+        OcrError::InitializationError(format!("Failed to download {}: {}", name, e))
+    };
+
+    let agent = download::agent(None);
+    let response = download::get(
+        url,
+        MAX_REDIRECTS,
+        |url| {
+            check_host(url, name, allowed_hosts)?;
+            Ok(agent.clone())
+        },
+        failed,
+    )?;
+    if response.status() == ureq::http::StatusCode::NOT_FOUND {
+        return Err(OcrError::NotFound(format!("{} is not at {}", name, url)));
+    }
+    if !response.status().is_success() {
+        return Err(failed(&format!("server responded {}", response.status())));
+    }
+
+    fs::create_dir_all(dir).map_err(|e| {
+        OcrError::InitializationError(format!("Failed to create cache directory: {}", e))
+    })?;
+    let mut file = NamedTempFile::new_in(dir).map_err(|e| {
+        OcrError::InitializationError(format!("Failed to create {} file: {}", name, e))
+    })?;
+    download::read_limited(response, max_size, &mut file).map_err(|e| match e {
+        BodyError::TooLarge(_) => too_large(),
+        BodyError::Read(e) => {
+            OcrError::InitializationError(format!("Failed to read {} response: {}", name, e))
+        }
+    })?;
+
+    file.flush().map_err(|e| {
+        OcrError::InitializationError(format!("Failed to write {} file: {}", name, e))
+    })?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use std::io::Read;
+
+    // Never fetched: tests pre-populate the cache
+    const URL: &str = "http://invalid.invalid/model.rten";
+    const MAX_SIZE: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_uncompressed_cache_uses_plain_file() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("model.rten"), b"weights").unwrap();
+        let cache = ModelCache::with_dir(dir.path().to_path_buf(), false, MAX_SIZE);
+
+        assert_eq!(cache.load("model.rten", URL).unwrap(), b"weights");
+        assert_eq!(
+            cache.path("model.rten", URL).unwrap(),
+            dir.path().join("model.rten")
+        );
+    }
+
+    #[test]
+    fn test_compressed_cache_converts_existing_plain_file() {
+        let dir = TempDir::new().unwrap();
+        let data = vec![7u8; 64 * 1024];
+        fs::write(dir.path().join("model.rten"), &data).unwrap();
+        let cache = ModelCache::with_dir(dir.path().to_path_buf(), true, MAX_SIZE);
+
+        assert_eq!(cache.load("model.rten", URL).unwrap(), data);
+
+        let compressed = dir.path().join("model.rten.zst");
+        assert!(compressed.exists());
+        assert!(!dir.path().join("model.rten").exists());
+        assert!(fs::metadata(compressed).unwrap().len() < data.len() as u64);
+    }
+
+    #[test]
+    fn test_compressed_cache_extracts_to_temp_dir() {
+        let dir = TempDir::new().unwrap();
+        let compressed = zstd::encode_all(&b"traineddata"[..], 3).unwrap();
+        fs::create_dir_all(dir.path().join("tessdata")).unwrap();
+        fs::write(dir.path().join("tessdata/eng.traineddata.zst"), compressed).unwrap();
+        let cache = ModelCache::with_dir(dir.path().to_path_buf(), true, MAX_SIZE);
+
+        let path = cache.path("tessdata/eng.traineddata", URL).unwrap();
+
+        assert!(!path.starts_with(dir.path()));
+        assert!(path.ends_with("tessdata/eng.traineddata"));
+        assert_eq!(fs::read(path).unwrap(), b"traineddata");
+    }
+
+    #[test]
+    fn test_uncompressed_cache_converts_existing_compressed_file() {
+        let dir = TempDir::new().unwrap();
+        let compressed = zstd::encode_all(&b"weights"[..], 3).unwrap();
+        fs::write(dir.path().join("model.rten.zst"), compressed).unwrap();
+        let cache = ModelCache::with_dir(dir.path().to_path_buf(), false, MAX_SIZE);
+
+        assert_eq!(cache.load("model.rten", URL).unwrap(), b"weights");
+        assert!(dir.path().join("model.rten").exists());
+        assert!(!dir.path().join("model.rten.zst").exists());
+    }
+
+    #[test]
+    fn test_records_checksum_of_cached_model() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("model.rten"), b"weights").unwrap();
+        let cache =
+            ModelCache::with_dir(dir.path().to_path_buf(), false, MAX_SIZE).with_checksums(true);
+
+        cache.load("model.rten", URL).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("model.rten.sha256")).unwrap(),
+            sha256_hex(b"weights")
+        );
+        assert!(cache.verify(false).is_empty());
+    }
+
+    #[test]
+    fn test_read_only_cache_dir_loads_without_checksums() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("model.rten"), b"weights").unwrap();
+        let writable = fs::metadata(dir.path()).unwrap().permissions();
+        let mut read_only = writable.clone();
+        read_only.set_readonly(true);
+        fs::set_permissions(dir.path(), read_only).unwrap();
+
+        let cache = ModelCache::with_dir(dir.path().to_path_buf(), false, MAX_SIZE);
+        assert_eq!(cache.load("model.rten", URL).unwrap(), b"weights");
+        assert!(!dir.path().join("model.rten.sha256").exists());
+
+        fs::set_permissions(dir.path(), writable).unwrap();
+    }
+
+    #[test]
+    fn test_unwritable_checksum_does_not_fail_load() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("model.rten"), b"weights").unwrap();
+        // A directory where the compressed copy's checksum would go can't be
+        // written over, even by root
+        fs::create_dir(dir.path().join("model.rten.zst.sha256")).unwrap();
+        let cache =
+            ModelCache::with_dir(dir.path().to_path_buf(), true, MAX_SIZE).with_checksums(true);
+
+        assert_eq!(cache.load("model.rten", URL).unwrap(), b"weights");
+        assert!(dir.path().join("model.rten.zst").exists());
+    }
+
+    #[test]
+    fn test_verify_detects_corruption() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("model.rten"), b"weights").unwrap();
+        let cache =
+            ModelCache::with_dir(dir.path().to_path_buf(), false, MAX_SIZE).with_checksums(true);
+        cache.load("model.rten", URL).unwrap();
+
+        fs::write(dir.path().join("model.rten"), b"wEights").unwrap();
+
+        assert_eq!(cache.verify(false), vec!["model.rten"]);
+        // Without repair the file is left alone
+        assert_eq!(fs::read(dir.path().join("model.rten")).unwrap(), b"wEights");
+    }
+
+    #[test]
+    fn test_verify_repairs_corrupted_model() {
+        let dir = TempDir::new().unwrap();
+        let url = serve_once(4096, true);
+        let cache =
+            ModelCache::with_dir(dir.path().to_path_buf(), true, MAX_SIZE).with_checksums(true);
+        fs::write(dir.path().join("model.rten"), vec![1u8; 4096]).unwrap();
+        cache.load("model.rten", &url).unwrap();
+
+        fs::write(dir.path().join("model.rten.zst"), b"garbage").unwrap();
+
+        assert_eq!(cache.verify(true), vec!["model.rten"]);
+        assert!(cache.verify(false).is_empty());
+        assert_eq!(cache.load("model.rten", &url).unwrap(), vec![1u8; 4096]);
+    }
+
+    #[test]
+    fn test_cached_lists_models_on_disk() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("model.rten"), b"weights").unwrap();
+        fs::create_dir(dir.path().join("tessdata")).unwrap();
+        fs::write(dir.path().join("tessdata/deu.traineddata.zst"), b"zst").unwrap();
+        // Checksums and unfinished downloads aren't models
+        fs::write(dir.path().join("model.rten.sha256"), b"abc").unwrap();
+        fs::write(dir.path().join(".tmpA1b2C3"), b"partial").unwrap();
+        let cache = ModelCache::with_dir(dir.path().to_path_buf(), true, MAX_SIZE);
+
+        assert_eq!(
+            cache.cached(),
+            vec![
+                CachedModel {
+                    name: "model.rten".to_string(),
+                    size_bytes: 7,
+                },
+                CachedModel {
+                    name: "tessdata/deu.traineddata".to_string(),
+                    size_bytes: 3,
+                },
+            ]
+        );
+        assert!(
+            ModelCache::with_dir(dir.path().join("missing"), true, MAX_SIZE)
+                .cached()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_download_rejects_host_not_allowed() {
+        let dir = TempDir::new().unwrap();
+        let cache = ModelCache::with_dir(dir.path().to_path_buf(), false, MAX_SIZE)
+            .with_allowed_hosts(Some(vec!["models.example.com".to_string()]));
+
+        let err = cache.load("model.rten", URL).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("host 'invalid.invalid' is not in the allowed"));
+    }
+
+    #[test]
+    fn test_download_checks_redirect_targets() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/model.rten", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request);
+            let _ = stream.write_all(
+                b"HTTP/1.1 302 Found\r\nLocation: http://elsewhere.invalid/model.rten\r\n\
+                  Content-Length: 0\r\n\r\n",
+            );
+        });
+
+        let dir = TempDir::new().unwrap();
+        let cache = ModelCache::with_dir(dir.path().to_path_buf(), false, MAX_SIZE)
+            .with_allowed_hosts(Some(vec!["127.0.0.1".to_string()]));
+
+        let err = cache.load("model.rten", &url).unwrap_err();
+        assert!(err.to_string().contains("host 'elsewhere.invalid'"));
+    }
+
+    /// Serve a single HTTP response with `body_len` bytes, returning its URL
+    fn serve_once(body_len: usize, announce_length: bool) -> String {
+        let headers = if announce_length {
+            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body_len)
+        } else {
+            "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n".to_string()
+        };
+        test_support::serve_once("/model.rten", &headers, vec![1u8; body_len])
+    }
+
+    #[test]
+    fn test_download_streams_into_cache() {
+        let dir = TempDir::new().unwrap();
+        let url = serve_once(4096, true);
+        let cache = ModelCache::with_dir(dir.path().to_path_buf(), true, MAX_SIZE);
+
+        assert_eq!(cache.load("model.rten", &url).unwrap(), vec![1u8; 4096]);
+        assert!(dir.path().join("model.rten.zst").exists());
+    }
+
+    #[test]
+    fn test_download_falls_back_to_later_urls() {
+        let dir = TempDir::new().unwrap();
+        // Nothing listens on port 1, so the first download fails
+        let urls = [
+            "http://127.0.0.1:1/model.rten".to_string(),
+            serve_once(4096, true),
+        ];
+        let cache = ModelCache::with_dir(dir.path().to_path_buf(), false, MAX_SIZE);
+
+        let path = cache.path_from_any("model.rten", &urls).unwrap();
+        assert_eq!(fs::read(path).unwrap(), vec![1u8; 4096]);
+
+        assert!(cache.path_from_any("other.rten", &urls[..1]).is_err());
+    }
+
+    #[test]
+    fn test_download_reports_missing_files() {
+        let dir = TempDir::new().unwrap();
+        let cache = ModelCache::with_dir(dir.path().to_path_buf(), false, MAX_SIZE);
+        let missing = || {
+            test_support::serve_once(
+                "/model.rten",
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+                Vec::new(),
+            )
+        };
+
+        let err = cache
+            .path_from_any("model.rten", &[missing(), missing()])
+            .unwrap_err();
+        assert!(matches!(err, OcrError::NotFound(_)), "{}", err);
+
+        // A source that failed otherwise might have had it
+        let urls = ["http://127.0.0.1:1/model.rten".to_string(), missing()];
+        let err = cache.path_from_any("model.rten", &urls).unwrap_err();
+        assert!(matches!(err, OcrError::InitializationError(_)), "{}", err);
+
+        // Configured models are expected to exist
+        let err = cache.load("model.rten", &missing()).unwrap_err();
+        assert!(matches!(err, OcrError::InitializationError(_)), "{}", err);
+    }
+
+    #[test]
+    fn test_download_rejects_announced_oversized_model() {
+        let dir = TempDir::new().unwrap();
+        let url = serve_once(4096, true);
+        let cache = ModelCache::with_dir(dir.path().to_path_buf(), false, 1024);
+
+        let err = cache.load("model.rten", &url).unwrap_err();
+        assert!(err.to_string().contains("maximum model size"));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_download_aborts_unannounced_oversized_model() {
+        let dir = TempDir::new().unwrap();
+        let url = serve_once(4096, false);
+        let cache = ModelCache::with_dir(dir.path().to_path_buf(), false, 1024);
+
+        let err = cache.load("model.rten", &url).unwrap_err();
+        assert!(err.to_string().contains("maximum model size"));
+        // The partial download is cleaned up
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
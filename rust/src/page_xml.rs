@@ -0,0 +1,371 @@
+//! PAGE-XML output
+//!
+//! Renders an OCR response as PAGE-XML (PRImA's Page Analysis and Ground
+//! truth Elements format), as consumed by digitization and IIIF pipelines.
+//! Engines only report word boxes, so lines are rebuilt from the order and
+//! vertical overlap of the words, and regions from the gaps between lines.
+
+use crate::server::{OcrResponse, TextBoxResponse};
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Content type of PAGE-XML responses
+pub const CONTENT_TYPE: &str = "application/vnd.prima.page+xml";
+
+const NAMESPACE: &str = "http://schema.primaresearch.org/PAGE/gts/pagecontent/2019-07-15";
+
+/// Gap between lines, relative to their average height, that starts a new region
+const REGION_GAP: f32 = 1.5;
+
+/// Axis-aligned rectangle in image pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+impl Rect {
+    pub fn of(word: &TextBoxResponse) -> Self {
+        Self {
+            x: word.x,
+            y: word.y,
+            right: word.x + word.width,
+            bottom: word.y + word.height,
+        }
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            x: self.x.min(other.x),
+            y: self.y.min(other.y),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    fn height(&self) -> u32 {
+        self.bottom - self.y
+    }
+
+    /// Corner points in PAGE's `x,y x,y ...` notation, clockwise from top left
+    fn points(&self) -> String {
+        format!(
+            "{x},{y} {r},{y} {r},{b} {x},{b}",
+            x = self.x,
+            y = self.y,
+            r = self.right,
+            b = self.bottom
+        )
+    }
+}
+
+/// Words on one line, in reading order
+pub(crate) struct Line<'a> {
+    pub words: Vec<&'a TextBoxResponse>,
+    pub bounds: Rect,
+}
+
+/// Render a response's word boxes as a PAGE-XML document for one image
+pub fn render(
+    response: &OcrResponse,
+    image_width: u32,
+    image_height: u32,
+    now: SystemTime,
+) -> String {
+    let words = response
+        .boxes
+        .as_ref()
+        .map(|boxes| boxes.boxes.as_slice())
+        .unwrap_or_default();
+    let timestamp = timestamp(now);
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(xml, "<PcGts xmlns=\"{}\">", NAMESPACE);
+    xml.push_str("  <Metadata>\n");
+    let _ = writeln!(
+        xml,
+        "    <Creator>activestorage-ocr-server {} ({})</Creator>",
+        env!("CARGO_PKG_VERSION"),
+        escape(&response.engine)
+    );
+    let _ = writeln!(xml, "    <Created>{}</Created>", timestamp);
+    let _ = writeln!(xml, "    <LastChange>{}</LastChange>", timestamp);
+    xml.push_str("  </Metadata>\n");
+    let _ = writeln!(
+        xml,
+        "  <Page imageFilename=\"\" imageWidth=\"{}\" imageHeight=\"{}\">",
+        image_width, image_height
+    );
+
+    for (r, region) in group_regions(group_lines(words)).iter().enumerate() {
+        let region_id = format!("r{}", r + 1);
+        let bounds = region
+            .iter()
+            .map(|line| line.bounds)
+            .reduce(Rect::union)
+            .expect("regions are never empty");
+
+        let _ = writeln!(
+            xml,
+            "    <TextRegion id=\"{}\" type=\"paragraph\">",
+            region_id
+        );
+        let _ = writeln!(xml, "      <Coords points=\"{}\"/>", bounds.points());
+
+        for (l, line) in region.iter().enumerate() {
+            let line_id = format!("{}_l{}", region_id, l + 1);
+            let _ = writeln!(xml, "      <TextLine id=\"{}\">", line_id);
+            let _ = writeln!(xml, "        <Coords points=\"{}\"/>", line.bounds.points());
+
+            for (w, word) in line.words.iter().enumerate() {
+                let _ = writeln!(xml, "        <Word id=\"{}_w{}\">", line_id, w + 1);
+                let _ = writeln!(
+                    xml,
+                    "          <Coords points=\"{}\"/>",
+                    Rect::of(word).points()
+                );
+                let conf = word
+                    .confidence
+                    .map(|c| format!(" conf=\"{:.2}\"", c.clamp(0.0, 1.0)))
+                    .unwrap_or_default();
+                let _ = writeln!(
+                    xml,
+                    "          <TextEquiv{}><Unicode>{}</Unicode></TextEquiv>",
+                    conf,
+                    escape(&word.text)
+                );
+                xml.push_str("        </Word>\n");
+            }
+
+            let _ = writeln!(
+                xml,
+                "        <TextEquiv><Unicode>{}</Unicode></TextEquiv>",
+                escape(&line_text(line))
+            );
+            xml.push_str("      </TextLine>\n");
+        }
+
+        let text: Vec<String> = region.iter().map(line_text).collect();
+        let _ = writeln!(
+            xml,
+            "      <TextEquiv><Unicode>{}</Unicode></TextEquiv>",
+            escape(&text.join("\n"))
+        );
+        xml.push_str("    </TextRegion>\n");
+    }
+
+    xml.push_str("  </Page>\n");
+    xml.push_str("</PcGts>\n");
+    xml
+}
+
+/// Group words into lines
+///
+/// Engines report words line by line in reading order, so a word starts a
+/// new line when it is left of the previous word or doesn't overlap it
+/// vertically by at least half the smaller height.
+pub(crate) fn group_lines(words: &[TextBoxResponse]) -> Vec<Line<'_>> {
+    let mut lines: Vec<Line> = Vec::new();
+
+    for word in words {
+        let rect = Rect::of(word);
+        if let Some(line) = lines.last_mut() {
+            let previous = Rect::of(line.words.last().expect("lines are never empty"));
+            let overlap = rect
+                .bottom
+                .min(previous.bottom)
+                .saturating_sub(rect.y.max(previous.y));
+            let same_line =
+                rect.x >= previous.x && overlap * 2 >= rect.height().min(previous.height());
+            if same_line {
+                line.words.push(word);
+                line.bounds = line.bounds.union(rect);
+                continue;
+            }
+        }
+        lines.push(Line {
+            words: vec![word],
+            bounds: rect,
+        });
+    }
+
+    lines
+}
+
+/// Group consecutive lines into regions, splitting at large vertical gaps
+pub(crate) fn group_regions(lines: Vec<Line<'_>>) -> Vec<Vec<Line<'_>>> {
+    let mut regions: Vec<Vec<Line>> = Vec::new();
+
+    for line in lines {
+        if let Some(region) = regions.last_mut() {
+            let previous = region.last().expect("regions are never empty").bounds;
+            let average_height = (previous.height() + line.bounds.height()) as f32 / 2.0;
+            let gap = line.bounds.y.saturating_sub(previous.bottom) as f32;
+            // Lines above the previous one start a new column
+            if line.bounds.y >= previous.y && gap <= average_height * REGION_GAP {
+                region.push(line);
+                continue;
+            }
+        }
+        regions.push(vec![line]);
+    }
+
+    regions
+}
+
+fn line_text(line: &Line) -> String {
+    let words: Vec<&str> = line.words.iter().map(|word| word.text.as_str()).collect();
+    words.join(" ")
+}
+
+/// Escape text for XML content and attribute values
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Format a time as an `xsd:dateTime` in UTC
+fn timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{BoxesResponse, ConfidenceStats};
+    use std::time::Duration;
+
+    fn word(text: &str, x: u32, y: u32, width: u32) -> TextBoxResponse {
+        TextBoxResponse {
+            text: text.to_string(),
+            page: 1,
+            x,
+            y,
+            width,
+            height: 20,
+            confidence: Some(0.9),
+            polygon: None,
+        }
+    }
+
+    fn response(words: Vec<TextBoxResponse>) -> OcrResponse {
+        OcrResponse {
+            schema_version: 1,
+            text: String::new(),
+            raw_text: None,
+            truncated: None,
+            confidence: Some(0.9),
+            confidence_stats: Some(ConfidenceStats::new(0.9, None)),
+            word_confidences: None,
+            processing_time_ms: 0,
+            warnings: Vec::new(),
+            engine: "ocrs".to_string(),
+            detected_format: "image/png".to_string(),
+            filename: None,
+            no_text_detected: false,
+            orientation_degrees: None,
+            preprocessing: None,
+            boxes: Some(BoxesResponse {
+                level: "word".to_string(),
+                boxes: words,
+            }),
+            lines: None,
+            regions: None,
+            pdf_verification: None,
+        }
+    }
+
+    #[test]
+    fn test_groups_words_into_lines_and_regions() {
+        let words = vec![
+            word("Hello", 10, 10, 50),
+            word("world", 70, 12, 50),
+            word("second", 10, 40, 60),
+            // Far below the first paragraph
+            word("Footer", 10, 200, 60),
+        ];
+
+        let regions = group_regions(group_lines(&words));
+
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].len(), 2);
+        assert_eq!(line_text(&regions[0][0]), "Hello world");
+        assert_eq!(
+            regions[0][0].bounds,
+            Rect {
+                x: 10,
+                y: 10,
+                right: 120,
+                bottom: 32
+            }
+        );
+        assert_eq!(line_text(&regions[1][0]), "Footer");
+    }
+
+    #[test]
+    fn test_render_produces_page_xml() {
+        let xml = render(
+            &response(vec![word("A&B", 10, 10, 50), word("<c>", 70, 10, 30)]),
+            640,
+            480,
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        );
+
+        assert!(xml.contains(NAMESPACE));
+        assert!(xml.contains("<Created>2023-11-14T22:13:20Z</Created>"));
+        assert!(xml.contains("imageWidth=\"640\" imageHeight=\"480\""));
+        assert!(xml.contains("<TextRegion id=\"r1\""));
+        assert!(xml.contains("<TextLine id=\"r1_l1\">"));
+        assert!(xml.contains("<Word id=\"r1_l1_w2\">"));
+        assert!(xml.contains("<Coords points=\"10,10 60,10 60,30 10,30\"/>"));
+        assert!(xml.contains("<TextEquiv conf=\"0.90\"><Unicode>A&amp;B</Unicode>"));
+        assert!(xml.contains("<Unicode>A&amp;B &lt;c&gt;</Unicode>"));
+    }
+
+    #[test]
+    fn test_render_without_words_has_empty_page() {
+        let xml = render(&response(Vec::new()), 10, 10, UNIX_EPOCH);
+
+        assert!(xml.contains("<Created>1970-01-01T00:00:00Z</Created>"));
+        assert!(!xml.contains("<TextRegion"));
+        assert!(xml.contains("</Page>"));
+    }
+}
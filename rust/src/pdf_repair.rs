@@ -0,0 +1,501 @@
+//! Recovery of malformed and password-protected PDFs
+//!
+//! Cheap scanners and some PDF writers produce files whose cross-reference
+//! table is missing, truncated or points at the wrong offsets. Viewers cope by
+//! scanning the file for `N G obj` headers instead of trusting the table; this
+//! does the same and appends the rebuilt table as an incremental update, so
+//! the original bytes (including encrypted objects) are left untouched.
+//!
+//! Encrypted PDFs are decrypted in memory with the password sent along with
+//! the upload, so the engines can read them.
+
+use crate::error::OcrError;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Trailer entries carried over to the rebuilt trailer
+const TRAILER_KEYS: [&[u8]; 4] = [b"/Root", b"/Info", b"/ID", b"/Encrypt"];
+
+/// Rebuild the cross-reference table of a PDF that fails to load
+///
+/// Returns None if the PDF loads as is. Encrypted objects can't be parsed
+/// without the password, so the structure is checked with the /Encrypt key
+/// hidden, as in `decrypt_pdf`.
+pub fn repair_pdf(data: &[u8]) -> Result<Option<Vec<u8>>, OcrError> {
+    let error = match lopdf::Document::load_mem(&mask_encrypt_key(data)) {
+        Ok(_) => return Ok(None),
+        Err(e) => e,
+    };
+    let failed = || OcrError::ProcessingError(format!("Failed to load PDF: {}", error));
+
+    let (repaired, objects) = rebuild_xref(data).ok_or_else(failed)?;
+    lopdf::Document::load_mem(&mask_encrypt_key(&repaired)).map_err(|_| failed())?;
+
+    tracing::info!(
+        "Repaired malformed PDF ({}), rebuilt cross-reference table for {} objects",
+        error,
+        objects
+    );
+
+    Ok(Some(repaired))
+}
+
+/// Decrypt a password-protected PDF in memory so the engines can read it
+///
+/// Returns None if the PDF is not encrypted. The password is never logged or
+/// included in error messages.
+pub fn decrypt_pdf(data: &[u8], password: &str) -> Result<Option<Vec<u8>>, OcrError> {
+    use lopdf::encryption::DecryptionError;
+
+    // lopdf only parses the objects of encrypted documents whose user password
+    // is empty, so load with the /Encrypt trailer key hidden and restore it
+    // before decrypting with the real password
+    let masked = mask_encrypt_key(data);
+    let mut doc = lopdf::Document::load_mem(&masked)
+        .map_err(|e| OcrError::ProcessingError(format!("Failed to load PDF: {}", e)))?;
+
+    let encrypt = match doc.trailer.remove(&HIDDEN_ENCRYPT_KEY[1..]) {
+        Some(encrypt) => encrypt,
+        None => return Ok(None),
+    };
+    doc.trailer.set("Encrypt", encrypt);
+
+    doc.decrypt(password).map_err(|e| match e {
+        lopdf::Error::Decryption(DecryptionError::IncorrectPassword) => {
+            OcrError::InvalidRequest("Incorrect PDF password".to_string())
+        }
+        lopdf::Error::Decryption(
+            DecryptionError::UnsupportedEncryption
+            | DecryptionError::UnsupportedVersion
+            | DecryptionError::UnsupportedRevision,
+        ) => OcrError::UnsupportedFormat(format!("Unsupported PDF encryption: {}", e)),
+        _ => OcrError::ProcessingError(format!("Failed to decrypt PDF: {}", e)),
+    })?;
+
+    let mut decrypted = Vec::new();
+    doc.save_to(&mut decrypted)
+        .map_err(|e| OcrError::Internal(format!("Failed to write decrypted PDF: {}", e)))?;
+
+    tracing::info!("Decrypted password-protected PDF");
+
+    Ok(Some(decrypted))
+}
+
+/// Same length as "/Encrypt" so xref byte offsets stay valid
+const HIDDEN_ENCRYPT_KEY: &[u8] = b"/EncHide";
+
+/// Rename the /Encrypt trailer key (but not e.g. /EncryptMetadata)
+fn mask_encrypt_key(data: &[u8]) -> Vec<u8> {
+    const KEY: &[u8] = b"/Encrypt";
+
+    let mut masked = data.to_vec();
+    let mut i = 0;
+    while i + KEY.len() < masked.len() {
+        let next = masked[i + KEY.len()];
+        let ends_name = next.is_ascii_whitespace() || b"/<[(".contains(&next);
+        if &masked[i..i + KEY.len()] == KEY && ends_name {
+            masked[i..i + KEY.len()].copy_from_slice(HIDDEN_ENCRYPT_KEY);
+            i += KEY.len();
+        } else {
+            i += 1;
+        }
+    }
+    masked
+}
+
+/// Append a cross-reference table rebuilt from the objects found in a PDF
+///
+/// Returns the repaired PDF (starting at its `%PDF-` header) and the number
+/// of objects found, or `None` if no document catalog could be located.
+fn rebuild_xref(data: &[u8]) -> Option<(Vec<u8>, usize)> {
+    let start = find(data, b"%PDF-", 0)?;
+    let data = &data[start..];
+
+    let objects = scan_objects(data);
+    if objects.is_empty() {
+        return None;
+    }
+
+    let mut entries: Vec<(&[u8], Vec<u8>)> = Vec::new();
+    for dict in trailer_dicts(data, &objects).iter().rev() {
+        for key in TRAILER_KEYS {
+            if entries.iter().all(|(k, _)| *k != key) {
+                if let Some(value) = dict_value(dict, key) {
+                    entries.push((key, value.to_vec()));
+                }
+            }
+        }
+    }
+    if entries.iter().all(|(key, _)| *key != b"/Root") {
+        let (number, generation) = find_catalog(data, &objects)?;
+        entries.push((
+            b"/Root",
+            format!("{} {} R", number, generation).into_bytes(),
+        ));
+    }
+
+    let mut repaired = data.to_vec();
+    if !repaired.ends_with(b"\n") {
+        repaired.push(b'\n');
+    }
+    let xref_start = repaired.len();
+
+    let size = objects.keys().next_back().map_or(1, |max| max + 1);
+    let mut xref = String::from("xref\n0 1\n0000000000 65535 f \n");
+    let ids: Vec<u32> = objects.keys().copied().collect();
+    for run in ids.chunk_by(|a, b| b - a == 1) {
+        let _ = writeln!(xref, "{} {}", run[0], run.len());
+        for number in run {
+            let (generation, offset) = objects[number];
+            let _ = writeln!(xref, "{:010} {:05} n ", offset, generation);
+        }
+    }
+    let _ = write!(xref, "trailer\n<< /Size {}", size);
+    repaired.extend_from_slice(xref.as_bytes());
+    for (key, value) in &entries {
+        repaired.push(b' ');
+        repaired.extend_from_slice(key);
+        repaired.push(b' ');
+        repaired.extend_from_slice(value);
+    }
+    repaired.extend_from_slice(format!(" >>\nstartxref\n{}\n%%EOF\n", xref_start).as_bytes());
+
+    Some((repaired, objects.len()))
+}
+
+/// Find `N G obj` headers at line starts, mapping object numbers to their
+/// generation and offset (later definitions win, as in incremental updates)
+fn scan_objects(data: &[u8]) -> BTreeMap<u32, (u16, usize)> {
+    let mut objects = BTreeMap::new();
+
+    for offset in 0..data.len() {
+        if offset > 0 && !matches!(data[offset - 1], b'\n' | b'\r') {
+            continue;
+        }
+        if let Some((number, generation)) = parse_object_header(&data[offset..]) {
+            objects.insert(number, (generation, offset));
+        }
+    }
+
+    objects
+}
+
+/// Parse `N G obj` at the start of `data`
+fn parse_object_header(data: &[u8]) -> Option<(u32, u16)> {
+    let (number, rest) = parse_number(data)?;
+    let rest = skip_whitespace(rest);
+    let (generation, rest) = parse_number(rest)?;
+    let rest = skip_whitespace(rest);
+
+    let after = rest.get(3).copied();
+    let ends_keyword = after.is_none_or(|b| b.is_ascii_whitespace() || b"<[/(".contains(&b));
+    if !rest.starts_with(b"obj") || !ends_keyword {
+        return None;
+    }
+
+    Some((u32::try_from(number).ok()?, u16::try_from(generation).ok()?))
+}
+
+/// Parse a run of digits followed by whitespace
+fn parse_number(data: &[u8]) -> Option<(u64, &[u8])> {
+    let digits = data.iter().take_while(|b| b.is_ascii_digit()).count();
+    if digits == 0 || digits > 10 || !data.get(digits)?.is_ascii_whitespace() {
+        return None;
+    }
+    let number = std::str::from_utf8(&data[..digits]).ok()?.parse().ok()?;
+    Some((number, &data[digits..]))
+}
+
+fn skip_whitespace(data: &[u8]) -> &[u8] {
+    let n = data.iter().take_while(|b| b.is_ascii_whitespace()).count();
+    &data[n..]
+}
+
+/// Dictionaries that may hold trailer entries, in file order: those after
+/// `trailer` keywords and those of cross-reference streams
+fn trailer_dicts<'a>(data: &'a [u8], objects: &BTreeMap<u32, (u16, usize)>) -> Vec<&'a [u8]> {
+    let mut found: Vec<(usize, &[u8])> = Vec::new();
+
+    let mut pos = 0;
+    while let Some(at) = find(data, b"trailer", pos) {
+        if let Some(dict) = find(data, b"<<", at).and_then(|open| dict_at(data, open)) {
+            found.push((at, dict));
+        }
+        pos = at + 1;
+    }
+
+    for &(_, offset) in objects.values() {
+        let body = object_body(data, offset);
+        if let Some(dict) = find(body, b"<<", 0).and_then(|open| dict_at(body, open)) {
+            if has_type(dict, b"/XRef") {
+                found.push((offset, dict));
+            }
+        }
+    }
+
+    found.sort_by_key(|(at, _)| *at);
+    found.into_iter().map(|(_, dict)| dict).collect()
+}
+
+/// Locate the document catalog among the scanned objects (the last one wins)
+fn find_catalog(data: &[u8], objects: &BTreeMap<u32, (u16, usize)>) -> Option<(u32, u16)> {
+    objects
+        .iter()
+        .filter(|(_, (_, offset))| {
+            let body = object_body(data, *offset);
+            find(body, b"<<", 0)
+                .and_then(|open| dict_at(body, open))
+                .is_some_and(|dict| has_type(dict, b"/Catalog"))
+        })
+        .max_by_key(|(_, (_, offset))| *offset)
+        .map(|(number, (generation, _))| (*number, *generation))
+}
+
+/// Bytes of the object starting at `offset`, up to its `endobj` (or the end
+/// of a truncated file)
+fn object_body(data: &[u8], offset: usize) -> &[u8] {
+    let end = find(data, b"endobj", offset).unwrap_or(data.len());
+    &data[offset..end]
+}
+
+/// Whether a dictionary has `/Type` equal to the given name
+fn has_type(dict: &[u8], name: &[u8]) -> bool {
+    dict_value(dict, b"/Type").is_some_and(|value| value == name)
+}
+
+/// The dictionary starting with the `<<` at `open`, including its delimiters
+fn dict_at(data: &[u8], open: usize) -> Option<&[u8]> {
+    let mut depth = 0;
+    let mut i = open;
+    while i + 1 < data.len() {
+        match &data[i..i + 2] {
+            b"<<" => {
+                depth += 1;
+                i += 2;
+            }
+            b">>" => {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return Some(&data[open..i]);
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Raw value of a top-level key in a dictionary: a reference, array,
+/// dictionary or name
+fn dict_value<'a>(dict: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    let mut pos = 2;
+    let mut depth = 0;
+
+    while pos < dict.len() {
+        let rest = &dict[pos..];
+        if rest.starts_with(b"<<") || rest.starts_with(b"[") {
+            depth += 1;
+        } else if rest.starts_with(b">>") || rest.starts_with(b"]") {
+            depth -= 1;
+        } else if depth == 0 && rest.starts_with(key) {
+            let next = rest.get(key.len()).copied();
+            if next.is_some_and(|b| b.is_ascii_whitespace() || b"<[/(".contains(&b)) {
+                return value_at(skip_whitespace(&rest[key.len()..]));
+            }
+        }
+        pos += if rest.starts_with(b"<<") || rest.starts_with(b">>") {
+            2
+        } else {
+            1
+        };
+    }
+
+    None
+}
+
+/// The value at the start of `data`
+fn value_at(data: &[u8]) -> Option<&[u8]> {
+    match data.first()? {
+        b'[' => {
+            let end = data.iter().position(|&b| b == b']')?;
+            Some(&data[..=end])
+        }
+        b'<' if data.starts_with(b"<<") => dict_at(data, 0),
+        b'/' => {
+            let end = data[1..]
+                .iter()
+                .position(|b| b.is_ascii_whitespace() || b"/<>[]()".contains(b))
+                .map_or(data.len(), |n| n + 1);
+            Some(&data[..end])
+        }
+        _ => {
+            // Indirect reference: N G R
+            let (_, rest) = parse_number(data)?;
+            let rest = skip_whitespace(rest);
+            let (_, rest) = parse_number(rest)?;
+            let rest = skip_whitespace(rest);
+            if !rest.starts_with(b"R") {
+                return None;
+            }
+            Some(&data[..data.len() - rest.len() + 1])
+        }
+    }
+}
+
+/// Position of the first occurrence of `pattern` at or after `from`
+fn find(data: &[u8], pattern: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?
+        .windows(pattern.len())
+        .position(|w| w == pattern)
+        .map(|i| i + from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{Document, EncryptionState, EncryptionVersion, Permissions};
+
+    const SAMPLE_PDF: &[u8] = include_bytes!("../tests/fixtures/sample_text.pdf");
+
+    fn page_count(data: &[u8]) -> usize {
+        Document::load_mem(data).unwrap().get_pages().len()
+    }
+
+    /// A PDF with everything from its (last) xref table on cut off
+    fn without_xref(pdf: &[u8]) -> Vec<u8> {
+        let xref = pdf
+            .windows(5)
+            .rposition(|w| w == b"xref\n" || w == b"xref\r")
+            .unwrap();
+        pdf[..xref].to_vec()
+    }
+
+    #[test]
+    fn test_rebuilds_missing_xref_and_trailer() {
+        let broken = without_xref(SAMPLE_PDF);
+        assert!(Document::load_mem(&broken).is_err());
+
+        let (repaired, objects) = rebuild_xref(&broken).unwrap();
+
+        assert!(objects > 0);
+        assert_eq!(page_count(&repaired), page_count(SAMPLE_PDF));
+    }
+
+    #[test]
+    fn test_rebuilds_xref_with_wrong_offsets() {
+        // Junk before the header shifts every offset in the original table
+        let mut broken = b"garbage from a scanner\n".to_vec();
+        broken.extend_from_slice(&without_xref(SAMPLE_PDF));
+        broken.extend_from_slice(b"xref\n0 2\n0000000000 65535 f \n0000000999 00000 n \n");
+
+        let (repaired, _) = rebuild_xref(&broken).unwrap();
+
+        assert!(repaired.starts_with(b"%PDF-"));
+        assert_eq!(page_count(&repaired), page_count(SAMPLE_PDF));
+    }
+
+    #[test]
+    fn test_rejects_data_without_objects() {
+        assert!(rebuild_xref(b"%PDF-1.4\nnothing here\n").is_none());
+        assert!(rebuild_xref(b"not a pdf").is_none());
+    }
+
+    #[test]
+    fn test_dict_value() {
+        let dict = b"<< /Size 9 /Root 1 0 R /ID [<ab> <cd>] /Info<< /Title (x) >> /Type /XRef >>";
+
+        assert_eq!(dict_value(dict, b"/Root"), Some(&b"1 0 R"[..]));
+        assert_eq!(dict_value(dict, b"/ID"), Some(&b"[<ab> <cd>]"[..]));
+        assert_eq!(dict_value(dict, b"/Info"), Some(&b"<< /Title (x) >>"[..]));
+        assert_eq!(dict_value(dict, b"/Type"), Some(&b"/XRef"[..]));
+        assert_eq!(dict_value(dict, b"/Title"), None);
+        assert_eq!(dict_value(dict, b"/Encrypt"), None);
+    }
+
+    fn encrypted_sample_pdf(password: &str) -> Vec<u8> {
+        let mut doc = Document::load_mem(SAMPLE_PDF).unwrap();
+        // Encryption keys are derived from the file ID, which the fixture lacks
+        let file_id = lopdf::Object::string_literal(b"0123456789abcdef".to_vec());
+        doc.trailer
+            .set("ID", lopdf::Object::Array(vec![file_id.clone(), file_id]));
+        let state = EncryptionState::try_from(EncryptionVersion::V2 {
+            document: &doc,
+            owner_password: password,
+            user_password: password,
+            key_length: 128,
+            permissions: Permissions::all(),
+        })
+        .unwrap();
+        doc.encrypt(&state).unwrap();
+
+        let mut encrypted = Vec::new();
+        doc.save_to(&mut encrypted).unwrap();
+        encrypted
+    }
+
+    #[test]
+    fn test_decrypt_pdf_ignores_unencrypted_pdf() {
+        assert!(decrypt_pdf(SAMPLE_PDF, "secret").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_decrypt_pdf_with_correct_password() {
+        let encrypted = encrypted_sample_pdf("secret");
+        let decrypted = decrypt_pdf(&encrypted, "secret").unwrap().unwrap();
+        let doc = Document::load_mem(&decrypted).unwrap();
+        assert!(!doc.is_encrypted());
+
+        // The page image must round-trip unchanged
+        let original = Document::load_mem(SAMPLE_PDF).unwrap();
+        let image_id = (8, 0);
+        assert_eq!(
+            doc.get_object(image_id)
+                .unwrap()
+                .as_stream()
+                .unwrap()
+                .content,
+            original
+                .get_object(image_id)
+                .unwrap()
+                .as_stream()
+                .unwrap()
+                .content
+        );
+    }
+
+    #[test]
+    fn test_decrypt_pdf_rejects_wrong_password() {
+        let encrypted = encrypted_sample_pdf("secret");
+        let err = decrypt_pdf(&encrypted, "wrong").unwrap_err();
+        assert!(matches!(err, OcrError::InvalidRequest(_)));
+        assert!(!err.to_string().contains("wrong"));
+    }
+
+    #[test]
+    fn test_repair_pdf_leaves_valid_pdf_alone() {
+        assert!(repair_pdf(SAMPLE_PDF).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_repair_pdf_rebuilds_missing_xref() {
+        let repaired = repair_pdf(&without_xref(SAMPLE_PDF)).unwrap().unwrap();
+        assert_eq!(Document::load_mem(&repaired).unwrap().get_pages().len(), 1);
+    }
+
+    #[test]
+    fn test_repair_pdf_keeps_encryption() {
+        let broken = without_xref(&encrypted_sample_pdf("secret"));
+
+        let repaired = repair_pdf(&broken).unwrap().unwrap();
+        let decrypted = decrypt_pdf(&repaired, "secret").unwrap().unwrap();
+
+        assert!(!Document::load_mem(&decrypted).unwrap().is_encrypted());
+    }
+
+    #[test]
+    fn test_repair_pdf_fails_without_catalog() {
+        let err = repair_pdf(b"%PDF-1.4\n1 0 obj\n<< >>\nendobj\n").unwrap_err();
+        assert!(err.to_string().contains("Failed to load PDF"));
+    }
+}
@@ -0,0 +1,531 @@
+//! Searchable PDF output
+//!
+//! Adds OCRed text to the original PDF as an invisible text layer, so a
+//! scanned document becomes searchable and selectable while looking exactly
+//! as before. Words are drawn in text render mode 3 (neither filled nor
+//! stroked) over the page images they were read from. An existing invisible
+//! layer, such as a broken one left by an earlier OCR pass, is removed first;
+//! visible text is never touched.
+//!
+//! Engines number word boxes by image, in the order [`images`] lists them,
+//! counting images that fail to decode. Each image is matched to the page
+//! that draws it and is assumed to fill that page's media box, as scanned
+//! pages do.
+
+use crate::error::OcrError;
+use crate::server::TextBoxResponse;
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId};
+use std::collections::{BTreeMap, HashMap};
+
+/// Content type of searchable PDF responses
+pub const CONTENT_TYPE: &str = "application/pdf";
+
+/// Resource name of the text layer's font
+const FONT_NAME: &str = "OcrText";
+
+/// Average advance width of a Helvetica character, in ems
+const AVERAGE_CHAR_WIDTH: f32 = 0.5;
+
+/// Text render mode that neither fills nor strokes glyphs
+const INVISIBLE: i64 = 3;
+
+/// Add an invisible text layer of `words` to a PDF, returning the new PDF
+/// and warnings about words that could not be placed
+pub fn embed(pdf: &[u8], words: &[TextBoxResponse]) -> Result<(Vec<u8>, Vec<String>), OcrError> {
+    let pdf_error =
+        |e: lopdf::Error| OcrError::ProcessingError(format!("Failed to build PDF: {}", e));
+    let mut doc = Document::load_mem(pdf)
+        .map_err(|e| OcrError::ProcessingError(format!("Failed to load PDF: {}", e)))?;
+
+    // Numbered from 1, like the engines number them
+    let images = images(&doc, false);
+
+    let mut image_pages = HashMap::new();
+    for page_id in doc.page_iter() {
+        for image_id in page_images(&doc, page_id) {
+            image_pages.entry(image_id).or_insert(page_id);
+        }
+    }
+
+    let mut layers: BTreeMap<ObjectId, Vec<Operation>> = BTreeMap::new();
+    let mut unplaced = 0;
+    let mut replaced = 0;
+    for word in words {
+        let placed = (word.page as usize)
+            .checked_sub(1)
+            .and_then(|index| images.get(index))
+            .and_then(|&image_id| {
+                let page_id = *image_pages.get(&image_id)?;
+                let (width, height) = image_size(&doc, image_id)?;
+                Some((page_id, media_box(&doc, page_id)?, width, height))
+            });
+        match placed {
+            Some((page_id, media_box, width, height)) => {
+                replaced += word
+                    .text
+                    .chars()
+                    .filter(|&c| win_ansi_byte(c).is_none())
+                    .count();
+                layers
+                    .entry(page_id)
+                    .or_default()
+                    .extend(word_operations(word, media_box, width, height));
+            }
+            None => unplaced += 1,
+        }
+    }
+
+    let mut warnings = Vec::new();
+    if unplaced > 0 {
+        warnings.push(format!(
+            "{} words could not be matched to a PDF page and were left out of the text layer",
+            unplaced
+        ));
+    }
+    if replaced > 0 {
+        warnings.push(format!(
+            "{} characters can't be shown in the text layer's font and were replaced with '?'",
+            replaced
+        ));
+    }
+
+    if !layers.is_empty() {
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        });
+        for (page_id, operations) in layers {
+            strip_invisible_text(&mut doc, page_id).map_err(pdf_error)?;
+            add_font(&mut doc, page_id, font_id).map_err(pdf_error)?;
+
+            let mut layer = vec![
+                Operation::new("q", vec![]),
+                Operation::new("BT", vec![]),
+                Operation::new("Tr", vec![INVISIBLE.into()]),
+            ];
+            layer.extend(operations);
+            layer.push(Operation::new("ET", vec![]));
+            layer.push(Operation::new("Q", vec![]));
+            let layer = Content { operations: layer }.encode().map_err(pdf_error)?;
+            append_isolated_content(&mut doc, page_id, layer).map_err(pdf_error)?;
+        }
+    }
+
+    let mut output = Vec::new();
+    doc.save_to(&mut output)
+        .map_err(|e| OcrError::Internal(format!("Failed to write PDF: {}", e)))?;
+    Ok((output, warnings))
+}
+
+/// Image XObjects the pages of a PDF draw, in page order, which is the order
+/// engines OCR and number them; only the first page's if `first_page_only`
+/// is set
+///
+/// An image shared by several pages is listed once, under the first of them.
+pub fn images(doc: &Document, first_page_only: bool) -> Vec<ObjectId> {
+    let mut pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    if first_page_only {
+        pages.truncate(1);
+    }
+    let mut images = Vec::new();
+    for page_id in pages {
+        for image_id in page_images(doc, page_id) {
+            if !images.contains(&image_id) {
+                images.push(image_id);
+            }
+        }
+    }
+    images
+}
+
+fn is_image(object: &Object) -> bool {
+    object
+        .as_stream()
+        .and_then(|stream| stream.dict.get(b"Subtype"))
+        .and_then(Object::as_name)
+        .is_ok_and(|subtype| subtype == b"Image")
+}
+
+/// Width and height of an image in pixels
+fn image_size(doc: &Document, image_id: ObjectId) -> Option<(f32, f32)> {
+    let dict = &doc.get_object(image_id).ok()?.as_stream().ok()?.dict;
+    let size = |key: &[u8]| {
+        dict.get(key)
+            .and_then(Object::as_i64)
+            .ok()
+            .filter(|v| *v > 0)
+    };
+    Some((size(b"Width")? as f32, size(b"Height")? as f32))
+}
+
+/// Operations drawing one word, scaled from image pixels to page space
+fn word_operations(
+    word: &TextBoxResponse,
+    [left, bottom, right, top]: [f32; 4],
+    image_width: f32,
+    image_height: f32,
+) -> Vec<Operation> {
+    let scale_x = (right - left) / image_width;
+    let scale_y = (top - bottom) / image_height;
+    let size = word.height as f32 * scale_y;
+    let text = win_ansi(&word.text);
+    if size <= 0.0 || text.is_empty() {
+        return Vec::new();
+    }
+
+    // Stretch the text to the word's width, so selecting it highlights the word
+    let natural_width = AVERAGE_CHAR_WIDTH * size * text.len() as f32;
+    let stretch = 100.0 * word.width as f32 * scale_x / natural_width;
+    let x = left + word.x as f32 * scale_x;
+    // PDF's y axis points up, from the bottom of the page
+    let y = top - (word.y + word.height) as f32 * scale_y;
+
+    let mut shown = text;
+    // Lets text extraction see the gap between words
+    shown.push(b' ');
+    vec![
+        Operation::new("Tf", vec![Object::Name(FONT_NAME.into()), size.into()]),
+        Operation::new("Tz", vec![stretch.into()]),
+        Operation::new(
+            "Tm",
+            vec![1.into(), 0.into(), 0.into(), 1.into(), x.into(), y.into()],
+        ),
+        Operation::new("Tj", vec![Object::string_literal(shown)]),
+    ]
+}
+
+/// Encode text for the WinAnsi-encoded standard font, replacing characters
+/// it can't show with `?`
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| win_ansi_byte(c).unwrap_or(b'?'))
+        .collect()
+}
+
+/// A character's WinAnsi code, if it has one
+fn win_ansi_byte(c: char) -> Option<u8> {
+    let code = match c {
+        ' '..='~' | '\u{A0}'..='\u{FF}' => c as u8,
+        // Typographic punctuation and letters WinAnsi puts in 0x80-0x9F,
+        // where Latin-1 has control characters
+        '€' => 0x80,
+        '‚' => 0x82,
+        'ƒ' => 0x83,
+        '„' => 0x84,
+        '…' => 0x85,
+        '†' => 0x86,
+        '‡' => 0x87,
+        'ˆ' => 0x88,
+        '‰' => 0x89,
+        'Š' => 0x8A,
+        '‹' => 0x8B,
+        'Œ' => 0x8C,
+        'Ž' => 0x8E,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        '˜' => 0x98,
+        '™' => 0x99,
+        'š' => 0x9A,
+        '›' => 0x9B,
+        'œ' => 0x9C,
+        'ž' => 0x9E,
+        'Ÿ' => 0x9F,
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Resources of a page, inherited from its ancestors in the page tree if it
+/// has none of its own
+fn page_resources(doc: &Document, page_id: ObjectId) -> Option<&Dictionary> {
+    let mut node = doc.get_dictionary(page_id).ok()?;
+    // Bounded, in case a malformed page tree has a cycle
+    for _ in 0..32 {
+        if let Ok(resources) = node.get(b"Resources") {
+            return doc.dereference(resources).ok()?.1.as_dict().ok();
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = doc.get_dictionary(parent).ok()?;
+    }
+    None
+}
+
+/// Images a page can draw
+pub fn page_images(doc: &Document, page_id: ObjectId) -> Vec<ObjectId> {
+    let Some(xobjects) = page_resources(doc, page_id)
+        .and_then(|resources| resources.get(b"XObject").ok())
+        .and_then(|xobjects| doc.dereference(xobjects).ok()?.1.as_dict().ok())
+    else {
+        return Vec::new();
+    };
+    xobjects
+        .iter()
+        .filter_map(|(_, xobject)| xobject.as_reference().ok())
+        .filter(|&id| doc.get_object(id).is_ok_and(is_image))
+        .collect()
+}
+
+/// A page's media box as left, bottom, right, top, inherited if needed
+fn media_box(doc: &Document, page_id: ObjectId) -> Option<[f32; 4]> {
+    let mut node = doc.get_dictionary(page_id).ok()?;
+    for _ in 0..32 {
+        if let Ok(media_box) = node.get(b"MediaBox") {
+            let values = doc.dereference(media_box).ok()?.1.as_array().ok()?;
+            let values: Vec<f32> = values.iter().filter_map(|v| v.as_float().ok()).collect();
+            let [x1, y1, x2, y2] = values[..] else {
+                return None;
+            };
+            return Some([x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2)]);
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = doc.get_dictionary(parent).ok()?;
+    }
+    None
+}
+
+/// Remove text drawn in the invisible render mode from a page's content
+fn strip_invisible_text(doc: &mut Document, page_id: ObjectId) -> lopdf::Result<()> {
+    let Ok(content) = Content::decode(&doc.get_page_content(page_id)?) else {
+        // Leave content lopdf can't parse alone rather than lose any of it
+        return Ok(());
+    };
+
+    let mut mode = 0;
+    // The render mode is part of the graphics state saved by q and restored by Q
+    let mut saved = Vec::new();
+    let mut stripped = false;
+    let mut operations = Vec::with_capacity(content.operations.len());
+    for operation in content.operations {
+        match operation.operator.as_str() {
+            "q" => saved.push(mode),
+            "Q" => mode = saved.pop().unwrap_or(mode),
+            "Tr" => {
+                mode = operation
+                    .operands
+                    .first()
+                    .and_then(|mode| mode.as_i64().ok())
+                    .unwrap_or(mode)
+            }
+            "Tj" | "TJ" if mode == INVISIBLE => {
+                stripped = true;
+                continue;
+            }
+            // These also move to the next line, which later text relies on
+            "'" if mode == INVISIBLE => {
+                stripped = true;
+                operations.push(Operation::new("T*", vec![]));
+                continue;
+            }
+            "\"" if mode == INVISIBLE => {
+                stripped = true;
+                let mut operands = operation.operands.into_iter();
+                if let (Some(word_spacing), Some(char_spacing)) = (operands.next(), operands.next())
+                {
+                    operations.push(Operation::new("Tw", vec![word_spacing]));
+                    operations.push(Operation::new("Tc", vec![char_spacing]));
+                }
+                operations.push(Operation::new("T*", vec![]));
+                continue;
+            }
+            _ => {}
+        }
+        operations.push(operation);
+    }
+
+    if stripped {
+        let content = Content { operations }.encode()?;
+        doc.change_page_content(page_id, content)?;
+    }
+    Ok(())
+}
+
+/// Make the text layer's font available to a page
+///
+/// A page without resources of its own gets a copy of the ones it inherits,
+/// so adding the font doesn't hide them.
+fn add_font(doc: &mut Document, page_id: ObjectId, font_id: ObjectId) -> lopdf::Result<()> {
+    let page = doc.get_dictionary(page_id)?;
+    let resources_id = match page.get(b"Resources") {
+        Ok(Object::Reference(id)) => Some(*id),
+        Ok(_) => None,
+        Err(_) => {
+            let inherited = page_resources(doc, page_id).cloned().unwrap_or_default();
+            doc.get_dictionary_mut(page_id)?.set("Resources", inherited);
+            None
+        }
+    };
+    let resources = match resources_id {
+        Some(id) => doc.get_dictionary_mut(id)?,
+        None => doc
+            .get_dictionary_mut(page_id)?
+            .get_mut(b"Resources")?
+            .as_dict_mut()?,
+    };
+
+    let fonts_id = match resources.get(b"Font") {
+        Ok(Object::Reference(id)) => Some(*id),
+        Ok(_) => None,
+        Err(_) => {
+            resources.set("Font", Dictionary::new());
+            None
+        }
+    };
+    let fonts = match fonts_id {
+        Some(id) => doc.get_dictionary_mut(id)?,
+        None => resources.get_mut(b"Font")?.as_dict_mut()?,
+    };
+    fonts.set(FONT_NAME, font_id);
+    Ok(())
+}
+
+/// Append content to a page, wrapping the existing content in q/Q so any
+/// graphics state it leaves behind doesn't move the appended content
+fn append_isolated_content(
+    doc: &mut Document,
+    page_id: ObjectId,
+    content: Vec<u8>,
+) -> lopdf::Result<()> {
+    let mut contents: Vec<Object> = match doc.get_dictionary(page_id)?.get(b"Contents") {
+        Ok(Object::Reference(id)) => vec![Object::Reference(*id)],
+        Ok(Object::Array(streams)) => streams.clone(),
+        _ => Vec::new(),
+    };
+    let open = doc.add_object(lopdf::Stream::new(Dictionary::new(), b"q\n".to_vec()));
+    let mut closed = b"Q\n".to_vec();
+    closed.extend(content);
+    let layer = doc.add_object(lopdf::Stream::new(Dictionary::new(), closed));
+
+    contents.insert(0, Object::Reference(open));
+    contents.push(Object::Reference(layer));
+    doc.get_dictionary_mut(page_id)?.set("Contents", contents);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::Stream;
+
+    /// A one-page PDF drawing a 200x100 pixel image over a 400x200 page,
+    /// with an old invisible text layer
+    fn scanned_pdf() -> Vec<u8> {
+        let mut doc = Document::with_version("1.5");
+        let image_id = doc.add_object(Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => 200,
+                "Height" => 100,
+                "ColorSpace" => "DeviceGray",
+                "BitsPerComponent" => 8,
+            },
+            vec![255; 200 * 100],
+        ));
+        let content = b"q 400 0 0 200 0 0 cm /Im0 Do Q BT 3 Tr /F1 12 Tf (stale) Tj ET".to_vec();
+        let content_id = doc.add_object(Stream::new(Dictionary::new(), content));
+
+        let pages_id = doc.new_object_id();
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+                "MediaBox" => vec![0.into(), 0.into(), 400.into(), 200.into()],
+                "Resources" => dictionary! {
+                    "XObject" => dictionary! { "Im0" => image_id },
+                },
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+
+        let mut pdf = Vec::new();
+        doc.save_to(&mut pdf).unwrap();
+        pdf
+    }
+
+    fn word(text: &str, page: u32) -> TextBoxResponse {
+        TextBoxResponse {
+            text: text.to_string(),
+            page,
+            x: 10,
+            y: 20,
+            width: 50,
+            height: 10,
+            confidence: Some(0.9),
+            polygon: None,
+        }
+    }
+
+    #[test]
+    fn test_embed_replaces_invisible_text_layer() {
+        let (pdf, warnings) =
+            embed(&scanned_pdf(), &[word("Invoice", 1), word("lost", 2)]).unwrap();
+        assert_eq!(
+            warnings,
+            ["1 words could not be matched to a PDF page and were left out of the text layer"]
+        );
+
+        let doc = Document::load_mem(&pdf).unwrap();
+        let page_id = *doc.get_pages().get(&1).unwrap();
+        let content = Content::decode(&doc.get_page_content(page_id).unwrap()).unwrap();
+        let shown: Vec<&Operation> = content
+            .operations
+            .iter()
+            .filter(|op| op.operator == "Tj")
+            .collect();
+        assert_eq!(shown.len(), 1);
+        assert_eq!(shown[0].operands[0].as_str().unwrap(), b"Invoice ");
+
+        // Pixels are doubled to page points, measured up from the bottom
+        let tm = content
+            .operations
+            .iter()
+            .find(|op| op.operator == "Tm")
+            .unwrap();
+        let position: Vec<f32> = tm.operands.iter().map(|v| v.as_float().unwrap()).collect();
+        assert_eq!(position[4..], [20.0, 140.0]);
+
+        // The image is still drawn and the font is available to the page
+        assert_eq!(page_images(&doc, page_id).len(), 1);
+        assert!(doc
+            .get_page_fonts(page_id)
+            .unwrap()
+            .contains_key(FONT_NAME.as_bytes()));
+    }
+
+    #[test]
+    fn test_embed_warns_about_replaced_characters() {
+        let (_, warnings) = embed(&scanned_pdf(), &[word("東京 Café", 1)]).unwrap();
+        assert_eq!(
+            warnings,
+            ["2 characters can't be shown in the text layer's font and were replaced with '?'"]
+        );
+    }
+
+    #[test]
+    fn test_win_ansi_replaces_unsupported_characters() {
+        assert_eq!(win_ansi("Café 東京"), b"Caf\xe9 ??");
+        assert_eq!(
+            win_ansi("“Don’t” – €5 … ™"),
+            b"\x93Don\x92t\x94 \x96 \x805 \x85 \x99"
+        );
+    }
+}
@@ -3,6 +3,11 @@
 //! Provides configurable preprocessing pipelines to improve OCR accuracy.
 
 pub mod pipeline;
+pub mod segment;
 pub mod steps;
 
-pub use pipeline::{Pipeline, Preset, StepTiming};
+pub use pipeline::{Pipeline, Preset, SourceRegion, StepName, StepTiming};
+pub use steps::grayscale::GrayscaleMethod;
+pub use steps::resize::ResizeMethod;
+pub use steps::threshold::{SauvolaParams, ThresholdMethod};
+pub use steps::StepMetadata;
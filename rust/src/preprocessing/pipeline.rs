@@ -1,9 +1,14 @@
 use crate::error::OcrError;
+use crate::memory;
 use image::DynamicImage;
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::time::Instant;
 
-use super::steps;
+use super::steps::grayscale::GrayscaleMethod;
+use super::steps::resize::{ResizeAction, ResizeMethod};
+use super::steps::threshold::{SauvolaParams, ThresholdMethod};
+use super::steps::{self, StepMetadata, StepOutcome};
 
 /// Preprocessing preset names
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -18,7 +23,8 @@ pub enum Preset {
     #[default]
     Default,
     /// Aggressive processing for poor quality images (~200-300ms)
-    /// Steps: grayscale, resize, denoise, normalize, sharpen, deskew, threshold
+    /// Steps: grayscale, resize, denoise, normalize, sharpen, crop_border,
+    /// deskew, contrast, threshold
     Aggressive,
 }
 
@@ -43,6 +49,67 @@ impl Preset {
             Self::Aggressive => "aggressive",
         }
     }
+
+    /// The next cheaper preset, used to shed load
+    pub fn downgraded(&self) -> Self {
+        match self {
+            Self::Aggressive => Self::Default,
+            Self::Default => Self::Minimal,
+            other => *other,
+        }
+    }
+}
+
+/// A preprocessing step that can be run on its own, for pipelines built
+/// from a list of steps instead of a preset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepName {
+    Grayscale,
+    Resize,
+    Denoise,
+    Normalize,
+    Sharpen,
+    CropBorder,
+    Deskew,
+    Contrast,
+    Threshold,
+}
+
+impl StepName {
+    /// Every step, in the order the aggressive preset runs them
+    pub const ALL: [Self; 9] = [
+        Self::Grayscale,
+        Self::Resize,
+        Self::Denoise,
+        Self::Normalize,
+        Self::Sharpen,
+        Self::CropBorder,
+        Self::Deskew,
+        Self::Contrast,
+        Self::Threshold,
+    ];
+
+    /// Parse from query parameter string
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|step| step.as_str() == s.to_lowercase())
+    }
+
+    /// Get the step name as a string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Grayscale => "grayscale",
+            Self::Resize => "resize",
+            Self::Denoise => "denoise",
+            Self::Normalize => "normalize",
+            Self::Sharpen => "sharpen",
+            Self::CropBorder => "crop_border",
+            Self::Deskew => "deskew",
+            Self::Contrast => "contrast",
+            Self::Threshold => "threshold",
+        }
+    }
 }
 
 /// Timing information for a single preprocessing step
@@ -50,6 +117,21 @@ impl Preset {
 pub struct StepTiming {
     pub name: String,
     pub time_ms: u64,
+    /// True if the step ran but left the image unchanged
+    pub skipped: bool,
+    /// Why the step was skipped (e.g. image already at target size)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<String>,
+    /// Whether a resizing step enlarged or shrank the image
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resize_action: Option<ResizeAction>,
+    /// Output size relative to the input, for resizing steps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale_factor: Option<f32>,
+    /// Most memory the step had allocated at once, in bytes (only when
+    /// memory tracking is on)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_memory_bytes: Option<u64>,
 }
 
 /// Result of preprocessing including timing stats
@@ -64,90 +146,714 @@ pub struct PreprocessingResult {
     pub preset: String,
     /// Individual step timings
     pub steps: Vec<StepTiming>,
+    /// What each step that measured something found, by step name
+    pub metadata: BTreeMap<String, StepMetadata>,
+    /// Image after each step, in order (only when intermediates are kept)
+    #[serde(skip)]
+    pub intermediates: Vec<StepImage>,
+    /// Part of the input image the result shows, when a step cropped it
+    #[serde(skip)]
+    pub source_region: Option<SourceRegion>,
+}
+
+/// A rectangle of the pipeline's input image, in its pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SourceRegion {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Image produced by a single preprocessing step
+#[derive(Debug, Clone)]
+pub struct StepImage {
+    pub name: String,
+    pub image: DynamicImage,
+}
+
+/// Step timings, plus a copy of each step's output when requested
+struct StepLog {
+    timings: Vec<StepTiming>,
+    images: Option<Vec<StepImage>>,
+    /// Size of the pipeline's input image
+    input_size: (u32, u32),
+    /// Part of the input still shown, once a step has cropped
+    region: Option<SourceRegion>,
+    metadata: BTreeMap<String, StepMetadata>,
 }
 
 /// Preprocessing pipeline that applies steps based on preset
 pub struct Pipeline {
     preset: Preset,
+    /// When set, run a low-resolution probe pass instead of the preset
+    probe_max_dimension: Option<u32>,
+    /// Keep a copy of the image after every step, for debugging
+    keep_intermediates: bool,
+    /// Measure skew on the image before resizing, correcting it afterwards
+    detect_skew_before_resize: bool,
+    /// Skip sharpening images whose Laplacian variance is above this
+    sharpen_skip_threshold: Option<f32>,
+    /// Record the peak memory allocated by each step
+    track_memory: bool,
+    /// Steps to run in order instead of the preset's
+    steps: Option<Vec<StepName>>,
+    /// Gamma correction to apply before any other step
+    gamma: Option<f32>,
+    /// How the grayscale step turns color into gray
+    grayscale_method: GrayscaleMethod,
+    /// How the resize step picks its scale
+    resize_method: ResizeMethod,
+    /// Resolution the input image says it has
+    input_dpi: Option<f32>,
+    /// How the threshold step binarizes
+    threshold_method: ThresholdMethod,
+    /// Window and sensitivity for Sauvola thresholding
+    sauvola: SauvolaParams,
 }
 
 impl Pipeline {
     pub fn new(preset: Preset) -> Self {
-        Self { preset }
+        Self {
+            preset,
+            probe_max_dimension: None,
+            keep_intermediates: false,
+            detect_skew_before_resize: false,
+            sharpen_skip_threshold: None,
+            track_memory: false,
+            steps: None,
+            gamma: None,
+            grayscale_method: GrayscaleMethod::default(),
+            resize_method: ResizeMethod::default(),
+            input_dpi: None,
+            threshold_method: ThresholdMethod::default(),
+            sauvola: SauvolaParams::default(),
+        }
+    }
+
+    /// Create a pipeline that runs the given steps in order
+    ///
+    /// Steps may repeat. The result reports its preset as "custom".
+    pub fn from_steps(steps: Vec<StepName>) -> Self {
+        Self {
+            steps: Some(steps),
+            ..Self::new(Preset::Default)
+        }
+    }
+
+    /// Create a probe pipeline for quick confidence estimates
+    /// Steps: grayscale, downscale (heavy steps are skipped)
+    pub fn probe(max_dimension: u32) -> Self {
+        Self {
+            preset: Preset::Minimal,
+            probe_max_dimension: Some(max_dimension),
+            keep_intermediates: false,
+            detect_skew_before_resize: false,
+            sharpen_skip_threshold: None,
+            track_memory: false,
+            steps: None,
+            gamma: None,
+            grayscale_method: GrayscaleMethod::default(),
+            resize_method: ResizeMethod::default(),
+            input_dpi: None,
+            threshold_method: ThresholdMethod::default(),
+            sauvola: SauvolaParams::default(),
+        }
+    }
+
+    /// Keep the image produced by every step in
+    /// [`PreprocessingResult::intermediates`]
+    ///
+    /// Each step's output is cloned, so only use this for debugging.
+    pub fn keep_intermediates(mut self) -> Self {
+        self.keep_intermediates = true;
+        self
+    }
+
+    /// Detect skew on the original-resolution image, before it is resized
+    ///
+    /// Fine detail lost when downscaling can throw off skew detection. The
+    /// correction is still applied at the usual point in the pipeline, so
+    /// later steps and OCR work on the resized image as before. Only affects
+    /// pipelines that deskew. With a list of steps, skew is measured before
+    /// the first one.
+    pub fn detect_skew_before_resize(mut self) -> Self {
+        self.detect_skew_before_resize = true;
+        self
+    }
+
+    /// Skip sharpening images that are already sharp
+    ///
+    /// Sharpness is the variance of the Laplacian, see
+    /// [`steps::sharpen::laplacian_variance`].
+    pub fn skip_sharpen_above(mut self, threshold: f32) -> Self {
+        self.sharpen_skip_threshold = Some(threshold);
+        self
+    }
+
+    /// Record the most memory each step had allocated at once in
+    /// [`StepTiming::peak_memory_bytes`]
+    ///
+    /// Only allocations on the calling thread are counted, see
+    /// [`crate::memory`].
+    pub fn track_memory(mut self) -> Self {
+        self.track_memory = true;
+        self
+    }
+
+    /// Apply gamma correction before the preset's or list's first step
+    ///
+    /// Also runs with the `none` preset. Probe passes skip it.
+    pub fn gamma(mut self, gamma: f32) -> Self {
+        self.gamma = Some(gamma);
+        self
+    }
+
+    /// Convert color to gray with `method` wherever the preset or list
+    /// grayscales
+    pub fn grayscale_method(mut self, method: GrayscaleMethod) -> Self {
+        self.grayscale_method = method;
+        self
+    }
+
+    /// Pick the scale with `method` wherever the preset or list resizes
+    pub fn resize_method(mut self, method: ResizeMethod) -> Self {
+        self.resize_method = method;
+        self
+    }
+
+    /// Scale from `dpi` instead of an assumed 72 DPI wherever the preset or
+    /// list resizes
+    pub fn input_dpi(mut self, dpi: f32) -> Self {
+        self.input_dpi = Some(dpi);
+        self
+    }
+
+    /// Binarize with `method` wherever the preset or list thresholds
+    pub fn threshold_method(mut self, method: ThresholdMethod) -> Self {
+        self.threshold_method = method;
+        self
+    }
+
+    /// Tune Sauvola thresholding, used unless the method is Otsu
+    pub fn sauvola(mut self, params: SauvolaParams) -> Self {
+        self.sauvola = params;
+        self
+    }
+
+    /// Whether the configured preset or list includes a grayscale step
+    pub fn grayscales(&self) -> bool {
+        if self.probe_max_dimension.is_some() {
+            return true;
+        }
+        match &self.steps {
+            Some(steps) => steps.contains(&StepName::Grayscale),
+            None => self.preset != Preset::None,
+        }
+    }
+
+    /// Whether the configured preset or list includes a resize step
+    pub fn resizes(&self) -> bool {
+        if self.probe_max_dimension.is_some() {
+            return false;
+        }
+        match &self.steps {
+            Some(steps) => steps.contains(&StepName::Resize),
+            None => matches!(self.preset, Preset::Default | Preset::Aggressive),
+        }
+    }
+
+    /// Whether the configured preset or list includes a threshold step
+    pub fn thresholds(&self) -> bool {
+        if self.probe_max_dimension.is_some() {
+            return false;
+        }
+        match &self.steps {
+            Some(steps) => steps.contains(&StepName::Threshold),
+            None => self.preset == Preset::Aggressive,
+        }
+    }
+
+    /// Whether the configured preset or steps include a deskew step
+    pub fn deskews(&self) -> bool {
+        if self.probe_max_dimension.is_some() {
+            return false;
+        }
+        match &self.steps {
+            Some(steps) => steps.contains(&StepName::Deskew),
+            None => self.preset == Preset::Aggressive,
+        }
     }
 
     /// Process an image according to the configured preset
     pub fn process(&self, image: DynamicImage) -> Result<PreprocessingResult, OcrError> {
         let start = Instant::now();
-        let mut steps_timing = Vec::new();
+        let mut log = StepLog {
+            timings: Vec::new(),
+            images: self.keep_intermediates.then(Vec::new),
+            input_size: (image.width(), image.height()),
+            region: None,
+            metadata: BTreeMap::new(),
+        };
+
+        if let Some(max_dimension) = self.probe_max_dimension {
+            let mut img = image;
+            img = self.run_step("downscale", img, &mut log, |img| {
+                steps::resize::downscale(img, max_dimension)
+            })?;
+            img = self.run_step("grayscale", img, &mut log, |img| {
+                steps::grayscale::apply(img, self.grayscale_method)
+            })?;
 
-        if self.preset == Preset::None {
             return Ok(PreprocessingResult {
-                image,
-                total_time_ms: 0,
-                preset: "none".to_string(),
-                steps: vec![],
+                image: img,
+                total_time_ms: start.elapsed().as_millis() as u64,
+                preset: "probe".to_string(),
+                steps: log.timings,
+                metadata: log.metadata,
+                intermediates: log.images.unwrap_or_default(),
+                source_region: log.region,
             });
         }
 
         let mut img = image;
+        if let Some(gamma) = self.gamma {
+            img = self.run_step("gamma", img, &mut log, |img| {
+                steps::gamma::apply(img, gamma)
+            })?;
+        }
+
+        if let Some(steps) = &self.steps {
+            return self.process_steps(img, steps, start, log);
+        }
+
+        if self.preset == Preset::None {
+            return Ok(PreprocessingResult {
+                image: img,
+                total_time_ms: start.elapsed().as_millis() as u64,
+                preset: "none".to_string(),
+                steps: log.timings,
+                metadata: log.metadata,
+                intermediates: log.images.unwrap_or_default(),
+                source_region: log.region,
+            });
+        }
 
         // All presets except None do grayscale
-        img = self.run_step("grayscale", img, &mut steps_timing, steps::grayscale::apply)?;
+        img = self.run_step("grayscale", img, &mut log, |img| {
+            steps::grayscale::apply(img, self.grayscale_method)
+        })?;
 
         if self.preset == Preset::Minimal {
             return Ok(PreprocessingResult {
                 image: img,
                 total_time_ms: start.elapsed().as_millis() as u64,
                 preset: "minimal".to_string(),
-                steps: steps_timing,
+                steps: log.timings,
+                metadata: log.metadata,
+                intermediates: log.images.unwrap_or_default(),
+                source_region: log.region,
             });
         }
 
+        // Measure skew before detail is lost to resizing, if requested
+        let mut skew_angle = None;
+        if self.deskews() && self.detect_skew_before_resize {
+            img = self.run_step("deskew_detect", img, &mut log, |img| {
+                skew_angle = Some(steps::deskew::detect(&img));
+                Ok::<_, OcrError>(img)
+            })?;
+        }
+
         // Default and Aggressive: resize for optimal OCR
-        img = self.run_step("resize", img, &mut steps_timing, steps::resize::apply)?;
+        img = self.run_step("resize", img, &mut log, |img| {
+            steps::resize::apply(img, self.resize_method, self.input_dpi)
+        })?;
 
         // Aggressive only: denoise before normalize
         if self.preset == Preset::Aggressive {
-            img = self.run_step("denoise", img, &mut steps_timing, steps::denoise::apply)?;
+            img = self.run_step("denoise", img, &mut log, steps::denoise::apply)?;
         }
 
         // Default and Aggressive: normalize contrast
-        img = self.run_step("normalize", img, &mut steps_timing, steps::normalize::apply)?;
+        img = self.run_step("normalize", img, &mut log, steps::normalize::apply)?;
 
         // Default and Aggressive: sharpen
-        img = self.run_step("sharpen", img, &mut steps_timing, steps::sharpen::apply)?;
+        img = self.run_step("sharpen", img, &mut log, |img| {
+            steps::sharpen::apply(img, self.sharpen_skip_threshold)
+        })?;
 
-        // Aggressive only: deskew and threshold
+        // Aggressive only: remove borders, deskew, stretch contrast and threshold
         if self.preset == Preset::Aggressive {
-            img = self.run_step("deskew", img, &mut steps_timing, steps::deskew::apply)?;
-            img = self.run_step("threshold", img, &mut steps_timing, steps::threshold::apply)?;
+            img = self.run_step("crop_border", img, &mut log, |img| {
+                steps::crop_border::apply(img, steps::crop_border::DEFAULT_MARGIN)
+            })?;
+            img = self.run_step("deskew", img, &mut log, |img| match skew_angle {
+                Some(angle) => steps::deskew::apply_angle(img, angle),
+                None => steps::deskew::apply(img),
+            })?;
+            img = self.run_step("contrast", img, &mut log, steps::contrast::apply)?;
+            img = self.run_step("threshold", img, &mut log, |img| {
+                steps::threshold::apply(img, self.threshold_method, self.sauvola)
+            })?;
         }
 
         Ok(PreprocessingResult {
             image: img,
             total_time_ms: start.elapsed().as_millis() as u64,
             preset: self.preset.as_str().to_string(),
-            steps: steps_timing,
+            steps: log.timings,
+            metadata: log.metadata,
+            intermediates: log.images.unwrap_or_default(),
+            source_region: log.region,
+        })
+    }
+
+    /// Run a list of steps in order
+    fn process_steps(
+        &self,
+        image: DynamicImage,
+        steps: &[StepName],
+        start: Instant,
+        mut log: StepLog,
+    ) -> Result<PreprocessingResult, OcrError> {
+        let mut img = image;
+
+        let mut skew_angle = None;
+        if self.deskews() && self.detect_skew_before_resize {
+            img = self.run_step("deskew_detect", img, &mut log, |img| {
+                skew_angle = Some(steps::deskew::detect(&img));
+                Ok::<_, OcrError>(img)
+            })?;
+        }
+
+        for step in steps {
+            img = self.run_step(
+                step.as_str(),
+                img,
+                &mut log,
+                |img| -> Result<StepOutcome, OcrError> {
+                    Ok(match step {
+                        StepName::Grayscale => {
+                            steps::grayscale::apply(img, self.grayscale_method)?.into()
+                        }
+                        StepName::Resize => {
+                            steps::resize::apply(img, self.resize_method, self.input_dpi)?
+                        }
+                        StepName::Denoise => steps::denoise::apply(img)?.into(),
+                        StepName::Normalize => steps::normalize::apply(img)?,
+                        StepName::Sharpen => {
+                            steps::sharpen::apply(img, self.sharpen_skip_threshold)?
+                        }
+                        StepName::CropBorder => {
+                            steps::crop_border::apply(img, steps::crop_border::DEFAULT_MARGIN)?
+                        }
+                        StepName::Deskew => match skew_angle {
+                            Some(angle) => steps::deskew::apply_angle(img, angle)?,
+                            None => steps::deskew::apply(img)?,
+                        },
+                        StepName::Contrast => steps::contrast::apply(img)?.into(),
+                        StepName::Threshold => {
+                            steps::threshold::apply(img, self.threshold_method, self.sauvola)?
+                                .into()
+                        }
+                    })
+                },
+            )?;
+        }
+
+        Ok(PreprocessingResult {
+            image: img,
+            total_time_ms: start.elapsed().as_millis() as u64,
+            preset: "custom".to_string(),
+            steps: log.timings,
+            metadata: log.metadata,
+            intermediates: log.images.unwrap_or_default(),
+            source_region: log.region,
         })
     }
 
-    fn run_step<F>(
+    fn run_step<F, R>(
         &self,
         name: &str,
         img: DynamicImage,
-        timings: &mut Vec<StepTiming>,
+        log: &mut StepLog,
         step_fn: F,
     ) -> Result<DynamicImage, OcrError>
     where
-        F: FnOnce(DynamicImage) -> Result<DynamicImage, OcrError>,
+        F: FnOnce(DynamicImage) -> Result<R, OcrError>,
+        R: Into<StepOutcome>,
     {
         let step_start = Instant::now();
-        let result = step_fn(img)?;
-        timings.push(StepTiming {
+        let input_size = (img.width(), img.height());
+        let (outcome, peak_memory_bytes) = if self.track_memory {
+            let (outcome, peak) = memory::track(|| step_fn(img));
+            (outcome?, Some(peak))
+        } else {
+            (step_fn(img)?, None)
+        };
+        let outcome: StepOutcome = outcome.into();
+        if let Some(crop) = outcome.crop {
+            // Crops are relative to the step's input, which may have been
+            // resized since the pipeline started
+            let (width, height) = log.input_size;
+            let region = log.region.unwrap_or(SourceRegion {
+                x: 0.0,
+                y: 0.0,
+                width: width as f32,
+                height: height as f32,
+            });
+            let scale_x = region.width / input_size.0 as f32;
+            let scale_y = region.height / input_size.1 as f32;
+            log.region = Some(SourceRegion {
+                x: region.x + crop.x as f32 * scale_x,
+                y: region.y + crop.y as f32 * scale_y,
+                width: crop.width as f32 * scale_x,
+                height: crop.height as f32 * scale_y,
+            });
+        }
+        if !outcome.metadata.is_empty() {
+            log.metadata.insert(name.to_string(), outcome.metadata);
+        }
+        log.timings.push(StepTiming {
             name: name.to_string(),
             time_ms: step_start.elapsed().as_millis() as u64,
+            skipped: outcome.skip_reason.is_some(),
+            skip_reason: outcome.skip_reason,
+            resize_action: outcome.resize.map(|resize| resize.action),
+            scale_factor: outcome.resize.map(|resize| resize.scale),
+            peak_memory_bytes,
+        });
+        if let Some(images) = log.images.as_mut() {
+            images.push(StepImage {
+                name: name.to_string(),
+                image: outcome.image.clone(),
+            });
+        }
+        Ok(outcome.image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+
+    #[test]
+    fn test_downgraded_preset_is_cheaper() {
+        assert_eq!(Preset::Aggressive.downgraded(), Preset::Default);
+        assert_eq!(Preset::Default.downgraded(), Preset::Minimal);
+        assert_eq!(Preset::Minimal.downgraded(), Preset::Minimal);
+        assert_eq!(Preset::None.downgraded(), Preset::None);
+    }
+
+    #[test]
+    fn test_intermediates_not_kept_by_default() {
+        let result = Pipeline::new(Preset::Default)
+            .process(DynamicImage::new_rgb8(40, 40))
+            .unwrap();
+        assert!(result.intermediates.is_empty());
+    }
+
+    #[test]
+    fn test_from_steps_runs_steps_in_order() {
+        let pipeline = Pipeline::from_steps(vec![
+            StepName::Grayscale,
+            StepName::Threshold,
+            StepName::Deskew,
+        ]);
+        assert!(pipeline.deskews());
+
+        let result = pipeline.process(DynamicImage::new_rgb8(40, 40)).unwrap();
+        let timed: Vec<&str> = result.steps.iter().map(|step| step.name.as_str()).collect();
+        assert_eq!(timed, vec!["grayscale", "threshold", "deskew"]);
+        assert_eq!(result.preset, "custom");
+        // No resize step, so the size is kept
+        assert_eq!((result.image.width(), result.image.height()), (40, 40));
+
+        assert!(!Pipeline::from_steps(vec![StepName::Resize]).deskews());
+    }
+
+    #[test]
+    fn test_step_names_round_trip() {
+        for step in StepName::ALL {
+            assert_eq!(StepName::from_str(step.as_str()), Some(step));
+        }
+        assert_eq!(StepName::from_str("Grayscale"), Some(StepName::Grayscale));
+        assert_eq!(StepName::from_str("blur"), None);
+    }
+
+    #[test]
+    fn test_keep_intermediates_records_every_step() {
+        let result = Pipeline::new(Preset::Aggressive)
+            .keep_intermediates()
+            .process(DynamicImage::new_rgb8(40, 40))
+            .unwrap();
+
+        let names: Vec<&str> = result
+            .intermediates
+            .iter()
+            .map(|step| step.name.as_str())
+            .collect();
+        let timed: Vec<&str> = result.steps.iter().map(|step| step.name.as_str()).collect();
+        assert_eq!(names, timed);
+        assert_eq!(names.first(), Some(&"grayscale"));
+        assert_eq!(names.last(), Some(&"threshold"));
+
+        // The last intermediate is the final image
+        let last = &result.intermediates.last().unwrap().image;
+        assert_eq!(last.as_bytes(), result.image.as_bytes());
+    }
+
+    #[test]
+    fn test_aggressive_stretches_contrast_before_threshold() {
+        let result = Pipeline::new(Preset::Aggressive)
+            .process(DynamicImage::new_rgb8(40, 40))
+            .unwrap();
+        let names: Vec<&str> = result.steps.iter().map(|step| step.name.as_str()).collect();
+        assert_eq!(names[names.len() - 2..], ["contrast", "threshold"]);
+
+        let default = Pipeline::new(Preset::Default)
+            .process(DynamicImage::new_rgb8(40, 40))
+            .unwrap();
+        assert!(default.steps.iter().all(|step| step.name != "contrast"));
+    }
+
+    #[test]
+    fn test_gamma_runs_first() {
+        let names = |pipeline: Pipeline| -> Vec<String> {
+            let result = pipeline.process(DynamicImage::new_rgb8(40, 40)).unwrap();
+            result.steps.into_iter().map(|step| step.name).collect()
+        };
+
+        assert_eq!(
+            names(Pipeline::new(Preset::Minimal).gamma(1.5)),
+            vec!["gamma", "grayscale"]
+        );
+        assert_eq!(names(Pipeline::new(Preset::None).gamma(1.5)), vec!["gamma"]);
+        assert_eq!(
+            names(Pipeline::from_steps(vec![StepName::Threshold]).gamma(0.8)),
+            vec!["gamma", "threshold"]
+        );
+        // Probe passes stay cheap
+        assert!(!names(Pipeline::probe(100).gamma(1.5)).contains(&"gamma".to_string()));
+    }
+
+    #[test]
+    fn test_threshold_method_is_used_by_threshold_step() {
+        // A large dark square, which Sauvola hollows out and Otsu keeps solid
+        let image = DynamicImage::ImageLuma8(GrayImage::from_fn(80, 80, |x, y| {
+            let inside = (20..60).contains(&x) && (20..60).contains(&y);
+            Luma([if inside { 30 } else { 220 }])
+        }));
+        let center = |pipeline: Pipeline| {
+            let result = pipeline.process(image.clone()).unwrap();
+            result.image.to_luma8().get_pixel(40, 40).0[0]
+        };
+
+        let steps = || Pipeline::from_steps(vec![StepName::Threshold]);
+        assert_eq!(center(steps()), 255);
+        assert_eq!(center(steps().threshold_method(ThresholdMethod::Otsu)), 0);
+
+        assert!(steps().thresholds());
+        assert!(Pipeline::new(Preset::Aggressive).thresholds());
+        assert!(!Pipeline::new(Preset::Default).thresholds());
+    }
+
+    #[test]
+    fn test_resize_method_is_used_by_resize_step() {
+        // Rows of 6 px "letters", which adaptive resizing scales to 30 px
+        let image = DynamicImage::ImageLuma8(GrayImage::from_fn(200, 100, |x, y| {
+            let glyph = (y / 6) % 3 == 1 && (x / 6) % 2 == 1;
+            Luma([if glyph { 0 } else { 255 }])
+        }));
+        let width = |pipeline: Pipeline| pipeline.process(image.clone()).unwrap().image.width();
+
+        let steps = || Pipeline::from_steps(vec![StepName::Resize]);
+        assert_eq!(width(steps()), 832);
+        assert_eq!(width(steps().resize_method(ResizeMethod::Adaptive)), 1000);
+        assert_eq!(width(steps().input_dpi(150.0)), 400);
+
+        assert!(steps().resizes());
+        assert!(Pipeline::new(Preset::Default).resizes());
+        assert!(!Pipeline::new(Preset::Minimal).resizes());
+        assert!(!Pipeline::probe(100).resizes());
+    }
+
+    #[test]
+    fn test_source_region_maps_crop_back_to_input() {
+        // Black frame around a block of text at 60..140 x 50..70
+        let page = image::GrayImage::from_fn(200, 120, |x, y| {
+            let border = x < 15 || y < 15 || x >= 185 || y >= 105;
+            let text = (60..140).contains(&x) && (50..70).contains(&y);
+            image::Luma([if border || text { 0 } else { 255 }])
         });
-        Ok(result)
+
+        let uncropped = Pipeline::from_steps(vec![StepName::Resize])
+            .process(DynamicImage::ImageLuma8(page.clone()))
+            .unwrap();
+        assert_eq!(uncropped.source_region, None);
+
+        // The crop happens on the resized image, but is reported in input pixels
+        let result = Pipeline::from_steps(vec![StepName::Resize, StepName::CropBorder])
+            .process(DynamicImage::ImageLuma8(page))
+            .unwrap();
+        assert_ne!(result.image.width(), 200);
+        let region = result.source_region.unwrap();
+        let margin = steps::crop_border::DEFAULT_MARGIN as f32;
+        assert!(region.x < 60.0 && region.x >= 60.0 - margin, "{:?}", region);
+        assert!(region.y < 50.0 && region.y >= 50.0 - margin, "{:?}", region);
+        assert!(region.x + region.width > 140.0 && region.x + region.width <= 140.0 + margin);
+        assert!(region.y + region.height > 70.0 && region.y + region.height <= 70.0 + margin);
+    }
+
+    #[test]
+    fn test_track_memory_records_every_step() {
+        let image = || DynamicImage::new_rgb8(200, 200);
+        let result = Pipeline::new(Preset::Aggressive)
+            .track_memory()
+            .process(image())
+            .unwrap();
+        assert!(result
+            .steps
+            .iter()
+            .all(|step| step.peak_memory_bytes.is_some()));
+        // Thresholding builds two integral images of 8-byte sums
+        let threshold = result.steps.iter().find(|step| step.name == "threshold");
+        assert!(threshold.unwrap().peak_memory_bytes.unwrap() > 2 * 200 * 200 * 8);
+
+        let result = Pipeline::new(Preset::Aggressive).process(image()).unwrap();
+        assert!(result
+            .steps
+            .iter()
+            .all(|step| step.peak_memory_bytes.is_none()));
+    }
+
+    #[test]
+    fn test_detect_skew_before_resize_adds_detection_step() {
+        let steps = |pipeline: Pipeline| -> Vec<String> {
+            let result = pipeline.process(DynamicImage::new_rgb8(40, 40)).unwrap();
+            result.steps.into_iter().map(|step| step.name).collect()
+        };
+
+        let aggressive = steps(Pipeline::new(Preset::Aggressive).detect_skew_before_resize());
+        let detect = aggressive.iter().position(|name| name == "deskew_detect");
+        let resize = aggressive.iter().position(|name| name == "resize");
+        assert!(detect.unwrap() < resize.unwrap());
+        assert!(aggressive.contains(&"deskew".to_string()));
+
+        // Presets without deskew are unaffected
+        let default = steps(Pipeline::new(Preset::Default).detect_skew_before_resize());
+        assert_eq!(default, steps(Pipeline::new(Preset::Default)));
+    }
+
+    #[test]
+    fn test_metadata_reported_by_step_name() {
+        let image = || DynamicImage::new_rgb8(100, 100);
+        let aggressive = Pipeline::new(Preset::Aggressive).process(image()).unwrap();
+        assert!(aggressive.metadata["deskew"].contains_key("skew_angle_degrees"));
+
+        let default = Pipeline::new(Preset::Default).process(image()).unwrap();
+        assert!(default.metadata.is_empty());
     }
 }
@@ -0,0 +1,317 @@
+//! Whitespace-based image segmentation
+//!
+//! Splits an image holding several separate documents (such as receipts
+//! photographed side by side) into one region per document using recursive
+//! XY-cuts: the ink projection profile is searched for wide blank bands, the
+//! image is cut along them, and each part is cut again until no band is left.
+//! Assumes dark content on a light background.
+
+use image::{DynamicImage, GrayImage};
+use imageproc::contrast::otsu_level;
+
+/// Fraction of the shorter image side a blank band must span to separate regions
+const MIN_GAP_RATIO: f32 = 0.04;
+
+/// Bands narrower than this many pixels never separate regions
+const MIN_GAP_PIXELS: u32 = 10;
+
+/// Regions smaller than this on either side are specks, not documents
+const MIN_REGION_PIXELS: u32 = 8;
+
+/// Regions with less than this fraction of the largest region's area are
+/// left out of a text block as smudges
+const MIN_BLOCK_AREA_RATIO: f32 = 0.01;
+
+/// Margin kept around a text block, as a fraction of the image's shorter side
+const BLOCK_MARGIN_RATIO: f32 = 0.02;
+
+/// Text blocks covering more than this fraction of the image aren't worth
+/// cropping to
+const MAX_BLOCK_AREA_RATIO: f32 = 0.8;
+
+/// Rectangle within an image, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Find the separate content regions of an image, in reading order
+///
+/// Returns an empty list for blank images and a single region when there is
+/// nothing to split.
+pub fn split_regions(image: &DynamicImage) -> Vec<Region> {
+    let gray = image.to_luma8();
+    let (width, height) = gray.dimensions();
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+
+    let ink = Ink {
+        image: &gray,
+        level: otsu_level(&gray),
+    };
+    let min_gap = ((width.min(height) as f32 * MIN_GAP_RATIO) as u32).max(MIN_GAP_PIXELS);
+
+    let mut regions = Vec::new();
+    cut(
+        &ink,
+        Region {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        },
+        min_gap,
+        &mut regions,
+    );
+    regions
+}
+
+/// Find the block of an image holding its text, for cropping to
+///
+/// The block spans every region [`split_regions`] finds, except ones much
+/// smaller than the largest, plus a margin. Returns None for blank images and
+/// when the block covers most of the image anyway.
+pub fn text_block(image: &DynamicImage) -> Option<Region> {
+    let regions = split_regions(image);
+    let area = |region: &Region| region.width as u64 * region.height as u64;
+    let largest = regions.iter().map(area).max()?;
+    let min_area = (largest as f32 * MIN_BLOCK_AREA_RATIO) as u64;
+    let kept = regions.iter().filter(|region| area(region) >= min_area);
+
+    let (width, height) = (image.width(), image.height());
+    let margin = ((width.min(height) as f32 * BLOCK_MARGIN_RATIO) as u32).max(MIN_GAP_PIXELS);
+    let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+    for region in kept {
+        left = left.min(region.x);
+        top = top.min(region.y);
+        right = right.max(region.x + region.width);
+        bottom = bottom.max(region.y + region.height);
+    }
+    let left = left.saturating_sub(margin);
+    let top = top.saturating_sub(margin);
+    let block = Region {
+        x: left,
+        y: top,
+        width: (right + margin).min(width) - left,
+        height: (bottom + margin).min(height) - top,
+    };
+
+    let covered = area(&block) as f32 / (width as u64 * height as u64) as f32;
+    (covered <= MAX_BLOCK_AREA_RATIO).then_some(block)
+}
+
+/// Grayscale image with a threshold separating ink from background
+struct Ink<'a> {
+    image: &'a GrayImage,
+    level: u8,
+}
+
+impl Ink<'_> {
+    fn at(&self, x: u32, y: u32) -> bool {
+        self.image.get_pixel(x, y)[0] <= self.level
+    }
+
+    /// Which rows of the region contain ink
+    fn rows(&self, region: Region) -> Vec<bool> {
+        (region.y..region.y + region.height)
+            .map(|y| (region.x..region.x + region.width).any(|x| self.at(x, y)))
+            .collect()
+    }
+
+    /// Which columns of the region contain ink
+    fn columns(&self, region: Region) -> Vec<bool> {
+        (region.x..region.x + region.width)
+            .map(|x| (region.y..region.y + region.height).any(|y| self.at(x, y)))
+            .collect()
+    }
+}
+
+/// Recursively cut a region along blank bands, collecting the leaves
+fn cut(ink: &Ink, region: Region, min_gap: u32, out: &mut Vec<Region>) {
+    let Some(region) = trim(ink, region) else {
+        return;
+    };
+    if region.width < MIN_REGION_PIXELS || region.height < MIN_REGION_PIXELS {
+        return;
+    }
+
+    // Cut between stacked documents first, then between side-by-side ones
+    if let Some(bands) = split_profile(&ink.rows(region), min_gap) {
+        for (start, length) in bands {
+            let band = Region {
+                y: region.y + start,
+                height: length,
+                ..region
+            };
+            cut(ink, band, min_gap, out);
+        }
+        return;
+    }
+
+    if let Some(columns) = split_profile(&ink.columns(region), min_gap) {
+        for (start, length) in columns {
+            let column = Region {
+                x: region.x + start,
+                width: length,
+                ..region
+            };
+            cut(ink, column, min_gap, out);
+        }
+        return;
+    }
+
+    out.push(region);
+}
+
+/// Shrink a region to the bounding box of its ink, or `None` if it is blank
+fn trim(ink: &Ink, region: Region) -> Option<Region> {
+    let rows = ink.rows(region);
+    let top = rows.iter().position(|&row| row)? as u32;
+    let bottom = rows.iter().rposition(|&row| row)? as u32;
+
+    let columns = ink.columns(region);
+    let left = columns.iter().position(|&column| column)? as u32;
+    let right = columns.iter().rposition(|&column| column)? as u32;
+
+    Some(Region {
+        x: region.x + left,
+        y: region.y + top,
+        width: right - left + 1,
+        height: bottom - top + 1,
+    })
+}
+
+/// Split a trimmed projection profile at blank runs of at least `min_gap`
+///
+/// Returns the `(start, length)` of each part, or `None` if there is no
+/// such run.
+fn split_profile(profile: &[bool], min_gap: u32) -> Option<Vec<(u32, u32)>> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < profile.len() {
+        if profile[i] {
+            i += 1;
+            continue;
+        }
+
+        let gap_start = i;
+        while i < profile.len() && !profile[i] {
+            i += 1;
+        }
+        if i - gap_start >= min_gap as usize && gap_start > start && i < profile.len() {
+            parts.push((start as u32, (gap_start - start) as u32));
+            start = i;
+        }
+    }
+
+    if parts.is_empty() {
+        return None;
+    }
+    parts.push((start as u32, (profile.len() - start) as u32));
+    Some(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    /// White canvas with black rectangles at (x, y, width, height)
+    fn canvas(width: u32, height: u32, blocks: &[(u32, u32, u32, u32)]) -> DynamicImage {
+        let mut image = GrayImage::from_pixel(width, height, Luma([255]));
+        for &(x, y, w, h) in blocks {
+            for py in y..y + h {
+                for px in x..x + w {
+                    image.put_pixel(px, py, Luma([0]));
+                }
+            }
+        }
+        DynamicImage::ImageLuma8(image)
+    }
+
+    fn region(x: u32, y: u32, width: u32, height: u32) -> Region {
+        Region {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn test_text_block_spans_content_with_margin() {
+        // Two text groups and a smudge on a large background
+        let image = canvas(
+            1000,
+            800,
+            &[(300, 200, 200, 60), (300, 400, 120, 30), (900, 700, 3, 3)],
+        );
+        assert_eq!(text_block(&image), Some(region(284, 184, 232, 262)));
+
+        // Nothing to crop on a full page or a blank one
+        assert_eq!(text_block(&canvas(200, 200, &[(5, 5, 190, 190)])), None);
+        assert_eq!(text_block(&canvas(200, 200, &[])), None);
+    }
+
+    #[test]
+    fn test_blank_image_has_no_regions() {
+        assert!(split_regions(&canvas(200, 200, &[])).is_empty());
+    }
+
+    #[test]
+    fn test_single_block_is_trimmed_to_its_content() {
+        let regions = split_regions(&canvas(200, 200, &[(30, 40, 100, 80)]));
+        assert_eq!(regions, vec![region(30, 40, 100, 80)]);
+    }
+
+    #[test]
+    fn test_side_by_side_documents_are_split() {
+        let image = canvas(400, 200, &[(10, 10, 150, 180), (240, 20, 150, 160)]);
+        assert_eq!(
+            split_regions(&image),
+            vec![region(10, 10, 150, 180), region(240, 20, 150, 160)]
+        );
+    }
+
+    #[test]
+    fn test_grid_is_returned_in_reading_order() {
+        let image = canvas(
+            400,
+            400,
+            &[
+                (10, 10, 150, 150),
+                (240, 10, 150, 150),
+                (10, 240, 150, 150),
+                (240, 240, 150, 150),
+            ],
+        );
+        assert_eq!(
+            split_regions(&image),
+            vec![
+                region(10, 10, 150, 150),
+                region(240, 10, 150, 150),
+                region(10, 240, 150, 150),
+                region(240, 240, 150, 150),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_narrow_gaps_do_not_split() {
+        // Lines of text within one document are only a few pixels apart
+        let image = canvas(400, 400, &[(20, 20, 300, 10), (20, 35, 300, 10)]);
+        assert_eq!(split_regions(&image), vec![region(20, 20, 300, 25)]);
+    }
+
+    #[test]
+    fn test_specks_are_dropped() {
+        let image = canvas(400, 400, &[(20, 20, 300, 300), (380, 380, 2, 2)]);
+        assert_eq!(split_regions(&image), vec![region(20, 20, 300, 300)]);
+    }
+}
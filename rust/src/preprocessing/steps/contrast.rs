@@ -0,0 +1,151 @@
+use crate::error::OcrError;
+use image::{DynamicImage, GrayImage};
+
+/// Share of pixels ignored at each end of the histogram, so a few specks of
+/// dust or glare don't decide the stretch
+const CLIP_FRACTION: f32 = 0.01;
+/// Images whose clipped range is at least this wide are left unchanged
+const WELL_EXPOSED_RANGE: u8 = 200;
+/// Largest gain applied, so near-uniform images don't turn into noise
+const MAX_ALPHA: f32 = 3.0;
+
+/// Stretch the contrast of dark or washed-out images
+///
+/// The gain (alpha) and bias (beta) are chosen so the darkest and brightest
+/// 1% of pixels map to 0 and 255. Well-exposed images are returned unchanged.
+pub fn apply(image: DynamicImage) -> Result<DynamicImage, OcrError> {
+    let gray = image.into_luma8();
+    let (alpha, beta) = auto_parameters(&gray);
+    adjust(DynamicImage::ImageLuma8(gray), alpha, beta)
+}
+
+/// Apply `out = alpha * in + beta` to every pixel, clamped to 0-255
+pub fn adjust(image: DynamicImage, alpha: f32, beta: f32) -> Result<DynamicImage, OcrError> {
+    let mut gray = image.into_luma8();
+    if alpha == 1.0 && beta == 0.0 {
+        return Ok(DynamicImage::ImageLuma8(gray));
+    }
+
+    let table: Vec<u8> = (0..=255u16)
+        .map(|value| (alpha * value as f32 + beta).round().clamp(0.0, 255.0) as u8)
+        .collect();
+    for pixel in gray.pixels_mut() {
+        pixel.0[0] = table[pixel.0[0] as usize];
+    }
+    Ok(DynamicImage::ImageLuma8(gray))
+}
+
+/// Gain and bias that stretch the clipped histogram to the full range
+///
+/// Returns `(1.0, 0.0)`, a no-op, for well-exposed images.
+fn auto_parameters(gray: &GrayImage) -> (f32, f32) {
+    let mut histogram = [0u64; 256];
+    for pixel in gray.pixels() {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+
+    let total: u64 = histogram.iter().sum();
+    let clip = (total as f32 * CLIP_FRACTION) as u64;
+    let (low, high) = (
+        percentile(&histogram, clip),
+        percentile_from_top(&histogram, clip),
+    );
+
+    if high <= low || high - low >= WELL_EXPOSED_RANGE {
+        return (1.0, 0.0);
+    }
+
+    let alpha = (255.0 / (high - low) as f32).min(MAX_ALPHA);
+    // Keep the midpoint of the clipped range where a full stretch would put it
+    let mid = (low as f32 + high as f32) / 2.0;
+    let beta = 127.5 - alpha * mid;
+    (alpha, beta)
+}
+
+/// Lowest value with more than `skip` pixels at or below it
+fn percentile(histogram: &[u64; 256], skip: u64) -> u8 {
+    let mut seen = 0;
+    for (value, count) in histogram.iter().enumerate() {
+        seen += count;
+        if seen > skip {
+            return value as u8;
+        }
+    }
+    255
+}
+
+/// Highest value with more than `skip` pixels at or above it
+fn percentile_from_top(histogram: &[u64; 256], skip: u64) -> u8 {
+    let mut seen = 0;
+    for (value, count) in histogram.iter().enumerate().rev() {
+        seen += count;
+        if seen > skip {
+            return value as u8;
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    fn mean(gray: &GrayImage) -> f32 {
+        gray.pixels().map(|p| p.0[0] as f32).sum::<f32>() / gray.pixels().len() as f32
+    }
+
+    fn range(gray: &GrayImage) -> u8 {
+        let min = gray.pixels().map(|p| p.0[0]).min().unwrap();
+        let max = gray.pixels().map(|p| p.0[0]).max().unwrap();
+        max - min
+    }
+
+    #[test]
+    fn test_contrast_brightens_dark_image_and_expands_range() {
+        // Underexposed scan: everything between 10 and 90
+        let img = GrayImage::from_fn(80, 10, |x, _| Luma([10 + x as u8]));
+
+        let result = apply(DynamicImage::ImageLuma8(img.clone()))
+            .unwrap()
+            .to_luma8();
+
+        assert!(mean(&result) > mean(&img) + 40.0);
+        assert!(range(&result) > range(&img) * 2);
+    }
+
+    #[test]
+    fn test_contrast_leaves_well_exposed_image_unchanged() {
+        let img = GrayImage::from_fn(256, 4, |x, _| Luma([x as u8]));
+
+        let result = apply(DynamicImage::ImageLuma8(img.clone()))
+            .unwrap()
+            .to_luma8();
+
+        assert_eq!(result, img);
+    }
+
+    #[test]
+    fn test_contrast_ignores_uniform_image() {
+        let img = GrayImage::from_pixel(10, 10, Luma([40]));
+
+        let result = apply(DynamicImage::ImageLuma8(img.clone()))
+            .unwrap()
+            .to_luma8();
+
+        assert_eq!(result, img);
+    }
+
+    #[test]
+    fn test_adjust_applies_gain_and_bias_with_clamping() {
+        let img = GrayImage::from_fn(3, 1, |x, _| Luma([[10, 100, 200][x as usize]]));
+
+        let result = adjust(DynamicImage::ImageLuma8(img), 1.5, 20.0)
+            .unwrap()
+            .to_luma8();
+
+        assert_eq!(result.get_pixel(0, 0).0[0], 35);
+        assert_eq!(result.get_pixel(1, 0).0[0], 170);
+        assert_eq!(result.get_pixel(2, 0).0[0], 255);
+    }
+}
@@ -0,0 +1,240 @@
+use super::StepOutcome;
+use crate::error::OcrError;
+use image::{imageops, DynamicImage, GrayImage};
+
+/// Pixels darker than this count as ink (or as part of a dark border)
+const DARK_THRESHOLD: u8 = 128;
+/// Rows and columns at least this dark belong to a scanner border, not text
+const BORDER_FILL: f32 = 0.6;
+/// Most of each side that can be treated as border
+const MAX_BORDER_FRACTION: f32 = 0.25;
+/// Rows and columns with fewer dark pixels than this share are specks,
+/// not content
+const NOISE_FRACTION: f32 = 0.01;
+/// Pixels skipped past the edge of a border, where resizing blurred it
+const BORDER_PADDING: u32 = 4;
+/// Space kept around the content, in pixels
+pub const DEFAULT_MARGIN: u32 = 10;
+
+/// Region of a step's input image that the step cropped to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Remove dark scanner borders and empty margins around the content
+///
+/// Uses projection profiles like [`super::deskew`]: rows and columns that
+/// are mostly dark along the edges are stripped as border, then the image is
+/// cropped to the rows and columns holding ink, plus `margin` pixels so the
+/// edges of glyphs aren't clipped.
+pub fn apply(image: DynamicImage, margin: u32) -> Result<StepOutcome, OcrError> {
+    let gray = image.into_luma8();
+    let (width, height) = gray.dimensions();
+
+    let Some((inner, ink)) = find_content(&gray) else {
+        return Ok(StepOutcome::skipped(
+            DynamicImage::ImageLuma8(gray),
+            "no content found",
+        ));
+    };
+
+    let x = ink.x.saturating_sub(margin).max(inner.x);
+    let y = ink.y.saturating_sub(margin).max(inner.y);
+    let right = (ink.x + ink.width + margin).min(inner.x + inner.width);
+    let bottom = (ink.y + ink.height + margin).min(inner.y + inner.height);
+    let crop = Crop {
+        x,
+        y,
+        width: right - x,
+        height: bottom - y,
+    };
+
+    if (crop.width, crop.height) == (width, height) {
+        return Ok(StepOutcome::skipped(
+            DynamicImage::ImageLuma8(gray),
+            "no border found",
+        ));
+    }
+
+    let cropped = imageops::crop_imm(&gray, crop.x, crop.y, crop.width, crop.height).to_image();
+    Ok(StepOutcome::applied(DynamicImage::ImageLuma8(cropped)).with_crop(crop))
+}
+
+/// The image inside any dark border, and the bounds of the ink within it
+fn find_content(gray: &GrayImage) -> Option<(Crop, Crop)> {
+    let (width, height) = gray.dimensions();
+    let rows = row_profile(gray, 0, width);
+    let columns = column_profile(gray, 0, height);
+
+    let (top, bottom) = border_extent(&rows, width);
+    let (left, right) = border_extent(&columns, height);
+    let pad = |border: u32| {
+        if border > 0 {
+            border + BORDER_PADDING
+        } else {
+            0
+        }
+    };
+    let (top, bottom, left, right) = (pad(top), pad(bottom), pad(left), pad(right));
+    if top + bottom >= height || left + right >= width {
+        return None;
+    }
+    let inner = Crop {
+        x: left,
+        y: top,
+        width: width - left - right,
+        height: height - top - bottom,
+    };
+
+    // Recount inside the border so it doesn't show up as content
+    let inner_rows = row_profile(gray, inner.x, inner.x + inner.width);
+    let inner_columns = column_profile(gray, inner.y, inner.y + inner.height);
+    let (y, y_end) = ink_extent(
+        &inner_rows[top as usize..(top + inner.height) as usize],
+        inner.width,
+    )?;
+    let (x, x_end) = ink_extent(
+        &inner_columns[left as usize..(left + inner.width) as usize],
+        inner.height,
+    )?;
+
+    let ink = Crop {
+        x: inner.x + x,
+        y: inner.y + y,
+        width: x_end - x + 1,
+        height: y_end - y + 1,
+    };
+    Some((inner, ink))
+}
+
+/// Dark pixels in each row, counting only columns in `x_start..x_end`
+fn row_profile(gray: &GrayImage, x_start: u32, x_end: u32) -> Vec<u32> {
+    (0..gray.height())
+        .map(|y| {
+            (x_start..x_end)
+                .filter(|&x| gray.get_pixel(x, y).0[0] < DARK_THRESHOLD)
+                .count() as u32
+        })
+        .collect()
+}
+
+/// Dark pixels in each column, counting only rows in `y_start..y_end`
+fn column_profile(gray: &GrayImage, y_start: u32, y_end: u32) -> Vec<u32> {
+    (0..gray.width())
+        .map(|x| {
+            (y_start..y_end)
+                .filter(|&y| gray.get_pixel(x, y).0[0] < DARK_THRESHOLD)
+                .count() as u32
+        })
+        .collect()
+}
+
+/// How many mostly-dark rows (or columns) sit at each end of a profile
+fn border_extent(profile: &[u32], length: u32) -> (u32, u32) {
+    let limit = (profile.len() as f32 * MAX_BORDER_FRACTION) as usize;
+    let is_border = |&count: &u32| count as f32 >= length as f32 * BORDER_FILL;
+    let start = profile
+        .iter()
+        .take(limit)
+        .take_while(|c| is_border(c))
+        .count();
+    let end = profile
+        .iter()
+        .rev()
+        .take(limit)
+        .take_while(|c| is_border(c))
+        .count();
+    (start as u32, end as u32)
+}
+
+/// First and last index of a profile holding more than specks of ink
+fn ink_extent(profile: &[u32], length: u32) -> Option<(u32, u32)> {
+    let min_count = ((length as f32 * NOISE_FRACTION).ceil() as u32).max(1);
+    let first = profile.iter().position(|&count| count >= min_count)?;
+    let last = profile.iter().rposition(|&count| count >= min_count)?;
+    Some((first as u32, last as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    /// White page with a dark block of "text" and an optional black frame
+    fn page(border: u32) -> GrayImage {
+        GrayImage::from_fn(200, 120, |x, y| {
+            let in_border = x < border || y < border || x >= 200 - border || y >= 120 - border;
+            let in_text = (60..140).contains(&x) && (50..70).contains(&y) && x % 4 != 0;
+            if in_border || in_text {
+                Luma([0])
+            } else {
+                Luma([255])
+            }
+        })
+    }
+
+    fn dark_pixels(gray: &GrayImage) -> usize {
+        gray.pixels().filter(|p| p.0[0] < DARK_THRESHOLD).count()
+    }
+
+    #[test]
+    fn test_crop_border_removes_frame_and_keeps_content() {
+        let original = page(15);
+        let outcome = apply(DynamicImage::ImageLuma8(original.clone()), DEFAULT_MARGIN).unwrap();
+        let result = outcome.image.to_luma8();
+
+        assert!(outcome.skip_reason.is_none());
+        assert!(result.width() < original.width() && result.height() < original.height());
+        // The whole text block survives, with the margin around it
+        assert_eq!(
+            outcome.crop,
+            Some(Crop {
+                x: 51,
+                y: 40,
+                width: 99,
+                height: 40
+            })
+        );
+        assert_eq!(dark_pixels(&result), dark_pixels(&page(0)));
+    }
+
+    #[test]
+    fn test_crop_border_margin_stops_at_frame() {
+        let outcome = apply(DynamicImage::ImageLuma8(page(15)), 100).unwrap();
+
+        // The frame, or its edge, is never brought back by a wide margin
+        assert_eq!(
+            outcome.crop,
+            Some(Crop {
+                x: 15 + BORDER_PADDING,
+                y: 15 + BORDER_PADDING,
+                width: 170 - 2 * BORDER_PADDING,
+                height: 90 - 2 * BORDER_PADDING
+            })
+        );
+    }
+
+    #[test]
+    fn test_crop_border_skips_blank_image() {
+        let blank = GrayImage::from_pixel(50, 50, Luma([255]));
+        let outcome = apply(DynamicImage::ImageLuma8(blank), DEFAULT_MARGIN).unwrap();
+
+        assert_eq!(outcome.skip_reason.as_deref(), Some("no content found"));
+        assert_eq!(outcome.image.width(), 50);
+    }
+
+    #[test]
+    fn test_crop_border_skips_content_reaching_edges() {
+        let full = GrayImage::from_fn(50, 50, |x, y| {
+            Luma([if (x + y) % 7 == 0 { 0 } else { 255 }])
+        });
+        let outcome = apply(DynamicImage::ImageLuma8(full), DEFAULT_MARGIN).unwrap();
+
+        assert_eq!(outcome.skip_reason.as_deref(), Some("no border found"));
+        assert!(outcome.crop.is_none());
+    }
+}
@@ -1,25 +1,60 @@
+use super::StepOutcome;
 use crate::error::OcrError;
 use image::{DynamicImage, GrayImage, Luma};
 use imageproc::geometric_transformations::{rotate_about_center, Interpolation};
 
 /// Deskew image by detecting and correcting rotation
 /// Uses projection profile method to find optimal angle
-pub fn apply(image: DynamicImage) -> Result<DynamicImage, OcrError> {
+pub fn apply(image: DynamicImage) -> Result<StepOutcome, OcrError> {
     let gray = image.to_luma8();
 
     // Find optimal rotation angle
     let angle = detect_skew_angle(&gray);
 
+    rotate(gray, angle)
+}
+
+/// Name of the detected angle in the step's metadata, in degrees
+pub const ANGLE_METADATA: &str = "skew_angle_degrees";
+
+/// Detect the skew angle of an image in radians, without correcting it
+///
+/// The angle survives uniform resizing, so it can be measured on the
+/// full-resolution image and corrected later with [`apply_angle`].
+pub fn detect(image: &DynamicImage) -> f32 {
+    match image.as_luma8() {
+        Some(gray) => detect_skew_angle(gray),
+        None => detect_skew_angle(&image.to_luma8()),
+    }
+}
+
+/// Correct a skew angle previously found by [`detect`]
+pub fn apply_angle(image: DynamicImage, angle: f32) -> Result<StepOutcome, OcrError> {
+    rotate(image.to_luma8(), angle)
+}
+
+fn rotate(gray: GrayImage, angle: f32) -> Result<StepOutcome, OcrError> {
+    // The search steps by tenths of a degree, so finer digits are noise
+    let degrees = (angle.to_degrees() * 100.0).round() / 100.0;
+
     // Skip if angle is negligible (less than 0.1 degrees)
     if angle.abs() < 0.1_f32.to_radians() {
-        return Ok(DynamicImage::ImageLuma8(gray));
+        return Ok(StepOutcome::skipped(
+            DynamicImage::ImageLuma8(gray),
+            format!(
+                "skew angle {:.2} degrees is below 0.1 degrees",
+                angle.to_degrees()
+            ),
+        )
+        .with_metadata(ANGLE_METADATA, degrees));
     }
 
     // Rotate to correct skew
     let background = Luma([255u8]); // White background
     let rotated = rotate_about_center(&gray, angle, Interpolation::Bilinear, background);
 
-    Ok(DynamicImage::ImageLuma8(rotated))
+    Ok(StepOutcome::applied(DynamicImage::ImageLuma8(rotated))
+        .with_metadata(ANGLE_METADATA, degrees))
 }
 
 /// Detect skew angle using projection profile variance
@@ -114,10 +149,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_apply_angle_matches_apply() {
+        let mut img = GrayImage::from_pixel(100, 50, Luma([255]));
+        for x in 10..90 {
+            img.put_pixel(x, 20 + x / 20, Luma([0])); // slightly tilted line
+        }
+        let img = DynamicImage::ImageLuma8(img);
+
+        let angle = detect(&img);
+        let direct = apply(img.clone()).unwrap();
+        let split = apply_angle(img, angle).unwrap();
+
+        assert_eq!(direct.image.as_bytes(), split.image.as_bytes());
+        assert_eq!(direct.skip_reason, split.skip_reason);
+        assert_eq!(direct.metadata, split.metadata);
+    }
+
+    #[test]
+    fn test_deskew_reports_detected_angle() {
+        let mut img = GrayImage::from_pixel(100, 50, Luma([255]));
+        for x in 10..90 {
+            img.put_pixel(x, 25, Luma([0]));
+        }
+        let straight = apply(DynamicImage::ImageLuma8(img)).unwrap();
+        assert_eq!(straight.metadata[ANGLE_METADATA], 0.0);
+
+        let tilted = apply_angle(DynamicImage::new_luma8(100, 50), 2.0_f32.to_radians()).unwrap();
+        assert_eq!(tilted.metadata[ANGLE_METADATA], 2.0);
+    }
+
     #[test]
     fn test_deskew_preserves_dimensions() {
         let img = GrayImage::new(100, 50);
-        let result = apply(DynamicImage::ImageLuma8(img)).unwrap();
+        let result = apply(DynamicImage::ImageLuma8(img)).unwrap().image;
         assert_eq!(result.width(), 100);
         assert_eq!(result.height(), 50);
     }
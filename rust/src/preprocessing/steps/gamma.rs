@@ -0,0 +1,61 @@
+use crate::error::OcrError;
+use image::DynamicImage;
+
+/// Apply gamma correction: `out = 255 * (in / 255) ^ (1 / gamma)`
+///
+/// A gamma above 1 brightens the midtones of dark scans while keeping black
+/// and white where they are; below 1 darkens them.
+pub fn apply(image: DynamicImage, gamma: f32) -> Result<DynamicImage, OcrError> {
+    let mut gray = image.into_luma8();
+    let table = lookup_table(gamma);
+    for pixel in gray.pixels_mut() {
+        pixel.0[0] = table[pixel.0[0] as usize];
+    }
+    Ok(DynamicImage::ImageLuma8(gray))
+}
+
+/// Output value for each of the 256 input values
+fn lookup_table(gamma: f32) -> [u8; 256] {
+    let exponent = 1.0 / gamma;
+    let mut table = [0u8; 256];
+    for (value, out) in table.iter_mut().enumerate() {
+        *out = (255.0 * (value as f32 / 255.0).powf(exponent))
+            .round()
+            .clamp(0.0, 255.0) as u8;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+
+    fn corrected(values: [u8; 3], gamma: f32) -> Vec<u8> {
+        let img = GrayImage::from_fn(3, 1, |x, _| Luma([values[x as usize]]));
+        let result = apply(DynamicImage::ImageLuma8(img), gamma).unwrap();
+        result.to_luma8().pixels().map(|p| p.0[0]).collect()
+    }
+
+    #[test]
+    fn test_gamma_brightens_midtones_and_keeps_extremes() {
+        let result = corrected([0, 128, 255], 1.5);
+
+        assert_eq!(result[0], 0);
+        assert!(result[1] > 150, "midtone only reached {}", result[1]);
+        assert_eq!(result[2], 255);
+    }
+
+    #[test]
+    fn test_gamma_below_one_darkens_midtones() {
+        let result = corrected([0, 128, 255], 0.5);
+
+        assert_eq!(result, vec![0, 64, 255]);
+    }
+
+    #[test]
+    fn test_gamma_of_one_is_identity() {
+        let table = lookup_table(1.0);
+        assert!(table.iter().enumerate().all(|(i, &v)| v as usize == i));
+    }
+}
@@ -1,16 +1,86 @@
 use crate::error::OcrError;
-use image::DynamicImage;
+use image::{DynamicImage, GrayImage, Luma, RgbImage};
+
+/// How color is turned into shades of gray
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GrayscaleMethod {
+    /// Luma, weighting the channels by how bright the eye sees them
+    #[default]
+    Luminosity,
+    /// The channel or luma with the most variance, so colored ink stays
+    /// dark instead of fading toward the background
+    MaxContrast,
+    /// Plain mean of red, green and blue
+    Average,
+}
+
+impl GrayscaleMethod {
+    /// Parse from query parameter string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "luminosity" => Some(Self::Luminosity),
+            "max_contrast" => Some(Self::MaxContrast),
+            "average" => Some(Self::Average),
+            _ => None,
+        }
+    }
+}
 
 /// Convert image to grayscale
 /// This is the foundation for most other preprocessing steps
-pub fn apply(image: DynamicImage) -> Result<DynamicImage, OcrError> {
-    Ok(DynamicImage::ImageLuma8(image.to_luma8()))
+pub fn apply(image: DynamicImage, method: GrayscaleMethod) -> Result<DynamicImage, OcrError> {
+    // Images without color come out the same whichever way
+    if method == GrayscaleMethod::Luminosity || !image.color().has_color() {
+        return Ok(DynamicImage::ImageLuma8(image.to_luma8()));
+    }
+
+    let rgb = image.to_rgb8();
+    let gray = match method {
+        GrayscaleMethod::Average => map(&rgb, |[r, g, b]| {
+            ((r as u16 + g as u16 + b as u16) / 3) as u8
+        }),
+        _ => max_contrast(&rgb),
+    };
+    Ok(DynamicImage::ImageLuma8(gray))
+}
+
+/// The red, green or blue channel or the luma, whichever varies most
+///
+/// Red ink vanishes from the red channel but is black in green and blue, and
+/// a yellow highlighter is invisible in red and green, so the most varied
+/// channel usually separates text from paper best.
+fn max_contrast(rgb: &RgbImage) -> GrayImage {
+    let candidates = [
+        map(rgb, |[r, _, _]| r),
+        map(rgb, |[_, g, _]| g),
+        map(rgb, |[_, _, b]| b),
+        DynamicImage::ImageRgb8(rgb.clone()).to_luma8(),
+    ];
+    candidates
+        .into_iter()
+        .max_by(|a, b| variance(a).total_cmp(&variance(b)))
+        .expect("there are always candidates")
+}
+
+fn map(rgb: &RgbImage, f: impl Fn([u8; 3]) -> u8) -> GrayImage {
+    GrayImage::from_fn(rgb.width(), rgb.height(), |x, y| {
+        Luma([f(rgb.get_pixel(x, y).0)])
+    })
+}
+
+fn variance(gray: &GrayImage) -> f64 {
+    let count = gray.pixels().len().max(1) as f64;
+    let mean = gray.pixels().map(|p| p.0[0] as f64).sum::<f64>() / count;
+    gray.pixels()
+        .map(|p| (p.0[0] as f64 - mean).powi(2))
+        .sum::<f64>()
+        / count
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use image::{Rgb, RgbImage};
+    use image::Rgb;
 
     #[test]
     fn test_grayscale_converts_color() {
@@ -19,7 +89,7 @@ mod tests {
         img.put_pixel(1, 0, Rgb([0, 255, 0])); // Green
         img.put_pixel(2, 0, Rgb([0, 0, 255])); // Blue
 
-        let result = apply(DynamicImage::ImageRgb8(img)).unwrap();
+        let result = apply(DynamicImage::ImageRgb8(img), GrayscaleMethod::default()).unwrap();
         let gray = result.to_luma8();
 
         // All pixels should have some value (within tolerance)
@@ -31,8 +101,54 @@ mod tests {
     #[test]
     fn test_grayscale_preserves_dimensions() {
         let img = RgbImage::new(100, 50);
-        let result = apply(DynamicImage::ImageRgb8(img)).unwrap();
-        assert_eq!(result.width(), 100);
-        assert_eq!(result.height(), 50);
+        for method in [
+            GrayscaleMethod::Luminosity,
+            GrayscaleMethod::MaxContrast,
+            GrayscaleMethod::Average,
+        ] {
+            let result = apply(DynamicImage::ImageRgb8(img.clone()), method).unwrap();
+            assert_eq!(result.width(), 100);
+            assert_eq!(result.height(), 50);
+        }
+    }
+
+    #[test]
+    fn test_max_contrast_keeps_red_ink_dark() {
+        // Red text on white paper
+        let img = RgbImage::from_fn(20, 10, |x, _| {
+            if x < 5 {
+                Rgb([220, 30, 30])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        });
+        let contrast = |method| {
+            let gray = apply(DynamicImage::ImageRgb8(img.clone()), method)
+                .unwrap()
+                .to_luma8();
+            gray.get_pixel(10, 0).0[0] - gray.get_pixel(0, 0).0[0]
+        };
+
+        let luminosity = contrast(GrayscaleMethod::Luminosity);
+        let max_contrast = contrast(GrayscaleMethod::MaxContrast);
+        assert!(
+            max_contrast > luminosity,
+            "{} vs {}",
+            max_contrast,
+            luminosity
+        );
+        assert_eq!(max_contrast, 225);
+        assert_eq!(contrast(GrayscaleMethod::Average), 162);
+    }
+
+    #[test]
+    fn test_gray_input_is_unchanged() {
+        let img = GrayImage::from_fn(4, 4, |x, y| Luma([(x * 60 + y) as u8]));
+        let result = apply(
+            DynamicImage::ImageLuma8(img.clone()),
+            GrayscaleMethod::MaxContrast,
+        )
+        .unwrap();
+        assert_eq!(result.to_luma8(), img);
     }
 }
@@ -1,9 +1,81 @@
 //! Individual preprocessing steps
 
+pub mod contrast;
+pub mod crop_border;
 pub mod denoise;
 pub mod deskew;
+pub mod gamma;
 pub mod grayscale;
 pub mod normalize;
 pub mod resize;
 pub mod sharpen;
 pub mod threshold;
+
+use crop_border::Crop;
+use image::DynamicImage;
+use resize::Resize;
+use std::collections::BTreeMap;
+
+/// Values a step measured while running, by name (e.g. the skew angle the
+/// deskew step found)
+pub type StepMetadata = BTreeMap<String, f32>;
+
+/// Result of running a single step, for steps that may decide to be a no-op
+#[derive(Debug, Clone)]
+pub struct StepOutcome {
+    pub image: DynamicImage,
+    /// Why the step left the image unchanged (None if it was applied)
+    pub skip_reason: Option<String>,
+    /// How a resizing step changed the image's size
+    pub resize: Option<Resize>,
+    /// Part of the input a cropping step kept
+    pub crop: Option<Crop>,
+    /// What the step measured, reported alongside its timing
+    pub metadata: StepMetadata,
+}
+
+impl StepOutcome {
+    pub fn applied(image: DynamicImage) -> Self {
+        Self {
+            image,
+            skip_reason: None,
+            resize: None,
+            crop: None,
+            metadata: StepMetadata::new(),
+        }
+    }
+
+    pub fn skipped(image: DynamicImage, reason: impl Into<String>) -> Self {
+        Self {
+            image,
+            skip_reason: Some(reason.into()),
+            resize: None,
+            crop: None,
+            metadata: StepMetadata::new(),
+        }
+    }
+
+    /// Record how the step resized the image
+    pub fn with_resize(mut self, resize: Resize) -> Self {
+        self.resize = Some(resize);
+        self
+    }
+
+    /// Record which part of the input the step kept
+    pub fn with_crop(mut self, crop: Crop) -> Self {
+        self.crop = Some(crop);
+        self
+    }
+
+    /// Record a value the step measured
+    pub fn with_metadata(mut self, name: &str, value: f32) -> Self {
+        self.metadata.insert(name.to_string(), value);
+        self
+    }
+}
+
+impl From<DynamicImage> for StepOutcome {
+    fn from(image: DynamicImage) -> Self {
+        Self::applied(image)
+    }
+}
@@ -1,15 +1,19 @@
+use super::StepOutcome;
 use crate::error::OcrError;
 use image::{DynamicImage, GrayImage, Luma};
 
 /// Normalize image contrast using histogram stretching
 /// Maps pixel values to use full 0-255 range
-pub fn apply(image: DynamicImage) -> Result<DynamicImage, OcrError> {
+pub fn apply(image: DynamicImage) -> Result<StepOutcome, OcrError> {
     let gray = image.to_luma8();
     let (min_val, max_val) = find_min_max(&gray);
 
     // Avoid division by zero
     if max_val <= min_val {
-        return Ok(DynamicImage::ImageLuma8(gray));
+        return Ok(StepOutcome::skipped(
+            DynamicImage::ImageLuma8(gray),
+            "image has uniform intensity",
+        ));
     }
 
     let range = (max_val - min_val) as f32;
@@ -19,7 +23,7 @@ pub fn apply(image: DynamicImage) -> Result<DynamicImage, OcrError> {
         Luma([normalized])
     });
 
-    Ok(DynamicImage::ImageLuma8(normalized))
+    Ok(StepOutcome::applied(DynamicImage::ImageLuma8(normalized)))
 }
 
 fn find_min_max(img: &GrayImage) -> (u8, u8) {
@@ -47,7 +51,7 @@ mod tests {
             Luma([val])
         });
 
-        let result = apply(DynamicImage::ImageLuma8(img)).unwrap();
+        let result = apply(DynamicImage::ImageLuma8(img)).unwrap().image;
         let result_gray = result.to_luma8();
 
         let (min, max) = find_min_max(&result_gray);
@@ -62,10 +66,17 @@ mod tests {
         // Uniform image (all same value)
         let img = GrayImage::from_pixel(10, 10, Luma([128]));
 
-        let result = apply(DynamicImage::ImageLuma8(img.clone())).unwrap();
+        let result = apply(DynamicImage::ImageLuma8(img.clone())).unwrap().image;
         let result_gray = result.to_luma8();
 
         // Should return unchanged (no division by zero)
         assert_eq!(result_gray.get_pixel(0, 0).0[0], 128);
     }
+
+    #[test]
+    fn test_normalize_reports_skip_for_uniform_image() {
+        let img = GrayImage::from_pixel(10, 10, Luma([128]));
+        let outcome = apply(DynamicImage::ImageLuma8(img)).unwrap();
+        assert!(outcome.skip_reason.is_some());
+    }
 }
@@ -1,22 +1,130 @@
+use super::StepOutcome;
 use crate::error::OcrError;
-use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use image::{imageops::FilterType, DynamicImage, GenericImageView, GrayImage, Luma};
+use imageproc::contrast::otsu_level;
+use imageproc::region_labelling::{connected_components, Connectivity};
+use serde::Serialize;
 
 /// Target DPI for OCR (300 DPI is generally optimal)
 const TARGET_DPI: u32 = 300;
 /// Assume input images are 72 DPI if no metadata available
-const ASSUMED_INPUT_DPI: u32 = 72;
+const ASSUMED_INPUT_DPI: f32 = 72.0;
 /// Maximum dimension to avoid memory issues
 const MAX_DIMENSION: u32 = 4000;
 /// Minimum dimension for reasonable OCR
 const MIN_DIMENSION: u32 = 300;
+/// Smallest and largest text x-height OCR engines read well, in pixels
+const X_HEIGHT_RANGE: std::ops::RangeInclusive<f32> = 20.0..=40.0;
+/// x-height adaptive resizing scales text outside the range to
+const TARGET_X_HEIGHT: f32 = 30.0;
+
+/// Name of the estimated text height in the step's metadata, in input pixels
+pub const X_HEIGHT_METADATA: &str = "x_height_px";
+
+/// How the resize step picks its scale
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResizeMethod {
+    /// Scale the input from its DPI, 72 unless known, to 300 DPI
+    #[default]
+    Dpi,
+    /// Measure the text and scale its x-height into [`X_HEIGHT_RANGE`]
+    Adaptive,
+}
+
+impl ResizeMethod {
+    /// Parse from query parameter string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "dpi" => Some(Self::Dpi),
+            "adaptive" => Some(Self::Adaptive),
+            _ => None,
+        }
+    }
+}
+
+/// Direction an image was resized in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResizeAction {
+    /// Enlarged, which can't add detail the original didn't have
+    Upscaled,
+    Downscaled,
+    Unchanged,
+}
+
+impl ResizeAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Upscaled => "upscaled",
+            Self::Downscaled => "downscaled",
+            Self::Unchanged => "unchanged",
+        }
+    }
+}
+
+/// How a step changed an image's size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Resize {
+    pub action: ResizeAction,
+    /// Output size relative to the input, along the longest side
+    pub scale: f32,
+}
+
+impl Resize {
+    /// Compare input and output dimensions
+    pub fn between(input: (u32, u32), output: (u32, u32)) -> Self {
+        let longest = |(width, height): (u32, u32)| width.max(height).max(1) as f32;
+        let scale = longest(output) / longest(input);
+        let action = if input == output {
+            ResizeAction::Unchanged
+        } else if scale > 1.0 {
+            ResizeAction::Upscaled
+        } else {
+            ResizeAction::Downscaled
+        };
+
+        Self {
+            action,
+            // Two decimals are plenty to tell resizes apart
+            scale: (scale * 100.0).round() / 100.0,
+        }
+    }
+}
 
 /// Resize image to optimal size for OCR
 /// Scales up low-res images and constrains very large ones
-pub fn apply(image: DynamicImage) -> Result<DynamicImage, OcrError> {
-    let (width, height) = image.dimensions();
+///
+/// `input_dpi` is the resolution the image says it has, if any. Adaptive
+/// resizing falls back to the DPI scale for images without measurable text.
+pub fn apply(
+    image: DynamicImage,
+    method: ResizeMethod,
+    input_dpi: Option<f32>,
+) -> Result<StepOutcome, OcrError> {
+    // Calculate scale factor (72 DPI source unless known, target 300 DPI)
+    let dpi_scale = TARGET_DPI as f32 / input_dpi.unwrap_or(ASSUMED_INPUT_DPI);
 
-    // Calculate scale factor (assume 72 DPI source, target 300 DPI)
-    let scale = TARGET_DPI as f32 / ASSUMED_INPUT_DPI as f32;
+    let x_height = match method {
+        ResizeMethod::Dpi => None,
+        ResizeMethod::Adaptive => estimate_x_height(&image.to_luma8()),
+    };
+    let Some(x_height) = x_height else {
+        return scale_by(image, dpi_scale);
+    };
+
+    // Text already in the range is left at its size
+    let scale = if X_HEIGHT_RANGE.contains(&x_height) {
+        1.0
+    } else {
+        TARGET_X_HEIGHT / x_height
+    };
+    Ok(scale_by(image, scale)?.with_metadata(X_HEIGHT_METADATA, x_height))
+}
+
+/// Scale an image, keeping it within [`MAX_DIMENSION`] and above
+/// [`MIN_DIMENSION`]
+fn scale_by(image: DynamicImage, scale: f32) -> Result<StepOutcome, OcrError> {
+    let (width, height) = image.dimensions();
 
     let mut new_width = (width as f32 * scale) as u32;
     let mut new_height = (height as f32 * scale) as u32;
@@ -29,6 +137,11 @@ pub fn apply(image: DynamicImage) -> Result<DynamicImage, OcrError> {
         new_height = (new_height as f32 * scale_down) as u32;
     }
 
+    // Truncation can round the short side of extreme aspect ratios down to
+    // zero, which `resize` can't produce
+    new_width = new_width.max(1);
+    new_height = new_height.max(1);
+
     // Ensure minimum dimension
     if new_width < MIN_DIMENSION && new_height < MIN_DIMENSION {
         let min_dim = new_width.min(new_height);
@@ -41,10 +154,75 @@ pub fn apply(image: DynamicImage) -> Result<DynamicImage, OcrError> {
     let width_ratio = new_width as f32 / width as f32;
     let height_ratio = new_height as f32 / height as f32;
     if (0.95..=1.05).contains(&width_ratio) && (0.95..=1.05).contains(&height_ratio) {
+        return Ok(
+            StepOutcome::skipped(image, "image already within 5% of target size")
+                .with_resize(Resize::between((width, height), (width, height))),
+        );
+    }
+
+    let resized = image.resize(new_width, new_height, FilterType::Lanczos3);
+    let resize = Resize::between((width, height), resized.dimensions());
+    Ok(StepOutcome::applied(resized).with_resize(resize))
+}
+
+/// Estimate the x-height of an image's text, in pixels
+///
+/// Dark connected components are taken as glyphs, and the median of their
+/// heights is the estimate: most letters are lowercase, so the median sits
+/// at the x-height even with capitals and ascenders about. Specks, and
+/// shapes taller than a quarter of the image such as rules and borders, are
+/// left out. Returns None when no glyphs are found. Assumes dark text on a
+/// light background.
+pub fn estimate_x_height(gray: &GrayImage) -> Option<f32> {
+    let level = otsu_level(gray);
+    let ink = GrayImage::from_fn(gray.width(), gray.height(), |x, y| {
+        Luma([if gray.get_pixel(x, y).0[0] <= level {
+            255
+        } else {
+            0
+        }])
+    });
+    let labels = connected_components(&ink, Connectivity::Eight, Luma([0]));
+
+    // Top and bottom row of each component, indexed by label
+    let mut spans: Vec<Option<(u32, u32)>> = Vec::new();
+    for (_, y, label) in labels.enumerate_pixels() {
+        let label = label.0[0] as usize;
+        if label == 0 {
+            continue;
+        }
+        if spans.len() <= label {
+            spans.resize(label + 1, None);
+        }
+        let span = spans[label].get_or_insert((y, y));
+        span.1 = y;
+    }
+
+    let max_height = gray.height() / 4;
+    let mut heights: Vec<u32> = spans
+        .into_iter()
+        .flatten()
+        .map(|(top, bottom)| bottom - top + 1)
+        .filter(|&height| height >= 2 && height <= max_height)
+        .collect();
+    if heights.is_empty() {
+        return None;
+    }
+    let middle = heights.len() / 2;
+    Some(*heights.select_nth_unstable(middle).1 as f32)
+}
+
+/// Downscale image so its longest side fits within `max_dimension`
+/// Never upscales; used for fast low-resolution probe passes
+pub fn downscale(image: DynamicImage, max_dimension: u32) -> Result<DynamicImage, OcrError> {
+    let (width, height) = image.dimensions();
+
+    if width.max(height) <= max_dimension {
         return Ok(image);
     }
 
-    Ok(image.resize(new_width, new_height, FilterType::Lanczos3))
+    // Triangle filter is much cheaper than Lanczos3 and good enough for a probe
+    Ok(image.resize(max_dimension, max_dimension, FilterType::Triangle))
 }
 
 #[cfg(test)]
@@ -56,17 +234,190 @@ mod tests {
     fn test_resize_upscales_small_image() {
         // 100x100 at 72 DPI should be scaled to ~416x416 at 300 DPI
         let img = GrayImage::new(100, 100);
-        let result = apply(DynamicImage::ImageLuma8(img)).unwrap();
+        let result = apply(DynamicImage::ImageLuma8(img), ResizeMethod::Dpi, None)
+            .unwrap()
+            .image;
         assert!(result.width() > 100);
         assert!(result.height() > 100);
     }
 
+    #[test]
+    fn test_resize_uses_known_input_dpi() {
+        let resize = |dpi| {
+            apply(
+                DynamicImage::ImageLuma8(GrayImage::new(500, 250)),
+                ResizeMethod::Dpi,
+                dpi,
+            )
+            .unwrap()
+        };
+
+        // A 300 DPI scan is already at the target
+        let scan = resize(Some(300.0));
+        assert_eq!(scan.resize.unwrap().action, ResizeAction::Unchanged);
+        assert!(scan.skip_reason.is_some());
+
+        assert_eq!(resize(Some(150.0)).image.dimensions(), (1000, 500));
+        // Without one, 72 DPI is assumed
+        assert_eq!(resize(None).resize.unwrap().scale, 4.16);
+    }
+
     #[test]
     fn test_resize_limits_large_image() {
         // Very large image should be constrained to MAX_DIMENSION
         let img = GrayImage::new(2000, 2000);
-        let result = apply(DynamicImage::ImageLuma8(img)).unwrap();
+        let result = apply(DynamicImage::ImageLuma8(img), ResizeMethod::Dpi, None)
+            .unwrap()
+            .image;
         assert!(result.width() <= MAX_DIMENSION);
         assert!(result.height() <= MAX_DIMENSION);
     }
+
+    #[test]
+    fn test_resize_reports_action_and_scale() {
+        let resize = |width, height| {
+            apply(
+                DynamicImage::ImageLuma8(GrayImage::new(width, height)),
+                ResizeMethod::Dpi,
+                None,
+            )
+            .unwrap()
+            .resize
+            .unwrap()
+        };
+
+        let small = resize(100, 100);
+        assert_eq!(small.action, ResizeAction::Upscaled);
+        assert_eq!(small.scale, 4.16);
+
+        let large = resize(8000, 4000);
+        assert_eq!(large.action, ResizeAction::Downscaled);
+        assert_eq!(large.scale, 0.5);
+
+        let unchanged = resize(MAX_DIMENSION, 100);
+        assert_eq!(unchanged.action, ResizeAction::Unchanged);
+        assert_eq!(unchanged.scale, 1.0);
+    }
+
+    #[test]
+    fn test_resize_reports_skip_when_already_target_size() {
+        // Upscaled size gets clamped back to MAX_DIMENSION, i.e. the original size
+        let img = GrayImage::new(MAX_DIMENSION, 100);
+        let outcome = apply(DynamicImage::ImageLuma8(img), ResizeMethod::Dpi, None).unwrap();
+        assert!(outcome.skip_reason.is_some());
+        assert_eq!(outcome.image.width(), MAX_DIMENSION);
+    }
+
+    #[test]
+    fn test_resize_handles_extreme_aspect_ratios() {
+        for (width, height) in [(1, 1000), (1000, 1), (1, 100_000)] {
+            let img = GrayImage::new(width, height);
+            let result = apply(DynamicImage::ImageLuma8(img), ResizeMethod::Dpi, None)
+                .unwrap()
+                .image;
+
+            assert!(result.width() >= 1 && result.height() >= 1);
+            assert!(result.width() <= MAX_DIMENSION && result.height() <= MAX_DIMENSION);
+            // The long side still gets the usual treatment
+            assert!(result.width().max(result.height()) >= MIN_DIMENSION);
+        }
+    }
+
+    /// Rows of `glyph`-pixel-tall dark "letters" on white paper
+    fn text(width: u32, height: u32, glyph: u32) -> GrayImage {
+        GrayImage::from_fn(width, height, |x, y| {
+            let in_glyph = (y / glyph) % 3 == 1 && (x / glyph) % 2 == 1;
+            Luma([if in_glyph { 0 } else { 255 }])
+        })
+    }
+
+    #[test]
+    fn test_estimate_x_height() {
+        assert_eq!(estimate_x_height(&text(400, 200, 6)), Some(6.0));
+        assert_eq!(estimate_x_height(&text(400, 200, 12)), Some(12.0));
+        // Blank pages and lone rules have no glyphs to measure
+        assert_eq!(
+            estimate_x_height(&GrayImage::from_pixel(100, 100, Luma([255]))),
+            None
+        );
+        let rule = GrayImage::from_fn(100, 100, |x, _| Luma([if x == 50 { 0 } else { 255 }]));
+        assert_eq!(estimate_x_height(&rule), None);
+    }
+
+    #[test]
+    fn test_adaptive_resize_scales_small_text_to_target() {
+        let img = DynamicImage::ImageLuma8(text(400, 200, 6));
+
+        let adaptive = apply(img.clone(), ResizeMethod::Adaptive, None).unwrap();
+        // 6 px letters become 30 px letters
+        assert_eq!(adaptive.resize.unwrap().scale, 5.0);
+        assert_eq!(adaptive.image.dimensions(), (2000, 1000));
+        assert_eq!(adaptive.metadata[X_HEIGHT_METADATA], 6.0);
+        assert_eq!(
+            estimate_x_height(&adaptive.image.to_luma8()),
+            Some(TARGET_X_HEIGHT)
+        );
+
+        // The DPI method scales the same image by a fixed factor
+        let dpi = apply(img, ResizeMethod::Dpi, None).unwrap();
+        assert_eq!(dpi.resize.unwrap().scale, 4.17);
+        assert!(dpi.metadata.is_empty());
+    }
+
+    #[test]
+    fn test_adaptive_resize_shrinks_large_text_and_keeps_readable_text() {
+        let large = apply(
+            DynamicImage::ImageLuma8(text(1200, 900, 60)),
+            ResizeMethod::Adaptive,
+            None,
+        )
+        .unwrap();
+        assert_eq!(large.resize.unwrap().action, ResizeAction::Downscaled);
+        assert_eq!(large.image.dimensions(), (600, 450));
+
+        let readable = apply(
+            DynamicImage::ImageLuma8(text(1200, 900, 30)),
+            ResizeMethod::Adaptive,
+            None,
+        )
+        .unwrap();
+        assert_eq!(readable.resize.unwrap().action, ResizeAction::Unchanged);
+        assert!(readable.skip_reason.is_some());
+    }
+
+    #[test]
+    fn test_adaptive_resize_keeps_max_dimension_and_falls_back_to_dpi() {
+        let tiny_text = apply(
+            DynamicImage::ImageLuma8(text(1500, 600, 3)),
+            ResizeMethod::Adaptive,
+            None,
+        )
+        .unwrap();
+        assert_eq!(tiny_text.image.width(), MAX_DIMENSION);
+
+        let blank = apply(
+            DynamicImage::ImageLuma8(GrayImage::from_pixel(100, 100, Luma([255]))),
+            ResizeMethod::Adaptive,
+            None,
+        )
+        .unwrap();
+        assert_eq!(blank.resize.unwrap().scale, 4.16);
+        assert!(blank.metadata.is_empty());
+    }
+
+    #[test]
+    fn test_downscale_fits_longest_side() {
+        let img = GrayImage::new(2000, 1000);
+        let result = downscale(DynamicImage::ImageLuma8(img), 800).unwrap();
+        assert_eq!(result.width(), 800);
+        assert_eq!(result.height(), 400);
+    }
+
+    #[test]
+    fn test_downscale_never_upscales() {
+        let img = GrayImage::new(300, 200);
+        let result = downscale(DynamicImage::ImageLuma8(img), 800).unwrap();
+        assert_eq!(result.width(), 300);
+        assert_eq!(result.height(), 200);
+    }
 }
@@ -1,18 +1,63 @@
+use super::StepOutcome;
 use crate::error::OcrError;
-use image::DynamicImage;
+use image::{DynamicImage, GrayImage};
 use imageproc::filter::filter3x3;
 
 /// Apply Laplacian-based sharpening
 /// Enhances edges to make text more distinct
-pub fn apply(image: DynamicImage) -> Result<DynamicImage, OcrError> {
+///
+/// Images whose sharpness (see [`laplacian_variance`]) is above
+/// `skip_above` are left alone, since sharpening crisp digital images turns
+/// their aliasing into false edges.
+pub fn apply(image: DynamicImage, skip_above: Option<f32>) -> Result<StepOutcome, OcrError> {
     let gray = image.to_luma8();
 
+    if let Some(threshold) = skip_above {
+        let sharpness = laplacian_variance(&gray);
+        if sharpness > threshold {
+            return Ok(StepOutcome::skipped(
+                image,
+                format!(
+                    "image already sharp (Laplacian variance {:.0} above {:.0})",
+                    sharpness, threshold
+                ),
+            ));
+        }
+    }
+
     // Laplacian-based sharpening kernel
     // Center weight 5, neighbors -1 each = edge enhancement
     let kernel: [f32; 9] = [0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0];
 
     let sharpened = filter3x3(&gray, &kernel);
-    Ok(DynamicImage::ImageLuma8(sharpened))
+    Ok(StepOutcome::applied(DynamicImage::ImageLuma8(sharpened)))
+}
+
+/// Estimate sharpness as the variance of the Laplacian over interior pixels
+///
+/// Blurry images have few strong edges and score in the single digits after
+/// the resize step, while crisp screenshots kept near their native size
+/// score in the hundreds.
+pub fn laplacian_variance(gray: &GrayImage) -> f32 {
+    let (width, height) = gray.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+
+    let pixel = |x: u32, y: u32| gray.get_pixel(x, y).0[0] as f64;
+    let (mut sum, mut sum_sq) = (0.0, 0.0);
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let laplacian = pixel(x - 1, y) + pixel(x + 1, y) + pixel(x, y - 1) + pixel(x, y + 1)
+                - 4.0 * pixel(x, y);
+            sum += laplacian;
+            sum_sq += laplacian * laplacian;
+        }
+    }
+
+    let count = ((width - 2) * (height - 2)) as f64;
+    let mean = sum / count;
+    (sum_sq / count - mean * mean) as f32
 }
 
 #[cfg(test)]
@@ -25,7 +70,9 @@ mod tests {
         // Create image with an edge (left half dark, right half light)
         let img = GrayImage::from_fn(20, 10, |x, _| if x < 10 { Luma([50]) } else { Luma([200]) });
 
-        let result = apply(DynamicImage::ImageLuma8(img.clone())).unwrap();
+        let result = apply(DynamicImage::ImageLuma8(img.clone()), None)
+            .unwrap()
+            .image;
         let result_gray = result.to_luma8();
 
         // Edge pixels should have enhanced contrast
@@ -43,4 +90,23 @@ mod tests {
             original_diff
         );
     }
+
+    #[test]
+    fn test_sharpen_skips_already_sharp_images() {
+        // Checkerboard: every pixel is an edge
+        let sharp = GrayImage::from_fn(20, 20, |x, y| {
+            Luma([if (x + y) % 2 == 0 { 0 } else { 255 }])
+        });
+        // Gentle gradient: no edges at all
+        let smooth = GrayImage::from_fn(20, 20, |x, _| Luma([(x * 10) as u8]));
+        assert!(laplacian_variance(&sharp) > 100_000.0);
+        assert_eq!(laplacian_variance(&smooth), 0.0);
+
+        let outcome = apply(DynamicImage::ImageLuma8(sharp.clone()), Some(1000.0)).unwrap();
+        assert!(outcome.skip_reason.unwrap().contains("already sharp"));
+        assert_eq!(outcome.image.to_luma8(), sharp);
+
+        let outcome = apply(DynamicImage::ImageLuma8(smooth), Some(1000.0)).unwrap();
+        assert!(outcome.skip_reason.is_none());
+    }
 }
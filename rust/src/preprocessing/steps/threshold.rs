@@ -1,19 +1,119 @@
 use crate::error::OcrError;
 use image::{DynamicImage, GrayImage, Luma};
 
-/// Sauvola threshold parameters
-const WINDOW_SIZE: u32 = 15;
-const K: f32 = 0.2;
+/// Default Sauvola threshold parameters
+pub const WINDOW_SIZE: u32 = 15;
+pub const K: f32 = 0.2;
 const R: f32 = 128.0; // Dynamic range / 2
 
-/// Apply Sauvola adaptive thresholding
-/// Better than Otsu for documents with uneven lighting
-pub fn apply(image: DynamicImage) -> Result<DynamicImage, OcrError> {
+/// How the threshold step decides between black and white
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ThresholdMethod {
+    /// Threshold from each pixel's neighbourhood, for uneven lighting
+    #[default]
+    Sauvola,
+    /// One threshold for the whole image, for clean, evenly lit scans
+    Otsu,
+}
+
+impl ThresholdMethod {
+    /// Parse from query parameter string
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "sauvola" => Some(Self::Sauvola),
+            "otsu" => Some(Self::Otsu),
+            _ => None,
+        }
+    }
+}
+
+/// Sauvola window and sensitivity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SauvolaParams {
+    /// Side of the square neighbourhood each pixel is compared with (odd)
+    pub window_size: u32,
+    /// How far below the local mean a pixel must be to turn black
+    pub k: f32,
+}
+
+impl Default for SauvolaParams {
+    fn default() -> Self {
+        Self {
+            window_size: WINDOW_SIZE,
+            k: K,
+        }
+    }
+}
+
+/// Binarize an image with the given method
+///
+/// Sauvola copes with shadows and uneven lighting, but over-fragments clean
+/// scans: flat areas have no local contrast to go by, so it speckles them
+/// and hollows out large dark shapes. Otsu avoids that when the lighting is
+/// even. `sauvola` is ignored by Otsu.
+pub fn apply(
+    image: DynamicImage,
+    method: ThresholdMethod,
+    sauvola: SauvolaParams,
+) -> Result<DynamicImage, OcrError> {
     let gray = image.to_luma8();
-    let binarized = sauvola_threshold(&gray, WINDOW_SIZE, K);
+    let binarized = match method {
+        ThresholdMethod::Sauvola => sauvola_threshold(&gray, sauvola.window_size, sauvola.k),
+        ThresholdMethod::Otsu => {
+            let level = otsu_level(&gray);
+            GrayImage::from_fn(gray.width(), gray.height(), |x, y| {
+                binarize(gray.get_pixel(x, y).0[0] as f32, level as f32)
+            })
+        }
+    };
     Ok(DynamicImage::ImageLuma8(binarized))
 }
 
+/// Pixels above the threshold turn white, the rest black
+fn binarize(pixel: f32, threshold: f32) -> Luma<u8> {
+    if pixel > threshold {
+        Luma([255u8])
+    } else {
+        Luma([0u8])
+    }
+}
+
+/// Otsu's global threshold: the level that maximizes the variance between
+/// the pixels at or below it and those above it
+fn otsu_level(img: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in img.pixels() {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+
+    let total = img.pixels().len() as f64;
+    let total_sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, &count)| value as f64 * count as f64)
+        .sum();
+
+    let (mut below, mut below_sum) = (0.0, 0.0);
+    let (mut best_level, mut best_variance) = (0u8, 0.0);
+    for (value, &count) in histogram.iter().enumerate() {
+        below += count as f64;
+        below_sum += value as f64 * count as f64;
+        let above = total - below;
+        if below == 0.0 || above == 0.0 {
+            continue;
+        }
+
+        let mean_below = below_sum / below;
+        let mean_above = (total_sum - below_sum) / above;
+        let variance = below * above * (mean_below - mean_above).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best_level = value as u8;
+        }
+    }
+    best_level
+}
+
 /// Sauvola adaptive thresholding
 ///
 /// For each pixel, threshold = mean * (1 + k * (std_dev / R - 1))
@@ -35,12 +135,7 @@ fn sauvola_threshold(img: &GrayImage, window_size: u32, k: f32) -> GrayImage {
 
         let threshold = mean * (1.0 + k * (std_dev / R - 1.0));
 
-        let pixel = img.get_pixel(x, y).0[0] as f32;
-        if pixel > threshold {
-            Luma([255u8])
-        } else {
-            Luma([0u8])
-        }
+        binarize(img.get_pixel(x, y).0[0] as f32, threshold)
     })
 }
 
@@ -92,9 +187,14 @@ mod tests {
     #[test]
     fn test_threshold_binarizes_image() {
         // Create a simple gradient image
-        let img = GrayImage::from_fn(50, 50, |x, _| Luma([(x as u8 * 5).min(255)]));
+        let img = GrayImage::from_fn(50, 50, |x, _| Luma([x as u8 * 5]));
 
-        let result = apply(DynamicImage::ImageLuma8(img)).unwrap();
+        let result = apply(
+            DynamicImage::ImageLuma8(img),
+            ThresholdMethod::Sauvola,
+            SauvolaParams::default(),
+        )
+        .unwrap();
         let result_gray = result.to_luma8();
 
         // Result should only contain 0 or 255
@@ -115,7 +215,12 @@ mod tests {
             img.put_pixel(x, 10, Luma([20])); // dark text
         }
 
-        let result = apply(DynamicImage::ImageLuma8(img)).unwrap();
+        let result = apply(
+            DynamicImage::ImageLuma8(img),
+            ThresholdMethod::Sauvola,
+            SauvolaParams::default(),
+        )
+        .unwrap();
         let result_gray = result.to_luma8();
 
         // Text pixels should be black (0)
@@ -123,4 +228,97 @@ mod tests {
         // Background should be white (255)
         assert_eq!(result_gray.get_pixel(25, 5).0[0], 255);
     }
+
+    /// Evenly lit page: a light gradient background with a large dark block
+    fn block_on_gradient() -> (GrayImage, impl Fn(u32, u32) -> bool) {
+        let is_ink = |x: u32, y: u32| (20..60).contains(&x) && (10..40).contains(&y);
+        let img = GrayImage::from_fn(80, 50, |x, y| {
+            if is_ink(x, y) {
+                Luma([30 + (x / 4) as u8])
+            } else {
+                Luma([200 + (x / 2) as u8])
+            }
+        });
+        (img, is_ink)
+    }
+
+    /// Pixels whose binarized value doesn't match the ink they came from
+    fn misclassified(method: ThresholdMethod) -> usize {
+        let (img, is_ink) = block_on_gradient();
+        let result = apply(
+            DynamicImage::ImageLuma8(img),
+            method,
+            SauvolaParams::default(),
+        )
+        .unwrap()
+        .to_luma8();
+        result
+            .enumerate_pixels()
+            .filter(|(x, y, pixel)| (pixel.0[0] == 0) != is_ink(*x, *y))
+            .count()
+    }
+
+    #[test]
+    fn test_otsu_is_cleaner_than_sauvola_on_even_lighting() {
+        // Sauvola hollows out the block, where there is no local contrast
+        let sauvola = misclassified(ThresholdMethod::Sauvola);
+        let otsu = misclassified(ThresholdMethod::Otsu);
+
+        assert_eq!(otsu, 0);
+        assert!(sauvola > 100, "Sauvola only misclassified {}", sauvola);
+    }
+
+    #[test]
+    fn test_sauvola_window_size_is_used() {
+        // Windows inside the block see no contrast and hollow it out; a
+        // window that reaches the background keeps it solid
+        let (img, _) = block_on_gradient();
+        let center = |window_size: u32| {
+            let params = SauvolaParams {
+                window_size,
+                ..SauvolaParams::default()
+            };
+            let result = apply(
+                DynamicImage::ImageLuma8(img.clone()),
+                ThresholdMethod::Sauvola,
+                params,
+            )
+            .unwrap();
+            result.to_luma8().get_pixel(40, 25).0[0]
+        };
+
+        assert_eq!(center(WINDOW_SIZE), 255);
+        assert_eq!(center(61), 0);
+    }
+
+    #[test]
+    fn test_otsu_splits_gradient_once_per_row() {
+        let img = GrayImage::from_fn(64, 8, |x, _| Luma([x as u8 * 4]));
+        let result = apply(
+            DynamicImage::ImageLuma8(img),
+            ThresholdMethod::Otsu,
+            SauvolaParams::default(),
+        )
+        .unwrap()
+        .to_luma8();
+
+        for y in 0..8 {
+            let row: Vec<u8> = (0..64).map(|x| result.get_pixel(x, y).0[0]).collect();
+            let transitions = row.windows(2).filter(|pair| pair[0] != pair[1]).count();
+            assert_eq!(transitions, 1, "row {} is {:?}", y, row);
+        }
+    }
+
+    #[test]
+    fn test_threshold_method_from_str() {
+        assert_eq!(
+            ThresholdMethod::from_str("sauvola"),
+            Some(ThresholdMethod::Sauvola)
+        );
+        assert_eq!(
+            ThresholdMethod::from_str("OTSU"),
+            Some(ThresholdMethod::Otsu)
+        );
+        assert_eq!(ThresholdMethod::from_str("niblack"), None);
+    }
 }
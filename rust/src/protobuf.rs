@@ -0,0 +1,477 @@
+//! Protobuf encoding of OCR responses
+//!
+//! Clients that send `Accept: application/x-protobuf` get the response in the
+//! schema published in `proto/ocr_response.proto`, which is much smaller and
+//! faster to parse than JSON for responses with many bounding boxes. The
+//! messages are declared by hand rather than generated, so building the
+//! server doesn't need `protoc`; keep them in sync with the schema.
+
+use crate::server;
+use prost::Message;
+
+/// Content type of protobuf responses
+pub const CONTENT_TYPE: &str = "application/x-protobuf";
+
+#[derive(Clone, PartialEq, Message)]
+pub struct OcrResponse {
+    #[prost(string, tag = "1")]
+    pub text: String,
+    #[prost(float, optional, tag = "2")]
+    pub confidence: Option<f32>,
+    #[prost(message, optional, tag = "3")]
+    pub confidence_stats: Option<ConfidenceStats>,
+    #[prost(uint64, tag = "4")]
+    pub processing_time_ms: u64,
+    #[prost(string, repeated, tag = "5")]
+    pub warnings: Vec<String>,
+    #[prost(string, tag = "6")]
+    pub engine: String,
+    #[prost(bool, tag = "7")]
+    pub no_text_detected: bool,
+    #[prost(message, optional, tag = "8")]
+    pub preprocessing: Option<PreprocessingStats>,
+    #[prost(message, optional, tag = "9")]
+    pub boxes: Option<Boxes>,
+    #[prost(message, repeated, tag = "10")]
+    pub regions: Vec<Region>,
+    #[prost(message, repeated, tag = "11")]
+    pub lines: Vec<TextLine>,
+    #[prost(string, optional, tag = "12")]
+    pub raw_text: Option<String>,
+    #[prost(string, tag = "13")]
+    pub detected_format: String,
+    #[prost(bool, optional, tag = "14")]
+    pub truncated: Option<bool>,
+    #[prost(message, optional, tag = "15")]
+    pub pdf_verification: Option<PdfVerification>,
+    #[prost(uint32, tag = "16")]
+    pub schema_version: u32,
+    #[prost(string, optional, tag = "17")]
+    pub filename: Option<String>,
+    #[prost(int32, optional, tag = "18")]
+    pub orientation_degrees: Option<i32>,
+    #[prost(message, repeated, tag = "19")]
+    pub word_confidences: Vec<WordConfidence>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct WordConfidence {
+    #[prost(string, tag = "1")]
+    pub text: String,
+    #[prost(float, tag = "2")]
+    pub confidence: f32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PdfVerification {
+    #[prost(string, tag = "1")]
+    pub embedded_text: String,
+    #[prost(float, tag = "2")]
+    pub similarity: f32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ConfidenceStats {
+    #[prost(float, optional, tag = "1")]
+    pub min: Option<f32>,
+    #[prost(float, optional, tag = "2")]
+    pub max: Option<f32>,
+    #[prost(float, tag = "3")]
+    pub mean: f32,
+    #[prost(float, optional, tag = "4")]
+    pub median: Option<f32>,
+    #[prost(float, optional, tag = "5")]
+    pub p25: Option<f32>,
+    #[prost(float, optional, tag = "6")]
+    pub p75: Option<f32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct PreprocessingStats {
+    #[prost(string, tag = "1")]
+    pub preset: String,
+    #[prost(uint64, tag = "2")]
+    pub total_time_ms: u64,
+    #[prost(message, repeated, tag = "3")]
+    pub steps: Vec<StepTiming>,
+    #[prost(btree_map = "string, message", tag = "4")]
+    pub metadata: std::collections::BTreeMap<String, StepMetadata>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct StepMetadata {
+    #[prost(btree_map = "string, float", tag = "1")]
+    pub values: std::collections::BTreeMap<String, f32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct StepTiming {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(uint64, tag = "2")]
+    pub time_ms: u64,
+    #[prost(bool, tag = "3")]
+    pub skipped: bool,
+    #[prost(string, optional, tag = "4")]
+    pub skip_reason: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub resize_action: Option<String>,
+    #[prost(float, optional, tag = "6")]
+    pub scale_factor: Option<f32>,
+    #[prost(uint64, optional, tag = "7")]
+    pub peak_memory_bytes: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Boxes {
+    #[prost(string, tag = "1")]
+    pub level: String,
+    #[prost(message, repeated, tag = "2")]
+    pub boxes: Vec<TextBox>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TextBox {
+    #[prost(string, tag = "1")]
+    pub text: String,
+    #[prost(uint32, tag = "2")]
+    pub page: u32,
+    #[prost(uint32, tag = "3")]
+    pub x: u32,
+    #[prost(uint32, tag = "4")]
+    pub y: u32,
+    #[prost(uint32, tag = "5")]
+    pub width: u32,
+    #[prost(uint32, tag = "6")]
+    pub height: u32,
+    #[prost(float, optional, tag = "7")]
+    pub confidence: Option<f32>,
+    #[prost(float, repeated, tag = "8")]
+    pub polygon: Vec<f32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TextLine {
+    #[prost(string, tag = "1")]
+    pub text: String,
+    #[prost(uint32, tag = "2")]
+    pub page: u32,
+    #[prost(uint32, tag = "3")]
+    pub x: u32,
+    #[prost(uint32, tag = "4")]
+    pub y: u32,
+    #[prost(uint32, tag = "5")]
+    pub width: u32,
+    #[prost(uint32, tag = "6")]
+    pub height: u32,
+    #[prost(float, optional, tag = "7")]
+    pub confidence: Option<f32>,
+    #[prost(float, tag = "8")]
+    pub baseline_angle: f32,
+    #[prost(float, repeated, tag = "9")]
+    pub polygon: Vec<f32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Region {
+    #[prost(uint32, tag = "1")]
+    pub x: u32,
+    #[prost(uint32, tag = "2")]
+    pub y: u32,
+    #[prost(uint32, tag = "3")]
+    pub width: u32,
+    #[prost(uint32, tag = "4")]
+    pub height: u32,
+    #[prost(string, tag = "5")]
+    pub text: String,
+    #[prost(float, optional, tag = "6")]
+    pub confidence: Option<f32>,
+    #[prost(message, optional, tag = "7")]
+    pub boxes: Option<Boxes>,
+    #[prost(message, repeated, tag = "8")]
+    pub lines: Vec<TextLine>,
+}
+
+impl From<server::OcrResponse> for OcrResponse {
+    fn from(response: server::OcrResponse) -> Self {
+        Self {
+            text: response.text,
+            confidence: response.confidence,
+            confidence_stats: response.confidence_stats.map(|stats| ConfidenceStats {
+                min: stats.min,
+                max: stats.max,
+                mean: stats.mean,
+                median: stats.median,
+                p25: stats.p25,
+                p75: stats.p75,
+            }),
+            processing_time_ms: response.processing_time_ms,
+            warnings: response.warnings,
+            engine: response.engine,
+            no_text_detected: response.no_text_detected,
+            preprocessing: response.preprocessing.map(|stats| PreprocessingStats {
+                preset: stats.preset,
+                total_time_ms: stats.total_time_ms,
+                steps: stats
+                    .steps
+                    .into_iter()
+                    .map(|step| StepTiming {
+                        name: step.name,
+                        time_ms: step.time_ms,
+                        skipped: step.skipped,
+                        skip_reason: step.skip_reason,
+                        resize_action: step.resize_action.map(|action| action.as_str().to_string()),
+                        scale_factor: step.scale_factor,
+                        peak_memory_bytes: step.peak_memory_bytes,
+                    })
+                    .collect(),
+                metadata: stats
+                    .metadata
+                    .into_iter()
+                    .map(|(step, values)| (step, StepMetadata { values }))
+                    .collect(),
+            }),
+            boxes: response.boxes.map(Boxes::from),
+            regions: response
+                .regions
+                .unwrap_or_default()
+                .into_iter()
+                .map(|region| Region {
+                    x: region.bounds.x,
+                    y: region.bounds.y,
+                    width: region.bounds.width,
+                    height: region.bounds.height,
+                    text: region.text,
+                    confidence: region.confidence,
+                    boxes: region.boxes.map(Boxes::from),
+                    lines: text_lines(region.lines),
+                })
+                .collect(),
+            lines: text_lines(response.lines),
+            raw_text: response.raw_text,
+            detected_format: response.detected_format,
+            truncated: response.truncated,
+            pdf_verification: response
+                .pdf_verification
+                .map(|verification| PdfVerification {
+                    embedded_text: verification.embedded_text,
+                    similarity: verification.similarity,
+                }),
+            schema_version: response.schema_version,
+            filename: response.filename,
+            orientation_degrees: response.orientation_degrees,
+            word_confidences: response
+                .word_confidences
+                .unwrap_or_default()
+                .into_iter()
+                .map(|word| WordConfidence {
+                    text: word.text,
+                    confidence: word.confidence,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn text_lines(lines: Option<Vec<server::TextLineResponse>>) -> Vec<TextLine> {
+    lines
+        .unwrap_or_default()
+        .into_iter()
+        .map(|line| TextLine {
+            text: line.text,
+            page: line.page,
+            x: line.x,
+            y: line.y,
+            width: line.width,
+            height: line.height,
+            confidence: line.confidence,
+            baseline_angle: line.baseline_angle,
+            polygon: flat_polygon(line.polygon),
+        })
+        .collect()
+}
+
+impl From<server::BoxesResponse> for Boxes {
+    fn from(boxes: server::BoxesResponse) -> Self {
+        Self {
+            level: boxes.level,
+            boxes: boxes
+                .boxes
+                .into_iter()
+                .map(|b| TextBox {
+                    text: b.text,
+                    page: b.page,
+                    x: b.x,
+                    y: b.y,
+                    width: b.width,
+                    height: b.height,
+                    confidence: b.confidence,
+                    polygon: flat_polygon(b.polygon),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Polygon corners as x0, y0, x1, y1, ..., or empty without a polygon
+fn flat_polygon(polygon: Option<[[f32; 2]; 4]>) -> Vec<f32> {
+    polygon.into_iter().flatten().flatten().collect()
+}
+
+/// Whether a request's Accept header asks for protobuf
+pub fn is_accepted(accept: Option<&str>) -> bool {
+    accept.is_some_and(|accept| {
+        accept.split(',').any(|media_type| {
+            let essence = media_type.split(';').next().unwrap_or_default().trim();
+            essence.eq_ignore_ascii_case(CONTENT_TYPE)
+        })
+    })
+}
+
+/// Encode an OCR response
+pub fn encode(response: server::OcrResponse) -> Vec<u8> {
+    OcrResponse::from(response).encode_to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{Bounds, RegionOcrResponse, TextBoxResponse, TextLineResponse};
+
+    fn response() -> server::OcrResponse {
+        let boxes = || server::BoxesResponse {
+            level: "word".to_string(),
+            boxes: vec![TextBoxResponse {
+                text: "Hello".to_string(),
+                page: 1,
+                x: 10,
+                y: 20,
+                width: 30,
+                height: 40,
+                confidence: Some(0.9),
+                polygon: Some([[10.0, 22.0], [40.0, 20.0], [40.0, 60.0], [10.0, 62.0]]),
+            }],
+        };
+        server::OcrResponse {
+            schema_version: server::SCHEMA_VERSION,
+            text: "Hello".to_string(),
+            raw_text: Some("Hello\n".to_string()),
+            truncated: Some(false),
+            pdf_verification: Some(server::PdfVerification {
+                embedded_text: "Hel1o".to_string(),
+                similarity: 0.5,
+            }),
+            confidence: Some(0.9),
+            confidence_stats: Some(server::ConfidenceStats {
+                min: None,
+                max: None,
+                mean: 0.9,
+                median: None,
+                p25: None,
+                p75: None,
+            }),
+            word_confidences: Some(vec![server::WordConfidenceResponse {
+                text: "Hello".to_string(),
+                confidence: 0.8,
+            }]),
+            processing_time_ms: 12,
+            warnings: vec!["careful".to_string()],
+            engine: "ocrs".to_string(),
+            detected_format: "image/png".to_string(),
+            filename: Some("scan.png".to_string()),
+            orientation_degrees: Some(90),
+            no_text_detected: false,
+            preprocessing: None,
+            boxes: Some(boxes()),
+            lines: Some(vec![TextLineResponse {
+                text: "Hello".to_string(),
+                page: 1,
+                x: 10,
+                y: 20,
+                width: 30,
+                height: 40,
+                confidence: None,
+                baseline_angle: 1.5,
+                polygon: None,
+            }]),
+            regions: Some(vec![RegionOcrResponse {
+                bounds: Bounds {
+                    x: 1,
+                    y: 2,
+                    width: 3,
+                    height: 4,
+                },
+                text: "Hello".to_string(),
+                confidence: None,
+                boxes: Some(boxes()),
+                lines: None,
+            }]),
+        }
+    }
+
+    #[test]
+    fn test_encode_round_trips() {
+        let decoded = OcrResponse::decode(encode(response()).as_slice()).unwrap();
+
+        assert_eq!(decoded.text, "Hello");
+        assert_eq!(decoded.raw_text.as_deref(), Some("Hello\n"));
+        assert_eq!(decoded.warnings, vec!["careful"]);
+        assert_eq!(decoded.engine, "ocrs");
+        assert_eq!(decoded.detected_format, "image/png");
+        assert_eq!(decoded.truncated, Some(false));
+        assert_eq!(decoded.pdf_verification.unwrap().similarity, 0.5);
+        assert_eq!(decoded.schema_version, server::SCHEMA_VERSION);
+        assert_eq!(decoded.filename.as_deref(), Some("scan.png"));
+        assert_eq!(decoded.orientation_degrees, Some(90));
+        assert!(decoded.preprocessing.is_none());
+
+        assert_eq!(decoded.confidence, Some(0.9));
+        let stats = decoded.confidence_stats.unwrap();
+        assert_eq!((stats.mean, stats.min), (0.9, None));
+        assert_eq!(decoded.word_confidences.len(), 1);
+        assert_eq!(
+            (
+                decoded.word_confidences[0].text.as_str(),
+                decoded.word_confidences[0].confidence
+            ),
+            ("Hello", 0.8)
+        );
+
+        let boxes = decoded.boxes.unwrap();
+        assert_eq!(boxes.level, "word");
+        assert_eq!(
+            (
+                boxes.boxes[0].x,
+                boxes.boxes[0].height,
+                boxes.boxes[0].confidence
+            ),
+            (10, 40, Some(0.9))
+        );
+        assert_eq!(
+            boxes.boxes[0].polygon,
+            vec![10.0, 22.0, 40.0, 20.0, 40.0, 60.0, 10.0, 62.0]
+        );
+        assert_eq!(decoded.regions.len(), 1);
+        assert_eq!(decoded.regions[0].width, 3);
+        assert_eq!(decoded.regions[0].confidence, None);
+        assert!(decoded.regions[0].lines.is_empty());
+
+        assert_eq!(decoded.lines.len(), 1);
+        assert_eq!(
+            (decoded.lines[0].confidence, decoded.lines[0].baseline_angle),
+            (None, 1.5)
+        );
+        assert!(decoded.lines[0].polygon.is_empty());
+    }
+
+    #[test]
+    fn test_is_accepted() {
+        assert!(is_accepted(Some("application/x-protobuf")));
+        assert!(is_accepted(Some(
+            "application/json;q=0.5, Application/X-Protobuf; q=1"
+        )));
+        assert!(!is_accepted(Some("application/json")));
+        assert!(!is_accepted(Some("*/*")));
+        assert!(!is_accepted(None));
+    }
+}
@@ -0,0 +1,230 @@
+//! Image resolution metadata
+//!
+//! Reads the DPI an image was scanned or saved at from its header: the
+//! `pHYs` chunk of a PNG, the JFIF density or EXIF `XResolution` of a JPEG,
+//! and the `XResolution` tag of a TIFF. Only the horizontal resolution is
+//! used; images with no unit, such as a JFIF header that only gives the
+//! pixel aspect ratio, have none.
+
+use crate::file_format::{detect_format, DetectedFormat, TiffHeader};
+
+/// Resolutions outside this range are junk and treated as missing
+const PLAUSIBLE_DPI: std::ops::RangeInclusive<f32> = 10.0..=10_000.0;
+
+const CM_PER_INCH: f32 = 2.54;
+
+/// The resolution of an image in dots per inch, None if it doesn't say
+pub fn dpi(data: &[u8]) -> Option<f32> {
+    let dpi = match detect_format(data)? {
+        DetectedFormat::Png => png_dpi(data),
+        DetectedFormat::Jpeg => jpeg_dpi(data),
+        DetectedFormat::Tiff => tiff_dpi(data),
+        _ => None,
+    }?;
+    PLAUSIBLE_DPI.contains(&dpi).then_some(dpi)
+}
+
+fn be_u16(bytes: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes(bytes.get(..2)?.try_into().ok()?))
+}
+
+fn be_u32(bytes: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(..4)?.try_into().ok()?))
+}
+
+/// Pixels per meter from the `pHYs` chunk, which comes before the image data
+fn png_dpi(data: &[u8]) -> Option<f32> {
+    let mut at = 8;
+    loop {
+        let length = be_u32(data.get(at..)?)? as usize;
+        let kind = data.get(at + 4..at + 8)?;
+        let body = at + 8;
+        match kind {
+            b"pHYs" => {
+                let chunk = data.get(body..body.checked_add(length)?)?;
+                // Unit 1 is the meter, 0 means only the aspect ratio is known
+                return match chunk.get(8)? {
+                    1 => Some(be_u32(chunk)? as f32 * CM_PER_INCH / 100.0),
+                    _ => None,
+                };
+            }
+            b"IDAT" | b"IEND" => return None,
+            // Length, type, body and CRC
+            _ => at = body.checked_add(length)?.checked_add(4)?,
+        }
+    }
+}
+
+/// Density from the JFIF header, or else the resolution in the EXIF data
+fn jpeg_dpi(data: &[u8]) -> Option<f32> {
+    let mut exif = None;
+    let mut at = 2;
+    // Metadata segments come before the scan starts
+    while let Some(&[0xFF, marker, ..]) = data.get(at..) {
+        if marker == 0xDA {
+            break;
+        }
+        let length = be_u16(data.get(at + 2..)?)? as usize;
+        let segment = data.get(at + 4..(at + 2).checked_add(length)?)?;
+        match marker {
+            0xE0 if segment.starts_with(b"JFIF\0") => {
+                let density = be_u16(segment.get(8..)?)? as f32;
+                match segment.get(7)? {
+                    1 => return Some(density),
+                    2 => return Some(density * CM_PER_INCH),
+                    _ => {}
+                }
+            }
+            0xE1 if segment.starts_with(b"Exif\0\0") => exif = tiff_dpi(&segment[6..]),
+            _ => {}
+        }
+        at += 2 + length;
+    }
+    exif
+}
+
+/// `XResolution` and `ResolutionUnit` from the first directory of a TIFF or
+/// BigTIFF, which EXIF data uses too
+fn tiff_dpi(data: &[u8]) -> Option<f32> {
+    const X_RESOLUTION: u64 = 282;
+    const RESOLUTION_UNIT: u64 = 296;
+
+    let header = TiffHeader::parse(data)?;
+    let read = |at: u64, len: usize| header.read(at, len);
+    let pointer_size = header.pointer_size;
+
+    let directory = read(pointer_size as u64, pointer_size)?;
+    let entries = read(directory, header.count_size)?;
+    let (mut resolution, mut unit) = (None, 2);
+    for i in 0..entries.min(u16::MAX as u64) {
+        let entry = directory + header.count_size as u64 + i * header.entry_size;
+        // The value follows the tag, type and count
+        let value = entry + 4 + pointer_size as u64;
+        match read(entry, 2)? {
+            X_RESOLUTION => {
+                // A rational, inline in BigTIFF and elsewhere in TIFF
+                let rational = if pointer_size == 8 {
+                    value
+                } else {
+                    read(value, 4)?
+                };
+                let denominator = read(rational + 4, 4)?;
+                resolution = (denominator > 0)
+                    .then(|| read(rational, 4).map(|n| n as f32 / denominator as f32))
+                    .flatten();
+            }
+            RESOLUTION_UNIT => unit = read(value, 2)?,
+            _ => {}
+        }
+    }
+
+    // Unit 2 is the inch and 3 the centimeter; 1 means there is none
+    match unit {
+        2 => resolution,
+        3 => resolution.map(|r| r * CM_PER_INCH),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(phys: &[u8]) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        let mut chunk = |kind: &[u8], body: &[u8]| {
+            png.extend((body.len() as u32).to_be_bytes());
+            png.extend(kind);
+            png.extend(body);
+            // CRCs aren't checked
+            png.extend([0; 4]);
+        };
+        chunk(b"IHDR", &[0; 13]);
+        chunk(b"pHYs", phys);
+        chunk(b"IDAT", &[]);
+        png
+    }
+
+    fn jfif(units: u8, density: u16) -> Vec<u8> {
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0, 16];
+        jpeg.extend(b"JFIF\0\x01\x02");
+        jpeg.push(units);
+        jpeg.extend(density.to_be_bytes());
+        jpeg.extend(density.to_be_bytes());
+        jpeg.extend([0, 0, 0xFF, 0xDA]);
+        jpeg
+    }
+
+    /// Little-endian TIFF with one directory holding XResolution and,
+    /// optionally, ResolutionUnit
+    fn tiff(resolution: (u32, u32), unit: Option<u16>) -> Vec<u8> {
+        let mut entries = vec![(282u16, 5u16, 1u32, 8 + 2 + 2 * 12 + 4)];
+        if let Some(unit) = unit {
+            entries.push((296, 3, 1, unit as u32));
+        }
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend(8u32.to_le_bytes());
+        tiff.extend((entries.len() as u16).to_le_bytes());
+        for (tag, kind, count, value) in &entries {
+            tiff.extend(tag.to_le_bytes());
+            tiff.extend(kind.to_le_bytes());
+            tiff.extend(count.to_le_bytes());
+            tiff.extend(value.to_le_bytes());
+        }
+        tiff.resize(8 + 2 + 2 * 12 + 4, 0);
+        tiff.extend(resolution.0.to_le_bytes());
+        tiff.extend(resolution.1.to_le_bytes());
+        tiff
+    }
+
+    #[test]
+    fn test_reads_png_phys() {
+        // 11811 pixels per meter is 300 DPI
+        let mut phys = 11811u32.to_be_bytes().to_vec();
+        phys.extend(11811u32.to_be_bytes());
+        phys.push(1);
+        assert_eq!(dpi(&png(&phys)).unwrap().round(), 300.0);
+
+        // Aspect ratio only
+        *phys.last_mut().unwrap() = 0;
+        assert_eq!(dpi(&png(&phys)), None);
+    }
+
+    #[test]
+    fn test_reads_jfif_density() {
+        assert_eq!(dpi(&jfif(1, 300)), Some(300.0));
+        assert_eq!(dpi(&jfif(2, 118)).unwrap().round(), 300.0);
+        assert_eq!(dpi(&jfif(0, 1)), None);
+    }
+
+    #[test]
+    fn test_reads_exif_resolution_in_jpeg() {
+        let exif = tiff((200, 1), None);
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        jpeg.extend((exif.len() as u16 + 8).to_be_bytes());
+        jpeg.extend(b"Exif\0\0");
+        jpeg.extend(exif);
+        jpeg.extend([0xFF, 0xDA]);
+
+        assert_eq!(dpi(&jpeg), Some(200.0));
+    }
+
+    #[test]
+    fn test_reads_tiff_resolution() {
+        // Inches are the default unit
+        assert_eq!(dpi(&tiff((600, 2), None)), Some(300.0));
+        assert_eq!(dpi(&tiff((204, 1), Some(2))), Some(204.0));
+        assert_eq!(dpi(&tiff((80, 1), Some(3))).unwrap().round(), 203.0);
+        assert_eq!(dpi(&tiff((300, 1), Some(1))), None);
+        assert_eq!(dpi(&tiff((300, 0), None)), None);
+    }
+
+    #[test]
+    fn test_ignores_implausible_and_unknown_resolutions() {
+        assert_eq!(dpi(&jfif(1, 1)), None);
+        assert_eq!(dpi(b"GIF89a"), None);
+        assert_eq!(dpi(b"not an image"), None);
+        // Cut off mid-header
+        assert_eq!(dpi(&jfif(1, 300)[..10]), None);
+    }
+}
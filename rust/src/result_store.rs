@@ -0,0 +1,132 @@
+//! Persisting OCR results on the server
+//!
+//! When a result store directory is configured, every successful JSON OCR
+//! result is written to `<dir>/<sha256 of the upload>-<options>.json`, so it
+//! can be fetched again from `GET /results/:hash` without re-running OCR.
+//! The result depends on the engine and options as much as on the file, so
+//! `<options>` is a hash of the normalized options; a later request for the
+//! same file with the same options replaces the stored result.
+
+use crate::error::OcrError;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use tempfile::NamedTempFile;
+
+/// Filesystem store of OCR results keyed by content and options hash
+#[derive(Debug, Clone)]
+pub struct ResultStore {
+    dir: PathBuf,
+}
+
+impl ResultStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Write a result, replacing any earlier one for the same hashes
+    ///
+    /// The result is written to a temporary file first so readers never see
+    /// a partial result.
+    pub fn put(&self, hash: &str, options: &str, result: &[u8]) -> Result<(), OcrError> {
+        let path = self.path(hash, options)?;
+        let failed = |e: &dyn std::fmt::Display| {
+            OcrError::Internal(format!("Failed to store result {}: {}", hash, e))
+        };
+
+        fs::create_dir_all(&self.dir).map_err(|e| failed(&e))?;
+        let mut file = NamedTempFile::new_in(&self.dir).map_err(|e| failed(&e))?;
+        file.write_all(result).map_err(|e| failed(&e))?;
+        file.persist(path).map_err(|e| failed(&e))?;
+        Ok(())
+    }
+
+    /// Read a stored result, or `None` if there is none for the hashes
+    pub fn get(&self, hash: &str, options: &str) -> Result<Option<Vec<u8>>, OcrError> {
+        match fs::read(self.path(hash, options)?) {
+            Ok(result) => Ok(Some(result)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(OcrError::Internal(format!(
+                "Failed to read result {}: {}",
+                hash, e
+            ))),
+        }
+    }
+
+    /// File a result is stored in, rejecting anything but a SHA-256 hex
+    /// digest so hashes can't name files outside the store
+    fn path(&self, hash: &str, options: &str) -> Result<PathBuf, OcrError> {
+        if !is_hash(hash) {
+            return Err(OcrError::InvalidRequest(format!(
+                "Invalid result hash '{}'. Expected 64 lowercase hex characters",
+                hash
+            )));
+        }
+        Ok(self.dir.join(format!("{}-{}.json", hash, options)))
+    }
+}
+
+/// Key of the normalized options a result was produced with
+pub fn options_hash(options: &str) -> String {
+    content_hash(options.as_bytes())[..16].to_string()
+}
+
+/// SHA-256 of an upload as lowercase hex, the key its result is stored under
+pub fn content_hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn is_hash(hash: &str) -> bool {
+    hash.len() == 64
+        && hash
+            .bytes()
+            .all(|byte| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_put_then_get() {
+        let dir = TempDir::new().unwrap();
+        let store = ResultStore::new(dir.path().join("results"));
+        let hash = content_hash(b"image");
+        let options = options_hash("{}");
+
+        assert_eq!(store.get(&hash, &options).unwrap(), None);
+        store.put(&hash, &options, b"{\"text\":\"first\"}").unwrap();
+        store
+            .put(&hash, &options, b"{\"text\":\"second\"}")
+            .unwrap();
+        assert_eq!(
+            store.get(&hash, &options).unwrap().as_deref(),
+            Some(&b"{\"text\":\"second\"}"[..])
+        );
+
+        // The same file read with other options has its own result
+        let other = options_hash("{\"engine\":\"leptess\"}");
+        assert_eq!(store.get(&hash, &other).unwrap(), None);
+    }
+
+    #[test]
+    fn test_rejects_invalid_hashes() {
+        let store = ResultStore::new("/tmp/results");
+
+        for hash in ["../secret", "ABC", &"A".repeat(64), &"0".repeat(63)] {
+            assert!(matches!(
+                store.get(hash, &options_hash("{}")),
+                Err(OcrError::InvalidRequest(_))
+            ));
+        }
+        assert_eq!(
+            content_hash(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! HTTP connection handling
+//!
+//! Replaces `axum::serve` so connection-level timeouts can be configured.
+//! Slow or stalled clients (slowloris-style) get their connections dropped
+//! instead of holding server resources indefinitely.
+
+use axum::Router;
+use hyper::body::Incoming;
+use hyper::Request;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tower::Service;
+
+/// Connection-level timeouts (distinct from OCR processing time)
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionTimeouts {
+    /// Close connections idle between requests for longer than this
+    /// (None disables keep-alive entirely)
+    pub keep_alive: Option<Duration>,
+    /// Close connections that don't send complete request headers in time
+    pub header_read: Option<Duration>,
+}
+
+/// Accept connections and serve the app until the listener fails
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    timeouts: ConnectionTimeouts,
+) -> anyhow::Result<()> {
+    let mut builder = Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .header_read_timeout(timeouts.header_read)
+        .keep_alive(timeouts.keep_alive.is_some());
+    let builder = Arc::new(builder);
+
+    loop {
+        let (stream, remote_addr) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                // Usually resource exhaustion (e.g. too many open files), back off briefly
+                tracing::warn!("Failed to accept connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let builder = builder.clone();
+        let app = app.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_connection(&builder, stream, app, timeouts.keep_alive).await {
+                tracing::debug!("Connection from {} closed with error: {}", remote_addr, e);
+            }
+        });
+    }
+}
+
+/// Serve a single connection, closing it once it has been idle too long
+async fn serve_connection(
+    builder: &Builder<TokioExecutor>,
+    stream: TcpStream,
+    app: Router,
+    keep_alive: Option<Duration>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let activity = Arc::new(Activity::new());
+    let io = TokioIo::new(TrackedIo {
+        inner: stream,
+        activity: activity.clone(),
+    });
+
+    let service_activity = activity.clone();
+    let service = hyper::service::service_fn(move |request: Request<Incoming>| {
+        let mut app = app.clone();
+        let guard = InFlightGuard::new(service_activity.clone());
+        async move {
+            let response = app.call(request).await;
+            drop(guard);
+            response
+        }
+    });
+
+    let conn = builder.serve_connection(io, service);
+    tokio::pin!(conn);
+
+    let Some(keep_alive) = keep_alive else {
+        return conn.await;
+    };
+
+    let mut shutting_down = false;
+    loop {
+        let wait = keep_alive.saturating_sub(activity.idle_for());
+        tokio::select! {
+            result = conn.as_mut() => return result,
+            _ = tokio::time::sleep(wait), if !shutting_down => {
+                if activity.in_flight() == 0 && activity.idle_for() >= keep_alive {
+                    tracing::debug!("Closing connection idle for over {:?}", keep_alive);
+                    conn.as_mut().graceful_shutdown();
+                    shutting_down = true;
+                }
+            }
+        }
+    }
+}
+
+/// Tracks when a connection last did any IO and how many requests it is serving
+struct Activity {
+    last_io: Mutex<Instant>,
+    in_flight: AtomicUsize,
+}
+
+impl Activity {
+    fn new() -> Self {
+        Self {
+            last_io: Mutex::new(Instant::now()),
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    fn touch(&self) {
+        *self.last_io.lock().unwrap() = Instant::now();
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_io.lock().unwrap().elapsed()
+    }
+
+    fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+}
+
+/// Marks a request as in flight so long OCR jobs aren't mistaken for idleness
+struct InFlightGuard(Arc<Activity>);
+
+impl InFlightGuard {
+    fn new(activity: Arc<Activity>) -> Self {
+        activity.in_flight.fetch_add(1, Ordering::SeqCst);
+        Self(activity)
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.0.touch();
+    }
+}
+
+/// TCP stream wrapper that records read/write activity
+struct TrackedIo {
+    inner: TcpStream,
+    activity: Arc<Activity>,
+}
+
+impl AsyncRead for TrackedIo {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if buf.filled().len() > filled {
+            self.activity.touch();
+        }
+        result
+    }
+}
+
+impl AsyncWrite for TrackedIo {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            if n > 0 {
+                self.activity.touch();
+            }
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn start(timeouts: ConnectionTimeouts) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/", get(|| async { "ok" }));
+        tokio::spawn(serve(listener, app, timeouts));
+        addr
+    }
+
+    /// Read until the server closes the connection, returning the bytes read
+    async fn read_until_closed(stream: &mut TcpStream) -> Vec<u8> {
+        let mut data = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut data))
+            .await
+            .expect("server did not close the connection")
+            .ok();
+        data
+    }
+
+    #[tokio::test]
+    async fn test_closes_connection_with_incomplete_headers() {
+        let addr = start(ConnectionTimeouts {
+            keep_alive: None,
+            header_read: Some(Duration::from_millis(200)),
+        })
+        .await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+
+        read_until_closed(&mut stream).await;
+    }
+
+    #[tokio::test]
+    async fn test_closes_idle_keep_alive_connection() {
+        let addr = start(ConnectionTimeouts {
+            keep_alive: Some(Duration::from_millis(200)),
+            header_read: None,
+        })
+        .await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+
+        let response = read_until_closed(&mut stream).await;
+        assert!(String::from_utf8_lossy(&response).ends_with("ok"));
+    }
+
+    #[tokio::test]
+    async fn test_closes_after_response_when_keep_alive_disabled() {
+        let addr = start(ConnectionTimeouts {
+            keep_alive: None,
+            header_read: None,
+        })
+        .await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+
+        let response = String::from_utf8_lossy(&read_until_closed(&mut stream).await).to_string();
+        assert!(response.contains("connection: close"));
+    }
+}
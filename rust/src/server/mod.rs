@@ -0,0 +1,4676 @@
+mod office;
+mod preprocess;
+mod reload;
+mod split;
+mod stream;
+
+use crate::alto;
+use crate::config::Config;
+use crate::engine::{
+    CoordsLevel, DecodeMethod, DetectedWord, LineBox, OcrEngine, OcrOptions, OcrResult,
+    RecognitionModel, TextBox, TextBoxes, MAX_BEAM_WIDTH,
+};
+use crate::engines::{recognize_pages, EngineRegistry};
+use crate::error::{ErrorResponse, OcrError};
+use crate::file_format::{detect_format, DetectedFormat, TiffHeader};
+use crate::hocr;
+use crate::jobs::{JobLookup, JobResponse, JobStore};
+use crate::languages::{is_valid_language, MAX_PSM};
+use crate::metrics::{self, Metrics};
+use crate::model_cache::CachedModel;
+use crate::page_xml;
+use crate::pdf_repair;
+use crate::pdf_text_layer;
+use crate::preprocessing::segment::{text_block, Region};
+use crate::preprocessing::{Pipeline, Preset, SourceRegion, StepMetadata, StepTiming};
+use crate::protobuf;
+use crate::resolution;
+use crate::result_store::{self, ResultStore};
+use crate::serve::{self, ConnectionTimeouts};
+use crate::tsv;
+use crate::url_fetch;
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, FromRequest, Multipart, Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Response},
+    routing::{get, post},
+    Router,
+};
+use image::codecs::gif::GifDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::metadata::Orientation;
+use image::{AnimationDecoder, DynamicImage, GenericImage, GenericImageView, ImageDecoder, Rgba};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
+use tower_http::timeout::TimeoutLayer;
+use tower_http::trace::TraceLayer;
+
+use office::{ocr_uploaded_document, office_document_mime};
+use preprocess::{handle_preprocess, PreprocessingOptions, PreprocessingParams};
+use reload::{check_fallback, handle_reload};
+use split::{document_regions, merge_stats, ocr_regions, parse_split};
+use stream::stream_ocr;
+
+/// Shared application state
+///
+/// The registry and config are swapped together by `POST /reload`. Handlers
+/// take a [`Snapshot`] up front so in-flight requests finish on the registry
+/// they started with.
+#[derive(Clone)]
+pub struct AppState {
+    current: Arc<RwLock<Snapshot>>,
+    /// Serializes reloads so concurrent calls don't build registries in parallel
+    reload_lock: Arc<tokio::sync::Mutex<()>>,
+    /// Number of OCR requests being processed, counting `/ocr` requests
+    /// waiting for a slot but not background jobs
+    ocr_in_flight: Arc<AtomicUsize>,
+    /// Request counters and timings served on `/metrics`, kept across reloads
+    metrics: Arc<Metrics>,
+    /// Slots for OCR requests processed at once, sized from `max_concurrency`
+    ocr_slots: Arc<Semaphore>,
+    /// Background OCR jobs, kept across reloads
+    jobs: Arc<JobStore>,
+    /// Places for background jobs that haven't started, sized from
+    /// `max_pending_jobs`
+    job_queue: Arc<Semaphore>,
+    /// Engine calls the watchdog gave up on, kept across reloads
+    abandoned: AbandonedWorkers,
+}
+
+/// Counts an OCR request as in flight until dropped
+struct InFlightOcr(Arc<AtomicUsize>);
+
+impl InFlightOcr {
+    /// Start tracking a request, returning the guard and the number of
+    /// requests in flight including this one
+    fn start(counter: &Arc<AtomicUsize>) -> (Self, usize) {
+        let count = counter.fetch_add(1, Ordering::SeqCst) + 1;
+        (Self(counter.clone()), count)
+    }
+}
+
+impl Drop for InFlightOcr {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Restarts an engine when an OCR job on it runs past the hard time limit
+///
+/// A hung engine call can't be cancelled, so its thread is abandoned. The
+/// engine it ran on is replaced with a fresh instance so later requests
+/// don't run into the same stuck state; the other engines are kept. Each
+/// abandoned thread holds on to its engine, so once `max_abandoned_workers`
+/// are still running, OCR requests are refused until some finish.
+#[derive(Clone)]
+struct Watchdog {
+    state: AppState,
+    /// Registry the watched job runs on
+    registry: Arc<EngineRegistry>,
+    limit: Duration,
+    max_abandoned: usize,
+}
+
+impl Watchdog {
+    /// Watch jobs on `snapshot`'s registry, if a hard limit is configured
+    fn new(state: &AppState, snapshot: &Snapshot) -> Option<Self> {
+        snapshot.config.engine_hard_timeout.map(|limit| Self {
+            state: state.clone(),
+            registry: snapshot.registry.clone(),
+            limit,
+            max_abandoned: snapshot.config.max_abandoned_workers,
+        })
+    }
+
+    /// Fail with `ServerBusy` while too many abandoned engine calls are
+    /// still running
+    fn check_capacity(&self) -> Result<(), OcrError> {
+        self.state.abandoned.check(self.max_abandoned)
+    }
+
+    /// Wait for a blocking engine job, restarting its engine if it takes too long
+    async fn watch<T: Send + 'static>(
+        &self,
+        engine: &str,
+        mut job: JoinHandle<T>,
+    ) -> Result<T, OcrError> {
+        match tokio::time::timeout(self.limit, &mut job).await {
+            Ok(result) => result.map_err(|e| OcrError::Internal(format!("OCR task failed: {}", e))),
+            Err(_) => {
+                tracing::error!(
+                    "{} engine exceeded the {}s hard limit, restarting it",
+                    engine,
+                    self.limit.as_secs()
+                );
+                self.state.abandoned.track(job);
+                tokio::spawn(self.clone().restart(engine.to_string()));
+                Err(OcrError::ProcessingError(format!(
+                    "{} engine did not finish within {}s and is being restarted",
+                    engine,
+                    self.limit.as_secs()
+                )))
+            }
+        }
+    }
+
+    /// Replace the stuck engine with a fresh instance and swap it in
+    async fn restart(self, engine: String) {
+        let _guard = self.state.reload_lock.lock().await;
+        let current = self.state.snapshot();
+        // Another stuck job or a reload already replaced this engine
+        let stuck = self.registry.get(&engine);
+        let replaced = match (current.registry.get(&engine), &stuck) {
+            (Some(current), Some(stuck)) => !Arc::ptr_eq(&current, stuck),
+            _ => true,
+        };
+        if replaced {
+            return;
+        }
+
+        let config = current.config.clone();
+        let registry = current.registry.clone();
+        let name = engine.clone();
+        match tokio::task::spawn_blocking(move || registry.restart(&name, &config)).await {
+            Ok(Ok(registry)) => {
+                self.state.swap(Snapshot {
+                    registry: Arc::new(registry),
+                    config: current.config,
+                });
+                tracing::info!("Restarted {} engine", engine);
+            }
+            Ok(Err(e)) => tracing::error!("Restarting {} engine failed: {}", engine, e),
+            Err(e) => tracing::error!("Restarting {} engine failed: {}", engine, e),
+        }
+    }
+}
+
+/// Counts engine calls abandoned by the watchdog that are still running
+#[derive(Clone, Default)]
+struct AbandonedWorkers(Arc<AtomicUsize>);
+
+impl AbandonedWorkers {
+    /// Count `job` until it finishes, if it ever does
+    fn track<T: Send + 'static>(&self, job: JoinHandle<T>) {
+        let count = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+        tracing::warn!("{} abandoned engine calls still running", count);
+        let counter = self.0.clone();
+        tokio::spawn(async move {
+            let _ = job.await;
+            counter.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    /// Fail with `ServerBusy` once `max` abandoned calls are running
+    fn check(&self, max: usize) -> Result<(), OcrError> {
+        let count = self.0.load(Ordering::SeqCst);
+        if count >= max {
+            return Err(OcrError::ServerBusy(format!(
+                "{} stuck engine calls are still running, try again later",
+                count
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Wait for a blocking OCR job, failing with a timeout after `limit`
+///
+/// Engine calls can't be interrupted, so a job that runs over is abandoned:
+/// its thread runs to the end on its own, its result is dropped and it
+/// counts as in flight and keeps its OCR slot until then. The watchdog keeps
+/// watching abandoned jobs, so engines stuck in one are still restarted.
+async fn wait_for_ocr<T: Send + 'static>(
+    engine: &'static str,
+    job: JoinHandle<T>,
+    watchdog: Option<Watchdog>,
+    limit: Option<Duration>,
+) -> Result<T, OcrError> {
+    let task_failed =
+        |e: tokio::task::JoinError| OcrError::Internal(format!("OCR task failed: {}", e));
+    let watched = tokio::spawn(async move {
+        match watchdog {
+            Some(watchdog) => watchdog.watch(engine, job).await,
+            None => job.await.map_err(task_failed),
+        }
+    });
+    let Some(limit) = limit else {
+        return watched.await.map_err(task_failed)?;
+    };
+    match tokio::time::timeout(limit, watched).await {
+        Ok(result) => result.map_err(task_failed)?,
+        Err(_) => {
+            tracing::warn!(
+                "{} engine did not finish within {}s, abandoning the request",
+                engine,
+                limit.as_secs()
+            );
+            Err(OcrError::Timeout(format!(
+                "{} engine did not finish within {}s",
+                engine,
+                limit.as_secs()
+            )))
+        }
+    }
+}
+
+/// Registry and config in effect for a single request
+#[derive(Clone)]
+pub struct Snapshot {
+    pub registry: Arc<EngineRegistry>,
+    pub config: Arc<Config>,
+}
+
+impl AppState {
+    fn new(registry: EngineRegistry, config: Config) -> Self {
+        let ocr_slots = Arc::new(Semaphore::new(config.max_concurrency));
+        let jobs = Arc::new(JobStore::new(config.max_jobs));
+        let job_queue = Arc::new(Semaphore::new(config.max_pending_jobs));
+        Self {
+            current: Arc::new(RwLock::new(Snapshot {
+                registry: Arc::new(registry),
+                config: Arc::new(config),
+            })),
+            reload_lock: Arc::new(tokio::sync::Mutex::new(())),
+            ocr_in_flight: Arc::new(AtomicUsize::new(0)),
+            metrics: Arc::new(Metrics::new()),
+            ocr_slots,
+            jobs,
+            job_queue,
+            abandoned: AbandonedWorkers::default(),
+        }
+    }
+
+    /// Current registry and config
+    fn snapshot(&self) -> Snapshot {
+        self.current
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Atomically replace the registry and config
+    fn swap(&self, snapshot: Snapshot) {
+        *self
+            .current
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = snapshot;
+    }
+}
+
+/// Query parameters for OCR requests
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct OcrQueryParams {
+    #[serde(flatten)]
+    pub preprocessing: PreprocessingParams,
+    /// Run a fast low-resolution probe pass for a rough confidence estimate
+    #[serde(default)]
+    pub probe: Option<bool>,
+    /// Recognition model: default, handwriting
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Include bounding boxes: word, character
+    #[serde(default)]
+    pub coords_level: Option<String>,
+    /// Shape of boxes and lines: rect (default), polygon (adds the corners
+    /// of rotated text)
+    #[serde(default)]
+    pub coords_shape: Option<String>,
+    /// Include recognized lines with their baseline angle
+    #[serde(default)]
+    pub lines: Option<bool>,
+    /// ocrs decode method: greedy, beam_search
+    #[serde(default)]
+    pub decode: Option<String>,
+    /// ocrs beam width for beam search decoding (implies decode=beam_search)
+    #[serde(default)]
+    pub beam_width: Option<u32>,
+    /// Stream NDJSON, one line per page as it completes, then the full result
+    #[serde(default)]
+    pub stream: Option<bool>,
+    /// Include the engine's unmodified text as `raw_text`
+    #[serde(default)]
+    pub include_raw: Option<bool>,
+    /// Split images holding several documents: none, auto
+    #[serde(default)]
+    pub split: Option<String>,
+    /// Crop images to the block holding their text before OCR
+    #[serde(default)]
+    pub autocrop: Option<bool>,
+    /// Preprocessing stats detail: basic (default), detailed (adds each
+    /// step's peak memory)
+    #[serde(default)]
+    pub timing: Option<String>,
+    /// Tesseract page segmentation mode (0-13)
+    #[serde(default)]
+    pub psm: Option<u32>,
+    /// Only recognize these characters (leptess only)
+    #[serde(default)]
+    pub whitelist: Option<String>,
+    /// Never recognize these characters (leptess only)
+    #[serde(default)]
+    pub blacklist: Option<String>,
+    /// Response format: json (default), page (PAGE-XML), pdf (the uploaded
+    /// PDF with an OCR text layer), hocr, alto, tsv (Tesseract's TSV), text
+    /// (plain text)
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Animated GIF or WebP frames, or TIFF pages, to OCR: a 0-based index,
+    /// all, auto
+    #[serde(default)]
+    pub frame: Option<String>,
+    /// Rectangles blanked out before OCR, as `x,y,width,height` separated
+    /// by `;`, in pixels of the (rotated) image
+    #[serde(default)]
+    pub mask: Option<String>,
+    /// Return at most this many words, stopping recognition early where the
+    /// engine allows
+    #[serde(default)]
+    pub max_words: Option<usize>,
+    /// Scale of returned confidences: fraction (0.0-1.0, default), percent (0-100)
+    #[serde(default)]
+    pub confidence_scale: Option<String>,
+    /// Compute confidence scores (false skips them, reporting null)
+    #[serde(default)]
+    pub confidence: Option<bool>,
+    /// Return a confidence for every recognized word
+    #[serde(default)]
+    pub word_confidence: Option<bool>,
+    /// Read only the first page of PDFs, for previews
+    #[serde(default)]
+    pub first_page_only: Option<bool>,
+    /// Detect which way up the text is and rotate to match (leptess only)
+    #[serde(default)]
+    pub auto_rotate: Option<bool>,
+    /// Fail with LOW_CONFIDENCE when the overall confidence is below this,
+    /// in the scale chosen by confidence_scale (also accepted as
+    /// min_confidence)
+    #[serde(default, alias = "min_confidence")]
+    pub fail_below_confidence: Option<f32>,
+    /// OCR a PDF's images and compare the text with its embedded text
+    #[serde(default)]
+    pub pdf_verify: Option<bool>,
+}
+
+/// Preprocessing statistics for response
+#[derive(Serialize)]
+pub struct PreprocessingStats {
+    pub preset: String,
+    pub total_time_ms: u64,
+    pub steps: Vec<StepTiming>,
+    /// What steps measured, by step name (e.g. the angle deskew found)
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, StepMetadata>,
+}
+
+/// Distribution of per-word confidences (0.0-1.0, unless rescaled)
+///
+/// Engines without per-word confidences only report the mean, which is the
+/// overall confidence.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ConfidenceStats {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f32>,
+    pub mean: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub median: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p25: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p75: Option<f32>,
+}
+
+impl ConfidenceStats {
+    /// Multiply every confidence by `factor`
+    fn scale(&mut self, factor: f32) {
+        for value in [
+            &mut self.min,
+            &mut self.max,
+            &mut self.median,
+            &mut self.p25,
+            &mut self.p75,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *value *= factor;
+        }
+        self.mean *= factor;
+    }
+
+    pub fn new(confidence: f32, word_confidences: Option<&[f32]>) -> Self {
+        let mut words = match word_confidences {
+            Some(words) if !words.is_empty() => words.to_vec(),
+            _ => {
+                return Self {
+                    min: None,
+                    max: None,
+                    mean: confidence,
+                    median: None,
+                    p25: None,
+                    p75: None,
+                }
+            }
+        };
+        words.sort_by(f32::total_cmp);
+
+        // Linear interpolation between the closest ranks
+        let percentile = |p: f32| {
+            let rank = p * (words.len() - 1) as f32;
+            let (low, high) = (words[rank.floor() as usize], words[rank.ceil() as usize]);
+            low + (high - low) * rank.fract()
+        };
+
+        Self {
+            min: words.first().copied(),
+            max: words.last().copied(),
+            mean: words.iter().sum::<f32>() / words.len() as f32,
+            median: Some(percentile(0.5)),
+            p25: Some(percentile(0.25)),
+            p75: Some(percentile(0.75)),
+        }
+    }
+}
+
+/// Version of the OCR and error response schema
+///
+/// Bumped when a field is removed, renamed or changes meaning. Added fields
+/// don't change it, so clients should ignore fields they don't know.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// OCR response
+#[derive(Serialize)]
+pub struct OcrResponse {
+    /// Always [`SCHEMA_VERSION`]
+    pub schema_version: u32,
+    pub text: String,
+    /// Text exactly as the engine returned it (only when include_raw=true)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_text: Option<String>,
+    /// Whether text was cut off at max_words (only when max_words is set)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<bool>,
+    /// Overall confidence (null when confidence=false)
+    pub confidence: Option<f32>,
+    pub confidence_stats: Option<ConfidenceStats>,
+    /// Every recognized word with its confidence (only when
+    /// word_confidence=true)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub word_confidences: Option<Vec<WordConfidenceResponse>>,
+    pub processing_time_ms: u64,
+    pub warnings: Vec<String>,
+    pub engine: String,
+    /// MIME type the upload was processed as, sniffed from its contents
+    pub detected_format: String,
+    /// Filename of the uploaded file part, so clients can match results to
+    /// files (only when the upload had one)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    /// True when OCR completed successfully but found no text, so an empty
+    /// result can be told apart from a failure
+    pub no_text_detected: bool,
+    /// Clockwise rotation in degrees applied to turn the text upright (only
+    /// with auto_rotate=true, when the orientation was detected)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orientation_degrees: Option<i32>,
+    /// Preprocessing statistics (null if preprocess=none)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preprocessing: Option<PreprocessingStats>,
+    /// Bounding boxes (only when coords_level is set)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boxes: Option<BoxesResponse>,
+    /// Recognized lines (only when lines=true)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lines: Option<Vec<TextLineResponse>>,
+    /// Per-document results (only when split=auto)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regions: Option<Vec<RegionOcrResponse>>,
+    /// Embedded text compared with the OCR text (only when pdf_verify=true)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pdf_verification: Option<PdfVerification>,
+}
+
+/// A recognized word and how confident the engine is in it
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WordConfidenceResponse {
+    pub text: String,
+    pub confidence: f32,
+}
+
+/// A PDF's embedded text layer compared with OCR of its images
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PdfVerification {
+    /// Text extracted directly from the PDF
+    pub embedded_text: String,
+    /// Character similarity of the embedded and OCR text, 0.0-1.0
+    pub similarity: f32,
+}
+
+/// OCR result for one document found by split=auto
+#[derive(Serialize)]
+pub struct RegionOcrResponse {
+    /// Position of the document in the uploaded image
+    #[serde(flatten)]
+    pub bounds: Bounds,
+    pub text: String,
+    pub confidence: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boxes: Option<BoxesResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lines: Option<Vec<TextLineResponse>>,
+}
+
+impl OcrResponse {
+    /// Multiply every confidence in the response by `factor`
+    fn scale_confidences(&mut self, factor: f32) {
+        if let Some(confidence) = self.confidence.as_mut() {
+            *confidence *= factor;
+        }
+        if let Some(stats) = self.confidence_stats.as_mut() {
+            stats.scale(factor);
+        }
+        for word in self.word_confidences.iter_mut().flatten() {
+            word.confidence *= factor;
+        }
+        if let Some(boxes) = self.boxes.as_mut() {
+            boxes.scale_confidences(factor);
+        }
+        scale_line_confidences(&mut self.lines, factor);
+        for region in self.regions.iter_mut().flatten() {
+            if let Some(confidence) = region.confidence.as_mut() {
+                *confidence *= factor;
+            }
+            if let Some(boxes) = region.boxes.as_mut() {
+                boxes.scale_confidences(factor);
+            }
+            scale_line_confidences(&mut region.lines, factor);
+        }
+    }
+}
+
+/// Bounding boxes for OCR response
+#[derive(Serialize)]
+pub struct BoxesResponse {
+    pub level: String,
+    pub boxes: Vec<TextBoxResponse>,
+}
+
+/// Single bounding box, in pixels of the uploaded image
+#[derive(Serialize)]
+pub struct TextBoxResponse {
+    pub text: String,
+    pub page: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    /// Corners of the rotated box (only when coords_shape=polygon)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub polygon: Option<[[f32; 2]; 4]>,
+}
+
+/// A recognized line, in pixels of the uploaded image
+#[derive(Serialize)]
+pub struct TextLineResponse {
+    pub text: String,
+    pub page: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    /// Degrees counterclockwise from horizontal
+    pub baseline_angle: f32,
+    /// Corners of the rotated line (only when coords_shape=polygon)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub polygon: Option<[[f32; 2]; 4]>,
+}
+
+impl From<LineBox> for TextLineResponse {
+    fn from(line: LineBox) -> Self {
+        let b = line.text_box;
+        Self {
+            text: b.text,
+            page: b.page,
+            x: b.x,
+            y: b.y,
+            width: b.width,
+            height: b.height,
+            confidence: b.confidence,
+            baseline_angle: (line.baseline_angle * 100.0).round() / 100.0,
+            polygon: b.polygon.map(round_polygon),
+        }
+    }
+}
+
+fn line_responses(lines: Option<Vec<LineBox>>) -> Option<Vec<TextLineResponse>> {
+    lines.map(|lines| lines.into_iter().map(TextLineResponse::from).collect())
+}
+
+fn scale_line_confidences(lines: &mut Option<Vec<TextLineResponse>>, factor: f32) {
+    for confidence in lines
+        .iter_mut()
+        .flatten()
+        .filter_map(|line| line.confidence.as_mut())
+    {
+        *confidence *= factor;
+    }
+}
+
+impl BoxesResponse {
+    fn scale_confidences(&mut self, factor: f32) {
+        for confidence in self.boxes.iter_mut().filter_map(|b| b.confidence.as_mut()) {
+            *confidence *= factor;
+        }
+    }
+}
+
+impl From<TextBoxes> for BoxesResponse {
+    fn from(boxes: TextBoxes) -> Self {
+        Self {
+            level: boxes.level.as_str().to_string(),
+            boxes: boxes
+                .boxes
+                .into_iter()
+                .map(|b| TextBoxResponse {
+                    text: b.text,
+                    page: b.page,
+                    x: b.x,
+                    y: b.y,
+                    width: b.width,
+                    height: b.height,
+                    confidence: b.confidence,
+                    polygon: b.polygon.map(round_polygon),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Round polygon corners to a tenth of a pixel, which is all the precision
+/// recognition has
+fn round_polygon(polygon: [[f32; 2]; 4]) -> [[f32; 2]; 4] {
+    polygon.map(|corner| corner.map(|v| (v * 10.0).round() / 10.0))
+}
+
+/// Text detection response
+#[derive(Serialize)]
+pub struct DetectResponse {
+    pub words: Vec<RegionResponse>,
+    pub lines: Vec<LineResponse>,
+    pub processing_time_ms: u64,
+    pub engine: String,
+}
+
+/// A detected line of text
+#[derive(Serialize)]
+pub struct LineResponse {
+    #[serde(flatten)]
+    pub bounds: Bounds,
+    pub words: Vec<RegionResponse>,
+}
+
+/// A detected word region
+#[derive(Serialize)]
+pub struct RegionResponse {
+    #[serde(flatten)]
+    pub bounds: Bounds,
+    /// Corners of the (possibly rotated) region as [x, y] pairs
+    pub corners: [[f32; 2]; 4],
+}
+
+/// Axis-aligned bounding box in pixels
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Bounds {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Bounds {
+    /// Smallest box containing all the given points
+    fn around<'a>(points: impl IntoIterator<Item = &'a [f32; 2]>) -> Self {
+        let (mut min_x, mut min_y) = (f32::MAX, f32::MAX);
+        let (mut max_x, mut max_y) = (f32::MIN, f32::MIN);
+        for [x, y] in points {
+            min_x = min_x.min(*x);
+            min_y = min_y.min(*y);
+            max_x = max_x.max(*x);
+            max_y = max_y.max(*y);
+        }
+
+        if min_x > max_x {
+            return Self {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            };
+        }
+
+        let (min_x, min_y) = (min_x.max(0.0).floor(), min_y.max(0.0).floor());
+        let (max_x, max_y) = (max_x.max(0.0).ceil(), max_y.max(0.0).ceil());
+        Self {
+            x: min_x as u32,
+            y: min_y as u32,
+            width: (max_x - min_x) as u32,
+            height: (max_y - min_y) as u32,
+        }
+    }
+}
+
+impl From<&DetectedWord> for RegionResponse {
+    fn from(word: &DetectedWord) -> Self {
+        Self {
+            bounds: Bounds::around(&word.corners),
+            corners: word.corners,
+        }
+    }
+}
+
+/// Engine info for /info response
+#[derive(Serialize)]
+pub struct EngineInfoResponse {
+    pub name: String,
+    pub description: String,
+    pub supported_formats: Vec<String>,
+    pub supported_languages: Vec<String>,
+    pub supported_models: Vec<String>,
+}
+
+/// Engine that failed to start, for /info response
+#[derive(Serialize)]
+pub struct UnavailableEngineResponse {
+    pub name: String,
+    pub error: String,
+}
+
+/// Health check response
+#[derive(Serialize)]
+pub struct HealthResponse {
+    pub status: String,
+    pub version: String,
+}
+
+/// Server info response
+#[derive(Serialize)]
+pub struct InfoResponse {
+    pub version: String,
+    pub available_engines: Vec<EngineInfoResponse>,
+    /// Built-in engines that failed to start (omitted when all started)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unavailable_engines: Vec<UnavailableEngineResponse>,
+    pub default_engine: String,
+    pub max_file_size_bytes: usize,
+    pub default_language: String,
+}
+
+/// Run the HTTP server
+pub async fn run(config: Config) -> anyhow::Result<()> {
+    let registry = EngineRegistry::new(&config)?;
+    let addr = format!("{}:{}", config.host, config.port);
+    let max_file_size = config.max_file_size;
+    let request_timeout = config.request_timeout;
+    let timeouts = ConnectionTimeouts {
+        keep_alive: config.keep_alive_timeout,
+        header_read: config.header_read_timeout,
+    };
+
+    tracing::info!("Available engines: {:?}", registry.list());
+
+    check_fallback(&config, &registry).map_err(anyhow::Error::msg)?;
+    if let Some(fallback) = config.fallback_engine.as_deref() {
+        tracing::info!("Fallback engine: {}", fallback);
+    }
+
+    let reload_enabled = config.admin_token.is_some();
+    let results_enabled = config.result_store_dir.is_some();
+    let warmup_on_start = config.warmup_on_start;
+    let model_verify_interval = config.model_verify_interval;
+    let job_ttl = config.job_ttl;
+    let state = AppState::new(registry, config);
+
+    let mut router = Router::new()
+        .route("/ocr", post(handle_ocr))
+        .route("/ocr/:engine", post(handle_ocr_with_engine))
+        .route("/ocr/jobs", post(handle_create_job))
+        .route("/ocr/jobs/:id", get(handle_job))
+        .route("/health", get(handle_health))
+        .route("/detect", post(handle_detect))
+        .route("/preprocess", post(handle_preprocess))
+        .route("/info", get(handle_info))
+        .route("/warmup", post(handle_warmup))
+        .route("/warmup/status", get(handle_warmup_status))
+        .route("/metrics", get(handle_metrics));
+
+    // Only expose reloading when a token has been configured
+    if reload_enabled {
+        router = router.route("/reload", post(handle_reload));
+    }
+    if results_enabled {
+        router = router.route("/results/:hash", get(handle_result));
+    }
+
+    let mut app = router
+        .layer(DefaultBodyLimit::max(max_file_size))
+        .layer(TraceLayer::new_for_http())
+        .with_state(state.clone());
+
+    // Requests exceeding the timeout get a 408 Request Timeout
+    if let Some(timeout) = request_timeout {
+        app = app.layer(TimeoutLayer::new(timeout));
+    }
+
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+
+    // Warm up after binding so port conflicts fail fast, but before accepting
+    // connections so the first request is fast
+    if warmup_on_start {
+        if let Some(engine) = state.snapshot().registry.default() {
+            let name = engine.name();
+            match tokio::task::spawn_blocking(move || warm_up(engine.as_ref())).await {
+                Ok(Ok(elapsed)) => {
+                    tracing::info!("Warmed up {} engine in {}ms", name, elapsed.as_millis())
+                }
+                Ok(Err(e)) => tracing::warn!("Warmup of {} engine failed: {}", name, e),
+                Err(e) => tracing::warn!("Warmup of {} engine failed: {}", name, e),
+            }
+        }
+    }
+
+    if let Some(interval) = model_verify_interval {
+        tokio::spawn(verify_models_periodically(state.clone(), interval));
+    }
+    tokio::spawn(expire_jobs_periodically(state.jobs.clone(), job_ttl));
+
+    tracing::info!("Server listening on http://{}", addr);
+
+    serve::serve(listener, app, timeouts).await
+}
+
+/// Re-check the cached models against their checksums every `interval`, so
+/// corruption on disk is noticed before the next restart fails to load them
+async fn verify_models_periodically(state: AppState, interval: std::time::Duration) {
+    tracing::info!("Verifying cached models every {}s", interval.as_secs());
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick completes immediately, and the models were just loaded
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+        // Reloads replace the registry along with its model cache
+        let snapshot = state.snapshot();
+        let cache = snapshot.registry.model_cache().clone();
+        let repair = snapshot.config.model_verify_repair;
+
+        match tokio::task::spawn_blocking(move || cache.verify(repair)).await {
+            Ok(corrupted) if corrupted.is_empty() => {
+                tracing::debug!("Cached models verified")
+            }
+            Ok(corrupted) => {
+                tracing::error!("Corrupted cached models: {}", corrupted.join(", "))
+            }
+            Err(e) => tracing::warn!("Model verification failed: {}", e),
+        }
+    }
+}
+
+/// Forget finished background jobs once they are older than `ttl`
+async fn expire_jobs_periodically(jobs: Arc<JobStore>, ttl: Duration) {
+    let mut ticker = tokio::time::interval(ttl.min(Duration::from_secs(60)));
+    loop {
+        ticker.tick().await;
+        let expired = jobs.remove_expired(ttl);
+        if expired > 0 {
+            tracing::debug!("Expired {} finished jobs", expired);
+        }
+    }
+}
+
+/// Handle OCR requests (uses default engine)
+async fn handle_ocr(
+    State(state): State<AppState>,
+    Query(params): Query<OcrQueryParams>,
+    headers: HeaderMap,
+    request: Request,
+) -> Result<Response, OcrError> {
+    let in_flight = InFlightOcr::start(&state.ocr_in_flight);
+    let snapshot = state.snapshot();
+    let watchdog = Watchdog::new(&state, &snapshot);
+    let engine = snapshot
+        .registry
+        .default()
+        .ok_or_else(|| OcrError::InitializationError("No default engine available".to_string()))?;
+
+    let name = engine.name();
+    let result = process_ocr_request(
+        snapshot,
+        engine,
+        OcrInput::Request { request, in_flight },
+        params,
+        &headers,
+        watchdog,
+        &state,
+    )
+    .await;
+    record_ocr_request(&state.metrics, name, result)
+}
+
+/// Handle OCR requests with specific engine
+async fn handle_ocr_with_engine(
+    State(state): State<AppState>,
+    Path(engine_name): Path<String>,
+    Query(params): Query<OcrQueryParams>,
+    headers: HeaderMap,
+    request: Request,
+) -> Result<Response, OcrError> {
+    let in_flight = InFlightOcr::start(&state.ocr_in_flight);
+    let snapshot = state.snapshot();
+    let watchdog = Watchdog::new(&state, &snapshot);
+    let engine = find_engine(&snapshot, &engine_name)?;
+
+    let name = engine.name();
+    let result = process_ocr_request(
+        snapshot,
+        engine,
+        OcrInput::Request { request, in_flight },
+        params,
+        &headers,
+        watchdog,
+        &state,
+    )
+    .await;
+    record_ocr_request(&state.metrics, name, result)
+}
+
+/// Look up an engine by name, explaining why it isn't available if it isn't
+fn find_engine(snapshot: &Snapshot, engine_name: &str) -> Result<Arc<dyn OcrEngine>, OcrError> {
+    snapshot.registry.get(engine_name).ok_or_else(|| {
+        let unavailable = snapshot
+            .registry
+            .unavailable()
+            .iter()
+            .find(|e| e.name == engine_name);
+        OcrError::InvalidRequest(match unavailable {
+            Some(e) => format!(
+                "Engine '{}' failed to start: {}. Available engines: {:?}",
+                e.name,
+                e.error,
+                snapshot.registry.list()
+            ),
+            None => format!(
+                "Unknown engine '{}'. Available engines: {:?}",
+                engine_name,
+                snapshot.registry.list()
+            ),
+        })
+    })
+}
+
+/// Start a background OCR job (uses default engine)
+///
+/// The upload is read before answering, unless `max_pending_jobs` jobs are
+/// already waiting, then OCR runs like a `/ocr` request and the JSON response
+/// is kept for `GET /ocr/jobs/:id`.
+async fn handle_create_job(
+    State(state): State<AppState>,
+    Query(params): Query<OcrQueryParams>,
+    request: Request,
+) -> Result<Response, OcrError> {
+    if params.stream.unwrap_or(false) {
+        return Err(OcrError::InvalidRequest(
+            "stream=true is not supported for jobs".to_string(),
+        ));
+    }
+    if parse_format(params.format.as_deref())? != OutputFormat::Json {
+        return Err(OcrError::InvalidRequest(
+            "Jobs only return JSON results".to_string(),
+        ));
+    }
+
+    // Turn the job away before reading its upload if too many are waiting
+    let queued = reserve_job_place(&state.job_queue)?;
+    let snapshot = state.snapshot();
+    let watchdog = Watchdog::new(&state, &snapshot);
+    if let Some(watchdog) = &watchdog {
+        watchdog.check_capacity()?;
+    }
+    let engine = snapshot
+        .registry
+        .default()
+        .ok_or_else(|| OcrError::InitializationError("No default engine available".to_string()))?;
+    let upload = read_ocr_upload(request, &snapshot.config).await?;
+
+    let job = state
+        .jobs
+        .create()
+        .ok_or_else(|| OcrError::ServerBusy("too many unfinished jobs".to_string()))?;
+    let id = job.job_id.clone();
+    let (jobs, started) = (state.jobs.clone(), id.clone());
+    let input = OcrInput::Upload {
+        upload,
+        on_start: Box::new(move || {
+            drop(queued);
+            jobs.start(&started)
+        }),
+    };
+
+    tokio::spawn(async move {
+        let name = engine.name();
+        // Without an Accept header the result is always JSON
+        let result = process_ocr_request(
+            snapshot,
+            engine,
+            input,
+            params,
+            &HeaderMap::new(),
+            watchdog,
+            &state,
+        )
+        .await;
+        let result = match record_ocr_request(&state.metrics, name, result) {
+            Ok(response) => axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .map_err(|e| OcrError::Internal(format!("Failed to read job result: {}", e)))
+                .and_then(|body| {
+                    serde_json::from_slice(&body)
+                        .map_err(|e| OcrError::Internal(format!("Job result is not JSON: {}", e)))
+                }),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(result) => state.jobs.complete(&id, result),
+            Err(e) => {
+                tracing::warn!("OCR job {} failed: {}", id, e);
+                let error = serde_json::to_value(ErrorResponse::from(&e)).unwrap_or_default();
+                state.jobs.fail(&id, error);
+            }
+        }
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(job)).into_response())
+}
+
+/// Handle polling for a background OCR job
+async fn handle_job(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<JobResponse>, OcrError> {
+    match state.jobs.get(&id) {
+        JobLookup::Found(job) => Ok(Json(job)),
+        JobLookup::Expired => Err(OcrError::JobExpired(format!(
+            "job {} finished and its result was discarded",
+            id
+        ))),
+        JobLookup::Unknown => Err(OcrError::NotFound(format!("no job {}", id))),
+    }
+}
+
+/// Count a finished OCR request by engine and status code
+fn record_ocr_request(
+    metrics: &Metrics,
+    engine: &str,
+    result: Result<Response, OcrError>,
+) -> Result<Response, OcrError> {
+    let status = match &result {
+        Ok(response) => response.status(),
+        Err(e) => e.status_and_code().0,
+    };
+    metrics.record_request(engine, status.as_u16());
+    result
+}
+
+/// Wait for a free OCR slot, failing with `ServerBusy` after `timeout`
+async fn acquire_ocr_slot(
+    slots: &Arc<Semaphore>,
+    timeout: Option<Duration>,
+) -> Result<OwnedSemaphorePermit, OcrError> {
+    let acquire = slots.clone().acquire_owned();
+    let permit = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, acquire).await.map_err(|_| {
+            OcrError::ServerBusy(format!(
+                "no OCR slot became free within {}s, try again later",
+                timeout.as_secs()
+            ))
+        })?,
+        None => acquire.await,
+    };
+    // The semaphore is never closed
+    permit.map_err(|e| OcrError::Internal(e.to_string()))
+}
+
+/// Reserve a place for a background job that hasn't started yet, failing
+/// with `ServerBusy` when every place is taken
+fn reserve_job_place(queue: &Arc<Semaphore>) -> Result<OwnedSemaphorePermit, OcrError> {
+    queue.clone().try_acquire_owned().map_err(|_| {
+        OcrError::ServerBusy(
+            "too many jobs are waiting for an OCR slot, try again later".to_string(),
+        )
+    })
+}
+
+/// Where an OCR request's upload comes from
+enum OcrInput {
+    /// Read from the request body once a slot is free; the request counts as
+    /// in flight while it waits
+    Request {
+        request: Request,
+        in_flight: (InFlightOcr, usize),
+    },
+    /// Already read, for background jobs; `on_start` is called once a slot
+    /// is free, and the job only counts as in flight from then on
+    Upload {
+        upload: OcrUpload,
+        on_start: Box<dyn FnOnce() + Send>,
+    },
+}
+
+/// Common OCR processing logic
+async fn process_ocr_request(
+    state: Snapshot,
+    engine: Arc<dyn OcrEngine>,
+    input: OcrInput,
+    params: OcrQueryParams,
+    headers: &HeaderMap,
+    watchdog: Option<Watchdog>,
+    app: &AppState,
+) -> Result<Response, OcrError> {
+    if let Some(watchdog) = &watchdog {
+        watchdog.check_capacity()?;
+    }
+    // Queue before reading the upload, so waiting requests don't hold it in memory
+    let slot = acquire_ocr_slot(&app.ocr_slots, state.config.queue_timeout).await?;
+    let start = Instant::now();
+    let wants_protobuf = protobuf::is_accepted(
+        headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok()),
+    );
+
+    let (upload, (in_flight, load)) = match input {
+        OcrInput::Request { request, in_flight } => {
+            (read_ocr_upload(request, &state.config).await?, in_flight)
+        }
+        OcrInput::Upload { upload, on_start } => {
+            on_start();
+            (upload, InFlightOcr::start(&app.ocr_in_flight))
+        }
+    };
+    let OcrUpload {
+        data,
+        content_type,
+        filename,
+        languages,
+        pdf_password,
+        ..
+    } = upload;
+    let data = data.ok_or(OcrError::MissingFile)?;
+
+    // Check file size
+    if data.len() > state.config.max_file_size {
+        return Err(OcrError::ImageTooLarge {
+            size: data.len(),
+            max: state.config.max_file_size,
+        });
+    }
+
+    // Only used for documents and unrecognized uploads; the contents decide
+    let mime = content_type.unwrap_or_else(|| "application/octet-stream".to_string());
+
+    let languages = parse_languages(languages)?;
+    let language_default = languages
+        .as_deref()
+        .map(|languages| state.config.language_defaults.get(languages))
+        .unwrap_or_default();
+
+    // Otherwise use the recommended preset for the document's language
+    let mut preprocessing = params
+        .preprocessing
+        .parse(language_default.preset.unwrap_or_default())?;
+    let mut request_warnings = Vec::new();
+    // Steps the client chose are run as asked, even under load
+    let preset = preprocessing.preset;
+    match state.config.adaptive_preset_threshold {
+        Some(threshold)
+            if preprocessing.steps.is_none()
+                && load > threshold
+                && preset.downgraded() != preset =>
+        {
+            preprocessing.preset = preset.downgraded();
+            request_warnings.push(format!(
+                "Server busy: used the {} preset instead of {}",
+                preprocessing.preset.as_str(),
+                preset.as_str()
+            ));
+        }
+        _ => {}
+    }
+
+    let frame = parse_frame(params.frame.as_deref())?;
+    let mask = parse_mask(params.mask.as_deref())?;
+    let format = parse_format(params.format.as_deref())?;
+    let page_xml = matches!(
+        format,
+        OutputFormat::PageXml | OutputFormat::Hocr | OutputFormat::Alto | OutputFormat::Tsv
+    );
+    let searchable_pdf = format == OutputFormat::Pdf;
+    let confidence_factor = parse_confidence_scale(params.confidence_scale.as_deref())?;
+    let min_confidence = params.fail_below_confidence;
+    if let Some(min) = min_confidence {
+        if !(0.0..=confidence_factor).contains(&min) {
+            return Err(OcrError::InvalidRequest(format!(
+                "Invalid fail_below_confidence {}. Valid: 0-{}",
+                min, confidence_factor
+            )));
+        }
+        if params.confidence == Some(false) {
+            return Err(OcrError::InvalidRequest(
+                "fail_below_confidence needs confidence scores, confidence=false is not supported"
+                    .to_string(),
+            ));
+        }
+    }
+    let pdf_verify = params.pdf_verify.unwrap_or(false);
+    // Split the page threads among the requests in flight, so a long PDF
+    // doesn't take every core from the others
+    let page_threads = (state.config.pdf_page_threads / load.max(1)).max(1);
+    let options = ocr_options(&params, engine.as_ref(), format, languages, page_threads)?;
+
+    let fallback = state
+        .config
+        .fallback_engine
+        .as_deref()
+        .and_then(|name| state.registry.get(name));
+
+    // What the upload is processed as, which may not be what the client said
+    let sniffed = detect_format(&data);
+    let document_mime = sniffed
+        .is_none()
+        .then(|| office_document_mime(&mime, &data))
+        .flatten();
+    // Damaged PDFs may have lost their header, so the declared type is
+    // trusted when the contents are unrecognized
+    let is_pdf = sniffed == Some(DetectedFormat::Pdf)
+        || (sniffed.is_none() && document_mime.is_none() && mime == "application/pdf");
+    let is_document = document_mime.is_some();
+    let detected_format = match (sniffed, document_mime) {
+        _ if is_pdf => "application/pdf",
+        (Some(format), _) => format.mime_type(),
+        (None, Some(document_mime)) => document_mime,
+        (None, None) => return Err(unrecognized_format(&mime)),
+    }
+    .to_string();
+    if !is_pdf && !is_document {
+        check_min_dimensions(&data, state.config.min_image_dimension)?;
+    }
+    if pdf_verify && !is_pdf {
+        request_warnings.push("pdf_verify only applies to PDFs, ignored".to_string());
+    }
+    if options.polygons && options.coords_level.is_none() && !options.lines {
+        request_warnings
+            .push("coords_shape only applies with coords_level or lines=true, ignored".to_string());
+    }
+    if params.word_confidence == Some(true) && options.skip_confidence {
+        request_warnings
+            .push("word_confidence doesn't apply with confidence=false, ignored".to_string());
+    }
+    let autocrop = params.autocrop.unwrap_or(false);
+    let detailed_timing = parse_timing(params.timing.as_deref())?;
+    if autocrop && (is_pdf || is_document) {
+        request_warnings.push("autocrop only applies to images, ignored".to_string());
+    }
+    // Ignoring a mask would return text the client must not see
+    if !mask.is_empty() && (is_pdf || is_document) {
+        return Err(OcrError::InvalidRequest(
+            "mask is only supported for images".to_string(),
+        ));
+    }
+    // PAGE-XML, hOCR, ALTO and TSV describe a single page image
+    let page_size = if page_xml {
+        if is_pdf || is_document || params.stream.unwrap_or(false) {
+            return Err(OcrError::InvalidRequest(format!(
+                "format={} is only supported for single images without stream=true",
+                format.as_str()
+            )));
+        }
+        Some(page_size(&data, format, frame, preprocessing.rotation)?)
+    } else {
+        None
+    };
+
+    if searchable_pdf && (!is_pdf || params.stream.unwrap_or(false)) {
+        return Err(OcrError::InvalidRequest(
+            "format=pdf is only supported for PDFs without stream=true".to_string(),
+        ));
+    }
+    if searchable_pdf && options.first_page_only {
+        return Err(OcrError::InvalidRequest(
+            "format=pdf adds text to every page, first_page_only is not supported".to_string(),
+        ));
+    }
+    // Kept to add the text layer to, repaired and decrypted like for OCR
+    let original_pdf =
+        searchable_pdf.then(|| (data.clone(), state.config.pdf_lenient, pdf_password.clone()));
+
+    // Only plain JSON results are persisted
+    let stored_as = state
+        .config
+        .result_store_dir
+        .as_ref()
+        .filter(|_| format == OutputFormat::Json && !params.stream.unwrap_or(false))
+        .map(|dir| {
+            let languages = options.languages.as_deref();
+            Ok::<_, OcrError>((
+                ResultStore::new(dir),
+                result_store::content_hash(&data),
+                result_options(&state.config, engine.name(), languages, &params)?,
+            ))
+        })
+        .transpose()?;
+
+    let job = OcrJob {
+        start,
+        is_pdf,
+        engine,
+        fallback,
+        data,
+        is_document,
+        detected_format,
+        filename,
+        max_file_size: state.config.max_file_size,
+        max_warnings: state.config.max_warnings,
+        pdf_password,
+        pdf_lenient: state.config.pdf_lenient,
+        preprocessing,
+        sharpen_skip_threshold: state.config.sharpen_skip_threshold,
+        include_raw: params.include_raw.unwrap_or(false),
+        probe: params.probe.unwrap_or(false),
+        probe_max_dimension: state.config.probe_max_dimension,
+        split: parse_split(params.split.as_deref())?,
+        autocrop,
+        detailed_timing,
+        frame,
+        mask,
+        pdf_verify: pdf_verify && is_pdf,
+        options,
+        request_warnings,
+        in_flight,
+        slot,
+        metrics: app.metrics.clone(),
+    };
+
+    if params.stream.unwrap_or(false) {
+        if format == OutputFormat::Text {
+            return Err(OcrError::InvalidRequest(
+                "format=text is not supported with stream=true".to_string(),
+            ));
+        }
+        if wants_protobuf {
+            return Err(OcrError::InvalidRequest(
+                "Protobuf responses are not supported with stream=true".to_string(),
+            ));
+        }
+        return Ok(stream_ocr(
+            job,
+            confidence_factor,
+            min_confidence,
+            watchdog,
+            state.config.processing_timeout,
+        ));
+    }
+
+    let engine = job.engine.name();
+    let task = tokio::task::spawn_blocking(move || run_ocr(job, &|_, _| {}));
+    let response = wait_for_ocr(engine, task, watchdog, state.config.processing_timeout).await??;
+    let mut response = check_min_confidence(response, min_confidence, confidence_factor)?;
+    if let Some((width, height)) = page_size {
+        return Ok(page_response(&response, format, width, height));
+    }
+    if let Some((data, lenient, password)) = original_pdf {
+        return searchable_pdf_response(response, data, lenient, password).await;
+    }
+    response.scale_confidences(confidence_factor);
+    if let Some((store, hash, options)) = stored_as {
+        store_result(store, hash, options, &response).await;
+    }
+    if format == OutputFormat::Text {
+        return Ok(text_response(response));
+    }
+    if wants_protobuf {
+        return Ok((
+            [(header::CONTENT_TYPE, protobuf::CONTENT_TYPE)],
+            protobuf::encode(response),
+        )
+            .into_response());
+    }
+    Ok(Json(response).into_response())
+}
+
+/// Validate the languages form field, None if it is empty
+fn parse_languages(languages: Option<String>) -> Result<Option<String>, OcrError> {
+    let languages = languages
+        .map(|languages| languages.trim().to_string())
+        .filter(|languages| !languages.is_empty());
+    if let Some(languages) = languages.as_deref() {
+        if !languages.split('+').all(is_valid_language) {
+            return Err(OcrError::InvalidRequest(format!(
+                "Invalid languages '{}'. Use Tesseract codes joined with '+', e.g. eng+deu",
+                languages
+            )));
+        }
+    }
+    Ok(languages)
+}
+
+/// Size of the page a PAGE-XML, hOCR, ALTO or TSV `format` response
+/// describes, after `rotation` or the image's EXIF orientation
+///
+/// These formats describe a single image, so several frames or TIFF pages
+/// are rejected.
+fn page_size(
+    data: &[u8],
+    format: OutputFormat,
+    frame: Option<FrameSelection>,
+    rotation: Option<Orientation>,
+) -> Result<(u32, u32), OcrError> {
+    if frame == Some(FrameSelection::All) {
+        return Err(OcrError::InvalidRequest(format!(
+            "format={} describes a single image, frame=all is not supported",
+            format.as_str()
+        )));
+    }
+    if frame.is_none() && tiff_pages(data).is_some_and(|pages| pages.offsets.len() > 1) {
+        return Err(OcrError::InvalidRequest(format!(
+            "format={} describes a single image, pass frame to pick a page of the multi-page TIFF",
+            format.as_str()
+        )));
+    }
+    let (width, height) = image_dimensions(data).ok_or_else(|| {
+        OcrError::UnsupportedFormat("Failed to read image dimensions".to_string())
+    })?;
+    let orientation = rotation
+        .or_else(|| exif_orientation(data))
+        .unwrap_or(Orientation::NoTransforms);
+    Ok(if swaps_axes(orientation) {
+        (height, width)
+    } else {
+        (width, height)
+    })
+}
+
+/// Validate the recognition options of an OCR request
+///
+/// Formats other than JSON and plain text are built from word boxes, so the
+/// response `format` decides which boxes are collected.
+fn ocr_options(
+    params: &OcrQueryParams,
+    engine: &dyn OcrEngine,
+    format: OutputFormat,
+    languages: Option<String>,
+    page_threads: usize,
+) -> Result<OcrOptions, OcrError> {
+    if let Some(psm) = params.psm.filter(|psm| *psm > MAX_PSM) {
+        return Err(OcrError::InvalidRequest(format!(
+            "Unknown psm '{}'. Valid: 0-{}",
+            psm, MAX_PSM
+        )));
+    }
+
+    let model = parse_model(params.model.as_deref(), engine)?;
+    let (char_whitelist, char_blacklist) =
+        parse_char_filter(params.whitelist.as_deref(), params.blacklist.as_deref())?;
+
+    let coords_level = params
+        .coords_level
+        .as_deref()
+        .map(|s| {
+            CoordsLevel::from_str(s).ok_or_else(|| {
+                OcrError::InvalidRequest(format!(
+                    "Unknown coords_level '{}'. Valid: word, character",
+                    s
+                ))
+            })
+        })
+        .transpose()?;
+
+    let polygons = match params.coords_shape.as_deref() {
+        None | Some("rect") => false,
+        Some("polygon") => true,
+        Some(other) => {
+            return Err(OcrError::InvalidRequest(format!(
+                "Unknown coords_shape '{}'. Valid: rect, polygon",
+                other
+            )))
+        }
+    };
+
+    let coords_level = if format == OutputFormat::Json {
+        coords_level
+    } else if format == OutputFormat::Text {
+        // Boxes aren't part of a plain text response
+        None
+    } else {
+        if coords_level == Some(CoordsLevel::Character) {
+            return Err(OcrError::InvalidRequest(format!(
+                "format={} is built from word boxes, coords_level=character is not supported",
+                format.as_str()
+            )));
+        }
+        if polygons {
+            return Err(OcrError::InvalidRequest(format!(
+                "format={} is built from word rectangles, coords_shape=polygon is not supported",
+                format.as_str()
+            )));
+        }
+        Some(CoordsLevel::Word)
+    };
+
+    let decode = params
+        .decode
+        .as_deref()
+        .map(|s| {
+            DecodeMethod::from_str(s).ok_or_else(|| {
+                OcrError::InvalidRequest(format!(
+                    "Unknown decode method '{}'. Valid: greedy, beam_search",
+                    s
+                ))
+            })
+        })
+        .transpose()?;
+    let (decode, beam_width) = parse_beam_width(decode, params.beam_width)?;
+
+    if params.max_words == Some(0) {
+        return Err(OcrError::InvalidRequest(
+            "max_words must be at least 1".to_string(),
+        ));
+    }
+    let auto_rotate = params.auto_rotate.unwrap_or(false);
+    if auto_rotate && !matches!(format, OutputFormat::Json | OutputFormat::Text) {
+        return Err(OcrError::InvalidRequest(format!(
+            "format={} places text on the uploaded image, auto_rotate is not supported",
+            format.as_str()
+        )));
+    }
+
+    Ok(OcrOptions {
+        model,
+        coords_level,
+        decode,
+        beam_width,
+        lines: params.lines.unwrap_or(false),
+        languages,
+        psm: params.psm,
+        char_whitelist,
+        char_blacklist,
+        max_words: params.max_words,
+        skip_confidence: !params.confidence.unwrap_or(true),
+        word_confidence: params.word_confidence.unwrap_or(false)
+            && params.confidence != Some(false),
+        // A broken text layer is replaced, so embedded text can't be trusted,
+        // and verifying needs the images' text to compare with
+        force_ocr: format == OutputFormat::Pdf || params.pdf_verify.unwrap_or(false),
+        first_page_only: params.first_page_only.unwrap_or(false),
+        polygons,
+        detect_orientation: auto_rotate,
+        page_threads,
+    })
+}
+
+/// Render a result as a document describing the uploaded image, in the
+/// PAGE-XML, hOCR, ALTO or TSV `format`
+///
+/// PAGE-XML and ALTO define confidences as 0.0-1.0 and hOCR and TSV as
+/// percentages, so they are never rescaled.
+fn page_response(
+    response: &OcrResponse,
+    format: OutputFormat,
+    width: u32,
+    height: u32,
+) -> Response {
+    let (content_type, body) = match format {
+        OutputFormat::Hocr => (hocr::CONTENT_TYPE, hocr::render(response, width, height)),
+        OutputFormat::Alto => (alto::CONTENT_TYPE, alto::render(response, width, height)),
+        OutputFormat::Tsv => (tsv::CONTENT_TYPE, tsv::render(response, width, height)),
+        _ => (
+            page_xml::CONTENT_TYPE,
+            page_xml::render(response, width, height, std::time::SystemTime::now()),
+        ),
+    };
+    ([(header::CONTENT_TYPE, content_type)], body).into_response()
+}
+
+/// Respond with the uploaded PDF, with the result's words added as an
+/// invisible text layer
+///
+/// The PDF is repaired and decrypted again like it was for OCR, with
+/// `lenient` and `password`.
+async fn searchable_pdf_response(
+    response: OcrResponse,
+    data: Bytes,
+    lenient: bool,
+    password: Option<String>,
+) -> Result<Response, OcrError> {
+    let words = response.boxes.map(|boxes| boxes.boxes).unwrap_or_default();
+    let mut warnings = response.warnings;
+    // Parsing and rewriting a long PDF takes a while, so keep it off the
+    // async workers
+    let (pdf, warnings) = tokio::task::spawn_blocking(move || {
+        // Its warnings were reported when the PDF was prepared for OCR
+        let prepared = prepare_pdf(&data, lenient, password.as_deref(), &mut Vec::new())?;
+        let (pdf, layer_warnings) =
+            pdf_text_layer::embed(prepared.as_deref().unwrap_or(&data), &words)?;
+        warnings.extend(layer_warnings);
+        Ok::<_, OcrError>((pdf, warnings))
+    })
+    .await
+    .map_err(|e| OcrError::Internal(format!("Text layer task failed: {}", e)))??;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(pdf_text_layer::CONTENT_TYPE),
+    );
+    if let Some(value) = warnings_header(&warnings) {
+        headers.insert(HeaderName::from_static("x-ocr-warnings"), value);
+    }
+    Ok((headers, pdf).into_response())
+}
+
+/// Content type of `format=text` responses
+const TEXT_CONTENT_TYPE: &str = "text/plain; charset=utf-8";
+
+/// Build a `format=text` response: the recognized text as the body, with the
+/// confidence and warnings moved to headers
+///
+/// `X-OCR-Warnings` holds the warnings as a JSON array of strings, since
+/// warnings can contain commas. Both headers are left out when empty.
+fn text_response(response: OcrResponse) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(TEXT_CONTENT_TYPE),
+    );
+    if let Some(confidence) = response.confidence {
+        if let Ok(value) = HeaderValue::from_str(&confidence.to_string()) {
+            headers.insert(HeaderName::from_static("x-ocr-confidence"), value);
+        }
+    }
+    if let Some(value) = warnings_header(&response.warnings) {
+        headers.insert(HeaderName::from_static("x-ocr-warnings"), value);
+    }
+    (headers, response.text).into_response()
+}
+
+/// Warnings as a JSON array for the `X-OCR-Warnings` header, None if there
+/// are none
+fn warnings_header(warnings: &[String]) -> Option<HeaderValue> {
+    if warnings.is_empty() {
+        return None;
+    }
+    let warnings = serde_json::to_string(warnings).unwrap_or_default();
+    // JSON escapes control characters, so this only fails on DEL
+    HeaderValue::from_bytes(warnings.as_bytes()).ok()
+}
+
+/// Hash of the options a stored result depends on, normalized so requests
+/// that differ only in spelling out defaults share a result
+fn result_options(
+    config: &Config,
+    engine: &str,
+    languages: Option<&str>,
+    params: &OcrQueryParams,
+) -> Result<String, OcrError> {
+    let mut options = match serde_json::to_value(params) {
+        Ok(serde_json::Value::Object(options)) => options,
+        _ => {
+            return Err(OcrError::Internal(
+                "Failed to normalize options".to_string(),
+            ))
+        }
+    };
+    // Only plain JSON results are stored, so the output options are the same
+    options.retain(|name, value| !value.is_null() && name != "format" && name != "stream");
+    options.insert("engine".into(), engine.into());
+    options.insert(
+        "languages".into(),
+        languages.unwrap_or(&config.default_language).into(),
+    );
+    let preprocessing = params.preprocessing.parse(Preset::default())?;
+    if preprocessing.steps.is_none() {
+        options.insert("preprocess".into(), preprocessing.preset.as_str().into());
+    }
+    let scale = parse_confidence_scale(params.confidence_scale.as_deref())?;
+    options.insert("confidence_scale".into(), scale.into());
+    // Keys are sorted, so equal options serialize the same
+    Ok(result_store::options_hash(
+        &serde_json::Value::Object(options).to_string(),
+    ))
+}
+
+/// Persist a result, logging rather than failing the request if that fails
+async fn store_result(store: ResultStore, hash: String, options: String, response: &OcrResponse) {
+    let json = match serde_json::to_vec(response) {
+        Ok(json) => json,
+        Err(e) => {
+            tracing::warn!("Failed to serialize result {}: {}", hash, e);
+            return;
+        }
+    };
+    match tokio::task::spawn_blocking(move || store.put(&hash, &options, &json)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => tracing::warn!("{}", e),
+        Err(e) => tracing::warn!("Failed to store result: {}", e),
+    }
+}
+
+/// Engine and languages a stored result was requested with, alongside the
+/// OCR query parameters
+#[derive(Debug, Deserialize, Default)]
+pub struct ResultQueryParams {
+    /// Engine the result was produced with (default: the default engine)
+    #[serde(default)]
+    pub engine: Option<String>,
+    /// Tesseract languages the result was produced with (default: the
+    /// default language)
+    #[serde(default)]
+    pub languages: Option<String>,
+}
+
+/// Handle requests for a stored result
+///
+/// Results are looked up with the options of the request that produced
+/// them, sent as query parameters.
+async fn handle_result(
+    State(state): State<AppState>,
+    Path(hash): Path<String>,
+    Query(selection): Query<ResultQueryParams>,
+    Query(params): Query<OcrQueryParams>,
+) -> Result<Response, OcrError> {
+    let snapshot = state.snapshot();
+    let Some(dir) = snapshot.config.result_store_dir.clone() else {
+        return Err(OcrError::NotFound("result store is disabled".to_string()));
+    };
+    let engine = selection
+        .engine
+        .as_deref()
+        .unwrap_or(snapshot.registry.default_name());
+    let languages = selection
+        .languages
+        .as_deref()
+        .map(str::trim)
+        .filter(|languages| !languages.is_empty());
+    let options = result_options(&snapshot.config, engine, languages, &params)?;
+
+    let store = ResultStore::new(dir);
+    let lookup = hash.clone();
+    let result = tokio::task::spawn_blocking(move || store.get(&lookup, &options))
+        .await
+        .map_err(|e| OcrError::Internal(format!("Result lookup failed: {}", e)))??;
+
+    match result {
+        Some(json) => Ok(([(header::CONTENT_TYPE, "application/json")], json).into_response()),
+        None => Err(OcrError::NotFound(format!("no stored result for {}", hash))),
+    }
+}
+
+/// Parse a recognition model, checking the engine supports it
+fn parse_model(model: Option<&str>, engine: &dyn OcrEngine) -> Result<RecognitionModel, OcrError> {
+    let model = model
+        .map(|s| {
+            RecognitionModel::from_str(s).ok_or_else(|| {
+                OcrError::InvalidRequest(format!(
+                    "Unknown model '{}'. Valid: default, handwriting",
+                    s
+                ))
+            })
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    if !engine.supported_models().contains(&model) {
+        return Err(OcrError::InvalidRequest(format!(
+            "Engine '{}' does not support the '{}' model",
+            engine.name(),
+            model.as_str()
+        )));
+    }
+    Ok(model)
+}
+
+/// Validate a requested character whitelist or blacklist
+///
+/// Tesseract would apply both, so only one may be given.
+fn parse_char_filter(
+    whitelist: Option<&str>,
+    blacklist: Option<&str>,
+) -> Result<(Option<String>, Option<String>), OcrError> {
+    if whitelist.is_some() && blacklist.is_some() {
+        return Err(OcrError::InvalidRequest(
+            "whitelist and blacklist can't be combined".to_string(),
+        ));
+    }
+    for (name, chars) in [("whitelist", whitelist), ("blacklist", blacklist)] {
+        match chars {
+            Some("") => {
+                return Err(OcrError::InvalidRequest(format!(
+                    "{} must list at least one character",
+                    name
+                )))
+            }
+            Some(chars) if chars.chars().any(char::is_control) => {
+                return Err(OcrError::InvalidRequest(format!(
+                    "{} can't contain control characters",
+                    name
+                )))
+            }
+            _ => {}
+        }
+    }
+    Ok((whitelist.map(str::to_string), blacklist.map(str::to_string)))
+}
+
+/// Validate a requested beam width, which selects beam search decoding
+/// unless another decode method was asked for
+fn parse_beam_width(
+    decode: Option<DecodeMethod>,
+    beam_width: Option<u32>,
+) -> Result<(Option<DecodeMethod>, Option<u32>), OcrError> {
+    let Some(width) = beam_width else {
+        return Ok((decode, None));
+    };
+    check_beam_width(width)?;
+    match decode {
+        Some(DecodeMethod::Greedy) => Err(OcrError::InvalidRequest(
+            "beam_width requires decode=beam_search".to_string(),
+        )),
+        _ => Ok((Some(DecodeMethod::BeamSearch), Some(width))),
+    }
+}
+
+/// Check a beam width is in the accepted range
+fn check_beam_width(width: u32) -> Result<(), OcrError> {
+    if (1..=MAX_BEAM_WIDTH).contains(&width) {
+        Ok(())
+    } else {
+        Err(OcrError::InvalidRequest(format!(
+            "Invalid beam_width {}. Valid: 1-{}",
+            width, MAX_BEAM_WIDTH
+        )))
+    }
+}
+
+/// Format of an OCR response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Json,
+    /// PAGE-XML document
+    PageXml,
+    /// The uploaded PDF with an invisible OCR text layer
+    Pdf,
+    /// hOCR document
+    Hocr,
+    /// ALTO v4 document
+    Alto,
+    /// Tesseract-style tab-separated values
+    Tsv,
+    /// The recognized text alone
+    Text,
+}
+
+impl OutputFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::PageXml => "page",
+            Self::Pdf => "pdf",
+            Self::Hocr => "hocr",
+            Self::Alto => "alto",
+            Self::Tsv => "tsv",
+            Self::Text => "text",
+        }
+    }
+}
+
+/// Parse the response format
+fn parse_format(format: Option<&str>) -> Result<OutputFormat, OcrError> {
+    match format {
+        None | Some("json") => Ok(OutputFormat::Json),
+        Some("page") => Ok(OutputFormat::PageXml),
+        Some("pdf") => Ok(OutputFormat::Pdf),
+        Some("hocr") => Ok(OutputFormat::Hocr),
+        Some("alto") => Ok(OutputFormat::Alto),
+        Some("tsv") => Ok(OutputFormat::Tsv),
+        Some("text") => Ok(OutputFormat::Text),
+        Some(other) => Err(OcrError::InvalidRequest(format!(
+            "Unknown format '{}'. Valid: json, page, pdf, hocr, alto, tsv, text",
+            other
+        ))),
+    }
+}
+
+/// Parse the preprocessing stats detail, returning true for detailed stats
+fn parse_timing(timing: Option<&str>) -> Result<bool, OcrError> {
+    match timing {
+        None | Some("basic") => Ok(false),
+        Some("detailed") => Ok(true),
+        Some(other) => Err(OcrError::InvalidRequest(format!(
+            "Unknown timing '{}'. Valid: basic, detailed",
+            other
+        ))),
+    }
+}
+
+/// Parse the confidence scale, returning the factor confidences are multiplied by
+fn parse_confidence_scale(scale: Option<&str>) -> Result<f32, OcrError> {
+    match scale {
+        None | Some("fraction") => Ok(1.0),
+        Some("percent") => Ok(100.0),
+        Some(other) => Err(OcrError::InvalidRequest(format!(
+            "Unknown confidence_scale '{}'. Valid: fraction, percent",
+            other
+        ))),
+    }
+}
+
+/// Frames of an animated GIF or WebP, or pages of a TIFF, to OCR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameSelection {
+    /// A single frame, counting from 0
+    Index(usize),
+    /// Every frame, reported as separate pages
+    All,
+    /// The frame with the most detected words
+    Auto,
+}
+
+/// Parse an animation frame or TIFF page selection
+fn parse_frame(frame: Option<&str>) -> Result<Option<FrameSelection>, OcrError> {
+    frame
+        .map(|frame| match frame {
+            "all" => Ok(FrameSelection::All),
+            "auto" => Ok(FrameSelection::Auto),
+            index => index.parse().map(FrameSelection::Index).map_err(|_| {
+                OcrError::InvalidRequest(format!(
+                    "Invalid frame '{}'. Valid: a frame index, all, auto",
+                    index
+                ))
+            }),
+        })
+        .transpose()
+}
+
+/// Parse mask rectangles, given as `x,y,width,height` separated by `;`
+fn parse_mask(mask: Option<&str>) -> Result<Vec<Region>, OcrError> {
+    let Some(mask) = mask else {
+        return Ok(Vec::new());
+    };
+    mask.split(';')
+        .map(|rect| {
+            let values: Option<Vec<u32>> = rect
+                .split(',')
+                .map(|value| value.trim().parse().ok())
+                .collect();
+            match values.as_deref() {
+                Some(&[x, y, width, height]) if width > 0 && height > 0 => Ok(Region {
+                    x,
+                    y,
+                    width,
+                    height,
+                }),
+                _ => Err(OcrError::InvalidRequest(format!(
+                    "Invalid mask '{}'. Expected x,y,width,height rectangles separated by ';'",
+                    rect
+                ))),
+            }
+        })
+        .collect()
+}
+
+/// Fill the masked rectangles with the image's background color, so no
+/// text inside them can be recognized
+///
+/// Parts of rectangles outside the image are ignored.
+fn apply_mask(image: &mut DynamicImage, mask: &[Region]) {
+    if mask.is_empty() {
+        return;
+    }
+    let background = background_color(image);
+    let (width, height) = image.dimensions();
+    for rect in mask {
+        let right = rect.x.saturating_add(rect.width).min(width);
+        let bottom = rect.y.saturating_add(rect.height).min(height);
+        for y in rect.y..bottom {
+            for x in rect.x..right {
+                image.put_pixel(x, y, background);
+            }
+        }
+    }
+}
+
+/// Median color of the pixels along the image's edges, which on documents
+/// is almost always the paper
+fn background_color(image: &DynamicImage) -> Rgba<u8> {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return Rgba([255; 4]);
+    }
+    let edge: Vec<Rgba<u8>> = (0..width)
+        .flat_map(|x| [(x, 0), (x, height - 1)])
+        .chain((0..height).flat_map(|y| [(0, y), (width - 1, y)]))
+        .map(|(x, y)| image.get_pixel(x, y))
+        .collect();
+    Rgba(std::array::from_fn(|channel| {
+        let mut values: Vec<u8> = edge.iter().map(|pixel| pixel[channel]).collect();
+        let middle = values.len() / 2;
+        *values.select_nth_unstable(middle).1
+    }))
+}
+
+/// Cut text off after `max_words` words, returning whether anything was cut
+fn truncate_words(text: &mut String, max_words: usize) -> bool {
+    let end = {
+        let mut words = text.split_whitespace();
+        match words.nth(max_words.saturating_sub(1)) {
+            Some(last) if words.next().is_some() => {
+                last.as_ptr() as usize - text.as_ptr() as usize + last.len()
+            }
+            _ => return false,
+        }
+    };
+    text.truncate(end);
+    true
+}
+
+/// A parsed OCR request, ready to run
+struct OcrJob {
+    start: Instant,
+    engine: Arc<dyn OcrEngine>,
+    fallback: Option<Arc<dyn OcrEngine>>,
+    data: Bytes,
+    is_pdf: bool,
+    /// DOCX or ODT document whose embedded images are OCRed
+    is_document: bool,
+    /// MIME type the upload is processed as, reported in the response
+    detected_format: String,
+    /// Filename the file part was uploaded with, reported in the response
+    filename: Option<String>,
+    /// Limit on the total size of images extracted from a document
+    max_file_size: usize,
+    /// Most warnings returned (None returns all)
+    max_warnings: Option<usize>,
+    pdf_password: Option<String>,
+    pdf_lenient: bool,
+    /// Preprocessing of images, including their rotation
+    preprocessing: PreprocessingOptions,
+    /// Sharpness above which the sharpen step is skipped
+    sharpen_skip_threshold: Option<f32>,
+    /// Return the engine's unmodified text alongside the cleaned text
+    include_raw: bool,
+    probe: bool,
+    probe_max_dimension: u32,
+    split: bool,
+    /// Crop single images to their text block before OCR
+    autocrop: bool,
+    /// Record each preprocessing step's peak memory
+    detailed_timing: bool,
+    /// Frames of an animation or pages of a TIFF to OCR (None uses the
+    /// first frame, or every TIFF page)
+    frame: Option<FrameSelection>,
+    /// Rectangles blanked out of every image before OCR
+    mask: Vec<Region>,
+    /// Compare the OCR text of a PDF with its embedded text
+    pdf_verify: bool,
+    options: OcrOptions,
+    /// Warnings about how the request was handled, added to the result
+    request_warnings: Vec<String>,
+    /// Keeps the request counted as in flight until the job is done
+    in_flight: InFlightOcr,
+    /// OCR slot, held until the job is done even if the request gave up on
+    /// it, so abandoned engine calls still count toward the limit
+    slot: OwnedSemaphorePermit,
+    /// Where the job's timings are recorded
+    metrics: Arc<Metrics>,
+}
+
+/// Run OCR for a parsed request, calling `on_page` as each page completes
+fn run_ocr(job: OcrJob, on_page: &dyn Fn(u32, &OcrResult)) -> Result<OcrResponse, OcrError> {
+    let mut regions = None;
+    let mut pdf_verification = None;
+
+    let (mut result, engine_name, preprocessing_stats) = if job.is_pdf {
+        let (result, engine, verification) = ocr_pdf(&job, on_page)?;
+        pdf_verification = verification;
+        (result, engine, None) // No preprocessing for PDFs
+    } else {
+        let ImageOcr {
+            result,
+            stats,
+            engine,
+        } = if job.is_document {
+            ocr_uploaded_document(&job, on_page)?
+        } else {
+            let (ocr, found) = ocr_uploaded_image(&job, on_page)?;
+            regions = found;
+            ocr
+        };
+        // Only report preprocessing stats when something ran
+        let stats = (job.probe || job.preprocessing.runs()).then_some(stats);
+        (result, engine, stats)
+    };
+
+    let OcrJob {
+        start,
+        detected_format,
+        filename,
+        max_warnings,
+        include_raw,
+        options,
+        request_warnings,
+        in_flight: _in_flight,
+        slot: _slot,
+        metrics,
+        ..
+    } = job;
+
+    let processing_time_ms = start.elapsed().as_millis() as u64;
+
+    let preprocess_time = preprocessing_stats
+        .as_ref()
+        .map(|s| s.total_time_ms)
+        .unwrap_or(0);
+
+    tracing::info!(
+        "[{}] OCR completed in {}ms (preprocessing: {}ms), confidence: {:.2}, text length: {}",
+        engine_name,
+        processing_time_ms,
+        preprocess_time,
+        result.confidence,
+        result.text.len()
+    );
+    metrics.observe(
+        &engine_name,
+        processing_time_ms,
+        preprocessing_stats.as_ref().map(|s| s.total_time_ms),
+    );
+
+    // Engines may recognize past the limit, or ignore it altogether
+    let truncated = options
+        .max_words
+        .map(|max_words| truncate_words(&mut result.text, max_words));
+
+    // Server-side cleanup of the text goes after this point, so raw_text
+    // stays what the engine produced
+    let raw_text = include_raw.then(|| result.text.clone());
+    let no_text_detected = result.text.trim().is_empty();
+    let mut warnings = request_warnings;
+    warnings.extend(result.warnings);
+    let warnings = condense_warnings(warnings, max_warnings);
+    let with_confidence = !options.skip_confidence;
+    let word_confidences = result.word_scores.map(|scores| {
+        scores
+            .into_iter()
+            .take(options.max_words.unwrap_or(usize::MAX))
+            .map(|(text, confidence)| WordConfidenceResponse { text, confidence })
+            .collect()
+    });
+
+    Ok(OcrResponse {
+        schema_version: SCHEMA_VERSION,
+        confidence_stats: with_confidence
+            .then(|| ConfidenceStats::new(result.confidence, result.word_confidences.as_deref())),
+        text: result.text,
+        raw_text,
+        truncated,
+        confidence: with_confidence.then_some(result.confidence),
+        word_confidences,
+        processing_time_ms,
+        warnings,
+        engine: engine_name,
+        detected_format,
+        filename,
+        no_text_detected,
+        orientation_degrees: result.orientation_degrees,
+        preprocessing: preprocessing_stats,
+        boxes: result.boxes.map(BoxesResponse::from),
+        lines: line_responses(result.lines),
+        regions,
+        pdf_verification,
+    })
+}
+
+impl OcrJob {
+    /// Build the job's preprocessing pipeline, adding a warning for each
+    /// option that doesn't apply to it
+    fn pipeline(&self, warnings: &mut Vec<String>) -> Pipeline {
+        self.preprocessing.pipeline(
+            self.sharpen_skip_threshold,
+            self.probe.then_some(self.probe_max_dimension),
+            self.detailed_timing,
+            warnings,
+        )
+    }
+}
+
+/// OCR an uploaded PDF, returning the result, the engine that produced it
+/// and, with pdf_verify, how well the PDF's embedded text matches
+fn ocr_pdf(
+    job: &OcrJob,
+    on_page: &dyn Fn(u32, &OcrResult),
+) -> Result<(OcrResult, String, Option<PdfVerification>), OcrError> {
+    // Engines read PDFs from a path
+    use std::io::Write;
+
+    let mut pdf_warnings = Vec::new();
+    let prepared = prepare_pdf(
+        &job.data,
+        job.pdf_lenient,
+        job.pdf_password.as_deref(),
+        &mut pdf_warnings,
+    )?;
+    let pdf_data = prepared.as_deref().unwrap_or(&job.data);
+
+    let mut temp_file = tempfile::Builder::new()
+        .suffix(".pdf")
+        .tempfile()
+        .map_err(|e| OcrError::Internal(format!("Failed to create temp file: {}", e)))?;
+
+    temp_file
+        .write_all(pdf_data)
+        .map_err(|e| OcrError::Internal(format!("Failed to write temp file: {}", e)))?;
+
+    let options = &job.options;
+    let engine = job.engine.route(None).unwrap_or_else(|| job.engine.clone());
+    let (mut result, engine_name) =
+        run_with_fallback(&engine, job.fallback.clone(), options, |e| {
+            e.process_pages(temp_file.path(), options, on_page)
+        })?;
+    result.warnings.extend(pdf_warnings);
+
+    let mut verification = None;
+    if job.pdf_verify {
+        match pdf_extract::extract_text(temp_file.path()) {
+            Ok(embedded) => {
+                let verified = verify_pdf_text(embedded.trim(), &result.text);
+                if verified.similarity < PDF_VERIFY_MIN_SIMILARITY {
+                    result.warnings.push(format!(
+                        "Embedded PDF text differs from OCR (similarity {:.2}), its text layer may be corrupt",
+                        verified.similarity
+                    ));
+                }
+                verification = Some(verified);
+            }
+            Err(e) => result.warnings.push(format!(
+                "pdf_verify: failed to extract embedded text: {}",
+                e
+            )),
+        }
+    }
+    if job.probe {
+        result
+            .warnings
+            .push("Probe mode is not supported for PDFs, ran a full pass".to_string());
+    }
+    if job.split {
+        result
+            .warnings
+            .push("split is not supported for PDFs, ignored".to_string());
+    }
+    if job.preprocessing.rotation.is_some() {
+        result
+            .warnings
+            .push("rotation is not supported for PDFs, ignored".to_string());
+    }
+    Ok((result, engine_name, verification))
+}
+
+/// OCR an uploaded image, along with the documents found in it with
+/// split=auto
+///
+/// Every frame of an animation and page of a TIFF that is OCRed is reported
+/// as its own page.
+fn ocr_uploaded_image(
+    job: &OcrJob,
+    on_page: &dyn Fn(u32, &OcrResult),
+) -> Result<(ImageOcr, Option<Vec<RegionOcrResponse>>), OcrError> {
+    let OcrJob {
+        data,
+        engine,
+        fallback,
+        options,
+        frame,
+        ..
+    } = job;
+    let rotation = job.preprocessing.rotation;
+    let mut warnings = Vec::new();
+    let tiff_pages = tiff_page_count(data, &mut warnings);
+    let mut pages: Vec<ImagePage> = match frame {
+        Some(selection) if is_gif(data) || is_animated_webp(data) => select_frames(
+            decode_frames(data, rotation)?,
+            *selection,
+            &mut warnings,
+            |frame| Ok(engine.detect(frame)?.words.len()),
+        )?
+        .into_iter()
+        .map(ImagePage::Decoded)
+        .collect(),
+        // Every page of a multi-page TIFF unless frame picks others
+        _ if tiff_pages.is_some() => select_frames(
+            (0..tiff_pages.unwrap_or_default()).map(Ok),
+            frame.unwrap_or(FrameSelection::All),
+            &mut warnings,
+            |&index| {
+                let page = decode_tiff_page(data, index, rotation, &mut Vec::new())?;
+                Ok(engine.detect(&page)?.words.len())
+            },
+        )?
+        .into_iter()
+        .map(ImagePage::Tiff)
+        .collect(),
+        Some(_) => {
+            warnings
+                .push("frame only applies to GIFs, animated WebPs and TIFFs, ignored".to_string());
+            vec![ImagePage::Decoded(decode_image(
+                data,
+                rotation,
+                &mut warnings,
+            )?)]
+        }
+        None => vec![ImagePage::Decoded(decode_image(
+            data,
+            rotation,
+            &mut warnings,
+        )?)],
+    };
+    let load = |page: ImagePage, warnings: &mut Vec<String>| {
+        let mut image = match page {
+            ImagePage::Decoded(image) => image,
+            ImagePage::Tiff(index) => decode_tiff_page(data, index, rotation, warnings)?,
+        };
+        apply_mask(&mut image, &job.mask);
+        Ok(image)
+    };
+    let paged = pages.len() > 1;
+
+    let mut pipeline = job.pipeline(&mut warnings);
+    // Scans that say they are 300 DPI need no enlarging
+    if let Some(dpi) = resolution::dpi(data) {
+        pipeline = pipeline.input_dpi(dpi);
+    }
+
+    let mut regions = None;
+    let mut ocr = if paged {
+        if job.split {
+            warnings.push("split is not supported with several frames, ignored".to_string());
+        }
+        if job.autocrop {
+            warnings.push("autocrop is not supported with several frames, ignored".to_string());
+        }
+        ocr_images(
+            pages,
+            load,
+            Vec::new(),
+            &pipeline,
+            engine,
+            fallback,
+            options,
+            on_page,
+        )?
+    } else if job.split {
+        if job.autocrop {
+            warnings.push("autocrop is not supported with split=auto, ignored".to_string());
+        }
+        let image = load(pages.remove(0), &mut warnings)?;
+        let (combined, found) = ocr_regions(
+            &image,
+            &document_regions(&image, &mut warnings),
+            &pipeline,
+            engine,
+            fallback,
+            options,
+        )?;
+        regions = Some(found);
+        combined
+    } else {
+        let image = load(pages.remove(0), &mut warnings)?;
+        // OCR the crop as a single region so boxes stay in image coordinates
+        match job.autocrop.then(|| text_block(&image)).flatten() {
+            Some(block) => ocr_regions(&image, &[block], &pipeline, engine, fallback, options)?.0,
+            None => ocr_image(image, &pipeline, engine, fallback, options)?,
+        }
+    };
+    ocr.result.warnings.extend(warnings);
+
+    if job.probe {
+        ocr.result
+            .warnings
+            .push("Probe mode: low-resolution pass, confidence is a rough estimate".to_string());
+    }
+    if !paged {
+        on_page(1, &ocr.result);
+    }
+    Ok((ocr, regions))
+}
+
+/// Fail a result whose overall confidence, multiplied by
+/// `confidence_factor`, is below `min_confidence`
+fn check_min_confidence(
+    response: OcrResponse,
+    min_confidence: Option<f32>,
+    confidence_factor: f32,
+) -> Result<OcrResponse, OcrError> {
+    let confidence = response.confidence.unwrap_or_default() * confidence_factor;
+    match min_confidence {
+        Some(required) if confidence < required => Err(OcrError::LowConfidence {
+            confidence,
+            required,
+            text: response.text,
+        }),
+        _ => Ok(response),
+    }
+}
+
+/// Merge warnings that differ only in their numbers, such as one for each
+/// image of a PDF that failed, and keep at most `max` of them
+///
+/// Merged warnings keep the first one's text with a count of the others.
+/// Dropped warnings are replaced by a note saying how many were left out.
+fn condense_warnings(warnings: Vec<String>, max: Option<usize>) -> Vec<String> {
+    let mut groups: Vec<(String, usize)> = Vec::new();
+    let mut by_shape: HashMap<String, usize> = HashMap::new();
+    for warning in warnings {
+        // Every run of digits counts as the same number
+        let mut shape = String::with_capacity(warning.len());
+        for c in warning.chars() {
+            if !c.is_ascii_digit() {
+                shape.push(c);
+            } else if !shape.ends_with('#') {
+                shape.push('#');
+            }
+        }
+        match by_shape.get(&shape) {
+            Some(&index) => groups[index].1 += 1,
+            None => {
+                by_shape.insert(shape, groups.len());
+                groups.push((warning, 1));
+            }
+        }
+    }
+
+    let mut condensed: Vec<String> = groups
+        .into_iter()
+        .map(|(warning, count)| match count {
+            1 => warning,
+            _ => format!("{} (and {} more like it)", warning, count - 1),
+        })
+        .collect();
+    if let Some(max) = max.filter(|max| condensed.len() > *max) {
+        let omitted = condensed.len() - max;
+        condensed.truncate(max);
+        condensed.push(format!("{} more warnings omitted", omitted));
+    }
+    condensed
+}
+
+/// Similarity below which embedded PDF text is reported as untrustworthy
+const PDF_VERIFY_MIN_SIMILARITY: f32 = 0.8;
+
+/// Compare a PDF's embedded text with the text OCR found in its images
+fn verify_pdf_text(embedded_text: &str, ocr_text: &str) -> PdfVerification {
+    PdfVerification {
+        similarity: text_similarity(embedded_text, ocr_text),
+        embedded_text: embedded_text.to_string(),
+    }
+}
+
+/// Character similarity of two texts from 0.0 (nothing shared) to 1.0
+///
+/// The Dice coefficient of their character pairs, ignoring case and
+/// differences in whitespace. Unlike an edit distance it takes linear time,
+/// so whole documents can be compared.
+fn text_similarity(a: &str, b: &str) -> f32 {
+    let bigrams = |text: &str| {
+        let chars: Vec<char> = text
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+            .chars()
+            .collect();
+        let mut counts: HashMap<(char, char), usize> = HashMap::new();
+        for pair in chars.windows(2) {
+            *counts.entry((pair[0], pair[1])).or_default() += 1;
+        }
+        (chars, counts)
+    };
+    let (a_chars, a) = bigrams(a);
+    let (b_chars, b) = bigrams(b);
+
+    let total: usize = a.values().sum::<usize>() + b.values().sum::<usize>();
+    if total == 0 {
+        // Too short to have pairs, so they either match or they don't
+        return if a_chars == b_chars { 1.0 } else { 0.0 };
+    }
+    let shared: usize = a
+        .iter()
+        .map(|(pair, count)| (*count).min(b.get(pair).copied().unwrap_or(0)))
+        .sum();
+    2.0 * shared as f32 / total as f32
+}
+
+/// Result of OCRing one image, or one region of it
+struct ImageOcr {
+    result: OcrResult,
+    stats: PreprocessingStats,
+    /// Engine that produced the result
+    engine: String,
+}
+
+/// Preprocess and OCR a single image
+fn ocr_image(
+    image: DynamicImage,
+    pipeline: &Pipeline,
+    engine: &Arc<dyn OcrEngine>,
+    fallback: &Option<Arc<dyn OcrEngine>>,
+    options: &OcrOptions,
+) -> Result<ImageOcr, OcrError> {
+    let (original_width, original_height) = (image.width(), image.height());
+    let preprocess_result = pipeline
+        .process(image)
+        .map_err(|e| OcrError::PreprocessingError(format!("Preprocessing failed: {}", e)))?;
+
+    // Perform OCR on preprocessed image
+    let engine = &engine
+        .route(Some(&preprocess_result.image))
+        .unwrap_or_else(|| engine.clone());
+    let (mut result, used_engine) = run_with_fallback(engine, fallback.clone(), options, |e| {
+        e.process_image(&preprocess_result.image, options)
+    })?;
+
+    // Report boxes relative to the uploaded image, not the preprocessed one,
+    // which may have been resized and cropped
+    let source = preprocess_result.source_region.unwrap_or(SourceRegion {
+        x: 0.0,
+        y: 0.0,
+        width: original_width as f32,
+        height: original_height as f32,
+    });
+    let scale_x = source.width / preprocess_result.image.width() as f32;
+    let scale_y = source.height / preprocess_result.image.height() as f32;
+    let (offset_x, offset_y) = (source.x.round() as u32, source.y.round() as u32);
+    if let Some(lines) = result.lines.as_mut() {
+        scale_lines(lines, scale_x, scale_y);
+        for line in lines.iter_mut() {
+            offset_box(&mut line.text_box, offset_x, offset_y);
+        }
+    }
+    if let Some(boxes) = result.boxes.as_mut() {
+        scale_boxes(boxes, scale_x, scale_y);
+        offset_boxes(boxes, offset_x, offset_y);
+        let deskewed = preprocess_result
+            .steps
+            .iter()
+            .any(|step| step.name == "deskew" && !step.skipped);
+        if deskewed {
+            result.warnings.push(
+                "Image was deskewed during preprocessing, box positions are approximate"
+                    .to_string(),
+            );
+        }
+    }
+
+    Ok(ImageOcr {
+        result,
+        stats: PreprocessingStats {
+            preset: preprocess_result.preset,
+            total_time_ms: preprocess_result.total_time_ms,
+            steps: preprocess_result.steps,
+            metadata: preprocess_result.metadata,
+        },
+        engine: used_engine,
+    })
+}
+
+/// Error for an upload whose contents match no supported format
+fn unrecognized_format(declared_mime: &str) -> OcrError {
+    let documents = if cfg!(feature = "documents") {
+        ", DOCX, ODT"
+    } else {
+        ""
+    };
+    OcrError::UnsupportedFormat(format!(
+        "Unrecognized file contents (sent as {}). Supported: PDF, PNG, JPEG, GIF, BMP, WebP, TIFF{}",
+        declared_mime, documents
+    ))
+}
+
+/// OCR several images as the pages of one result, calling `on_page` as each
+/// one completes
+///
+/// Pages are `load`ed and recognized in batches of `options.page_threads`,
+/// like the pages of a PDF, so only one batch of images is in memory at once.
+/// Images that fail to load or OCR are reported in the warnings, which start
+/// out as `warnings`, and left out of the result.
+#[allow(clippy::too_many_arguments)]
+fn ocr_images<T: Send>(
+    pages: Vec<T>,
+    load: impl Fn(T, &mut Vec<String>) -> Result<DynamicImage, OcrError> + Sync,
+    mut warnings: Vec<String>,
+    pipeline: &Pipeline,
+    engine: &Arc<dyn OcrEngine>,
+    fallback: &Option<Arc<dyn OcrEngine>>,
+    options: &OcrOptions,
+    on_page: &dyn Fn(u32, &OcrResult),
+) -> Result<ImageOcr, OcrError> {
+    let mut texts = Vec::new();
+    let mut boxes = options.coords_level.map(|level| TextBoxes {
+        level,
+        boxes: Vec::new(),
+    });
+    let mut lines = options.lines.then(Vec::new);
+    // Only kept if every image has per-word confidences
+    let mut word_confidences = Some(Vec::new());
+    let mut word_scores = options.word_confidence.then(Vec::new);
+    let mut stats: Option<PreprocessingStats> = None;
+    let mut engine_name = engine.name().to_string();
+    let (mut total_confidence, mut confidence_count) = (0.0, 0);
+
+    let recognize = |_, page: T| {
+        let mut page_warnings = Vec::new();
+        let ocr = load(page, &mut page_warnings)
+            .and_then(|image| ocr_image(image, pipeline, engine, fallback, options));
+        (page_warnings, ocr)
+    };
+    recognize_pages(
+        pages,
+        options.page_threads,
+        recognize,
+        |i, (page_warnings, ocr)| {
+            let page = i as u32 + 1;
+            for warning in page_warnings {
+                if !warnings.contains(&warning) {
+                    warnings.push(warning);
+                }
+            }
+            let ImageOcr {
+                mut result,
+                stats: image_stats,
+                engine: used_engine,
+            } = match ocr {
+                Ok(ocr) => ocr,
+                Err(e) => {
+                    warnings.push(format!("Failed to OCR image {}: {}", page, e));
+                    return ControlFlow::Continue(());
+                }
+            };
+
+            if used_engine != engine.name() {
+                engine_name = used_engine;
+            }
+            stats = Some(match stats.take() {
+                Some(total) => merge_stats(total, image_stats),
+                None => image_stats,
+            });
+
+            // Number boxes by the image they came from
+            if let Some(found) = result.boxes.as_mut() {
+                found.boxes.iter_mut().for_each(|b| b.page = page);
+            }
+            for line in result.lines.iter_mut().flatten() {
+                line.text_box.page = page;
+            }
+            on_page(page, &result);
+
+            for warning in result.warnings {
+                if !warnings.contains(&warning) {
+                    warnings.push(warning);
+                }
+            }
+            if let (Some(all), Some(found)) = (boxes.as_mut(), result.boxes) {
+                all.level = found.level;
+                all.boxes.extend(found.boxes);
+            }
+            if let (Some(all), Some(found)) = (lines.as_mut(), result.lines) {
+                all.extend(found);
+            }
+            if let (Some(all), Some(found)) = (word_scores.as_mut(), result.word_scores) {
+                all.extend(found);
+            }
+            word_confidences =
+                word_confidences
+                    .take()
+                    .zip(result.word_confidences)
+                    .map(|(mut all, found)| {
+                        all.extend(found);
+                        all
+                    });
+            if !result.text.is_empty() {
+                texts.push(result.text);
+                total_confidence += result.confidence;
+                confidence_count += 1;
+            }
+            ControlFlow::Continue(())
+        },
+    );
+
+    let confidence = if confidence_count > 0 {
+        total_confidence / confidence_count as f32
+    } else {
+        0.0
+    };
+
+    Ok(ImageOcr {
+        result: OcrResult {
+            text: texts.join("\n\n"),
+            confidence,
+            warnings,
+            boxes,
+            word_confidences,
+            word_scores,
+            lines,
+            orientation_degrees: None,
+        },
+        stats: stats.unwrap_or(PreprocessingStats {
+            preset: String::new(),
+            total_time_ms: 0,
+            steps: Vec::new(),
+            metadata: BTreeMap::new(),
+        }),
+        engine: engine_name,
+    })
+}
+
+/// Small image with a line of text, used to warm up engines at startup
+const WARMUP_IMAGE: &[u8] = include_bytes!("../../assets/warmup.png");
+
+/// Run the bundled warmup image through an engine the way a request would,
+/// returning how long it took
+fn warm_up(engine: &dyn OcrEngine) -> Result<std::time::Duration, OcrError> {
+    let start = Instant::now();
+    let image = decode_image(WARMUP_IMAGE, None, &mut Vec::new())?;
+    let preprocessed = Pipeline::new(Preset::Default).process(image)?;
+    engine.process_image(&preprocessed.image, &OcrOptions::default())?;
+    Ok(start.elapsed())
+}
+
+/// Reject images too small to contain readable text
+///
+/// An image is only too small when both sides are under `min`, so a single
+/// wide, short line of text is still read. Only the image header is read. Images whose size can't be determined are
+/// left for the decoder to report.
+fn check_min_dimensions(data: &[u8], min: u32) -> Result<(), OcrError> {
+    if min == 0 {
+        return Ok(());
+    }
+
+    match image_dimensions(data) {
+        Some((width, height)) if width < min && height < min => {
+            Err(OcrError::InvalidRequest(format!(
+                "Image too small for OCR: {}x{} pixels (at least one side must be {} pixels)",
+                width, height, min
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Read an image's EXIF orientation, if it has one
+fn exif_orientation(data: &[u8]) -> Option<Orientation> {
+    image::ImageReader::new(std::io::Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?
+        .orientation()
+        .ok()
+}
+
+/// Whether an orientation turns the image on its side
+fn swaps_axes(orientation: Orientation) -> bool {
+    matches!(
+        orientation,
+        Orientation::Rotate90
+            | Orientation::Rotate270
+            | Orientation::Rotate90FlipH
+            | Orientation::Rotate270FlipH
+    )
+}
+
+/// Read an image's width and height from its header
+fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    image::ImageReader::new(std::io::Cursor::new(data))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok())
+}
+
+/// Repair (with `lenient`) and decrypt (with a `password`) an uploaded PDF
+///
+/// Returns the PDF to process, or None if the upload can be used as it is.
+fn prepare_pdf(
+    data: &[u8],
+    lenient: bool,
+    password: Option<&str>,
+    warnings: &mut Vec<String>,
+) -> Result<Option<Vec<u8>>, OcrError> {
+    let repaired = if lenient {
+        pdf_repair::repair_pdf(data)?
+    } else {
+        None
+    };
+    if repaired.is_some() {
+        warnings.push("PDF was malformed, its cross-reference table was rebuilt".to_string());
+    }
+    let data = repaired.as_deref().unwrap_or(data);
+
+    let decrypted = match password {
+        Some(password) => {
+            let decrypted = pdf_repair::decrypt_pdf(data, password)?;
+            if decrypted.is_none() {
+                warnings.push("pdf_password was provided but the PDF is not encrypted".to_string());
+            }
+            decrypted
+        }
+        None => None,
+    };
+    Ok(decrypted.or(repaired))
+}
+
+/// Run OCR with the requested engine, retrying with the configured fallback
+/// engine if it fails with a processing error
+///
+/// Returns the result along with the name of the engine that produced it.
+fn run_with_fallback<F>(
+    engine: &Arc<dyn OcrEngine>,
+    fallback: Option<Arc<dyn OcrEngine>>,
+    options: &OcrOptions,
+    run: F,
+) -> Result<(OcrResult, String), OcrError>
+where
+    F: Fn(&dyn OcrEngine) -> Result<OcrResult, OcrError>,
+{
+    let error = match run(engine.as_ref()) {
+        Ok(result) => return Ok((result, engine.name().to_string())),
+        Err(error @ OcrError::ProcessingError(_)) => error,
+        Err(error) => return Err(error),
+    };
+
+    let fallback = fallback
+        .filter(|fallback| fallback.name() != engine.name())
+        .filter(|fallback| fallback.supported_models().contains(&options.model));
+    let Some(fallback) = fallback else {
+        return Err(error);
+    };
+
+    tracing::warn!(
+        "[{}] OCR failed ({}), retrying with fallback engine {}",
+        engine.name(),
+        error,
+        fallback.name()
+    );
+
+    let mut result = run(fallback.as_ref())?;
+    result.warnings.push(format!(
+        "Engine '{}' failed ({}), used fallback engine '{}'",
+        engine.name(),
+        error,
+        fallback.name()
+    ));
+
+    Ok((result, fallback.name().to_string()))
+}
+
+/// Scale box coordinates, e.g. back to the original image size after resizing
+fn scale_boxes(boxes: &mut TextBoxes, scale_x: f32, scale_y: f32) {
+    for b in &mut boxes.boxes {
+        scale_box(b, scale_x, scale_y);
+    }
+}
+
+/// Scale line coordinates, adjusting the baseline angle when the axes are
+/// scaled differently
+fn scale_lines(lines: &mut [LineBox], scale_x: f32, scale_y: f32) {
+    for line in lines {
+        scale_box(&mut line.text_box, scale_x, scale_y);
+        let angle = line.baseline_angle.to_radians();
+        line.baseline_angle = (angle.sin() * scale_y)
+            .atan2(angle.cos() * scale_x)
+            .to_degrees();
+    }
+}
+
+fn scale_box(b: &mut TextBox, scale_x: f32, scale_y: f32) {
+    b.x = (b.x as f32 * scale_x).round() as u32;
+    b.y = (b.y as f32 * scale_y).round() as u32;
+    b.width = (b.width as f32 * scale_x).round() as u32;
+    b.height = (b.height as f32 * scale_y).round() as u32;
+    for [px, py] in b.polygon.iter_mut().flatten() {
+        *px *= scale_x;
+        *py *= scale_y;
+    }
+}
+
+/// Shift boxes found in a cropped region back into the full image
+fn offset_boxes(boxes: &mut TextBoxes, x: u32, y: u32) {
+    for b in &mut boxes.boxes {
+        offset_box(b, x, y);
+    }
+}
+
+fn offset_box(b: &mut TextBox, x: u32, y: u32) {
+    b.x += x;
+    b.y += y;
+    for [px, py] in b.polygon.iter_mut().flatten() {
+        *px += x as f32;
+        *py += y as f32;
+    }
+}
+
+/// Decode an uploaded image
+///
+/// Handles every format the `image` crate is built with, including lossless
+/// and extended (VP8X) WebP. Animated and multi-page images are decoded to
+/// their first frame; [`decode_frames`] reaches the others of a GIF or WebP
+/// and [`decode_tiff_page`] those of a TIFF.
+fn decode_image(
+    data: &[u8],
+    rotation: Option<Orientation>,
+    warnings: &mut Vec<String>,
+) -> Result<DynamicImage, OcrError> {
+    let load_error =
+        |e: image::ImageError| OcrError::PreprocessingError(format!("Failed to load image: {}", e));
+    let mut decoder = image::ImageReader::new(std::io::Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|e| OcrError::PreprocessingError(format!("Failed to load image: {}", e)))?
+        .into_decoder()
+        .map_err(load_error)?;
+    let exif = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder).map_err(load_error)?;
+
+    // A manual rotation replaces the EXIF orientation instead of adding to
+    // it, so the image is never rotated twice
+    if rotation.is_some() && exif != Orientation::NoTransforms {
+        warnings.push("rotation overrides the image's EXIF orientation".to_string());
+    }
+    image.apply_orientation(rotation.unwrap_or(exif));
+
+    if is_animated_webp(data) {
+        warnings.push(
+            "Animated WebP: only the first frame was processed, pass frame to pick others"
+                .to_string(),
+        );
+    }
+
+    Ok(image)
+}
+
+/// Most frames of an animated GIF or WebP, or pages of a TIFF, considered
+/// for a frame selection
+const MAX_FRAMES: usize = 100;
+
+fn is_gif(data: &[u8]) -> bool {
+    data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a")
+}
+
+fn frame_load_error(e: image::ImageError) -> OcrError {
+    OcrError::PreprocessingError(format!("Failed to load image: {}", e))
+}
+
+/// Decode the frames of a GIF or animated WebP one at a time, as the
+/// animation shows them
+///
+/// Frames can only be decoded in order, since each one is drawn over the
+/// last. Decoding allocates within the `image` crate's default limits, like
+/// [`decode_image`].
+fn decode_frames(
+    data: &[u8],
+    rotation: Option<Orientation>,
+) -> Result<impl Iterator<Item = Result<DynamicImage, OcrError>> + '_, OcrError> {
+    let data = std::io::Cursor::new(data);
+    let frames = if is_gif(data.get_ref()) {
+        // GifDecoder starts out without limits, unlike ImageReader
+        let mut decoder = GifDecoder::new(data).map_err(frame_load_error)?;
+        decoder
+            .set_limits(image::Limits::default())
+            .map_err(frame_load_error)?;
+        decoder.into_frames()
+    } else {
+        let mut decoder = WebPDecoder::new(data).map_err(frame_load_error)?;
+        let limits = image::Limits::default();
+        // WebPDecoder only checks the canvas size against limits, not what
+        // it allocates, so check the buffer each frame is decoded into
+        let (width, height) = decoder.dimensions();
+        if limits
+            .max_alloc
+            .is_some_and(|max| u64::from(width) * u64::from(height) * 4 > max)
+        {
+            return Err(frame_load_error(image::ImageError::Limits(
+                image::error::LimitError::from_kind(
+                    image::error::LimitErrorKind::InsufficientMemory,
+                ),
+            )));
+        }
+        decoder.set_limits(limits).map_err(frame_load_error)?;
+        decoder.into_frames()
+    };
+    Ok(frames.map(move |frame| {
+        let mut image = DynamicImage::ImageRgba8(frame.map_err(frame_load_error)?.into_buffer());
+        image.apply_orientation(rotation.unwrap_or(Orientation::NoTransforms));
+        Ok(image)
+    }))
+}
+
+/// Pick which frames or TIFF pages to OCR as `frames` produces them, up to
+/// [`MAX_FRAMES`]
+///
+/// Frames after a chosen index are never produced, and
+/// [`FrameSelection::Auto`] keeps only the best frame so far, scored by
+/// `count_words`, so a long animation isn't held in memory to pick one frame.
+fn select_frames<T>(
+    frames: impl IntoIterator<Item = Result<T, OcrError>>,
+    selection: FrameSelection,
+    warnings: &mut Vec<String>,
+    count_words: impl Fn(&T) -> Result<usize, OcrError>,
+) -> Result<Vec<T>, OcrError> {
+    let mut selected = Vec::new();
+    let (mut best, mut best_words) = (0, 0);
+    let mut count = 0;
+    for (index, frame) in frames.into_iter().enumerate() {
+        if index == MAX_FRAMES {
+            warnings.push(format!(
+                "The image has more than {} frames, only the first {} were considered",
+                MAX_FRAMES, MAX_FRAMES
+            ));
+            break;
+        }
+        count += 1;
+        let frame = frame?;
+        match selection {
+            FrameSelection::All => selected.push(frame),
+            FrameSelection::Index(wanted) if wanted == index => return Ok(vec![frame]),
+            FrameSelection::Index(_) => {}
+            FrameSelection::Auto => {
+                let words = count_words(&frame)?;
+                // Ties go to the earlier frame
+                if selected.is_empty() || words > best_words {
+                    selected = vec![frame];
+                    (best, best_words) = (index, words);
+                }
+            }
+        }
+    }
+
+    match selection {
+        _ if count == 0 => Err(OcrError::PreprocessingError(
+            "The image has no frames".to_string(),
+        )),
+        FrameSelection::Index(index) => Err(OcrError::InvalidRequest(format!(
+            "frame {} is out of range, the image has {} frames",
+            index, count
+        ))),
+        FrameSelection::Auto if count > 1 => {
+            warnings.push(format!("frame=auto picked frame {} of {}", best, count));
+            Ok(selected)
+        }
+        _ => Ok(selected),
+    }
+}
+
+/// A page of an image upload to OCR
+enum ImagePage {
+    /// A frame of an animation, or the only image
+    Decoded(DynamicImage),
+    /// A page of a multi-page TIFF by index, decoded once it is OCRed so a
+    /// long TIFF isn't held in memory all at once
+    Tiff(usize),
+}
+
+/// Most pages decoded from a multi-page TIFF
+const MAX_TIFF_PAGES: usize = 100;
+
+/// Where a TIFF's header points at its first page, and where each page's
+/// image file directory starts
+struct TiffPages {
+    little_endian: bool,
+    pointer: std::ops::Range<usize>,
+    offsets: Vec<u64>,
+}
+
+/// Follow the chain of image file directories of a TIFF or BigTIFF, up to
+/// one past [`MAX_TIFF_PAGES`]
+///
+/// Returns None for other formats. The chain ends at the first directory
+/// that is out of bounds or seen before.
+fn tiff_pages(data: &[u8]) -> Option<TiffPages> {
+    let header = TiffHeader::parse(data)?;
+    let pointer = header.first_directory_pointer();
+
+    let mut offsets = Vec::new();
+    let mut next = header.read(pointer.start as u64, header.pointer_size);
+    while let Some(offset) = next.filter(|&offset| offset != 0 && !offsets.contains(&offset)) {
+        let Some(entries) = header.read(offset, header.count_size) else {
+            break;
+        };
+        offsets.push(offset);
+        if offsets.len() > MAX_TIFF_PAGES {
+            break;
+        }
+        next = entries
+            .checked_mul(header.entry_size)
+            .and_then(|size| size.checked_add(offset + header.count_size as u64))
+            .and_then(|end| header.read(end, header.pointer_size));
+    }
+    Some(TiffPages {
+        little_endian: header.little_endian,
+        pointer,
+        offsets,
+    })
+}
+
+/// Number of pages of a TIFF to OCR, at most [`MAX_TIFF_PAGES`], or None
+/// for other formats
+fn tiff_page_count(data: &[u8], warnings: &mut Vec<String>) -> Option<usize> {
+    let count = tiff_pages(data)?.offsets.len();
+    if count > MAX_TIFF_PAGES {
+        warnings.push(format!(
+            "TIFF has more than {} pages, only the first {} were processed",
+            MAX_TIFF_PAGES, MAX_TIFF_PAGES
+        ));
+    }
+    Some(count.min(MAX_TIFF_PAGES))
+}
+
+/// Decode page `index` of a TIFF, counting from 0
+///
+/// The `image` crate only reads a TIFF's first page, so a later page is
+/// decoded from a copy whose header points at that page instead.
+fn decode_tiff_page(
+    data: &[u8],
+    index: usize,
+    rotation: Option<Orientation>,
+    warnings: &mut Vec<String>,
+) -> Result<DynamicImage, OcrError> {
+    if index == 0 {
+        return decode_image(data, rotation, warnings);
+    }
+    let pages = tiff_pages(data);
+    let Some((pages, &offset)) = pages
+        .as_ref()
+        .and_then(|pages| Some((pages, pages.offsets.get(index)?)))
+    else {
+        return Err(OcrError::PreprocessingError(format!(
+            "TIFF has no page {}",
+            index + 1
+        )));
+    };
+
+    let mut page = data.to_vec();
+    let width = pages.pointer.len();
+    let offset = if pages.little_endian {
+        offset.to_le_bytes()[..width].to_vec()
+    } else {
+        offset.to_be_bytes()[8 - width..].to_vec()
+    };
+    page[pages.pointer.clone()].copy_from_slice(&offset);
+    decode_image(&page, rotation, warnings)
+}
+
+/// Check the VP8X header's animation flag
+fn is_animated_webp(data: &[u8]) -> bool {
+    const ANIMATION_FLAG: u8 = 0x02;
+
+    data.len() > 20
+        && &data[0..4] == b"RIFF"
+        && &data[8..12] == b"WEBP"
+        && &data[12..16] == b"VP8X"
+        && data[20] & ANIMATION_FLAG != 0
+}
+
+/// Handle text detection requests (word and line regions, no recognition)
+///
+/// Detection runs the engine like OCR does, so it waits for an OCR slot and
+/// is subject to the processing timeout and the watchdog.
+async fn handle_detect(
+    State(app): State<AppState>,
+    multipart: Multipart,
+) -> Result<Response, OcrError> {
+    let in_flight = InFlightOcr::start(&app.ocr_in_flight);
+    let state = app.snapshot();
+    let watchdog = Watchdog::new(&app, &state);
+    let engine = state.registry.get("ocrs").ok_or_else(|| {
+        OcrError::InvalidRequest("The /detect endpoint requires the ocrs engine".to_string())
+    })?;
+
+    let name = engine.name();
+    let result = detect_text(state, engine, multipart, in_flight, watchdog, &app).await;
+    record_ocr_request(&app.metrics, name, result.map(IntoResponse::into_response))
+}
+
+/// Find the words and lines of an uploaded image
+async fn detect_text(
+    state: Snapshot,
+    engine: Arc<dyn OcrEngine>,
+    mut multipart: Multipart,
+    in_flight: (InFlightOcr, usize),
+    watchdog: Option<Watchdog>,
+    app: &AppState,
+) -> Result<Json<DetectResponse>, OcrError> {
+    if let Some(watchdog) = &watchdog {
+        watchdog.check_capacity()?;
+    }
+    // Queue before reading the upload, like OCR requests
+    let slot = acquire_ocr_slot(&app.ocr_slots, state.config.queue_timeout).await?;
+    let start = Instant::now();
+    let data = read_image_upload(&mut multipart, &state.config, "/detect").await?;
+
+    let name = engine.name();
+    let task = tokio::task::spawn_blocking(move || {
+        // Held until detection finishes, even if the request gave up on it
+        let _held = (in_flight, slot);
+        let image = decode_image(&data, None, &mut Vec::new())?;
+        let detection = engine.detect(&image)?;
+        let lines = detection
+            .lines
+            .iter()
+            .map(|line| LineResponse {
+                bounds: Bounds::around(line.iter().flat_map(|word| &word.corners)),
+                words: line.iter().map(RegionResponse::from).collect(),
+            })
+            .collect::<Vec<_>>();
+        let words = detection.words.iter().map(RegionResponse::from).collect();
+        Ok::<_, OcrError>((words, lines))
+    });
+    let (words, lines): (Vec<RegionResponse>, Vec<LineResponse>) =
+        wait_for_ocr(name, task, watchdog, state.config.processing_timeout).await??;
+    let processing_time_ms = start.elapsed().as_millis() as u64;
+
+    tracing::info!(
+        "[{}] Detected {} words in {} lines in {}ms",
+        name,
+        words.len(),
+        lines.len(),
+        processing_time_ms
+    );
+
+    Ok(Json(DetectResponse {
+        words,
+        lines,
+        processing_time_ms,
+        engine: name.to_string(),
+    }))
+}
+
+/// A file uploaded for OCR, with the form fields that accompany it
+struct OcrUpload {
+    data: Option<Bytes>,
+    /// Where to fetch the file from, when it wasn't uploaded
+    url: Option<String>,
+    content_type: Option<String>,
+    filename: Option<String>,
+    languages: Option<String>,
+    pdf_password: Option<String>,
+}
+
+/// JSON body accepted by `/ocr` in place of a multipart form
+#[derive(Debug, Deserialize)]
+struct OcrJsonUpload {
+    /// The file, base64 encoded
+    #[serde(default)]
+    image_base64: Option<String>,
+    /// Where to fetch the file from instead
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    content_type: Option<String>,
+    #[serde(default)]
+    filename: Option<String>,
+    #[serde(default)]
+    languages: Option<String>,
+    #[serde(default)]
+    pdf_password: Option<String>,
+}
+
+/// Room for the other fields of a JSON upload, beyond the encoded file
+const JSON_UPLOAD_OVERHEAD: usize = 64 * 1024;
+
+/// Read an OCR upload sent as a multipart form or, with
+/// `Content-Type: application/json`, as a JSON body with a base64 file
+///
+/// Files sent as a `url` are downloaded here.
+async fn read_ocr_upload(request: Request, config: &Config) -> Result<OcrUpload, OcrError> {
+    let max_file_size = config.max_file_size;
+    let is_json = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"));
+
+    let mut upload = if is_json {
+        // Base64 takes four bytes for every three of the file
+        let limit = max_file_size.div_ceil(3) * 4 + JSON_UPLOAD_OVERHEAD;
+        let body = axum::body::to_bytes(request.into_body(), limit)
+            .await
+            .map_err(|e| OcrError::InvalidRequest(format!("Failed to read JSON body: {}", e)))?;
+        parse_json_upload(&body, max_file_size)?
+    } else {
+        let mut multipart = Multipart::from_request(request, &())
+            .await
+            .map_err(|e| OcrError::InvalidRequest(format!("Failed to parse multipart: {}", e)))?;
+        read_multipart_upload(&mut multipart).await?
+    };
+
+    let Some(url) = upload.url.take() else {
+        return Ok(upload);
+    };
+    if upload.data.is_some() {
+        return Err(OcrError::InvalidRequest(
+            "Send either a file or a url, not both".to_string(),
+        ));
+    }
+    let timeout = config.url_fetch_timeout;
+    let download_hosts = config.allowed_download_hosts.clone();
+    let private_hosts = config.url_fetch_allowed_hosts.clone();
+    let fetched = tokio::task::spawn_blocking(move || {
+        url_fetch::fetch(
+            &url,
+            max_file_size,
+            timeout,
+            download_hosts.as_deref(),
+            &private_hosts,
+        )
+    })
+    .await
+    .map_err(|e| OcrError::Internal(format!("URL fetch task failed: {}", e)))??;
+    upload.data = Some(Bytes::from(fetched.data));
+    // A content type the client declared wins over the fetched one
+    upload.content_type = upload.content_type.or(fetched.content_type);
+    Ok(upload)
+}
+
+/// Decode a JSON upload, rejecting malformed base64 and oversized files
+fn parse_json_upload(body: &[u8], max_file_size: usize) -> Result<OcrUpload, OcrError> {
+    use base64::Engine;
+
+    let upload: OcrJsonUpload = serde_json::from_slice(body)
+        .map_err(|e| OcrError::InvalidRequest(format!("Invalid JSON body: {}", e)))?;
+
+    let url = upload.url.filter(|url| !url.is_empty());
+    let Some(image_base64) = upload.image_base64 else {
+        return Ok(OcrUpload {
+            data: None,
+            url,
+            content_type: upload.content_type,
+            filename: upload.filename.filter(|name| !name.is_empty()),
+            languages: upload.languages,
+            pdf_password: upload.pdf_password,
+        });
+    };
+
+    // Check the size before decoding, so huge strings aren't decoded for nothing
+    let encoded: Vec<u8> = image_base64
+        .bytes()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .collect();
+    let estimated = encoded.len() / 4 * 3;
+    if estimated > max_file_size + 2 {
+        return Err(OcrError::ImageTooLarge {
+            size: estimated,
+            max: max_file_size,
+        });
+    }
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(&encoded)
+        .map_err(|e| OcrError::InvalidRequest(format!("Invalid image_base64: {}", e)))?;
+    if data.is_empty() {
+        return Err(OcrError::MissingFile);
+    }
+
+    Ok(OcrUpload {
+        data: Some(Bytes::from(data)),
+        url,
+        content_type: upload.content_type,
+        filename: upload.filename.filter(|name| !name.is_empty()),
+        languages: upload.languages,
+        pdf_password: upload.pdf_password,
+    })
+}
+
+/// Read the file and fields of a multipart OCR upload
+async fn read_multipart_upload(multipart: &mut Multipart) -> Result<OcrUpload, OcrError> {
+    let mut file_data: Option<Bytes> = None;
+    let mut url: Option<String> = None;
+    let mut content_type: Option<String> = None;
+    let mut filename: Option<String> = None;
+    let mut languages: Option<String> = None;
+    let mut pdf_password: Option<String> = None;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| OcrError::InvalidRequest(format!("Failed to parse multipart: {}", e)))?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+
+        match name.as_str() {
+            "file" => {
+                content_type = field.content_type().map(|s| s.to_string());
+                filename = field
+                    .file_name()
+                    .filter(|name| !name.is_empty())
+                    .map(|name| name.to_string());
+                file_data = Some(field.bytes().await.map_err(|e| {
+                    OcrError::InvalidRequest(format!("Failed to read file data: {}", e))
+                })?);
+            }
+            "url" => {
+                url = Some(
+                    field
+                        .text()
+                        .await
+                        .map_err(|e| OcrError::InvalidRequest(format!("Invalid url: {}", e)))?,
+                )
+                .filter(|url| !url.is_empty());
+            }
+            "languages" => {
+                languages =
+                    Some(field.text().await.map_err(|e| {
+                        OcrError::InvalidRequest(format!("Invalid languages: {}", e))
+                    })?);
+            }
+            "pdf_password" => {
+                // Never include the password itself in errors or logs
+                pdf_password = Some(field.text().await.map_err(|_| {
+                    OcrError::InvalidRequest("Invalid pdf_password field".to_string())
+                })?);
+            }
+            _ => {
+                // Ignore unknown fields
+            }
+        }
+    }
+
+    Ok(OcrUpload {
+        data: file_data,
+        url,
+        content_type,
+        filename,
+        languages,
+        pdf_password,
+    })
+}
+
+/// Read the `file` field of an upload, rejecting PDFs and oversized files
+async fn read_image_upload(
+    multipart: &mut Multipart,
+    config: &Config,
+    endpoint: &str,
+) -> Result<Bytes, OcrError> {
+    let mut file_data: Option<Bytes> = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| OcrError::InvalidRequest(format!("Failed to parse multipart: {}", e)))?
+    {
+        if field.name() == Some("file") {
+            file_data = Some(field.bytes().await.map_err(|e| {
+                OcrError::InvalidRequest(format!("Failed to read file data: {}", e))
+            })?);
+        }
+    }
+
+    let data = file_data.ok_or(OcrError::MissingFile)?;
+    if data.len() > config.max_file_size {
+        return Err(OcrError::ImageTooLarge {
+            size: data.len(),
+            max: config.max_file_size,
+        });
+    }
+    if detect_format(&data) == Some(DetectedFormat::Pdf) {
+        return Err(OcrError::UnsupportedFormat(format!(
+            "PDFs are not supported by {}, upload an image",
+            endpoint
+        )));
+    }
+
+    Ok(data)
+}
+
+/// Handle health check requests
+async fn handle_health() -> impl IntoResponse {
+    Json(HealthResponse {
+        status: "ok".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    })
+}
+
+/// Handle Prometheus scrapes
+async fn handle_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let in_flight = state.ocr_in_flight.load(Ordering::SeqCst);
+    (
+        [(header::CONTENT_TYPE, metrics::CONTENT_TYPE)],
+        state.metrics.render(in_flight),
+    )
+}
+
+/// Handle info requests
+async fn handle_info(State(state): State<AppState>) -> Json<InfoResponse> {
+    Json(info_response(&state.snapshot()))
+}
+
+/// Build the `/info` payload for a registry and config
+fn info_response(state: &Snapshot) -> InfoResponse {
+    let engines: Vec<EngineInfoResponse> = state
+        .registry
+        .info()
+        .into_iter()
+        .map(|e| EngineInfoResponse {
+            name: e.name.to_string(),
+            description: e.description.to_string(),
+            supported_formats: e.supported_formats,
+            supported_languages: e.supported_languages,
+            supported_models: e
+                .supported_models
+                .iter()
+                .map(|m| m.as_str().to_string())
+                .collect(),
+        })
+        .collect();
+
+    InfoResponse {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        available_engines: engines,
+        unavailable_engines: state
+            .registry
+            .unavailable()
+            .iter()
+            .map(|e| UnavailableEngineResponse {
+                name: e.name.to_string(),
+                error: e.error.clone(),
+            })
+            .collect(),
+        default_engine: state.registry.default_name().to_string(),
+        max_file_size_bytes: state.config.max_file_size,
+        default_language: state.config.default_language.clone(),
+    }
+}
+
+/// Body of `POST /warmup`; every field is optional
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct WarmupRequest {
+    /// Engine to prepare (None uses the default engine)
+    pub engine: Option<String>,
+    /// Tesseract languages to download (None or empty uses the default
+    /// language)
+    pub languages: Option<Vec<String>>,
+    /// Recognition model: default, handwriting
+    pub model: Option<String>,
+}
+
+/// Response of `POST /warmup`
+#[derive(Debug, Serialize)]
+pub struct WarmupResponse {
+    pub engine: String,
+    pub model: String,
+    /// Languages that were prepared (null when the default was)
+    pub languages: Option<Vec<String>>,
+    pub processing_time_ms: u64,
+}
+
+/// Handle warmup requests: download and load the models and languages a
+/// later request will need, returning once they are ready
+///
+/// Lets deployments fetch data before taking traffic instead of on the first
+/// request that needs it.
+async fn handle_warmup(
+    State(state): State<AppState>,
+    body: Bytes,
+) -> Result<Json<WarmupResponse>, OcrError> {
+    let start = Instant::now();
+    let request: WarmupRequest = if body.is_empty() {
+        WarmupRequest::default()
+    } else {
+        serde_json::from_slice(&body)
+            .map_err(|e| OcrError::InvalidRequest(format!("Invalid warmup body: {}", e)))?
+    };
+
+    let snapshot = state.snapshot();
+    let engine = match request.engine.as_deref() {
+        Some(name) => find_engine(&snapshot, name)?,
+        None => snapshot.registry.default().ok_or_else(|| {
+            OcrError::InitializationError("No default engine available".to_string())
+        })?,
+    };
+    let model = parse_model(request.model.as_deref(), engine.as_ref())?;
+    let languages = request.languages.filter(|languages| !languages.is_empty());
+    if let Some(languages) = &languages {
+        if let Some(invalid) = languages.iter().find(|l| !is_valid_language(l)) {
+            return Err(OcrError::InvalidRequest(format!(
+                "Invalid language '{}'. Use Tesseract codes, e.g. deu",
+                invalid
+            )));
+        }
+    }
+
+    let name = engine.name();
+    let prepared = languages.clone();
+    tokio::task::spawn_blocking(move || {
+        let languages = match prepared {
+            Some(languages) => languages.into_iter().map(Some).collect(),
+            None => vec![None],
+        };
+        for language in languages {
+            let options = OcrOptions {
+                model,
+                languages: language,
+                ..OcrOptions::default()
+            };
+            engine.prepare(&options)?;
+        }
+        Ok::<_, OcrError>(())
+    })
+    .await
+    .map_err(|e| OcrError::Internal(format!("Warmup task failed: {}", e)))??;
+
+    tracing::info!(
+        "Warmed up {} ({} model, languages {:?}) in {:?}",
+        name,
+        model.as_str(),
+        languages,
+        start.elapsed()
+    );
+    Ok(Json(WarmupResponse {
+        engine: name.to_string(),
+        model: model.as_str().to_string(),
+        languages,
+        processing_time_ms: start.elapsed().as_millis() as u64,
+    }))
+}
+
+/// A model file in the model cache
+#[derive(Debug, Serialize)]
+pub struct CachedModelResponse {
+    pub name: String,
+    pub size_bytes: u64,
+}
+
+/// Response of `GET /warmup/status`
+#[derive(Debug, Serialize)]
+pub struct WarmupStatusResponse {
+    /// Every model on disk, including Tesseract traineddata
+    pub models: Vec<CachedModelResponse>,
+    /// Tesseract languages with traineddata on disk for any model
+    pub languages: Vec<String>,
+}
+
+/// Handle warmup status requests: list the models and languages cached on disk
+async fn handle_warmup_status(State(state): State<AppState>) -> Json<WarmupStatusResponse> {
+    let cache = state.snapshot().registry.model_cache().clone();
+    let models = tokio::task::spawn_blocking(move || cache.cached())
+        .await
+        .unwrap_or_default();
+    Json(warmup_status(models))
+}
+
+/// Build the `/warmup/status` payload from the cached models
+fn warmup_status(models: Vec<CachedModel>) -> WarmupStatusResponse {
+    let languages: BTreeSet<String> = models
+        .iter()
+        .filter_map(|model| {
+            let file = model.name.rsplit('/').next()?;
+            file.strip_suffix(".traineddata").map(str::to_string)
+        })
+        .collect();
+    WarmupStatusResponse {
+        models: models
+            .into_iter()
+            .map(|model| CachedModelResponse {
+                name: model.name,
+                size_bytes: model.size_bytes,
+            })
+            .collect(),
+        languages: languages.into_iter().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::preprocess::encode_png;
+    use super::*;
+    use crate::engine::TextBox;
+
+    /// Engine stub that fails or returns fixed text
+    struct StubEngine {
+        name: &'static str,
+        error: Option<fn() -> OcrError>,
+    }
+
+    impl OcrEngine for StubEngine {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn description(&self) -> &'static str {
+            "stub"
+        }
+
+        fn process(
+            &self,
+            _path: &std::path::Path,
+            options: &OcrOptions,
+        ) -> Result<OcrResult, OcrError> {
+            self.process_image(&DynamicImage::new_rgb8(1, 1), options)
+        }
+
+        fn process_image(&self, _: &DynamicImage, _: &OcrOptions) -> Result<OcrResult, OcrError> {
+            match self.error {
+                Some(error) => Err(error()),
+                None => Ok(OcrResult {
+                    text: format!("from {}", self.name),
+                    confidence: 0.9,
+                    warnings: Vec::new(),
+                    boxes: None,
+                    word_confidences: None,
+                    word_scores: None,
+                    lines: None,
+                    orientation_degrees: None,
+                }),
+            }
+        }
+
+        fn supported_formats(&self) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn supported_languages(&self) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn detect(&self, _: &DynamicImage) -> Result<crate::engine::Detection, OcrError> {
+            Ok(crate::engine::Detection {
+                words: Vec::new(),
+                lines: Vec::new(),
+            })
+        }
+
+        fn supported_models(&self) -> Vec<RecognitionModel> {
+            vec![RecognitionModel::Default]
+        }
+    }
+
+    fn fixture(name: &str) -> Vec<u8> {
+        std::fs::read(format!(
+            "{}/tests/fixtures/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_decode_image_handles_webp_variants() {
+        let expected = image::load_from_memory(&fixture("sample_text.png"))
+            .unwrap()
+            .to_rgb8();
+
+        for name in [
+            "sample_text_lossless.webp",
+            "sample_text_extended.webp",
+            "sample_text_animated.webp",
+        ] {
+            let mut warnings = Vec::new();
+            let image = decode_image(&fixture(name), None, &mut warnings)
+                .unwrap_or_else(|e| panic!("{} failed to decode: {}", name, e));
+
+            // Lossless variants decode to exactly the source pixels
+            assert_eq!(image.to_rgb8(), expected, "{} pixels differ", name);
+        }
+
+        let mut warnings = Vec::new();
+        decode_image(&fixture("sample_text.webp"), None, &mut warnings).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    /// Insert an EXIF APP1 segment with the given orientation into a JPEG
+    fn with_exif_orientation(jpeg: &[u8], orientation: u8) -> Vec<u8> {
+        let mut exif = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\x01".to_vec();
+        // Orientation tag, SHORT, one value, then no further IFDs
+        exif.extend_from_slice(&[0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, orientation, 0, 0]);
+        exif.extend_from_slice(&[0, 0, 0, 0]);
+
+        let mut data = jpeg[..2].to_vec();
+        data.extend_from_slice(&[0xFF, 0xE1]);
+        data.extend_from_slice(&((exif.len() + 2) as u16).to_be_bytes());
+        data.extend_from_slice(&exif);
+        data.extend_from_slice(&jpeg[2..]);
+        data
+    }
+
+    #[test]
+    fn test_decode_image_rotation_overrides_exif_orientation() {
+        let mut jpeg = Vec::new();
+        DynamicImage::new_rgb8(40, 20)
+            .write_to(
+                &mut std::io::Cursor::new(&mut jpeg),
+                image::ImageFormat::Jpeg,
+            )
+            .unwrap();
+        // Orientation 6: rotate 90 degrees clockwise to display
+        let rotated = with_exif_orientation(&jpeg, 6);
+
+        let mut warnings = Vec::new();
+        let image = decode_image(&rotated, None, &mut warnings).unwrap();
+        assert_eq!((image.width(), image.height()), (20, 40));
+        assert!(warnings.is_empty());
+
+        // rotation=0 keeps the stored pixels instead of rotating twice
+        let image = decode_image(&rotated, Some(Orientation::NoTransforms), &mut warnings).unwrap();
+        assert_eq!((image.width(), image.height()), (40, 20));
+        assert_eq!(warnings.len(), 1);
+
+        let image = decode_image(&rotated, Some(Orientation::Rotate90), &mut Vec::new()).unwrap();
+        assert_eq!((image.width(), image.height()), (20, 40));
+    }
+
+    #[test]
+    fn test_decode_image_warns_for_animated_webp() {
+        let mut warnings = Vec::new();
+        decode_image(&fixture("sample_text_animated.webp"), None, &mut warnings).unwrap();
+        assert_eq!(warnings.len(), 1);
+
+        let mut warnings = Vec::new();
+        decode_image(&fixture("sample_text_extended.webp"), None, &mut warnings).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    /// A two-frame GIF whose second frame has a black bar across it
+    fn two_frame_gif() -> Vec<u8> {
+        let blank = image::RgbaImage::from_pixel(40, 20, image::Rgba([255, 255, 255, 255]));
+        let mut barred = blank.clone();
+        for x in 5..35 {
+            for y in 8..12 {
+                barred.put_pixel(x, y, image::Rgba([0, 0, 0, 255]));
+            }
+        }
+
+        let mut gif = Vec::new();
+        image::codecs::gif::GifEncoder::new(&mut gif)
+            .encode_frames([image::Frame::new(blank), image::Frame::new(barred)])
+            .unwrap();
+        gif
+    }
+
+    #[test]
+    fn test_low_confidence_error_includes_text() {
+        let error = OcrError::LowConfidence {
+            confidence: 42.0,
+            required: 80.0,
+            text: "b4d sc4n".to_string(),
+        };
+        assert_eq!(error.status_and_code().0.as_u16(), 422);
+
+        let body = serde_json::to_value(ErrorResponse::from(&error)).unwrap();
+        assert_eq!(body["code"], "LOW_CONFIDENCE");
+        assert_eq!(body["schema_version"], SCHEMA_VERSION);
+        assert_eq!(body["text"], "b4d sc4n");
+        assert_eq!(body["confidence"], 42.0);
+        // Other errors don't carry them
+        let body = serde_json::to_value(ErrorResponse::from(&OcrError::MissingFile)).unwrap();
+        assert!(body.get("text").is_none());
+    }
+
+    #[test]
+    fn test_condense_warnings_merges_and_caps() {
+        let warnings = vec![
+            "Failed to OCR image 1: bad data".to_string(),
+            "split is not supported for PDFs, ignored".to_string(),
+            "Failed to OCR image 12: bad data".to_string(),
+            "Failed to OCR image 3: bad data".to_string(),
+            "rotation is not supported for PDFs, ignored".to_string(),
+        ];
+
+        assert_eq!(
+            condense_warnings(warnings.clone(), None),
+            vec![
+                "Failed to OCR image 1: bad data (and 2 more like it)",
+                "split is not supported for PDFs, ignored",
+                "rotation is not supported for PDFs, ignored",
+            ]
+        );
+        assert_eq!(
+            condense_warnings(warnings, Some(1)),
+            vec![
+                "Failed to OCR image 1: bad data (and 2 more like it)",
+                "2 more warnings omitted",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_text_similarity() {
+        assert_eq!(text_similarity("Hello  World", "hello world"), 1.0);
+        assert_eq!(text_similarity("", ""), 1.0);
+        assert_eq!(text_similarity("a", "b"), 0.0);
+
+        let close = text_similarity("Invoice number 12345", "Invoice nurnber 12345");
+        assert!(close > 0.8, "Expected > 0.8, got {}", close);
+        let garbage = text_similarity("Invoice number 12345", "x7#q zv&k 00pl");
+        assert!(garbage < 0.2, "Expected < 0.2, got {}", garbage);
+    }
+
+    #[test]
+    fn test_parse_mask() {
+        assert_eq!(parse_mask(None).unwrap(), vec![]);
+        assert_eq!(
+            parse_mask(Some("1,2,3,4; 5,6,7,8")).unwrap(),
+            vec![
+                Region {
+                    x: 1,
+                    y: 2,
+                    width: 3,
+                    height: 4
+                },
+                Region {
+                    x: 5,
+                    y: 6,
+                    width: 7,
+                    height: 8
+                },
+            ]
+        );
+        assert!(parse_mask(Some("1,2,3")).is_err());
+        assert!(parse_mask(Some("1,2,0,4")).is_err());
+        assert!(parse_mask(Some("1,2,3,4;")).is_err());
+    }
+
+    #[test]
+    fn test_apply_mask_fills_with_background() {
+        let mut image = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            10,
+            10,
+            image::Rgb([250, 240, 230]),
+        ));
+        image.put_pixel(4, 4, Rgba([0, 0, 0, 255]));
+        image.put_pixel(8, 8, Rgba([0, 0, 0, 255]));
+
+        let mask = parse_mask(Some("3,3,3,3;9,9,100,100")).unwrap();
+        apply_mask(&mut image, &mask);
+
+        assert_eq!(image.get_pixel(4, 4), Rgba([250, 240, 230, 255]));
+        // Outside the mask is untouched
+        assert_eq!(image.get_pixel(8, 8), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_select_gif_frames() {
+        let gif = two_frame_gif();
+        assert!(is_gif(&gif));
+        assert_eq!(decode_frames(&gif, None).unwrap().count(), 2);
+
+        // Score frames by their dark pixels, standing in for detected words
+        let dark_pixels =
+            |frame: &DynamicImage| Ok(frame.to_luma8().pixels().filter(|p| p.0[0] < 128).count());
+        let select = |selection, warnings: &mut Vec<String>| {
+            let frames = select_frames(
+                decode_frames(&gif, None).unwrap(),
+                selection,
+                warnings,
+                dark_pixels,
+            )?;
+            frames
+                .iter()
+                .map(dark_pixels)
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        assert_eq!(
+            select(FrameSelection::Index(1), &mut Vec::new()).unwrap(),
+            [120]
+        );
+        assert_eq!(
+            select(FrameSelection::All, &mut Vec::new()).unwrap(),
+            [0, 120]
+        );
+        assert!(select(FrameSelection::Index(2), &mut Vec::new()).is_err());
+
+        let mut warnings = Vec::new();
+        assert_eq!(select(FrameSelection::Auto, &mut warnings).unwrap(), [120]);
+        assert_eq!(warnings, ["frame=auto picked frame 1 of 2"]);
+
+        // Frames after the chosen one are never decoded
+        let mut decoded = 0;
+        let frames = decode_frames(&gif, None).unwrap().inspect(|_| decoded += 1);
+        select_frames(
+            frames,
+            FrameSelection::Index(0),
+            &mut Vec::new(),
+            dark_pixels,
+        )
+        .unwrap();
+        assert_eq!(decoded, 1);
+
+        assert_eq!(
+            parse_frame(Some("3")).unwrap(),
+            Some(FrameSelection::Index(3))
+        );
+        assert!(parse_frame(Some("last")).is_err());
+    }
+
+    #[test]
+    fn test_decode_frames_within_memory_limits() {
+        // A tiny GIF claiming a 65535x65535 screen, which every frame is
+        // drawn onto
+        let mut gif = two_frame_gif();
+        gif[6..10].copy_from_slice(&[0xFF; 4]);
+        assert!(decode_frames(&gif, None).unwrap().next().unwrap().is_err());
+
+        // An animated WebP claiming the largest canvas, 16384x16384
+        let mut webp = fixture("sample_text_animated.webp");
+        webp[24..30].copy_from_slice(&[0xFF, 0x3F, 0x00, 0xFF, 0x3F, 0x00]);
+        assert!(decode_frames(&webp, None).is_err());
+    }
+
+    #[test]
+    fn test_decode_animated_webp_frames() {
+        let webp = fixture("sample_text_animated.webp");
+        assert!(is_animated_webp(&webp));
+
+        let count = decode_frames(&webp, None).unwrap().count();
+        assert!(count > 1);
+
+        // Frames after the chosen one are never decoded
+        let mut decoded = 0;
+        let frames = decode_frames(&webp, None)
+            .unwrap()
+            .inspect(|_| decoded += 1);
+        select_frames(frames, FrameSelection::Index(0), &mut Vec::new(), |_| Ok(0)).unwrap();
+        assert_eq!(decoded, 1);
+
+        // A frame past the end is an error once every frame has been seen
+        let mut decoded = 0;
+        let frames = decode_frames(&webp, None)
+            .unwrap()
+            .inspect(|_| decoded += 1);
+        let past_end = FrameSelection::Index(count);
+        assert!(select_frames(frames, past_end, &mut Vec::new(), |_| Ok(0)).is_err());
+        assert_eq!(decoded, count);
+    }
+
+    #[test]
+    fn test_decode_tiff_pages() {
+        let tiff = fixture("multi_page.tiff");
+        let mut warnings = Vec::new();
+        assert_eq!(tiff_page_count(&tiff, &mut warnings), Some(2));
+
+        let first = decode_tiff_page(&tiff, 0, None, &mut warnings).unwrap();
+        let second = decode_tiff_page(&tiff, 1, None, &mut warnings).unwrap();
+        assert_ne!(first, second);
+        assert!(warnings.is_empty());
+        // Each page is decoded the same as an upload of that page alone
+        assert_eq!(first, decode_image(&tiff, None, &mut Vec::new()).unwrap());
+        assert!(decode_tiff_page(&tiff, 2, None, &mut warnings).is_err());
+
+        let single = fixture("sample_text.tiff");
+        assert_eq!(tiff_page_count(&single, &mut warnings), Some(1));
+        assert_eq!(
+            tiff_page_count(&fixture("sample_text.png"), &mut warnings),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tiff_pages_stop_at_a_loop() {
+        let mut tiff = fixture("multi_page.tiff");
+        let pages = tiff_pages(&tiff).unwrap();
+        assert!(pages.little_endian);
+        // Point the second page's next-page pointer back at the first
+        let second = pages.offsets[1] as usize;
+        let entries = u16::from_le_bytes([tiff[second], tiff[second + 1]]) as usize;
+        let next = second + 2 + entries * 12;
+        tiff[next..next + 4].copy_from_slice(&(pages.offsets[0] as u32).to_le_bytes());
+
+        assert_eq!(tiff_pages(&tiff).unwrap().offsets, pages.offsets);
+    }
+
+    #[test]
+    fn test_bounds_around_rotated_corners() {
+        let corners = [[10.4, 20.0], [50.2, 15.5], [52.0, 30.0], [12.0, 34.6]];
+
+        let bounds = Bounds::around(&corners);
+
+        assert_eq!(
+            bounds,
+            Bounds {
+                x: 10,
+                y: 15,
+                width: 42,
+                height: 20
+            }
+        );
+    }
+
+    #[test]
+    fn test_bounds_around_clamps_negative_coordinates() {
+        let bounds = Bounds::around(&[[-3.0, -1.0], [5.0, 4.0]]);
+        assert_eq!(
+            (bounds.x, bounds.y, bounds.width, bounds.height),
+            (0, 0, 5, 4)
+        );
+    }
+
+    pub(super) fn stub(name: &'static str, error: Option<fn() -> OcrError>) -> Arc<dyn OcrEngine> {
+        Arc::new(StubEngine { name, error })
+    }
+
+    fn run_stubs(
+        primary: Arc<dyn OcrEngine>,
+        fallback: Option<Arc<dyn OcrEngine>>,
+        options: &OcrOptions,
+    ) -> Result<(OcrResult, String), OcrError> {
+        let image = DynamicImage::new_luma8(1, 1);
+        run_with_fallback(&primary, fallback, options, |e| {
+            e.process_image(&image, options)
+        })
+    }
+
+    #[test]
+    fn test_fallback_engine_used_on_processing_error() {
+        let primary = stub("ocrs", Some(|| OcrError::ProcessingError("boom".into())));
+        let fallback = stub("leptess", None);
+
+        let (result, engine) = run_stubs(primary, Some(fallback), &OcrOptions::default()).unwrap();
+
+        assert_eq!(engine, "leptess");
+        assert_eq!(result.text, "from leptess");
+        assert!(result.warnings[0].contains("used fallback engine 'leptess'"));
+    }
+
+    #[test]
+    fn test_fallback_engine_not_used_on_success_or_request_errors() {
+        let (_, engine) = run_stubs(
+            stub("ocrs", None),
+            Some(stub("leptess", None)),
+            &OcrOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(engine, "ocrs");
+
+        let result = run_stubs(
+            stub("ocrs", Some(|| OcrError::InvalidRequest("bad".into()))),
+            Some(stub("leptess", None)),
+            &OcrOptions::default(),
+        );
+        assert!(matches!(result, Err(OcrError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn test_fallback_skipped_when_model_unsupported() {
+        let options = OcrOptions {
+            model: RecognitionModel::Handwriting,
+            ..Default::default()
+        };
+
+        let result = run_stubs(
+            stub("ocrs", Some(|| OcrError::ProcessingError("boom".into()))),
+            Some(stub("leptess", None)),
+            &options,
+        );
+
+        assert!(matches!(result, Err(OcrError::ProcessingError(_))));
+    }
+
+    #[test]
+    fn test_auto_engine_reports_the_engine_it_routed_to() {
+        use crate::engines::auto::AutoEngine;
+
+        let auto: Arc<dyn OcrEngine> =
+            Arc::new(AutoEngine::new(stub("ocrs", None), stub("leptess", None)));
+        let pipeline = Pipeline::new(Preset::None);
+        let options = OcrOptions::default();
+
+        let clean = DynamicImage::ImageLuma8(image::GrayImage::from_fn(40, 40, |x, _| {
+            image::Luma([if x < 20 { 0 } else { 255 }])
+        }));
+        let result = ocr_image(clean, &pipeline, &auto, &None, &options).unwrap();
+        assert_eq!(result.engine, "ocrs");
+        assert_eq!(result.result.text, "from ocrs");
+
+        // A blank gray image has no contrast at all
+        let faded =
+            DynamicImage::ImageLuma8(image::GrayImage::from_pixel(40, 40, image::Luma([128])));
+        let result = ocr_image(faded, &pipeline, &auto, &None, &options).unwrap();
+        assert_eq!(result.engine, "leptess");
+    }
+
+    #[test]
+    fn test_scale_boxes_maps_back_to_original_size() {
+        let mut boxes = TextBoxes {
+            level: CoordsLevel::Word,
+            boxes: vec![TextBox {
+                text: "Hello".to_string(),
+                page: 1,
+                x: 100,
+                y: 40,
+                width: 250,
+                height: 61,
+                confidence: Some(0.9),
+                polygon: Some([[100.0, 60.0], [340.0, 40.0], [350.0, 101.0], [110.0, 121.0]]),
+            }],
+        };
+
+        scale_boxes(&mut boxes, 0.5, 0.25);
+
+        let b = &boxes.boxes[0];
+        assert_eq!((b.x, b.y, b.width, b.height), (50, 10, 125, 15));
+        assert_eq!(b.text, "Hello");
+        assert_eq!(
+            b.polygon,
+            Some([[50.0, 15.0], [170.0, 10.0], [175.0, 25.25], [55.0, 30.25]])
+        );
+    }
+
+    #[test]
+    fn test_scale_lines_adjusts_baseline_angle() {
+        let line = |baseline_angle| LineBox {
+            text_box: TextBox {
+                text: "Hello world".to_string(),
+                page: 1,
+                x: 100,
+                y: 40,
+                width: 400,
+                height: 60,
+                confidence: None,
+                polygon: None,
+            },
+            baseline_angle,
+        };
+        let mut lines = vec![line(45.0), line(0.0)];
+
+        scale_lines(&mut lines, 0.5, 0.25);
+
+        let b = &lines[0].text_box;
+        assert_eq!((b.x, b.y, b.width, b.height), (50, 10, 200, 15));
+        // Squashing the height flattens a rising baseline
+        assert!((lines[0].baseline_angle - 26.57).abs() < 0.01);
+        assert_eq!(lines[1].baseline_angle, 0.0);
+    }
+
+    pub(super) const SAMPLE_PDF: &[u8] = include_bytes!("../../tests/fixtures/sample_text.pdf");
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        encode_png(&DynamicImage::new_luma8(width, height)).unwrap()
+    }
+
+    #[test]
+    fn test_result_options_depend_on_what_changes_the_result() {
+        let config = test_config();
+        let key = |engine: &str, languages: Option<&str>, params: OcrQueryParams| {
+            result_options(&config, engine, languages, &params).unwrap()
+        };
+        let default = key("ocrs", None, OcrQueryParams::default());
+
+        // Spelled-out defaults and output-only options share the result
+        let explicit = OcrQueryParams {
+            preprocessing: PreprocessingParams {
+                preprocess: Some("default".to_string()),
+                ..Default::default()
+            },
+            confidence_scale: Some("fraction".to_string()),
+            format: Some("json".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(key("ocrs", None, explicit), default);
+        let language = config.default_language.clone();
+        assert_eq!(
+            key("ocrs", Some(&language), OcrQueryParams::default()),
+            default
+        );
+
+        assert_ne!(key("leptess", None, OcrQueryParams::default()), default);
+        assert_ne!(key("ocrs", Some("deu"), OcrQueryParams::default()), default);
+        let percent = OcrQueryParams {
+            confidence_scale: Some("percent".to_string()),
+            ..Default::default()
+        };
+        assert_ne!(key("ocrs", None, percent), default);
+        let aggressive = OcrQueryParams {
+            preprocessing: PreprocessingParams {
+                preprocess: Some("aggressive".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_ne!(key("ocrs", None, aggressive), default);
+    }
+
+    #[test]
+    fn test_confidence_stats_from_word_confidences() {
+        let stats = ConfidenceStats::new(0.5, Some(&[0.9, 0.5, 0.7, 0.3]));
+
+        let close = |a: Option<f32>, b: f32| (a.unwrap() - b).abs() < 1e-6;
+        assert!(close(stats.min, 0.3));
+        assert!(close(stats.max, 0.9));
+        assert!((stats.mean - 0.6).abs() < 1e-6);
+        assert!(close(stats.median, 0.6));
+        assert!(close(stats.p25, 0.45));
+        assert!(close(stats.p75, 0.75));
+    }
+
+    #[test]
+    fn test_confidence_stats_without_word_confidences_has_only_mean() {
+        for words in [None, Some(&[][..])] {
+            let stats = ConfidenceStats::new(0.8, words);
+            assert_eq!(
+                serde_json::to_value(&stats).unwrap(),
+                serde_json::json!({"mean": 0.8f32})
+            );
+        }
+    }
+
+    #[test]
+    fn test_confidence_stats_scale_to_percent() {
+        let mut stats = ConfidenceStats::new(0.5, Some(&[0.9, 0.5, 0.7, 0.3]));
+        stats.scale(parse_confidence_scale(Some("percent")).unwrap());
+
+        assert!((stats.min.unwrap() - 30.0).abs() < 1e-4);
+        assert!((stats.max.unwrap() - 90.0).abs() < 1e-4);
+        assert!((stats.mean - 60.0).abs() < 1e-4);
+        assert!((stats.p75.unwrap() - 75.0).abs() < 1e-4);
+        assert!(parse_confidence_scale(Some("permille")).is_err());
+    }
+
+    #[test]
+    fn test_check_min_dimensions_rejects_tiny_images() {
+        let err = check_min_dimensions(&png(16, 16), 20).unwrap_err();
+        assert!(matches!(err, OcrError::InvalidRequest(_)));
+        assert!(err.to_string().contains("too small for OCR"));
+
+        // A wide, short line of text is long enough to read
+        assert!(check_min_dimensions(&png(400, 12), 20).is_ok());
+        assert!(check_min_dimensions(&png(12, 400), 20).is_ok());
+        assert!(check_min_dimensions(&png(20, 12), 20).is_ok());
+        assert!(check_min_dimensions(&png(19, 12), 20).is_err());
+        // 0 disables the check
+        assert!(check_min_dimensions(&png(1, 1), 0).is_ok());
+        // Undecodable data is left for the decoder to reject
+        assert!(check_min_dimensions(b"not an image", 20).is_ok());
+    }
+
+    #[test]
+    fn test_offset_boxes_moves_into_full_image() {
+        let mut boxes = TextBoxes {
+            level: CoordsLevel::Word,
+            boxes: vec![TextBox {
+                text: "Total".to_string(),
+                page: 1,
+                x: 5,
+                y: 7,
+                width: 30,
+                height: 10,
+                confidence: None,
+                polygon: None,
+            }],
+        };
+        offset_boxes(&mut boxes, 240, 20);
+        assert_eq!((boxes.boxes[0].x, boxes.boxes[0].y), (245, 27));
+        assert_eq!((boxes.boxes[0].width, boxes.boxes[0].height), (30, 10));
+    }
+
+    #[test]
+    fn test_min_confidence_is_an_alias_for_fail_below_confidence() {
+        let uri: axum::http::Uri = "/ocr?min_confidence=0.4".parse().unwrap();
+        let Query(params) = Query::<OcrQueryParams>::try_from_uri(&uri).unwrap();
+        assert_eq!(params.fail_below_confidence, Some(0.4));
+
+        let uri: axum::http::Uri = "/ocr?fail_below_confidence=0.4".parse().unwrap();
+        let Query(params) = Query::<OcrQueryParams>::try_from_uri(&uri).unwrap();
+        assert_eq!(params.fail_below_confidence, Some(0.4));
+    }
+
+    #[test]
+    fn test_query_params_parse_numbers_and_flags() {
+        let uri: axum::http::Uri =
+            "/ocr?preprocess=aggressive&gamma=1.5&threshold_window=21&rotation=90&psm=6&stream=true"
+                .parse()
+                .unwrap();
+        let Query(params) = Query::<OcrQueryParams>::try_from_uri(&uri).unwrap();
+        assert_eq!(
+            params.preprocessing.preprocess.as_deref(),
+            Some("aggressive")
+        );
+        assert_eq!(params.preprocessing.gamma, Some(1.5));
+        assert_eq!(params.preprocessing.threshold_window, Some(21));
+        assert_eq!(params.preprocessing.rotation, Some(90));
+        assert_eq!(params.psm, Some(6));
+        assert_eq!(params.stream, Some(true));
+
+        let uri: axum::http::Uri = "/ocr?gamma=bright".parse().unwrap();
+        assert!(Query::<OcrQueryParams>::try_from_uri(&uri).is_err());
+    }
+
+    #[test]
+    fn test_warm_up_runs_bundled_image_through_engine() {
+        assert!(warm_up(stub("primary", None).as_ref()).is_ok());
+
+        let failing = stub("failing", Some(|| OcrError::ProcessingError("boom".into())));
+        assert!(warm_up(failing.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_in_flight_ocr_counts_until_dropped() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let (first, count) = InFlightOcr::start(&counter);
+        assert_eq!(count, 1);
+        let (second, count) = InFlightOcr::start(&counter);
+        assert_eq!(count, 2);
+
+        drop(first);
+        drop(second);
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_ocr_slot_queues_then_times_out() {
+        let slots = Arc::new(Semaphore::new(1));
+        let held = acquire_ocr_slot(&slots, None).await.unwrap();
+
+        let err = acquire_ocr_slot(&slots, Some(Duration::from_millis(10)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, OcrError::ServerBusy(_)));
+        assert_eq!(err.status_and_code().1, "SERVER_BUSY");
+
+        // A queued request gets the slot once it is released
+        let waiting = tokio::spawn({
+            let slots = slots.clone();
+            async move { acquire_ocr_slot(&slots, Some(Duration::from_secs(5))).await }
+        });
+        drop(held);
+        assert!(waiting.await.unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_reserve_job_place_rejects_jobs_over_the_limit() {
+        let queue = Arc::new(Semaphore::new(1));
+        let queued = reserve_job_place(&queue).unwrap();
+
+        let err = reserve_job_place(&queue).unwrap_err();
+        assert_eq!(err.status_and_code().1, "SERVER_BUSY");
+
+        // Starting the job frees its place
+        drop(queued);
+        assert!(reserve_job_place(&queue).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_workers_refuse_requests_until_they_finish() {
+        let abandoned = AbandonedWorkers::default();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let stuck = tokio::task::spawn_blocking(move || {
+            let _ = release_rx.recv();
+        });
+        abandoned.track(stuck);
+        assert!(abandoned.check(2).is_ok());
+
+        let err = abandoned.check(1).unwrap_err();
+        assert_eq!(err.status_and_code().1, "SERVER_BUSY");
+
+        drop(release_tx);
+        for _ in 0..100 {
+            if abandoned.check(1).is_ok() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("the finished call is still counted");
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_ocr_abandons_jobs_over_the_limit() {
+        let quick = tokio::task::spawn_blocking(|| 42);
+        let result = wait_for_ocr("ocrs", quick, None, Some(Duration::from_secs(5))).await;
+        assert_eq!(result.unwrap(), 42);
+
+        let (finished_tx, finished_rx) = std::sync::mpsc::channel();
+        let slow = tokio::task::spawn_blocking(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            let _ = finished_tx.send(());
+        });
+        let err = wait_for_ocr("ocrs", slow, None, Some(Duration::from_millis(10)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, OcrError::Timeout(_)));
+        assert_eq!(err.status_and_code().0, StatusCode::GATEWAY_TIMEOUT);
+        // The abandoned job still runs to the end on its own thread
+        assert!(finished_rx.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+
+    pub(super) fn test_config() -> Config {
+        use clap::Parser;
+        Config::from(crate::Args::parse_from(["activestorage-ocr-server"]))
+    }
+
+    #[test]
+    fn test_parse_json_upload() {
+        let upload = parse_json_upload(
+            br#"{"image_base64": "iVBO\nRw0K", "content_type": "image/png", "languages": "eng", "extra": 1}"#,
+            1024,
+        )
+        .unwrap();
+        assert_eq!(&upload.data.unwrap()[..], b"\x89PNG\r\n");
+        assert_eq!(upload.content_type.as_deref(), Some("image/png"));
+        assert_eq!(upload.languages.as_deref(), Some("eng"));
+        assert_eq!(upload.filename, None);
+
+        for body in [&br#"{"image_base64": "not base64!"}"#[..], b"not json"] {
+            assert!(matches!(
+                parse_json_upload(body, 1024),
+                Err(OcrError::InvalidRequest(_))
+            ));
+        }
+        assert!(matches!(
+            parse_json_upload(br#"{"image_base64": ""}"#, 1024),
+            Err(OcrError::MissingFile)
+        ));
+        // Files can be fetched from a url instead
+        let upload =
+            parse_json_upload(br#"{"url": "https://example.com/scan.png"}"#, 1024).unwrap();
+        assert!(upload.data.is_none());
+        assert_eq!(upload.url.as_deref(), Some("https://example.com/scan.png"));
+        // Limits apply to the decoded file, not the encoded text
+        assert!(parse_json_upload(br#"{"image_base64": "AAAAAAAA"}"#, 6).is_ok());
+        assert!(matches!(
+            parse_json_upload(br#"{"image_base64": "AAAAAAAAAAAA"}"#, 6),
+            Err(OcrError::ImageTooLarge { size: 9, max: 6 })
+        ));
+    }
+
+    #[test]
+    fn test_warmup_status_lists_cached_languages() {
+        let model = |name: &str| CachedModel {
+            name: name.to_string(),
+            size_bytes: 1,
+        };
+        let status = warmup_status(vec![
+            model("tessdata/eng.traineddata"),
+            model("tessdata_best/deu.traineddata"),
+            model("tessdata_best/eng.traineddata"),
+            model("text-detection.rten"),
+        ]);
+
+        assert_eq!(status.models.len(), 4);
+        assert_eq!(status.languages, vec!["deu", "eng"]);
+    }
+
+    #[test]
+    fn test_parse_char_filter() {
+        assert_eq!(parse_char_filter(None, None).unwrap(), (None, None));
+        assert_eq!(
+            parse_char_filter(Some("ABC123-"), None).unwrap(),
+            (Some("ABC123-".to_string()), None)
+        );
+        assert_eq!(
+            parse_char_filter(None, Some("|")).unwrap(),
+            (None, Some("|".to_string()))
+        );
+        for (whitelist, blacklist) in [
+            (Some("0123456789"), Some("O")),
+            (Some(""), None),
+            (None, Some("a\nb")),
+        ] {
+            assert!(matches!(
+                parse_char_filter(whitelist, blacklist),
+                Err(OcrError::InvalidRequest(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_beam_width() {
+        let beam = Some(DecodeMethod::BeamSearch);
+        assert_eq!(parse_beam_width(None, None).unwrap(), (None, None));
+        assert_eq!(parse_beam_width(beam, None).unwrap(), (beam, None));
+        // A beam width on its own selects beam search
+        assert_eq!(parse_beam_width(None, Some(20)).unwrap(), (beam, Some(20)));
+        assert_eq!(
+            parse_beam_width(beam, Some(MAX_BEAM_WIDTH)).unwrap(),
+            (beam, Some(MAX_BEAM_WIDTH))
+        );
+
+        for (decode, width) in [
+            (None, 0),
+            (beam, MAX_BEAM_WIDTH + 1),
+            (Some(DecodeMethod::Greedy), 20),
+        ] {
+            assert!(matches!(
+                parse_beam_width(decode, Some(width)),
+                Err(OcrError::InvalidRequest(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_truncate_words_keeps_layout_of_kept_words() {
+        let mut text = "Invoice  42\nTotal: $10\n\nThanks".to_string();
+        assert!(truncate_words(&mut text, 3));
+        assert_eq!(text, "Invoice  42\nTotal:");
+
+        // Exactly max_words words is not truncated
+        let mut text = "one two\n".to_string();
+        assert!(!truncate_words(&mut text, 2));
+        assert_eq!(text, "one two\n");
+        assert!(!truncate_words(&mut String::new(), 1));
+    }
+}
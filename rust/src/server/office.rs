@@ -0,0 +1,148 @@
+//! OCR of the images embedded in DOCX and ODT documents
+//!
+//! Office documents are only recognized with the documents feature; without
+//! it they are rejected like any other unrecognized upload.
+
+#[cfg(feature = "documents")]
+use super::ocr_images;
+use super::{ImageOcr, OcrJob};
+#[cfg(feature = "documents")]
+use crate::documents;
+use crate::engine::{OcrEngine, OcrOptions, OcrResult};
+use crate::error::OcrError;
+use crate::preprocessing::Pipeline;
+use std::sync::Arc;
+
+/// MIME type of a DOCX or ODT upload
+#[cfg(feature = "documents")]
+pub(super) fn office_document_mime(mime: &str, data: &[u8]) -> Option<&'static str> {
+    documents::document_mime(mime, data)
+}
+
+/// Office documents are only recognized with the documents feature
+#[cfg(not(feature = "documents"))]
+pub(super) fn office_document_mime(_mime: &str, _data: &[u8]) -> Option<&'static str> {
+    None
+}
+
+/// OCR an uploaded DOCX or ODT document
+pub(super) fn ocr_uploaded_document(
+    job: &OcrJob,
+    on_page: &dyn Fn(u32, &OcrResult),
+) -> Result<ImageOcr, OcrError> {
+    let mut warnings = Vec::new();
+    let pipeline = job.pipeline(&mut warnings);
+    if job.split {
+        warnings.push("split is not supported for documents, ignored".to_string());
+    }
+    if job.preprocessing.rotation.is_some() {
+        warnings.push("rotation is not supported for documents, ignored".to_string());
+    }
+
+    let mut ocr = ocr_document(
+        &job.data,
+        job.max_file_size,
+        &pipeline,
+        &job.engine,
+        &job.fallback,
+        &job.options,
+        on_page,
+    )?;
+    ocr.result.warnings.extend(warnings);
+    Ok(ocr)
+}
+
+/// OCR each image embedded in a DOCX or ODT document, like the images of a
+/// scanned PDF, calling `on_page` as each one completes
+#[cfg(feature = "documents")]
+fn ocr_document(
+    data: &[u8],
+    max_size: usize,
+    pipeline: &Pipeline,
+    engine: &Arc<dyn OcrEngine>,
+    fallback: &Option<Arc<dyn OcrEngine>>,
+    options: &OcrOptions,
+    on_page: &dyn Fn(u32, &OcrResult),
+) -> Result<ImageOcr, OcrError> {
+    let documents::DocumentImages {
+        images,
+        mut warnings,
+    } = documents::extract_images(data, max_size)?;
+    tracing::info!("Extracted {} images from document", images.len());
+
+    if images.is_empty() {
+        warnings.push("No images found in document".to_string());
+    }
+
+    ocr_images(
+        images,
+        |image, _| Ok(image),
+        warnings,
+        pipeline,
+        engine,
+        fallback,
+        options,
+        on_page,
+    )
+}
+
+/// Office documents are only recognized with the documents feature
+#[cfg(not(feature = "documents"))]
+fn ocr_document(
+    _data: &[u8],
+    _max_size: usize,
+    _pipeline: &Pipeline,
+    _engine: &Arc<dyn OcrEngine>,
+    _fallback: &Option<Arc<dyn OcrEngine>>,
+    _options: &OcrOptions,
+    _on_page: &dyn Fn(u32, &OcrResult),
+) -> Result<ImageOcr, OcrError> {
+    Err(OcrError::UnsupportedFormat(
+        "Office documents require the documents feature".to_string(),
+    ))
+}
+
+#[cfg(all(test, feature = "documents"))]
+mod tests {
+    use super::*;
+    use crate::preprocessing::Preset;
+    use crate::server::tests::stub;
+
+    #[test]
+    fn test_ocr_document_reports_each_image_as_a_page() {
+        use std::io::Write;
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for name in [
+            "word/document.xml",
+            "word/media/image1.png",
+            "word/media/image2.png",
+        ] {
+            let contents = if name.ends_with(".png") {
+                include_bytes!("../../tests/fixtures/sample_text.png").as_slice()
+            } else {
+                b"<w:document/>".as_slice()
+            };
+            zip.start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        let docx = zip.finish().unwrap().into_inner();
+
+        let pages = std::sync::Mutex::new(Vec::new());
+        let combined = ocr_document(
+            &docx,
+            usize::MAX,
+            &Pipeline::new(Preset::Minimal),
+            &stub("primary", None),
+            &None,
+            &OcrOptions::default(),
+            &|page, _| pages.lock().unwrap().push(page),
+        )
+        .unwrap();
+
+        assert_eq!(*pages.lock().unwrap(), vec![1, 2]);
+        assert_eq!(combined.result.text, "from primary\n\nfrom primary");
+        assert_eq!(combined.engine, "primary");
+    }
+}
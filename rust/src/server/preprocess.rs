@@ -0,0 +1,678 @@
+//! Preprocessing options and `/preprocess` previews
+//!
+//! OCR requests and previews take the same preprocessing query parameters,
+//! so a preview shows exactly what an OCR request with those parameters
+//! hands to the engine.
+
+use super::{acquire_ocr_slot, decode_image, read_image_upload, AppState};
+use crate::error::OcrError;
+use crate::preprocessing::{
+    GrayscaleMethod, Pipeline, Preset, ResizeMethod, SauvolaParams, StepName, ThresholdMethod,
+};
+use crate::resolution;
+use axum::{
+    extract::{Multipart, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use image::metadata::Orientation;
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+/// Query parameters that control preprocessing, taken by OCR requests and
+/// previews alike
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct PreprocessingParams {
+    /// Preprocessing preset: none, minimal, default, aggressive
+    #[serde(default)]
+    pub preprocess: Option<String>,
+    /// Comma-separated preprocessing steps to run in order, instead of a preset
+    #[serde(default)]
+    pub steps: Option<String>,
+    /// Gamma correction applied before the other preprocessing steps
+    /// (above 1 brightens midtones)
+    #[serde(default, deserialize_with = "from_text")]
+    pub gamma: Option<f32>,
+    /// How the grayscale step turns color into gray: luminosity (default),
+    /// max_contrast, average
+    #[serde(default)]
+    pub grayscale: Option<String>,
+    /// How the resize step picks its scale: dpi (default), adaptive
+    #[serde(default)]
+    pub resize: Option<String>,
+    /// How the threshold step binarizes: sauvola (default), otsu
+    #[serde(default)]
+    pub threshold: Option<String>,
+    /// Sauvola window size in pixels (odd, default 15)
+    #[serde(default, deserialize_with = "from_text")]
+    pub threshold_window: Option<u32>,
+    /// Sauvola sensitivity (default 0.2)
+    #[serde(default, deserialize_with = "from_text")]
+    pub threshold_k: Option<f32>,
+    /// Image skew is measured on: resized (default), original
+    #[serde(default)]
+    pub skew_detection: Option<String>,
+    /// Clockwise rotation applied before preprocessing (0, 90, 180, 270),
+    /// overriding the image's EXIF orientation
+    #[serde(default, deserialize_with = "from_text")]
+    pub rotation: Option<u32>,
+}
+
+/// Deserialize an optional number from query text
+///
+/// serde_urlencoded parses numbers itself, except in flattened structs like
+/// [`PreprocessingParams`], whose fields reach it buffered as text.
+fn from_text<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|text| text.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Validated preprocessing parameters
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct PreprocessingOptions {
+    pub preset: Preset,
+    /// Steps run instead of the preset's
+    pub steps: Option<Vec<StepName>>,
+    /// Gamma correction run before the preset or steps
+    pub gamma: Option<f32>,
+    /// Grayscale conversion (None uses the default)
+    pub grayscale: Option<GrayscaleMethod>,
+    /// Scale selection for the resize step (None uses the default)
+    pub resize: Option<ResizeMethod>,
+    /// Binarization for the threshold step (None uses the default)
+    pub threshold_method: Option<ThresholdMethod>,
+    /// Sauvola window and sensitivity (None uses the defaults)
+    pub sauvola: Option<SauvolaParams>,
+    pub detect_skew_before_resize: bool,
+    /// Manual rotation, replacing the image's EXIF orientation
+    pub rotation: Option<Orientation>,
+}
+
+impl PreprocessingParams {
+    /// Validate the parameters, running `default_preset` unless a preset or
+    /// steps are given
+    pub(super) fn parse(&self, default_preset: Preset) -> Result<PreprocessingOptions, OcrError> {
+        Ok(PreprocessingOptions {
+            preset: parse_preset(self.preprocess.as_deref())?.unwrap_or(default_preset),
+            steps: parse_steps(self.steps.as_deref(), self.preprocess.as_deref())?,
+            gamma: parse_gamma(self.gamma)?,
+            grayscale: parse_grayscale(self.grayscale.as_deref())?,
+            resize: parse_resize(self.resize.as_deref())?,
+            threshold_method: parse_threshold(self.threshold.as_deref())?,
+            sauvola: parse_sauvola(self.threshold_window, self.threshold_k)?,
+            detect_skew_before_resize: parse_skew_detection(self.skew_detection.as_deref())?,
+            rotation: parse_rotation(self.rotation)?,
+        })
+    }
+}
+
+impl PreprocessingOptions {
+    /// Whether anything runs before OCR, so there are stats to report
+    pub(super) fn runs(&self) -> bool {
+        self.preset != Preset::None || self.steps.is_some() || self.gamma.is_some()
+    }
+
+    /// Build the preprocessing pipeline
+    ///
+    /// Steps, when given, replace the preset. Probe mode replaces both with a
+    /// cheap downscale. Options that don't apply to the pipeline are ignored
+    /// with a warning.
+    pub(super) fn pipeline(
+        &self,
+        sharpen_skip_threshold: Option<f32>,
+        probe_max_dimension: Option<u32>,
+        track_memory: bool,
+        warnings: &mut Vec<String>,
+    ) -> Pipeline {
+        let mut pipeline = match probe_max_dimension {
+            Some(max_dimension) => Pipeline::probe(max_dimension),
+            None => match &self.steps {
+                Some(steps) => Pipeline::from_steps(steps.clone()),
+                None => Pipeline::new(self.preset),
+            },
+        };
+        if track_memory {
+            pipeline = pipeline.track_memory();
+        }
+        if let Some(gamma) = self.gamma {
+            pipeline = pipeline.gamma(gamma);
+        }
+        if let Some(method) = self.grayscale {
+            if pipeline.grayscales() {
+                pipeline = pipeline.grayscale_method(method);
+            } else {
+                warnings.push(
+                    "grayscale doesn't apply to the none preset or steps without grayscale, ignored"
+                        .to_string(),
+                );
+            }
+        }
+        if let Some(method) = self.resize {
+            if pipeline.resizes() {
+                pipeline = pipeline.resize_method(method);
+            } else {
+                warnings.push(
+                    "resize only applies to the default and aggressive presets or steps with resize, ignored"
+                        .to_string(),
+                );
+            }
+        }
+        if let Some(method) = self.threshold_method {
+            if pipeline.thresholds() {
+                pipeline = pipeline.threshold_method(method);
+            } else {
+                warnings.push(
+                    "threshold only applies to the aggressive preset or steps with threshold, ignored"
+                        .to_string(),
+                );
+            }
+        }
+        if let Some(params) = self.sauvola {
+            if !pipeline.thresholds() {
+                warnings.push(
+                    "threshold_window and threshold_k only apply to the aggressive preset or steps with threshold, ignored"
+                        .to_string(),
+                );
+            } else if self.threshold_method == Some(ThresholdMethod::Otsu) {
+                warnings.push(
+                    "threshold_window and threshold_k only apply to threshold=sauvola, ignored"
+                        .to_string(),
+                );
+            } else {
+                pipeline = pipeline.sauvola(params);
+            }
+        }
+        if let Some(threshold) = sharpen_skip_threshold {
+            pipeline = pipeline.skip_sharpen_above(threshold);
+        }
+        if self.detect_skew_before_resize {
+            if pipeline.deskews() {
+                pipeline = pipeline.detect_skew_before_resize();
+            } else {
+                warnings.push(
+                    "skew_detection only applies to the aggressive preset or steps with deskew, ignored"
+                        .to_string(),
+                );
+            }
+        }
+        pipeline
+    }
+}
+
+/// Parse a preprocessing preset, None if not specified
+fn parse_preset(preset: Option<&str>) -> Result<Option<Preset>, OcrError> {
+    preset
+        .map(|s| {
+            Preset::from_str(s).ok_or_else(|| {
+                OcrError::InvalidRequest(format!(
+                    "Unknown preprocessing preset '{}'. Valid: none, minimal, default, aggressive",
+                    s
+                ))
+            })
+        })
+        .transpose()
+}
+
+/// Parse a comma-separated list of preprocessing steps, which can't be
+/// combined with a preset
+fn parse_steps(
+    steps: Option<&str>,
+    preset: Option<&str>,
+) -> Result<Option<Vec<StepName>>, OcrError> {
+    let Some(steps) = steps else {
+        return Ok(None);
+    };
+    if preset.is_some() {
+        return Err(OcrError::InvalidRequest(
+            "Pass either preprocess or steps, not both".to_string(),
+        ));
+    }
+    let valid = || {
+        StepName::ALL
+            .iter()
+            .map(StepName::as_str)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let steps = steps
+        .split(',')
+        .map(|step| {
+            StepName::from_str(step.trim()).ok_or_else(|| {
+                OcrError::InvalidRequest(format!(
+                    "Unknown preprocessing step '{}'. Valid: {}",
+                    step.trim(),
+                    valid()
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Some(steps))
+}
+
+/// Parse a grayscale method, None if not specified
+fn parse_grayscale(method: Option<&str>) -> Result<Option<GrayscaleMethod>, OcrError> {
+    method
+        .map(|s| {
+            GrayscaleMethod::from_str(s).ok_or_else(|| {
+                OcrError::InvalidRequest(format!(
+                    "Unknown grayscale method '{}'. Valid: luminosity, max_contrast, average",
+                    s
+                ))
+            })
+        })
+        .transpose()
+}
+
+/// Parse a resize method, None if not specified
+fn parse_resize(method: Option<&str>) -> Result<Option<ResizeMethod>, OcrError> {
+    method
+        .map(|s| {
+            ResizeMethod::from_str(s).ok_or_else(|| {
+                OcrError::InvalidRequest(format!(
+                    "Unknown resize method '{}'. Valid: dpi, adaptive",
+                    s
+                ))
+            })
+        })
+        .transpose()
+}
+
+/// Parse a threshold method, None if not specified
+fn parse_threshold(method: Option<&str>) -> Result<Option<ThresholdMethod>, OcrError> {
+    method
+        .map(|s| {
+            ThresholdMethod::from_str(s).ok_or_else(|| {
+                OcrError::InvalidRequest(format!(
+                    "Unknown threshold method '{}'. Valid: sauvola, otsu",
+                    s
+                ))
+            })
+        })
+        .transpose()
+}
+
+/// Sauvola windows accepted by `threshold_window=`
+const MIN_THRESHOLD_WINDOW: u32 = 3;
+const MAX_THRESHOLD_WINDOW: u32 = 255;
+
+/// Sauvola sensitivities accepted by `threshold_k=`
+const MIN_THRESHOLD_K: f32 = 0.01;
+const MAX_THRESHOLD_K: f32 = 1.0;
+
+/// Validate a requested Sauvola window and sensitivity, None if neither is
+/// specified
+///
+/// A missing value keeps its default.
+fn parse_sauvola(
+    window_size: Option<u32>,
+    k: Option<f32>,
+) -> Result<Option<SauvolaParams>, OcrError> {
+    if window_size.is_none() && k.is_none() {
+        return Ok(None);
+    }
+    let defaults = SauvolaParams::default();
+    let window_size = window_size.unwrap_or(defaults.window_size);
+    if window_size.is_multiple_of(2)
+        || !(MIN_THRESHOLD_WINDOW..=MAX_THRESHOLD_WINDOW).contains(&window_size)
+    {
+        return Err(OcrError::InvalidRequest(format!(
+            "Invalid threshold_window {}. Valid: odd numbers from {} to {}",
+            window_size, MIN_THRESHOLD_WINDOW, MAX_THRESHOLD_WINDOW
+        )));
+    }
+    let k = k.unwrap_or(defaults.k);
+    if !(MIN_THRESHOLD_K..=MAX_THRESHOLD_K).contains(&k) {
+        return Err(OcrError::InvalidRequest(format!(
+            "Invalid threshold_k {}. Valid: {}-{}",
+            k, MIN_THRESHOLD_K, MAX_THRESHOLD_K
+        )));
+    }
+    Ok(Some(SauvolaParams { window_size, k }))
+}
+
+/// Gamma values accepted by `gamma=`; outside this range scans turn almost
+/// entirely black or white
+const MIN_GAMMA: f32 = 0.1;
+const MAX_GAMMA: f32 = 10.0;
+
+/// Validate a requested gamma correction
+fn parse_gamma(gamma: Option<f32>) -> Result<Option<f32>, OcrError> {
+    match gamma {
+        Some(gamma) if !(MIN_GAMMA..=MAX_GAMMA).contains(&gamma) => {
+            Err(OcrError::InvalidRequest(format!(
+                "Invalid gamma {}. Valid: {}-{}",
+                gamma, MIN_GAMMA, MAX_GAMMA
+            )))
+        }
+        gamma => Ok(gamma),
+    }
+}
+
+/// Parse a clockwise rotation in degrees
+fn parse_rotation(rotation: Option<u32>) -> Result<Option<Orientation>, OcrError> {
+    rotation
+        .map(|degrees| match degrees {
+            0 => Ok(Orientation::NoTransforms),
+            90 => Ok(Orientation::Rotate90),
+            180 => Ok(Orientation::Rotate180),
+            270 => Ok(Orientation::Rotate270),
+            other => Err(OcrError::InvalidRequest(format!(
+                "Invalid rotation {}. Valid: 0, 90, 180, 270",
+                other
+            ))),
+        })
+        .transpose()
+}
+
+/// Parse where skew is detected, returning true to detect it on the original
+/// image before resizing
+fn parse_skew_detection(skew_detection: Option<&str>) -> Result<bool, OcrError> {
+    match skew_detection {
+        None | Some("resized") => Ok(false),
+        Some("original") => Ok(true),
+        Some(other) => Err(OcrError::InvalidRequest(format!(
+            "Unknown skew_detection '{}'. Valid: resized, original",
+            other
+        ))),
+    }
+}
+
+/// Query parameters for preprocessing previews
+#[derive(Debug, Deserialize, Default)]
+pub struct PreprocessQueryParams {
+    #[serde(flatten)]
+    pub preprocessing: PreprocessingParams,
+    /// Return the image after every step instead of only the final image
+    #[serde(default)]
+    pub debug_steps: Option<bool>,
+}
+
+/// Handle preprocess requests: return the image as the OCR engine would see it
+///
+/// Responds with a PNG, or with `debug_steps=true` a `multipart/mixed` body
+/// holding the original followed by the output of each step. Preprocessing
+/// is as heavy as OCR's, so it waits for an OCR slot too.
+pub(super) async fn handle_preprocess(
+    State(app): State<AppState>,
+    Query(params): Query<PreprocessQueryParams>,
+    mut multipart: Multipart,
+) -> Result<Response, OcrError> {
+    let state = app.snapshot();
+    let preprocessing = params.preprocessing.parse(Preset::default())?;
+    let debug_steps = params.debug_steps.unwrap_or(false);
+
+    let slot = acquire_ocr_slot(&app.ocr_slots, state.config.queue_timeout).await?;
+    let data = read_image_upload(&mut multipart, &state.config, "/preprocess").await?;
+
+    // A preview has nowhere to report options that don't apply
+    let mut pipeline = preprocessing.pipeline(
+        state.config.sharpen_skip_threshold,
+        None,
+        false,
+        &mut Vec::new(),
+    );
+    if let Some(dpi) = resolution::dpi(&data) {
+        pipeline = pipeline.input_dpi(dpi);
+    }
+    if debug_steps {
+        pipeline = pipeline.keep_intermediates();
+    }
+    let rotation = preprocessing.rotation;
+
+    // Decoding, preprocessing and encoding are all CPU-bound
+    let parts = tokio::task::spawn_blocking(move || {
+        let _slot = slot;
+        let image = decode_image(&data, rotation, &mut Vec::new())?;
+        let original = debug_steps.then(|| image.clone());
+        let result = pipeline
+            .process(image)
+            .map_err(|e| OcrError::PreprocessingError(format!("Preprocessing failed: {}", e)))?;
+
+        tracing::info!(
+            "Preprocessed image with '{}' preset in {}ms",
+            result.preset,
+            result.total_time_ms
+        );
+
+        let Some(original) = original else {
+            return Ok(vec![(
+                "preprocessed".to_string(),
+                encode_png(&result.image)?,
+            )]);
+        };
+        let mut parts = vec![("original".to_string(), encode_png(&original)?)];
+        for step in &result.intermediates {
+            parts.push((step.name.clone(), encode_png(&step.image)?));
+        }
+        Ok::<_, OcrError>(parts)
+    })
+    .await
+    .map_err(|e| OcrError::Internal(format!("Preprocessing task failed: {}", e)))??;
+
+    if !debug_steps {
+        let png = parts
+            .into_iter()
+            .next()
+            .map(|(_, png)| png)
+            .unwrap_or_default();
+        return Ok(([(header::CONTENT_TYPE, "image/png")], png).into_response());
+    }
+
+    let boundary = format!(
+        "preprocess-steps-{:x}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+    );
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            format!("multipart/mixed; boundary={}", boundary),
+        )],
+        multipart_mixed(&boundary, &parts),
+    )
+        .into_response())
+}
+
+/// Encode an image as PNG
+pub(super) fn encode_png(image: &DynamicImage) -> Result<Vec<u8>, OcrError> {
+    let mut png = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| OcrError::Internal(format!("Failed to encode PNG: {}", e)))?;
+    Ok(png)
+}
+
+/// Build a `multipart/mixed` body with one PNG part per named image
+fn multipart_mixed(boundary: &str, parts: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (name, png) in parts {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Type: image/png\r\nContent-Disposition: attachment; filename=\"{}.png\"\r\n\r\n",
+                boundary, name
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(png);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multipart_mixed_has_one_part_per_image() {
+        let parts = vec![
+            ("original".to_string(), b"one".to_vec()),
+            ("grayscale".to_string(), b"two".to_vec()),
+        ];
+        let body = String::from_utf8(multipart_mixed("b0undary", &parts)).unwrap();
+
+        assert_eq!(
+            body,
+            "--b0undary\r\nContent-Type: image/png\r\n\
+             Content-Disposition: attachment; filename=\"original.png\"\r\n\r\none\r\n\
+             --b0undary\r\nContent-Type: image/png\r\n\
+             Content-Disposition: attachment; filename=\"grayscale.png\"\r\n\r\ntwo\r\n\
+             --b0undary--\r\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_steps() {
+        assert_eq!(parse_steps(None, None).unwrap(), None);
+        assert_eq!(
+            parse_steps(Some("grayscale, threshold,Deskew"), None).unwrap(),
+            Some(vec![
+                StepName::Grayscale,
+                StepName::Threshold,
+                StepName::Deskew
+            ])
+        );
+
+        for (steps, preset) in [
+            (Some("grayscale,blur"), None),
+            (Some(""), None),
+            (Some("grayscale,"), None),
+            (Some("grayscale"), Some("minimal")),
+        ] {
+            assert!(matches!(
+                parse_steps(steps, preset),
+                Err(OcrError::InvalidRequest(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_grayscale() {
+        assert_eq!(parse_grayscale(None).unwrap(), None);
+        assert_eq!(
+            parse_grayscale(Some("max_contrast")).unwrap(),
+            Some(GrayscaleMethod::MaxContrast)
+        );
+        assert!(matches!(
+            parse_grayscale(Some("lab")),
+            Err(OcrError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_resize() {
+        assert_eq!(parse_resize(None).unwrap(), None);
+        assert_eq!(
+            parse_resize(Some("adaptive")).unwrap(),
+            Some(ResizeMethod::Adaptive)
+        );
+        assert!(matches!(
+            parse_resize(Some("x_height")),
+            Err(OcrError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_threshold() {
+        assert_eq!(parse_threshold(None).unwrap(), None);
+        assert_eq!(
+            parse_threshold(Some("otsu")).unwrap(),
+            Some(ThresholdMethod::Otsu)
+        );
+        assert!(matches!(
+            parse_threshold(Some("niblack")),
+            Err(OcrError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_sauvola() {
+        assert_eq!(parse_sauvola(None, None).unwrap(), None);
+        assert_eq!(
+            parse_sauvola(Some(7), None).unwrap(),
+            Some(SauvolaParams {
+                window_size: 7,
+                k: 0.2
+            })
+        );
+        assert_eq!(
+            parse_sauvola(None, Some(0.5)).unwrap(),
+            Some(SauvolaParams {
+                window_size: 15,
+                k: 0.5
+            })
+        );
+        for window_size in [0, 1, 2, 8, 257] {
+            assert!(matches!(
+                parse_sauvola(Some(window_size), None),
+                Err(OcrError::InvalidRequest(_))
+            ));
+        }
+        for k in [0.0, -0.2, 1.5, f32::NAN] {
+            assert!(matches!(
+                parse_sauvola(None, Some(k)),
+                Err(OcrError::InvalidRequest(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_gamma() {
+        assert_eq!(parse_gamma(None).unwrap(), None);
+        assert_eq!(parse_gamma(Some(1.5)).unwrap(), Some(1.5));
+        for gamma in [0.0, -1.0, 0.05, 11.0, f32::NAN] {
+            assert!(matches!(
+                parse_gamma(Some(gamma)),
+                Err(OcrError::InvalidRequest(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_rotation() {
+        assert_eq!(parse_rotation(None).unwrap(), None);
+        assert_eq!(
+            parse_rotation(Some(90)).unwrap(),
+            Some(Orientation::Rotate90)
+        );
+        assert!(matches!(
+            parse_rotation(Some(45)),
+            Err(OcrError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_preprocess_query_params_share_the_preprocessing_params() {
+        let uri: axum::http::Uri =
+            "/preprocess?steps=grayscale,threshold&threshold_k=0.3&rotation=180&debug_steps=true"
+                .parse()
+                .unwrap();
+        let Query(params) = Query::<PreprocessQueryParams>::try_from_uri(&uri).unwrap();
+        assert_eq!(params.debug_steps, Some(true));
+
+        let options = params.preprocessing.parse(Preset::Minimal).unwrap();
+        assert_eq!(
+            options.steps,
+            Some(vec![StepName::Grayscale, StepName::Threshold])
+        );
+        assert_eq!(
+            options.sauvola,
+            Some(SauvolaParams {
+                k: 0.3,
+                ..Default::default()
+            })
+        );
+        assert_eq!(options.rotation, Some(Orientation::Rotate180));
+        // No preset was given, so the default one is kept
+        assert_eq!(options.preset, Preset::Minimal);
+    }
+}
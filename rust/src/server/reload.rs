@@ -0,0 +1,200 @@
+//! Token-protected `POST /reload`
+//!
+//! Rebuilds the engine registry with the given config overrides and swaps it
+//! in. Requests already running finish on the registry they started with.
+
+use super::{check_beam_width, info_response, AppState, InfoResponse, Snapshot};
+use crate::config::Config;
+use crate::engine::DecodeMethod;
+use crate::engines::EngineRegistry;
+use crate::error::OcrError;
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap},
+    response::Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Settings that can be changed by `POST /reload`
+///
+/// Omitted fields keep their current value. Listener settings (host, port,
+/// timeouts, body limit) only take effect on restart.
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ReloadRequest {
+    pub default_language: Option<String>,
+    pub handwriting_model_url: Option<String>,
+    pub fallback_engine: Option<String>,
+    pub decode_method: Option<String>,
+    pub beam_width: Option<u32>,
+}
+
+impl ReloadRequest {
+    /// Apply the overrides to a copy of the current config
+    fn apply(self, config: &Config) -> Result<Config, OcrError> {
+        let mut config = config.clone();
+
+        if let Some(language) = self.default_language {
+            config.default_language = language;
+        }
+        // An empty string clears the optional settings
+        if let Some(url) = self.handwriting_model_url {
+            config.handwriting_model_url = Some(url).filter(|url| !url.is_empty());
+        }
+        if let Some(engine) = self.fallback_engine {
+            config.fallback_engine = Some(engine).filter(|engine| !engine.is_empty());
+        }
+        if let Some(method) = self.decode_method {
+            config.decode_method = DecodeMethod::from_str(&method).ok_or_else(|| {
+                OcrError::InvalidRequest(format!(
+                    "Unknown decode method '{}'. Valid: greedy, beam_search",
+                    method
+                ))
+            })?;
+        }
+        if let Some(width) = self.beam_width {
+            check_beam_width(width)?;
+            config.beam_width = width;
+        }
+
+        Ok(config)
+    }
+}
+
+/// Handle reload requests: rebuild the engine registry and swap it in
+pub(super) async fn handle_reload(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<InfoResponse>, OcrError> {
+    let current = state.snapshot();
+    if !is_authorized(&headers, current.config.admin_token.as_deref()) {
+        return Err(OcrError::Unauthorized(
+            "missing or invalid bearer token".to_string(),
+        ));
+    }
+
+    let request: ReloadRequest = if body.is_empty() {
+        ReloadRequest::default()
+    } else {
+        serde_json::from_slice(&body)
+            .map_err(|e| OcrError::InvalidRequest(format!("Invalid reload body: {}", e)))?
+    };
+
+    let _guard = state.reload_lock.lock().await;
+    // Re-read under the lock so back-to-back reloads build on each other
+    let config = request.apply(&state.snapshot().config)?;
+
+    tracing::info!("Reloading engines");
+    let (registry, config) = tokio::task::spawn_blocking(move || {
+        EngineRegistry::new(&config).map(|registry| (registry, config))
+    })
+    .await
+    .map_err(|e| OcrError::Internal(format!("Reload task failed: {}", e)))??;
+
+    check_fallback(&config, &registry).map_err(OcrError::InvalidRequest)?;
+
+    let snapshot = Snapshot {
+        registry: Arc::new(registry),
+        config: Arc::new(config),
+    };
+    state.swap(snapshot.clone());
+    tracing::info!("Reloaded engines: {:?}", snapshot.registry.list());
+
+    Ok(Json(info_response(&snapshot)))
+}
+
+/// Check the `Authorization: Bearer` header against the configured token
+fn is_authorized(headers: &HeaderMap, token: Option<&str>) -> bool {
+    let Some(token) = token else {
+        return false;
+    };
+
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|provided| constant_time_eq(provided.as_bytes(), token.as_bytes()))
+}
+
+/// Compare without short-circuiting so timing doesn't reveal the matching prefix
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Ensure the configured fallback engine exists in the registry
+pub(super) fn check_fallback(config: &Config, registry: &EngineRegistry) -> Result<(), String> {
+    match config.fallback_engine.as_deref() {
+        Some(fallback) if registry.get(fallback).is_none() => Err(format!(
+            "Unknown fallback engine '{}'. Available engines: {:?}",
+            fallback,
+            registry.list()
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::tests::test_config;
+
+    fn bearer(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_is_authorized_requires_matching_bearer_token() {
+        assert!(is_authorized(&bearer("Bearer s3cret"), Some("s3cret")));
+        assert!(!is_authorized(&bearer("Bearer wrong"), Some("s3cret")));
+        assert!(!is_authorized(&bearer("s3cret"), Some("s3cret")));
+        assert!(!is_authorized(&HeaderMap::new(), Some("s3cret")));
+        // No configured token means nothing is authorized
+        assert!(!is_authorized(&bearer("Bearer "), None));
+    }
+
+    #[test]
+    fn test_reload_request_applies_overrides() {
+        let mut config = test_config();
+        config.handwriting_model_url = Some("https://example.com/old.rten".to_string());
+
+        let request: ReloadRequest = serde_json::from_str(
+            r#"{"default_language": "deu", "handwriting_model_url": "", "decode_method": "beam_search", "beam_width": 8}"#,
+        )
+        .unwrap();
+        let updated = request.apply(&config).unwrap();
+
+        assert_eq!(updated.default_language, "deu");
+        assert_eq!(updated.handwriting_model_url, None);
+        assert_eq!(updated.decode_method, DecodeMethod::BeamSearch);
+        assert_eq!(updated.beam_width, 8);
+        // Untouched settings carry over
+        assert_eq!(updated.max_file_size, config.max_file_size);
+        assert_eq!(updated.fallback_engine, config.fallback_engine);
+    }
+
+    #[test]
+    fn test_reload_request_rejects_invalid_values() {
+        let err = ReloadRequest {
+            decode_method: Some("fancy".to_string()),
+            ..Default::default()
+        }
+        .apply(&test_config())
+        .unwrap_err();
+        assert!(matches!(err, OcrError::InvalidRequest(_)));
+
+        let err = ReloadRequest {
+            beam_width: Some(0),
+            ..Default::default()
+        }
+        .apply(&test_config())
+        .unwrap_err();
+        assert!(matches!(err, OcrError::InvalidRequest(_)));
+
+        assert!(serde_json::from_str::<ReloadRequest>(r#"{"port": 1}"#).is_err());
+    }
+}
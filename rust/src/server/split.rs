@@ -0,0 +1,323 @@
+//! OCR of images holding several documents (`split=auto`)
+//!
+//! Each document found by [`split_regions`] is preprocessed and recognized on
+//! its own, then the results are combined in reading order.
+
+use super::{
+    line_responses, ocr_image, offset_box, offset_boxes, Bounds, BoxesResponse, ImageOcr,
+    PreprocessingStats, RegionOcrResponse,
+};
+use crate::engine::{OcrEngine, OcrOptions, OcrResult, TextBoxes};
+use crate::error::OcrError;
+use crate::preprocessing::segment::{split_regions, Region};
+use crate::preprocessing::Pipeline;
+use image::DynamicImage;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Most documents accepted by split=auto before treating the image as one
+const MAX_SPLIT_REGIONS: usize = 16;
+
+/// Parse the split mode, returning true to OCR each document in the image
+/// separately
+pub(super) fn parse_split(split: Option<&str>) -> Result<bool, OcrError> {
+    match split {
+        None | Some("none") => Ok(false),
+        Some("auto") => Ok(true),
+        Some(other) => Err(OcrError::InvalidRequest(format!(
+            "Unknown split mode '{}'. Valid: none, auto",
+            other
+        ))),
+    }
+}
+
+/// Find the separate documents in an image for split=auto
+///
+/// Falls back to the whole image when it is blank or splits into too many
+/// pieces to plausibly be separate documents.
+pub(super) fn document_regions(image: &DynamicImage, warnings: &mut Vec<String>) -> Vec<Region> {
+    let regions = split_regions(image);
+    if regions.len() > MAX_SPLIT_REGIONS {
+        warnings.push(format!(
+            "Found {} regions, more than the {} split=auto supports, processed the whole image",
+            regions.len(),
+            MAX_SPLIT_REGIONS
+        ));
+    }
+    if regions.is_empty() || regions.len() > MAX_SPLIT_REGIONS {
+        return vec![Region {
+            x: 0,
+            y: 0,
+            width: image.width(),
+            height: image.height(),
+        }];
+    }
+    regions
+}
+
+/// OCR each region of an image separately
+///
+/// Returns the combined result, with texts joined in reading order and the
+/// confidence weighted by text length, along with the per-region results.
+pub(super) fn ocr_regions(
+    image: &DynamicImage,
+    regions: &[Region],
+    pipeline: &Pipeline,
+    engine: &Arc<dyn OcrEngine>,
+    fallback: &Option<Arc<dyn OcrEngine>>,
+    options: &OcrOptions,
+) -> Result<(ImageOcr, Vec<RegionOcrResponse>), OcrError> {
+    let mut texts = Vec::new();
+    let mut warnings: Vec<String> = Vec::new();
+    let mut boxes: Option<TextBoxes> = None;
+    let mut lines = options.lines.then(Vec::new);
+    // Only kept if every region has per-word confidences
+    let mut word_confidences = Some(Vec::new());
+    let mut word_scores = options.word_confidence.then(Vec::new);
+    let mut stats: Option<PreprocessingStats> = None;
+    let mut engine_name = engine.name().to_string();
+    let (mut weighted_confidence, mut total_chars) = (0.0, 0);
+    let mut responses = Vec::with_capacity(regions.len());
+
+    for region in regions {
+        let crop = image.crop_imm(region.x, region.y, region.width, region.height);
+        let ImageOcr {
+            mut result,
+            stats: region_stats,
+            engine: used_engine,
+        } = ocr_image(crop, pipeline, engine, fallback, options)?;
+
+        if used_engine != engine.name() {
+            engine_name = used_engine;
+        }
+        stats = Some(match stats {
+            Some(total) => merge_stats(total, region_stats),
+            None => region_stats,
+        });
+        for warning in &result.warnings {
+            if !warnings.contains(warning) {
+                warnings.push(warning.clone());
+            }
+        }
+
+        let chars = result.text.chars().count();
+        weighted_confidence += result.confidence * chars as f32;
+        total_chars += chars;
+        if !result.text.is_empty() {
+            texts.push(result.text.clone());
+        }
+
+        if let Some(found) = result.boxes.as_mut() {
+            offset_boxes(found, region.x, region.y);
+            let all = boxes.get_or_insert_with(|| TextBoxes {
+                level: found.level,
+                boxes: Vec::new(),
+            });
+            all.boxes.extend(found.boxes.iter().cloned());
+        }
+        if let Some(found) = result.lines.as_mut() {
+            for line in found.iter_mut() {
+                offset_box(&mut line.text_box, region.x, region.y);
+            }
+            lines
+                .get_or_insert_with(Vec::new)
+                .extend(found.iter().cloned());
+        }
+        if let (Some(all), Some(found)) = (word_scores.as_mut(), result.word_scores.take()) {
+            all.extend(found);
+        }
+        word_confidences =
+            word_confidences
+                .zip(result.word_confidences.take())
+                .map(|(mut all, found)| {
+                    all.extend(found);
+                    all
+                });
+
+        responses.push(RegionOcrResponse {
+            bounds: Bounds {
+                x: region.x,
+                y: region.y,
+                width: region.width,
+                height: region.height,
+            },
+            text: result.text,
+            confidence: (!options.skip_confidence).then_some(result.confidence),
+            boxes: result.boxes.map(BoxesResponse::from),
+            lines: line_responses(result.lines),
+        });
+    }
+
+    let confidence = if total_chars > 0 {
+        weighted_confidence / total_chars as f32
+    } else {
+        0.0
+    };
+
+    let combined = ImageOcr {
+        result: OcrResult {
+            text: texts.join("\n\n"),
+            confidence,
+            warnings,
+            boxes,
+            word_confidences,
+            word_scores,
+            lines,
+            orientation_degrees: None,
+        },
+        stats: stats.unwrap_or(PreprocessingStats {
+            preset: String::new(),
+            total_time_ms: 0,
+            steps: Vec::new(),
+            metadata: BTreeMap::new(),
+        }),
+        engine: engine_name,
+    };
+    Ok((combined, responses))
+}
+
+/// Add up the preprocessing stats of two runs of the same pipeline
+pub(super) fn merge_stats(
+    mut total: PreprocessingStats,
+    other: PreprocessingStats,
+) -> PreprocessingStats {
+    total.total_time_ms += other.total_time_ms;
+    for (step, other) in total.steps.iter_mut().zip(other.steps) {
+        step.time_ms += other.time_ms;
+        // A step counts as skipped only if it was skipped for every region
+        if !other.skipped {
+            step.skipped = false;
+            step.skip_reason = None;
+        }
+        // Regions of different sizes are resized differently
+        if (step.resize_action, step.scale_factor) != (other.resize_action, other.scale_factor) {
+            step.resize_action = None;
+            step.scale_factor = None;
+        }
+        // Regions are preprocessed one after another, so memory peaks don't add up
+        step.peak_memory_bytes = step.peak_memory_bytes.max(other.peak_memory_bytes);
+    }
+    // Each region is measured on its own, so only values they agree on are kept
+    total.metadata.retain(|step, values| {
+        let other = other.metadata.get(step);
+        values.retain(|name, value| other.and_then(|other| other.get(name)) == Some(value));
+        !values.is_empty()
+    });
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preprocessing::{Preset, StepMetadata, StepTiming};
+    use crate::server::tests::stub;
+
+    #[test]
+    fn test_ocr_regions_combines_results_in_order() {
+        let image = DynamicImage::new_luma8(400, 200);
+        let regions = [
+            Region {
+                x: 10,
+                y: 10,
+                width: 150,
+                height: 180,
+            },
+            Region {
+                x: 240,
+                y: 20,
+                width: 150,
+                height: 160,
+            },
+        ];
+
+        let (combined, responses) = ocr_regions(
+            &image,
+            &regions,
+            &Pipeline::new(Preset::Minimal),
+            &stub("primary", None),
+            &None,
+            &OcrOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(combined.result.text, "from primary\n\nfrom primary");
+        assert!((combined.result.confidence - 0.9).abs() < 1e-6);
+        assert_eq!(combined.engine, "primary");
+        assert_eq!(combined.stats.steps.len(), 1);
+
+        assert_eq!(responses.len(), 2);
+        assert_eq!(
+            responses[1].bounds,
+            Bounds {
+                x: 240,
+                y: 20,
+                width: 150,
+                height: 160
+            }
+        );
+    }
+
+    #[test]
+    fn test_document_regions_falls_back_to_whole_image() {
+        let mut warnings = Vec::new();
+        let regions = document_regions(&DynamicImage::new_luma8(50, 40), &mut warnings);
+        assert_eq!(
+            regions,
+            vec![Region {
+                x: 0,
+                y: 0,
+                width: 50,
+                height: 40
+            }]
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_merge_stats_sums_times() {
+        let stats = |time_ms, skipped| PreprocessingStats {
+            preset: "default".to_string(),
+            total_time_ms: time_ms,
+            steps: vec![StepTiming {
+                name: "resize".to_string(),
+                time_ms,
+                skipped,
+                skip_reason: skipped.then(|| "already large enough".to_string()),
+                resize_action: None,
+                scale_factor: None,
+                peak_memory_bytes: Some(time_ms * 1000),
+            }],
+            metadata: BTreeMap::new(),
+        };
+
+        let merged = merge_stats(stats(3, true), stats(4, false));
+        assert_eq!(merged.total_time_ms, 7);
+        assert_eq!(merged.steps[0].time_ms, 7);
+        assert!(!merged.steps[0].skipped);
+        assert_eq!(merged.steps[0].skip_reason, None);
+        assert_eq!(merged.steps[0].peak_memory_bytes, Some(4000));
+    }
+
+    #[test]
+    fn test_merge_stats_keeps_metadata_regions_agree_on() {
+        let stats = |angle: f32, level: f32| PreprocessingStats {
+            preset: "aggressive".to_string(),
+            total_time_ms: 1,
+            steps: Vec::new(),
+            metadata: BTreeMap::from([
+                (
+                    "deskew".to_string(),
+                    StepMetadata::from([("skew_angle_degrees".to_string(), angle)]),
+                ),
+                (
+                    "threshold".to_string(),
+                    StepMetadata::from([("level".to_string(), level)]),
+                ),
+            ]),
+        };
+
+        let merged = merge_stats(stats(1.5, 100.0), stats(1.5, 120.0));
+        assert_eq!(merged.metadata["deskew"]["skew_angle_degrees"], 1.5);
+        assert!(!merged.metadata.contains_key("threshold"));
+    }
+}
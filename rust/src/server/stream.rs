@@ -0,0 +1,301 @@
+//! Streamed OCR responses (`stream=true`)
+//!
+//! Results are sent as NDJSON: one line per page as it completes, then the
+//! combined result, or an error if processing fails after the stream started.
+
+use super::{
+    check_min_confidence, line_responses, run_ocr, scale_line_confidences, wait_for_ocr,
+    BoxesResponse, OcrJob, OcrResponse, TextLineResponse, Watchdog,
+};
+use crate::engine::OcrResult;
+use crate::error::ErrorResponse;
+use axum::{
+    body::{Body, Bytes},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use futures::StreamExt;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Result for a single page of a streamed OCR response
+#[derive(Serialize)]
+pub struct PageResponse {
+    pub page: u32,
+    pub text: String,
+    pub confidence: Option<f32>,
+    pub warnings: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boxes: Option<BoxesResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lines: Option<Vec<TextLineResponse>>,
+}
+
+impl PageResponse {
+    /// Multiply every confidence in the page by `factor`
+    fn scale_confidences(&mut self, factor: f32) {
+        if let Some(confidence) = self.confidence.as_mut() {
+            *confidence *= factor;
+        }
+        if let Some(boxes) = self.boxes.as_mut() {
+            boxes.scale_confidences(factor);
+        }
+        scale_line_confidences(&mut self.lines, factor);
+    }
+
+    /// Page `page` of a result, without confidence when `skip_confidence`
+    fn new(page: u32, result: &OcrResult, skip_confidence: bool) -> Self {
+        Self {
+            page,
+            text: result.text.clone(),
+            confidence: (!skip_confidence).then_some(result.confidence),
+            warnings: result.warnings.clone(),
+            boxes: result.boxes.clone().map(BoxesResponse::from),
+            lines: line_responses(result.lines.clone()),
+        }
+    }
+}
+
+/// One line of a streamed (NDJSON) OCR response
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    /// A page finished processing
+    Page(PageResponse),
+    /// The combined result, always the last line on success
+    Result(Box<OcrResponse>),
+    /// Processing failed after the stream started
+    Error(ErrorResponse),
+}
+
+impl StreamEvent {
+    /// Serialize as a newline-terminated JSON line
+    fn to_line(&self) -> Bytes {
+        let mut line = serde_json::to_vec(self).unwrap_or_default();
+        line.push(b'\n');
+        Bytes::from(line)
+    }
+}
+
+/// Run an OCR job in the background, streaming NDJSON lines as pages complete
+///
+/// Confidences are multiplied by `confidence_factor`, and a result below
+/// `min_confidence` ends the stream with an error instead. A job that runs
+/// longer than `timeout` or the `watchdog` allows ends the stream with an
+/// error.
+pub(super) fn stream_ocr(
+    job: OcrJob,
+    confidence_factor: f32,
+    min_confidence: Option<f32>,
+    watchdog: Option<Watchdog>,
+    timeout: Option<Duration>,
+) -> Response {
+    let (tx, rx) = futures::channel::mpsc::unbounded();
+    let engine = job.engine.name();
+    let skip_confidence = job.options.skip_confidence;
+    let watchdog_tx = tx.clone();
+
+    let task = tokio::task::spawn_blocking(move || {
+        // Sending fails only if the client has disconnected, in which case
+        // there is no one left to tell
+        let send = |event: StreamEvent| {
+            let _ = tx.unbounded_send(event.to_line());
+        };
+
+        let last = match run_ocr(job, &|page, result| {
+            let mut page = PageResponse::new(page, result, skip_confidence);
+            page.scale_confidences(confidence_factor);
+            send(StreamEvent::Page(page))
+        })
+        .and_then(|response| check_min_confidence(response, min_confidence, confidence_factor))
+        {
+            Ok(mut response) => {
+                response.scale_confidences(confidence_factor);
+                StreamEvent::Result(Box::new(response))
+            }
+            Err(e) => StreamEvent::Error(ErrorResponse::from(&e)),
+        };
+        send(last);
+    });
+
+    tokio::spawn(async move {
+        if let Err(e) = wait_for_ocr(engine, task, watchdog, timeout).await {
+            let _ =
+                watchdog_tx.unbounded_send(StreamEvent::Error(ErrorResponse::from(&e)).to_line());
+            // The stuck job still holds a sender, so end the stream explicitly
+            watchdog_tx.close_channel();
+        }
+    });
+
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(rx.map(Ok::<_, std::convert::Infallible>)),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{OcrEngine, OcrOptions};
+    use crate::error::OcrError;
+    use crate::metrics::Metrics;
+    use crate::preprocessing::Preset;
+    use crate::server::preprocess::PreprocessingParams;
+    use crate::server::tests::{stub, SAMPLE_PDF};
+    use crate::server::InFlightOcr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Instant;
+    use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+    #[test]
+    fn test_stream_events_are_tagged_json_lines() {
+        let result = OcrResult {
+            text: "Hello".to_string(),
+            confidence: 0.9,
+            warnings: Vec::new(),
+            boxes: None,
+            word_confidences: None,
+            word_scores: None,
+            lines: None,
+            orientation_degrees: None,
+        };
+        let line = StreamEvent::Page(PageResponse::new(2, &result, false)).to_line();
+        assert!(line.ends_with(b"\n"));
+        let json: serde_json::Value = serde_json::from_slice(&line).unwrap();
+        assert_eq!(json["type"], "page");
+        assert_eq!(json["page"], 2);
+        assert_eq!(json["text"], "Hello");
+        assert!((json["confidence"].as_f64().unwrap() - 0.9).abs() < 1e-6);
+
+        // confidence=false reports null rather than leaving the field out
+        let line = StreamEvent::Page(PageResponse::new(2, &result, true)).to_line();
+        let json: serde_json::Value = serde_json::from_slice(&line).unwrap();
+        assert!(json["confidence"].is_null());
+
+        let error = OcrError::ProcessingError("boom".to_string());
+        let line = StreamEvent::Error(ErrorResponse::from(&error)).to_line();
+        let json: serde_json::Value = serde_json::from_slice(&line).unwrap();
+        assert_eq!(json["type"], "error");
+        assert_eq!(json["code"], "PROCESSING_ERROR");
+    }
+
+    /// OCR job for the sample PDF with default options
+    fn pdf_job(
+        engine: Arc<dyn OcrEngine>,
+        counter: &Arc<AtomicUsize>,
+        slot: OwnedSemaphorePermit,
+    ) -> OcrJob {
+        OcrJob {
+            start: Instant::now(),
+            engine,
+            fallback: None,
+            data: Bytes::from_static(SAMPLE_PDF),
+            is_pdf: true,
+            is_document: false,
+            detected_format: "application/pdf".to_string(),
+            filename: Some("scan.pdf".to_string()),
+            max_file_size: usize::MAX,
+            max_warnings: None,
+            pdf_password: None,
+            pdf_lenient: false,
+            preprocessing: PreprocessingParams::default()
+                .parse(Preset::Default)
+                .unwrap(),
+            sharpen_skip_threshold: None,
+            include_raw: true,
+            probe: false,
+            probe_max_dimension: 1024,
+            split: false,
+            autocrop: false,
+            detailed_timing: false,
+            frame: None,
+            mask: Vec::new(),
+            pdf_verify: false,
+            options: OcrOptions::default(),
+            request_warnings: vec!["Server busy".to_string()],
+            in_flight: InFlightOcr::start(counter).0,
+            slot,
+            metrics: Arc::new(Metrics::new()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_ocr_emits_pages_then_result() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let slot = Arc::new(Semaphore::new(1)).acquire_owned().await.unwrap();
+        let job = pdf_job(stub("primary", None), &counter, slot);
+
+        let response = stream_ocr(job, 100.0, None, None, None);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/x-ndjson"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let lines: Vec<serde_json::Value> = body
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["type"], "page");
+        assert_eq!(lines[0]["page"], 1);
+        assert_eq!(lines[1]["type"], "result");
+        assert_eq!(lines[1]["text"], "from primary");
+        assert_eq!(lines[1]["raw_text"], "from primary");
+        assert_eq!(lines[1]["detected_format"], "application/pdf");
+        assert_eq!(lines[1]["filename"], "scan.pdf");
+        assert_eq!(lines[1]["engine"], "primary");
+        assert_eq!(lines[1]["warnings"][0], "Server busy");
+        // Confidences are on the requested scale
+        assert_eq!(lines[0]["confidence"].as_f64().unwrap().round(), 90.0);
+        assert_eq!(lines[1]["confidence"].as_f64().unwrap().round(), 90.0);
+        assert_eq!(
+            lines[1]["confidence_stats"]["mean"]
+                .as_f64()
+                .unwrap()
+                .round(),
+            90.0
+        );
+        // The request stops counting as in flight once the job is done
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_job_keeps_its_slot() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let slots = Arc::new(Semaphore::new(1));
+        let slot = slots.clone().acquire_owned().await.unwrap();
+        // An engine that takes longer than the request waits
+        let slow = stub(
+            "slow",
+            Some(|| {
+                std::thread::sleep(Duration::from_millis(300));
+                OcrError::ProcessingError("too slow".to_string())
+            }),
+        );
+
+        let response = stream_ocr(
+            pdf_job(slow, &counter, slot),
+            100.0,
+            None,
+            None,
+            Some(Duration::from_millis(10)),
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("TIMEOUT"));
+
+        // The engine is still running, so its slot isn't free yet
+        assert_eq!(slots.available_permits(), 0);
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+        let freed = tokio::time::timeout(Duration::from_secs(5), slots.acquire()).await;
+        assert!(freed.is_ok());
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,21 @@
+//! Helpers shared by unit tests
+
+use std::io::{Read, Write};
+
+/// Serve a single HTTP response with the given headers and body, returning
+/// the URL of `path` on it
+pub fn serve_once(path: &str, headers: &str, body: Vec<u8>) -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}{}", listener.local_addr().unwrap(), path);
+    let headers = headers.to_string();
+
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0u8; 1024];
+        let _ = stream.read(&mut request);
+        let _ = stream.write_all(headers.as_bytes());
+        let _ = stream.write_all(&body);
+    });
+
+    url
+}
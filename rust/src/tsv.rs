@@ -0,0 +1,195 @@
+//! TSV output
+//!
+//! Renders an OCR response in the tab-separated layout Tesseract writes with
+//! its `tsv` config, which loads straight into a dataframe. After a header
+//! row, every element of the page gets a row with these columns:
+//!
+//! - `level`: 1 page, 2 block, 3 paragraph, 4 line, 5 word
+//! - `page_num`: always 1, the image is a single page
+//! - `block_num`, `par_num`, `line_num`, `word_num`: the element's position
+//!   in its parent, counting from 1; 0 for levels above the row's own
+//! - `left`, `top`, `width`, `height`: the bounding box in pixels
+//! - `conf`: word confidence as a percentage, -1 for other levels and for
+//!   words without a confidence
+//! - `text`: the word, empty for other levels
+//!
+//! Blocks and lines are rebuilt from the word boxes the same way as for
+//! PAGE-XML, with one paragraph per block.
+
+use crate::page_xml::{group_lines, group_regions, Rect};
+use crate::server::OcrResponse;
+use std::fmt::Write;
+
+/// Content type of TSV responses
+pub const CONTENT_TYPE: &str = "text/tab-separated-values";
+
+const HEADER: &str =
+    "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext";
+
+/// Render a response's word boxes as Tesseract TSV for one image
+pub fn render(response: &OcrResponse, image_width: u32, image_height: u32) -> String {
+    let words = response
+        .boxes
+        .as_ref()
+        .map(|boxes| boxes.boxes.as_slice())
+        .unwrap_or_default();
+
+    let mut tsv = String::new();
+    tsv.push_str(HEADER);
+    tsv.push('\n');
+    let page = Rect {
+        x: 0,
+        y: 0,
+        right: image_width,
+        bottom: image_height,
+    };
+    row(&mut tsv, [1, 1, 0, 0, 0, 0], page, None, "");
+
+    for (b, block) in group_regions(group_lines(words)).iter().enumerate() {
+        let block_num = b as u32 + 1;
+        let bounds = block
+            .iter()
+            .map(|line| line.bounds)
+            .reduce(Rect::union)
+            .expect("regions are never empty");
+        row(&mut tsv, [2, 1, block_num, 0, 0, 0], bounds, None, "");
+        row(&mut tsv, [3, 1, block_num, 1, 0, 0], bounds, None, "");
+
+        for (l, line) in block.iter().enumerate() {
+            let line_num = l as u32 + 1;
+            row(
+                &mut tsv,
+                [4, 1, block_num, 1, line_num, 0],
+                line.bounds,
+                None,
+                "",
+            );
+            for (w, word) in line.words.iter().enumerate() {
+                row(
+                    &mut tsv,
+                    [5, 1, block_num, 1, line_num, w as u32 + 1],
+                    Rect::of(word),
+                    word.confidence,
+                    &word.text,
+                );
+            }
+        }
+    }
+
+    tsv
+}
+
+/// Append one row: the level and numbering columns, then the box,
+/// confidence and text
+fn row(tsv: &mut String, numbers: [u32; 6], rect: Rect, confidence: Option<f32>, text: &str) {
+    for number in numbers {
+        let _ = write!(tsv, "{}\t", number);
+    }
+    let confidence = confidence
+        .map(|c| format!("{:.6}", c.clamp(0.0, 1.0) * 100.0))
+        .unwrap_or_else(|| "-1".to_string());
+    // Tabs and line breaks would split the row
+    let text = text.replace(['\t', '\n', '\r'], " ");
+    let _ = writeln!(
+        tsv,
+        "{}\t{}\t{}\t{}\t{}\t{}",
+        rect.x,
+        rect.y,
+        rect.right - rect.x,
+        rect.bottom - rect.y,
+        confidence,
+        text
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{BoxesResponse, TextBoxResponse};
+
+    fn word(text: &str, x: u32, y: u32, confidence: Option<f32>) -> TextBoxResponse {
+        TextBoxResponse {
+            text: text.to_string(),
+            page: 1,
+            x,
+            y,
+            width: 50,
+            height: 20,
+            confidence,
+            polygon: None,
+        }
+    }
+
+    fn response(words: Vec<TextBoxResponse>) -> OcrResponse {
+        OcrResponse {
+            schema_version: 1,
+            text: String::new(),
+            raw_text: None,
+            truncated: None,
+            confidence: Some(0.9),
+            confidence_stats: None,
+            word_confidences: None,
+            processing_time_ms: 0,
+            warnings: Vec::new(),
+            engine: "ocrs".to_string(),
+            detected_format: "image/png".to_string(),
+            filename: None,
+            no_text_detected: false,
+            orientation_degrees: None,
+            preprocessing: None,
+            boxes: Some(BoxesResponse {
+                level: "word".to_string(),
+                boxes: words,
+            }),
+            lines: None,
+            regions: None,
+            pdf_verification: None,
+        }
+    }
+
+    #[test]
+    fn test_renders_tesseract_layout() {
+        let tsv = render(
+            &response(vec![
+                word("Fish", 10, 10, Some(0.875)),
+                word("a\tb", 70, 12, None),
+                word("Chips", 10, 40, Some(1.2)),
+                word("Far", 10, 200, Some(0.5)),
+            ]),
+            200,
+            300,
+        );
+
+        let rows: Vec<&str> = tsv.lines().collect();
+        assert_eq!(rows[0], HEADER);
+        assert_eq!(rows[1], "1\t1\t0\t0\t0\t0\t0\t0\t200\t300\t-1\t");
+        assert_eq!(rows[2], "2\t1\t1\t0\t0\t0\t10\t10\t110\t50\t-1\t");
+        assert_eq!(rows[3], "3\t1\t1\t1\t0\t0\t10\t10\t110\t50\t-1\t");
+        assert_eq!(rows[4], "4\t1\t1\t1\t1\t0\t10\t10\t110\t22\t-1\t");
+        assert_eq!(rows[5], "5\t1\t1\t1\t1\t1\t10\t10\t50\t20\t87.500000\tFish");
+        // Words without a confidence get -1, and tabs can't split the row
+        assert_eq!(rows[6], "5\t1\t1\t1\t1\t2\t70\t12\t50\t20\t-1\ta b");
+        // Line numbers restart in each block and word numbers in each line
+        assert_eq!(rows[7], "4\t1\t1\t1\t2\t0\t10\t40\t50\t20\t-1\t");
+        assert_eq!(
+            rows[8],
+            "5\t1\t1\t1\t2\t1\t10\t40\t50\t20\t100.000000\tChips"
+        );
+        assert_eq!(rows[9], "2\t1\t2\t0\t0\t0\t10\t200\t50\t20\t-1\t");
+        assert_eq!(
+            rows[12],
+            "5\t1\t2\t1\t1\t1\t10\t200\t50\t20\t50.000000\tFar"
+        );
+        assert_eq!(rows.len(), 13);
+    }
+
+    #[test]
+    fn test_renders_page_row_without_words() {
+        let tsv = render(&response(Vec::new()), 200, 100);
+
+        assert_eq!(
+            tsv,
+            format!("{}\n1\t1\t0\t0\t0\t0\t0\t0\t200\t100\t-1\t\n", HEADER)
+        );
+    }
+}
@@ -0,0 +1,424 @@
+//! Fetching uploads from URLs
+//!
+//! Clients whose files already live in object storage can send a URL instead
+//! of the file. Since the server makes the request, URLs pointing at
+//! loopback, private or link-local addresses are refused unless their host is
+//! explicitly allowed, so requests can't be used to reach internal services.
+//! When the operator restricts downloads to a list of hosts, URLs on any
+//! other host are refused as well.
+
+use crate::download::{self, BodyError};
+use crate::error::OcrError;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+use ureq::unversioned::resolver::{ResolvedSocketAddrs, Resolver};
+use ureq::unversioned::transport::{DefaultConnector, NextTimeout};
+
+/// Redirects followed before giving up
+const MAX_REDIRECTS: usize = 5;
+
+/// A file downloaded from a URL
+#[derive(Debug)]
+pub struct Fetched {
+    pub data: Vec<u8>,
+    /// Content type from the response, or sniffed from the data when the
+    /// server didn't say
+    pub content_type: Option<String>,
+}
+
+/// Download a file, refusing files over `max_size` bytes
+///
+/// Redirects are followed here rather than by ureq so every hop is checked
+/// against `download_hosts` (None allows any host) and, unless its host is
+/// one of `private_hosts`, for non-public addresses. Checked hosts are
+/// connected to at exactly the addresses that were checked, bypassing any
+/// proxy, so a DNS server can't answer differently when connecting.
+pub fn fetch(
+    url: &str,
+    max_size: usize,
+    timeout: Option<Duration>,
+    download_hosts: Option<&[String]>,
+    private_hosts: &[String],
+) -> Result<Fetched, OcrError> {
+    let failed =
+        |e: &dyn std::fmt::Display| OcrError::InvalidRequest(format!("Failed to fetch url: {}", e));
+
+    let agent = download::agent(timeout);
+    let response = download::get(
+        url,
+        MAX_REDIRECTS,
+        |url| {
+            Ok(match check_url(url, download_hosts, private_hosts)? {
+                Some(addresses) => pinned_agent(timeout, addresses),
+                None => agent.clone(),
+            })
+        },
+        failed,
+    )?;
+    if !response.status().is_success() {
+        return Err(failed(&format!("server responded {}", response.status())));
+    }
+
+    let content_type = response
+        .headers()
+        .get(ureq::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|mime| mime.trim().to_ascii_lowercase())
+        .filter(|mime| !mime.is_empty() && mime != "application/octet-stream");
+
+    let mut data = Vec::new();
+    download::read_limited(response, max_size as u64, &mut data).map_err(|e| match e {
+        BodyError::TooLarge(size) => OcrError::ImageTooLarge {
+            size: size as usize,
+            max: max_size,
+        },
+        BodyError::Read(e) => failed(&e),
+    })?;
+
+    let content_type = content_type.or_else(|| sniff_content_type(&data));
+    Ok(Fetched { data, content_type })
+}
+
+/// Reject URLs that aren't http(s), whose host isn't one of `download_hosts`,
+/// or whose host resolves to an address that isn't public, unless the host
+/// is one of `private_hosts`
+///
+/// Returns the checked addresses, or None for `private_hosts`, which aren't
+/// resolved here.
+fn check_url(
+    url: &str,
+    download_hosts: Option<&[String]>,
+    private_hosts: &[String],
+) -> Result<Option<Vec<SocketAddr>>, OcrError> {
+    let refuse = |reason: String| {
+        OcrError::InvalidRequest(format!("Refusing to fetch url '{}': {}", url, reason))
+    };
+
+    let uri = url
+        .parse::<ureq::http::Uri>()
+        .map_err(|_| refuse("not a valid URL".to_string()))?;
+    let port = match uri.scheme_str() {
+        Some("http") => 80,
+        Some("https") => 443,
+        _ => return Err(refuse("only http and https URLs are supported".to_string())),
+    };
+    let host = uri
+        .host()
+        .filter(|host| !host.is_empty())
+        .ok_or_else(|| refuse("no host".to_string()))?;
+    let listed = |hosts: &[String]| hosts.iter().any(|h| h.eq_ignore_ascii_case(host));
+    if let Some(download_hosts) = download_hosts.filter(|hosts| !listed(hosts)) {
+        return Err(refuse(format!(
+            "host '{}' is not in the allowed download hosts ({})",
+            host,
+            download_hosts.join(", ")
+        )));
+    }
+    if listed(private_hosts) {
+        return Ok(None);
+    }
+
+    // IPv6 hosts are bracketed in URLs
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addresses: Vec<SocketAddr> = (host, uri.port_u16().unwrap_or(port))
+        .to_socket_addrs()
+        .map_err(|e| refuse(format!("could not resolve host '{}': {}", host, e)))?
+        .collect();
+    if let Some(address) = addresses.iter().find(|address| !is_public(address.ip())) {
+        return Err(refuse(format!(
+            "host '{}' resolves to non-public address {}",
+            host,
+            address.ip()
+        )));
+    }
+    if addresses.is_empty() {
+        return Err(refuse(format!("host '{}' has no addresses", host)));
+    }
+    Ok(Some(addresses))
+}
+
+/// An agent that connects to `addresses` whatever the URL's host resolves
+/// to when connecting
+fn pinned_agent(timeout: Option<Duration>, addresses: Vec<SocketAddr>) -> ureq::Agent {
+    // A proxy would resolve the host itself
+    let config = download::config(timeout).proxy(None).build();
+    ureq::Agent::with_parts(config, DefaultConnector::default(), Pinned(addresses))
+}
+
+/// Resolves every host to addresses checked beforehand
+#[derive(Debug)]
+struct Pinned(Vec<SocketAddr>);
+
+impl Resolver for Pinned {
+    fn resolve(
+        &self,
+        _uri: &ureq::http::Uri,
+        _config: &ureq::config::Config,
+        _timeout: NextTimeout,
+    ) -> Result<ResolvedSocketAddrs, ureq::Error> {
+        let mut resolved = self.empty();
+        for &address in self.0.iter().take(16) {
+            resolved.push(address);
+        }
+        Ok(resolved)
+    }
+}
+
+/// Whether an address is reachable on the public internet, as opposed to
+/// loopback, private, link-local and other special-purpose ranges
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match embedded_v4(ip) {
+            Some(embedded) => is_public_v4(embedded),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+/// The IPv4 address an IPv6 address reaches: the last 32 bits of
+/// IPv4-mapped (::ffff:a.b.c.d), IPv4-compatible (::a.b.c.d) and NAT64
+/// (64:ff9b::a.b.c.d) addresses
+fn embedded_v4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let [prefix @ .., high, low] = ip.segments();
+    match prefix {
+        [0, 0, 0, 0, 0, 0xffff] | [0, 0, 0, 0, 0, 0] | [0x64, 0xff9b, 0, 0, 0, 0] => {
+            Some(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)))
+        }
+        _ => None,
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // "This network" and carrier-grade NAT
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        // Reserved for future use
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local (fc00::/7) and link-local (fe80::/10)
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        // Local-use NAT64 (64:ff9b:1::/48)
+        || ip.segments()[..3] == [0x64, 0xff9b, 1])
+}
+
+/// Guess a content type from a file's first bytes
+fn sniff_content_type(data: &[u8]) -> Option<String> {
+    if data.starts_with(b"%PDF-") {
+        return Some("application/pdf".to_string());
+    }
+    image::guess_format(data)
+        .ok()
+        .map(|format| format.to_mime_type().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    const MAX_SIZE: usize = 1024;
+
+    fn local() -> Vec<String> {
+        vec!["127.0.0.1".to_string()]
+    }
+
+    /// Serve a single HTTP response with the given headers and body,
+    /// returning its URL
+    fn serve_once(headers: &str, body: Vec<u8>) -> String {
+        test_support::serve_once("/scan", headers, body)
+    }
+
+    #[test]
+    fn test_is_public() {
+        for ip in [
+            "93.184.215.14",
+            "8.8.8.8",
+            "2606:4700::1111",
+            "64:ff9b::808:808",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::127.0.0.1",
+            "::10.0.0.1",
+            "64:ff9b::127.0.0.1",
+            "64:ff9b::169.254.169.254",
+            "64:ff9b:1::a00:1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test_check_url_refuses_internal_hosts() {
+        for url in [
+            "http://127.0.0.1/scan.png",
+            "http://localhost:8080/scan.png",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://[::1]/scan.png",
+            "file:///etc/passwd",
+            "ftp://example.com/scan.png",
+        ] {
+            assert!(
+                matches!(check_url(url, None, &[]), Err(OcrError::InvalidRequest(_))),
+                "{}",
+                url
+            );
+        }
+
+        // Allowed hosts skip the address check
+        assert!(check_url("http://127.0.0.1/scan.png", None, &local()).is_ok());
+        let localhost = ["localhost".to_string()];
+        assert!(check_url("http://LOCALHOST/scan.png", None, &localhost).is_ok());
+    }
+
+    #[test]
+    fn test_check_url_enforces_download_hosts() {
+        let download_hosts = ["127.0.0.1".to_string()];
+        let err = check_url(
+            "http://93.184.215.14/scan.png",
+            Some(&download_hosts),
+            &local(),
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("host '93.184.215.14' is not in the allowed download hosts"));
+
+        // Listed hosts still need to be exempted from the address check
+        assert!(check_url("http://127.0.0.1/scan.png", Some(&download_hosts), &[]).is_err());
+        assert!(check_url("http://127.0.0.1/scan.png", Some(&download_hosts), &local()).is_ok());
+    }
+
+    #[test]
+    fn test_fetch_checks_redirect_targets_against_download_hosts() {
+        let url = serve_once(
+            "HTTP/1.1 302 Found\r\nLocation: http://elsewhere.invalid/scan.png\r\n\
+             Content-Length: 0\r\n\r\n",
+            Vec::new(),
+        );
+
+        let err = fetch(&url, MAX_SIZE, None, Some(&local()), &local()).unwrap_err();
+        assert!(err.to_string().contains("host 'elsewhere.invalid'"));
+    }
+
+    #[test]
+    fn test_fetch_sniffs_missing_content_type() {
+        let png = b"\x89PNG\r\n\x1a\n rest of the file".to_vec();
+        let url = serve_once(
+            &format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", png.len()),
+            png.clone(),
+        );
+
+        let fetched = fetch(&url, MAX_SIZE, None, None, &local()).unwrap();
+        assert_eq!(fetched.data, png);
+        assert_eq!(fetched.content_type.as_deref(), Some("image/png"));
+    }
+
+    #[test]
+    fn test_fetch_uses_response_content_type() {
+        let url = serve_once(
+            "HTTP/1.1 200 OK\r\nContent-Type: image/jpeg; charset=binary\r\n\
+             Content-Length: 4\r\n\r\n",
+            b"data".to_vec(),
+        );
+
+        let fetched = fetch(&url, MAX_SIZE, None, None, &local()).unwrap();
+        assert_eq!(fetched.content_type.as_deref(), Some("image/jpeg"));
+    }
+
+    #[test]
+    fn test_fetch_rejects_oversized_files() {
+        let announced = serve_once(
+            &format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+                MAX_SIZE + 1
+            ),
+            vec![0; MAX_SIZE + 1],
+        );
+        assert!(matches!(
+            fetch(&announced, MAX_SIZE, None, None, &local()),
+            Err(OcrError::ImageTooLarge { .. })
+        ));
+
+        let streamed = serve_once(
+            "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n",
+            vec![0; MAX_SIZE * 2],
+        );
+        assert!(matches!(
+            fetch(&streamed, MAX_SIZE, None, None, &local()),
+            Err(OcrError::ImageTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_fetch_checks_redirect_targets() {
+        let url = serve_once(
+            "HTTP/1.1 302 Found\r\nLocation: http://10.0.0.1/scan.png\r\n\
+             Content-Length: 0\r\n\r\n",
+            Vec::new(),
+        );
+
+        let err = fetch(&url, MAX_SIZE, None, None, &local()).unwrap_err();
+        assert!(err.to_string().contains("non-public address 10.0.0.1"));
+    }
+
+    #[test]
+    fn test_fetch_reports_error_status() {
+        let url = serve_once(
+            "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n",
+            Vec::new(),
+        );
+
+        let err = fetch(&url, MAX_SIZE, None, None, &local()).unwrap_err();
+        assert!(err.to_string().contains("403"));
+    }
+
+    #[test]
+    fn test_pinned_agent_ignores_dns() {
+        let url = serve_once(
+            "HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\n",
+            b"scan".to_vec(),
+        );
+        let address: SocketAddr = url["http://".len()..url.len() - "/scan".len()]
+            .parse()
+            .unwrap();
+
+        // The host doesn't resolve, so only the pinned address can be used
+        let pinned = format!("http://scans.invalid:{}/scan", address.port());
+        let response = pinned_agent(None, vec![address])
+            .get(&pinned)
+            .call()
+            .unwrap();
+        assert_eq!(response.into_body().read_to_vec().unwrap(), b"scan");
+    }
+}
@@ -98,7 +98,7 @@ async fn test_ocr_file(
     mime_type: &str,
 ) -> OcrResponse {
     let path = test_fixture_path(filename);
-    let file_bytes = fs::read(&path).expect(&format!("Failed to read {}", path));
+    let file_bytes = fs::read(&path).unwrap_or_else(|_| panic!("Failed to read {}", path));
 
     let part = Part::bytes(file_bytes)
         .file_name(filename.to_string())
@@ -108,7 +108,7 @@ async fn test_ocr_file(
     let form = Form::new().part("file", part);
 
     let response = client
-        .post(&format!("{}/ocr", base_url))
+        .post(format!("{}/ocr", base_url))
         .multipart(form)
         .send()
         .await
@@ -123,7 +123,7 @@ async fn test_health_endpoint() {
     let client = reqwest::Client::new();
 
     let response: HealthResponse = client
-        .get(&format!("{}/health", server.base_url()))
+        .get(format!("{}/health", server.base_url()))
         .send()
         .await
         .expect("Failed to send request")
@@ -134,6 +134,29 @@ async fn test_health_endpoint() {
     assert_eq!(response.status, "ok");
 }
 
+#[tokio::test]
+async fn test_metrics_endpoint() {
+    let server = TestServer::start();
+    let client = reqwest::Client::new();
+
+    test_ocr_file(&client, &server.base_url(), "sample_text.png", "image/png").await;
+
+    let response = client
+        .get(format!("{}/metrics", server.base_url()))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    let body = response.text().await.expect("Failed to read response");
+
+    assert!(body.contains("ocr_requests_total{engine=\"ocrs\",status=\"200\"} 1"));
+    assert!(body.contains("ocr_processing_time_ms_count{engine=\"ocrs\"} 1"));
+    assert!(body.contains("ocr_requests_in_flight 0"));
+}
+
 #[tokio::test]
 async fn test_ocr_png() {
     let server = TestServer::start();
@@ -203,6 +226,212 @@ async fn test_ocr_webp() {
     assert!(result.confidence > 0.0);
 }
 
+#[tokio::test]
+async fn test_ocr_webp_lossless() {
+    let server = TestServer::start();
+    let client = reqwest::Client::new();
+
+    let result = test_ocr_file(
+        &client,
+        &server.base_url(),
+        "sample_text_lossless.webp",
+        "image/webp",
+    )
+    .await;
+
+    assert!(result.text.contains("Hello"));
+    assert!(result.text.contains("World"));
+    assert!(result.confidence > 0.0);
+}
+
+#[tokio::test]
+async fn test_ocr_webp_extended() {
+    let server = TestServer::start();
+    let client = reqwest::Client::new();
+
+    let result = test_ocr_file(
+        &client,
+        &server.base_url(),
+        "sample_text_extended.webp",
+        "image/webp",
+    )
+    .await;
+
+    assert!(result.text.contains("Hello"));
+    assert!(result.text.contains("World"));
+    assert!(result.confidence > 0.0);
+}
+
+#[tokio::test]
+async fn test_ocr_webp_animated_uses_first_frame() {
+    let server = TestServer::start();
+    let client = reqwest::Client::new();
+
+    let result = test_ocr_file(
+        &client,
+        &server.base_url(),
+        "sample_text_animated.webp",
+        "image/webp",
+    )
+    .await;
+
+    assert!(result.text.contains("Hello"));
+    assert!(result.text.contains("World"));
+    assert!(result.warnings.iter().any(|w| w.contains("first frame")));
+}
+
+#[tokio::test]
+async fn test_ocr_json_base64_upload() {
+    use base64::Engine;
+
+    let server = TestServer::start();
+    let client = reqwest::Client::new();
+    let file_bytes = fs::read(test_fixture_path("sample_text.png")).unwrap();
+
+    let response = client
+        .post(format!("{}/ocr", server.base_url()))
+        .json(&serde_json::json!({
+            "image_base64": base64::engine::general_purpose::STANDARD.encode(&file_bytes),
+            "content_type": "image/png",
+            "languages": "eng",
+        }))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert!(
+        response.status().is_success(),
+        "Expected success status, got {}",
+        response.status()
+    );
+    let result: OcrResponse = response.json().await.unwrap();
+    assert!(result.text.contains("Hello"));
+    assert!(result.text.contains("World"));
+
+    let response = client
+        .post(format!("{}/ocr", server.base_url()))
+        .json(&serde_json::json!({ "image_base64": "not base64!" }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_ocr_word_confidence() {
+    let server = TestServer::start();
+    let client = reqwest::Client::new();
+
+    let file_bytes = fs::read(test_fixture_path("sample_text.png")).unwrap();
+    let part = Part::bytes(file_bytes)
+        .file_name("sample_text.png")
+        .mime_str("image/png")
+        .unwrap();
+
+    let response = client
+        .post(format!("{}/ocr?word_confidence=true", server.base_url()))
+        .multipart(Form::new().part("file", part))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert!(response.status().is_success());
+    let result: serde_json::Value = response.json().await.unwrap();
+    let words = result["word_confidences"]
+        .as_array()
+        .expect("Expected word_confidences");
+    let text = result["text"].as_str().unwrap();
+    assert_eq!(words.len(), text.split_whitespace().count());
+    assert!(words.iter().any(|word| word["text"] == "Hello"));
+    assert!(words
+        .iter()
+        .all(|word| (0.0..=1.0).contains(&word["confidence"].as_f64().unwrap())));
+}
+
+#[tokio::test]
+async fn test_ocr_format_text() {
+    let server = TestServer::start();
+    let client = reqwest::Client::new();
+
+    let file_bytes = fs::read(test_fixture_path("sample_text.png")).unwrap();
+    let part = Part::bytes(file_bytes)
+        .file_name("sample_text.png")
+        .mime_str("image/png")
+        .unwrap();
+
+    let response = client
+        .post(format!("{}/ocr?format=text", server.base_url()))
+        .multipart(Form::new().part("file", part))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert!(response.status().is_success());
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/plain; charset=utf-8"
+    );
+    let confidence: f32 = response.headers()["x-ocr-confidence"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((0.0..=1.0).contains(&confidence));
+    let text = response.text().await.unwrap();
+    assert!(text.contains("Hello"), "Expected 'Hello' in: {}", text);
+}
+
+#[tokio::test]
+async fn test_ocr_job_polling() {
+    let server = TestServer::start();
+    let client = reqwest::Client::new();
+
+    let file_bytes = fs::read(test_fixture_path("sample_text.pdf")).unwrap();
+    let part = Part::bytes(file_bytes)
+        .file_name("sample_text.pdf")
+        .mime_str("application/pdf")
+        .unwrap();
+
+    let response = client
+        .post(format!("{}/ocr/jobs", server.base_url()))
+        .multipart(Form::new().part("file", part))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 202);
+    let job: serde_json::Value = response.json().await.unwrap();
+    let id = job["job_id"]
+        .as_str()
+        .expect("Expected a job id")
+        .to_string();
+
+    let mut job = serde_json::Value::Null;
+    for _ in 0..120 {
+        job = client
+            .get(format!("{}/ocr/jobs/{}", server.base_url(), id))
+            .send()
+            .await
+            .expect("Failed to send request")
+            .json()
+            .await
+            .unwrap();
+        if job["status"] == "done" || job["status"] == "failed" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+
+    assert_eq!(job["status"], "done", "{}", job);
+    assert!(job["result"]["text"].as_str().unwrap().contains("Hello"));
+
+    let response = client
+        .get(format!("{}/ocr/jobs/unknown", server.base_url()))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), 404);
+}
+
 #[tokio::test]
 async fn test_ocr_tiff() {
     let server = TestServer::start();
@@ -221,6 +450,59 @@ async fn test_ocr_tiff() {
     assert!(result.confidence > 0.0);
 }
 
+#[tokio::test]
+async fn test_ocr_tiff_sent_as_octet_stream() {
+    let server = TestServer::start();
+    let client = reqwest::Client::new();
+
+    let result = test_ocr_file(
+        &client,
+        &server.base_url(),
+        "sample_text.tiff",
+        "application/octet-stream",
+    )
+    .await;
+
+    assert!(result.text.contains("Hello"));
+}
+
+#[tokio::test]
+async fn test_ocr_multi_page_tiff() {
+    let server = TestServer::start();
+    let client = reqwest::Client::new();
+
+    let result = test_ocr_file(&client, &server.base_url(), "multi_page.tiff", "image/tiff").await;
+
+    // Every page is read, like the pages of a fax
+    assert!(result.text.contains("Hello"));
+    assert!(result.text.contains("Second"));
+    assert!(result.text.contains("67890"));
+    assert!(result.confidence > 0.0);
+}
+
+#[tokio::test]
+async fn test_ocr_rejects_unrecognized_contents() {
+    let server = TestServer::start();
+    let client = reqwest::Client::new();
+
+    let part = Part::bytes(b"just some text".to_vec())
+        .file_name("notes.png")
+        .mime_str("image/png")
+        .unwrap();
+
+    let response = client
+        .post(format!("{}/ocr", server.base_url()))
+        .multipart(Form::new().part("file", part))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "UNSUPPORTED_FORMAT");
+    assert!(body["error"].as_str().unwrap().contains("image/png"));
+}
+
 #[tokio::test]
 async fn test_ocr_pdf() {
     let server = TestServer::start();
@@ -240,6 +522,78 @@ async fn test_ocr_pdf() {
     assert!(result.confidence > 0.0);
 }
 
+#[tokio::test]
+async fn test_ocr_pdf_with_jpeg_images() {
+    let server = TestServer::start();
+    let client = reqwest::Client::new();
+
+    let result = test_ocr_file(
+        &client,
+        &server.base_url(),
+        "scanned_document_jpeg.pdf",
+        "application/pdf",
+    )
+    .await;
+
+    assert!(result.text.contains("Hello"));
+    assert!(result.text.contains("World"));
+    assert!(result.confidence > 0.0);
+}
+
+#[tokio::test]
+async fn test_ocr_pdf_with_ccitt_images() {
+    let server = TestServer::start();
+    let client = reqwest::Client::new();
+
+    let result = test_ocr_file(
+        &client,
+        &server.base_url(),
+        "scanned_document_ccitt.pdf",
+        "application/pdf",
+    )
+    .await;
+
+    assert!(result.text.contains("Hello"));
+    assert!(result.text.contains("World"));
+    assert!(result.confidence > 0.0);
+}
+
+#[tokio::test]
+async fn test_ocr_pdf_streams_ndjson() {
+    let server = TestServer::start();
+    let client = reqwest::Client::new();
+
+    let file_bytes = fs::read(test_fixture_path("sample_text.pdf")).unwrap();
+    let part = Part::bytes(file_bytes)
+        .file_name("sample_text.pdf")
+        .mime_str("application/pdf")
+        .unwrap();
+
+    let response = client
+        .post(format!("{}/ocr?stream=true", server.base_url()))
+        .multipart(Form::new().part("file", part))
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert!(response.status().is_success());
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+
+    let body = response.text().await.unwrap();
+    let lines: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Each line should be JSON"))
+        .collect();
+
+    let (last, pages) = lines.split_last().expect("Expected at least one line");
+    assert!(!pages.is_empty());
+    assert!(pages.iter().all(|line| line["type"] == "page"));
+    assert_eq!(pages[0]["page"], 1);
+
+    assert_eq!(last["type"], "result");
+    assert!(last["text"].as_str().unwrap().contains("Hello"));
+}
+
 #[tokio::test]
 async fn test_ocr_returns_engine_field() {
     let server = TestServer::start();
@@ -260,7 +614,7 @@ async fn test_info_endpoint() {
     let client = reqwest::Client::new();
 
     let response: InfoResponse = client
-        .get(&format!("{}/info", server.base_url()))
+        .get(format!("{}/info", server.base_url()))
         .send()
         .await
         .expect("Failed to send request")
@@ -296,6 +650,48 @@ async fn test_info_endpoint() {
         .contains(&"application/pdf".to_string()));
 }
 
+#[tokio::test]
+async fn test_warmup_then_status() {
+    let server = TestServer::start();
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/warmup", server.base_url()))
+        .json(&serde_json::json!({ "engine": "ocrs" }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert!(response.status().is_success());
+    let warmup: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(warmup["engine"], "ocrs");
+    assert_eq!(warmup["model"], "default");
+
+    let status: serde_json::Value = client
+        .get(format!("{}/warmup/status", server.base_url()))
+        .send()
+        .await
+        .expect("Failed to send request")
+        .json()
+        .await
+        .expect("Failed to parse response");
+    let names: Vec<&str> = status["models"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|model| model["name"].as_str().unwrap())
+        .collect();
+    assert!(names.contains(&"text-detection.rten"));
+    assert!(names.contains(&"text-recognition.rten"));
+
+    let response = client
+        .post(format!("{}/warmup", server.base_url()))
+        .json(&serde_json::json!({ "engine": "nope" }))
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
 async fn test_ocr_file_with_engine(
     client: &reqwest::Client,
     base_url: &str,
@@ -304,7 +700,7 @@ async fn test_ocr_file_with_engine(
     engine: &str,
 ) -> OcrResponse {
     let path = test_fixture_path(filename);
-    let file_bytes = fs::read(&path).expect(&format!("Failed to read {}", path));
+    let file_bytes = fs::read(&path).unwrap_or_else(|_| panic!("Failed to read {}", path));
 
     let part = Part::bytes(file_bytes)
         .file_name(filename.to_string())
@@ -314,7 +710,7 @@ async fn test_ocr_file_with_engine(
     let form = Form::new().part("file", part);
 
     let response = client
-        .post(&format!("{}/ocr/{}", base_url, engine))
+        .post(format!("{}/ocr/{}", base_url, engine))
         .multipart(form)
         .send()
         .await
@@ -347,3 +743,32 @@ async fn test_ocr_with_explicit_ocrs_engine() {
     assert!(result.text.contains("Hello"));
     assert_eq!(result.engine, Some("ocrs".to_string()));
 }
+
+#[cfg(feature = "engine-leptess")]
+#[tokio::test]
+async fn test_ocr_leptess_with_several_languages() {
+    let server = TestServer::start();
+    let client = reqwest::Client::new();
+
+    let path = test_fixture_path("sample_text.png");
+    let part = Part::bytes(fs::read(&path).unwrap())
+        .file_name("sample_text.png")
+        .mime_str("image/png")
+        .unwrap();
+    let form = Form::new().part("file", part).text("languages", "deu+eng");
+
+    let response = client
+        .post(format!("{}/ocr/leptess", server.base_url()))
+        .multipart(form)
+        .send()
+        .await
+        .expect("Failed to send request");
+
+    assert!(
+        response.status().is_success(),
+        "Expected success status, got {}",
+        response.status()
+    );
+    let result: OcrResponse = response.json().await.unwrap();
+    assert!(result.text.contains("Hello"));
+}
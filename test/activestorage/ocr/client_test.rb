@@ -172,6 +172,17 @@ class ActiveStorage::Ocr::ClientTest < ActiveStorage::Ocr::TestCase
     assert_equal "ocrs", result.engine
   end
 
+  def test_extract_text_from_file_returns_no_text_detected_flag
+    empty_response = @success_response.merge(text: "", confidence: 0.0, no_text_detected: true)
+    stub_request(:post, "#{@server_url}/ocr?preprocess=default")
+      .to_return(status: 200, body: empty_response.to_json)
+
+    file = StringIO.new("fake image data")
+    result = @client.extract_text_from_file(file, "image/png", "test.png")
+
+    assert result.no_text_detected?
+  end
+
   # Preprocessing tests
 
   def test_extract_text_from_file_uses_configured_preprocess
@@ -30,6 +30,26 @@ class ActiveStorage::Ocr::ResultTest < ActiveStorage::Ocr::TestCase
     refute result.success?
   end
 
+  def test_no_text_detected_defaults_to_false
+    result = ActiveStorage::Ocr::Result.new(
+      text: "Hello World",
+      confidence: 0.95,
+      processing_time_ms: 100
+    )
+    refute result.no_text_detected?
+  end
+
+  def test_no_text_detected_when_flagged_by_server
+    result = ActiveStorage::Ocr::Result.new(
+      text: "",
+      confidence: 0.0,
+      processing_time_ms: 100,
+      no_text_detected: true
+    )
+    assert result.no_text_detected?
+    refute result.success?
+  end
+
   def test_to_h_returns_hash_representation
     result = ActiveStorage::Ocr::Result.new(
       text: "Test",